@@ -0,0 +1,164 @@
+//! Structured access logging.
+//!
+//! [`AccessLog`] writes one JSON line per request to stdout, where the
+//! Lambda runtime forwards it to CloudWatch Logs:
+//!
+//! ```text
+//! {"method":"GET","route":"/users/{user_id}","path":"/users/42","status":200,"duration_ms":12,"bytes":87,"client_ip":"203.0.113.9","user_agent":"curl/8.4.0","request_id":"c6af9ac6-..."}
+//! ```
+//!
+//! # Example
+//! ```ignore
+//! use choko::access_log::AccessLog;
+//!
+//! app.middleware(AccessLog::new());
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, ResponseBody};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// One access log line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessLogEntry {
+    pub method: String,
+    /// The matched route pattern, e.g. `/users/{user_id}`.
+    pub route: Option<String>,
+    pub path: String,
+    /// The response status; 500 if the handler returned an error.
+    pub status: u16,
+    pub duration_ms: u64,
+    /// Response body size before any compression.
+    pub bytes: usize,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// The API Gateway request ID.
+    pub request_id: Option<String>,
+    /// The correlation ID from [`Request::request_id`].
+    pub correlation_id: Option<String>,
+}
+
+type SinkFn = Arc<dyn Fn(&AccessLogEntry) + Send + Sync>;
+
+/// Middleware logging every request it sees.
+///
+/// Register it first so its duration covers the rest of the chain.
+/// Streamed bodies are buffered to measure them, so they are no longer sent
+/// incrementally by the container server or Function URL streaming mode.
+pub struct AccessLog {
+    sink: SinkFn,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccessLog {
+    /// Log to stdout.
+    pub fn new() -> Self {
+        Self {
+            sink: Arc::new(|entry| match serde_json::to_string(entry) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("Failed to serialize access log entry: {e}"),
+            }),
+        }
+    }
+
+    /// Send entries to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&AccessLogEntry) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+}
+
+fn body_len(body: &ResponseBody) -> usize {
+    match body {
+        ResponseBody::Json(value) => serde_json::to_vec(value).map_or(0, |v| v.len()),
+        ResponseBody::Text(text) => text.len(),
+        ResponseBody::Binary(bytes) => bytes.len(),
+        ResponseBody::Empty | ResponseBody::Stream(_) => 0,
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let started = Instant::now();
+        let mut entry = AccessLogEntry {
+            method: req.method().to_string(),
+            route: req.route().map(str::to_string),
+            path: req.path().to_string(),
+            status: 0,
+            duration_ms: 0,
+            bytes: 0,
+            client_ip: req.client_ip().map(|ip| ip.to_string()),
+            user_agent: req.header("user-agent").map(str::to_string),
+            request_id: req.request_context().request_id.clone(),
+            correlation_id: req.request_id().map(str::to_string),
+        };
+        let sink = Arc::clone(&self.sink);
+        Box::pin(async move {
+            let result = match next.run(req).await {
+                Ok(resp) => resp.buffered().await,
+                Err(e) => Err(e),
+            };
+            match &result {
+                Ok(resp) => {
+                    entry.status = u16::try_from(resp.status_code).unwrap_or(500);
+                    entry.bytes = body_len(&resp.body);
+                }
+                Err(_) => entry.status = 500,
+            }
+            entry.duration_ms = started.elapsed().as_millis() as u64;
+            sink(&entry);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::sync::Mutex;
+
+    async fn log<F, Fut>(handler: F) -> AccessLogEntry
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+    {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&entries);
+        let mw = AccessLog::new().sink(move |e| captured.lock().unwrap().push(e.clone()));
+        let req = Request {
+            headers: HashMap::from([("User-Agent".to_string(), "curl/8.4.0".to_string())]),
+            ..Default::default()
+        };
+        let _ = run_middleware(mw, handler, req).await;
+        let entry = entries.lock().unwrap().pop();
+        entry.unwrap()
+    }
+
+    #[tokio::test]
+    async fn logs_status_and_size() {
+        let entry = log(|_req| async { Ok(Response::text("hello").with_status(201)) }).await;
+        assert_eq!(entry.status, 201);
+        assert_eq!(entry.bytes, 5);
+        assert_eq!(entry.user_agent.as_deref(), Some("curl/8.4.0"));
+    }
+
+    #[tokio::test]
+    async fn handler_errors_are_logged_as_500() {
+        let entry = log(|_req| async { Err("boom".into()) }).await;
+        assert_eq!(entry.status, 500);
+        assert_eq!(entry.bytes, 0);
+    }
+}
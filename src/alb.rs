@@ -0,0 +1,199 @@
+//! Application Load Balancer target support (`alb` feature).
+//!
+//! ALB invokes Lambda targets with its own event shape. Requests are
+//! translated into the API Gateway form the router works on, and responses
+//! back, accounting for ALB's differences:
+//!
+//! - query strings arrive exactly as sent, still percent-encoded;
+//! - with multi-value headers enabled on the target group, requests carry
+//!   only the `multiValue*` maps and responses must answer the same way;
+//! - responses need a `statusDescription`.
+
+use crate::{Choko, Error, LambdaContext};
+use aws_lambda_events::event::alb::{AlbTargetGroupRequest, AlbTargetGroupResponse};
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::query_map::QueryMap;
+use std::collections::HashMap;
+
+/// Decode a raw query string component (`+` is a space).
+fn decode_query(component: &str) -> String {
+    crate::cookie::decode_value(&component.replace('+', " "))
+}
+
+fn decode_query_map(raw: &QueryMap) -> QueryMap {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for (k, v) in raw.iter() {
+        params
+            .entry(decode_query(k))
+            .or_default()
+            .push(decode_query(v));
+    }
+    QueryMap::from(params)
+}
+
+/// The API Gateway event equivalent to `event`.
+pub(crate) fn to_apigw_request(event: AlbTargetGroupRequest) -> ApiGatewayProxyRequest {
+    let mut req = ApiGatewayProxyRequest::default();
+    req.http_method = event.http_method;
+    req.path = event.path;
+    if event.multi_value_headers.is_empty() {
+        req.headers = event.headers;
+        req.query_string_parameters = decode_query_map(&event.query_string_parameters);
+    } else {
+        // The router reads single-value headers; repeated ones are joined
+        // the way HTTP allows
+        for name in event.multi_value_headers.keys() {
+            let joined = event
+                .multi_value_headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if let Ok(value) = http::HeaderValue::from_str(&joined) {
+                req.headers.insert(name.clone(), value);
+            }
+        }
+        req.multi_value_headers = event.multi_value_headers;
+        req.multi_value_query_string_parameters =
+            decode_query_map(&event.multi_value_query_string_parameters);
+    }
+    req.body = event.body;
+    req.is_base64_encoded = event.is_base64_encoded;
+    req
+}
+
+/// The ALB response equivalent to `resp`, using multi-value headers when
+/// the request did.
+pub(crate) fn to_alb_response(
+    resp: ApiGatewayProxyResponse,
+    multi_value: bool,
+) -> AlbTargetGroupResponse {
+    let mut out = AlbTargetGroupResponse::default();
+    out.status_code = resp.status_code;
+    out.status_description = Some(
+        http::StatusCode::from_u16(u16::try_from(resp.status_code).unwrap_or(500))
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .map_or_else(
+                || resp.status_code.to_string(),
+                |reason| format!("{} {reason}", resp.status_code),
+            ),
+    );
+    if multi_value {
+        let mut headers = resp.headers;
+        for (name, value) in resp.multi_value_headers.iter() {
+            headers.append(name.clone(), value.clone());
+        }
+        out.multi_value_headers = headers;
+    } else {
+        // Without multi-value headers ALB can send each header only once,
+        // so the last `Set-Cookie` (say) wins
+        let mut headers = resp.headers;
+        for (name, value) in resp.multi_value_headers.iter() {
+            headers.insert(name.clone(), value.clone());
+        }
+        out.headers = headers;
+    }
+    out.body = resp.body;
+    out.is_base64_encoded = resp.is_base64_encoded;
+    out
+}
+
+impl Choko {
+    /// Run the application as the Lambda target of an Application Load
+    /// Balancer target group.
+    ///
+    /// Routes, middleware and hooks behave as with [`run`](Self::run).
+    /// API Gateway-only request data (stage, authorizer, request context
+    /// identity) is absent; the client address comes from
+    /// `X-Forwarded-For`.
+    pub async fn run_alb(self) -> Result<(), Error> {
+        self.serve(|app, event: AlbTargetGroupRequest, context| async move {
+            app.dispatch_alb(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_alb(
+        &self,
+        event: AlbTargetGroupRequest,
+        context: Option<LambdaContext>,
+    ) -> Result<AlbTargetGroupResponse, Error> {
+        let multi_value = !event.multi_value_headers.is_empty();
+        let resp = self
+            .dispatch_with_context(to_apigw_request(event), context)
+            .await?;
+        Ok(to_alb_response(resp, multi_value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use aws_lambda_events::encodings::Body;
+
+    fn alb_request(path: &str, query: &[(&str, &str)], multi_value: bool) -> AlbTargetGroupRequest {
+        let mut event = AlbTargetGroupRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some(path.to_string());
+        let params: HashMap<String, Vec<String>> = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+            .collect();
+        let host = http::HeaderValue::from_static("internal.example.com");
+        if multi_value {
+            event.multi_value_query_string_parameters = QueryMap::from(params);
+            event.multi_value_headers.append("host", host);
+        } else {
+            event.query_string_parameters = QueryMap::from(params);
+            event.headers.insert("host", host);
+        }
+        event
+    }
+
+    #[tokio::test]
+    async fn decodes_query_and_describes_status() {
+        let mut app = Choko::new("test");
+        app.get("/search", |req| async move {
+            Ok(Response::text(req.query_params["q"][0].clone()))
+        });
+
+        let resp = app
+            .dispatch_alb(
+                alb_request("/search", &[("q", "caf%C3%A9+au+lait")], false),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.status_description.as_deref(), Some("200 OK"));
+        assert_eq!(resp.body, Some(Body::Text("café au lait".to_string())));
+        assert!(resp.multi_value_headers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn answers_multi_value_requests_with_multi_value_headers() {
+        let mut app = Choko::new("test");
+        app.get("/", |_req| async {
+            Ok(Response::no_content()
+                .with_cookie(crate::Cookie::new("a", "1"))
+                .with_cookie(crate::Cookie::new("b", "2")))
+        });
+
+        let resp = app
+            .dispatch_alb(alb_request("/", &[], true), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 204);
+        assert_eq!(
+            resp.multi_value_headers
+                .get_all("set-cookie")
+                .iter()
+                .count(),
+            2
+        );
+        assert!(resp.headers.is_empty());
+    }
+}
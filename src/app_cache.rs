@@ -0,0 +1,228 @@
+//! A typed in-memory cache scoped to the execution environment.
+//!
+//! Warm Lambda containers serve many requests, so values that are slow to
+//! load and change rarely (exchange rates, a tenant's settings, a JWKS) can
+//! be kept between invocations. [`AppCache`] keeps each value for a TTL
+//! instead of forever, as a `static` would, so updates are picked up.
+//!
+//! Concurrent requests for a missing key share one load: the first runs the
+//! loader and the others wait for its value. Failed loads aren't cached.
+//!
+//! # Example
+//! ```ignore
+//! use choko::app_cache::AppCache;
+//!
+//! app.state(AppCache::<String, Rates>::new());
+//!
+//! app.get("/prices/{currency}", |req| async move {
+//!     let cache = req.state::<AppCache<String, Rates>>().ok_or("cache not configured")?;
+//!     let currency = req.path_params["currency"].clone();
+//!     let rates = cache
+//!         .get_or_init(currency.clone(), Duration::from_secs(300), || fetch_rates(currency))
+//!         .await?;
+//!     Ok(Response::json(json!(rates)))
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// A value and when it expires, set once its load completes.
+type Slot<V> = Arc<OnceCell<(V, Instant)>>;
+
+/// A map of values that expire after a TTL. Cheap to clone; clones share
+/// the same entries.
+pub struct AppCache<K, V> {
+    entries: Arc<Mutex<HashMap<K, Slot<V>>>>,
+    max_entries: usize,
+}
+
+impl<K, V> Clone for AppCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: Arc::clone(&self.entries),
+            max_entries: self.max_entries,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for AppCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> AppCache<K, V> {
+    /// An empty cache holding up to 1,000 values.
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            max_entries: 1_000,
+        }
+    }
+
+    /// The most values kept. When full, expired values are dropped first,
+    /// then the ones closest to expiring.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max.max(1);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, Slot<V>>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The value for `key`, or the one `loader` returns, kept for `ttl`.
+    ///
+    /// While a load is running, other callers for the same key wait for it
+    /// instead of starting their own. If it fails, the error is returned to
+    /// its caller and the next waiter runs its loader.
+    pub async fn get_or_init<F, Fut, E>(&self, key: K, ttl: Duration, loader: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        let slot = {
+            let mut entries = self.lock();
+            match entries.get(&key) {
+                Some(slot) if fresh(slot, Instant::now()) => Arc::clone(slot),
+                _ => {
+                    let slot = Slot::default();
+                    self.insert_slot(&mut entries, key, Arc::clone(&slot));
+                    slot
+                }
+            }
+        };
+        let (value, _) = slot
+            .get_or_try_init(|| async move { Ok::<_, E>((loader().await?, Instant::now() + ttl)) })
+            .await?;
+        Ok(value.clone())
+    }
+
+    /// The value for `key`, if it's cached and hasn't expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let now = Instant::now();
+        let entries = self.lock();
+        match entries.get(key)?.get() {
+            Some((value, expires)) if *expires > now => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Cache `value` for `key` for `ttl`, replacing any cached value.
+    pub fn insert(&self, key: K, value: V, ttl: Duration) {
+        let slot = Arc::new(OnceCell::new_with(Some((value, Instant::now() + ttl))));
+        let mut entries = self.lock();
+        self.insert_slot(&mut entries, key, slot);
+    }
+
+    /// Drop the value for `key`, so the next lookup loads it again.
+    pub fn invalidate(&self, key: &K) {
+        self.lock().remove(key);
+    }
+
+    /// Drop every value.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn insert_slot(&self, entries: &mut HashMap<K, Slot<V>>, key: K, slot: Slot<V>) {
+        let now = Instant::now();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, slot| fresh(slot, now));
+            while entries.len() >= self.max_entries {
+                // Loads still running have no expiry and are kept
+                let soonest = entries
+                    .iter()
+                    .filter_map(|(key, slot)| Some((key, slot.get()?.1)))
+                    .min_by_key(|(_, expires)| *expires)
+                    .map(|(key, _)| key.clone());
+                match soonest {
+                    Some(soonest) => entries.remove(&soonest),
+                    None => break,
+                };
+            }
+        }
+        entries.insert(key, slot);
+    }
+}
+
+/// Whether `slot` is loading or holds an unexpired value.
+fn fresh<V>(slot: &OnceCell<(V, Instant)>, now: Instant) -> bool {
+    slot.get().is_none_or(|(_, expires)| *expires > now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_load() {
+        let cache = AppCache::<&str, u32>::new();
+        let loads = AtomicUsize::new(0);
+        let loads = &loads;
+        let load = move || async move {
+            loads.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, String>(7)
+        };
+        let ttl = Duration::from_secs(60);
+        let (a, b, c) = tokio::join!(
+            cache.get_or_init("rates", ttl, load),
+            cache.get_or_init("rates", ttl, load),
+            cache.get_or_init("rates", ttl, load),
+        );
+        assert_eq!((a, b, c), (Ok(7), Ok(7), Ok(7)));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.get(&"rates"), Some(7));
+    }
+
+    #[tokio::test]
+    async fn reloads_expired_values() {
+        let cache = AppCache::<&str, u32>::new();
+        let loaded = cache
+            .get_or_init("k", Duration::ZERO, || async { Ok::<_, String>(1) })
+            .await;
+        assert_eq!(loaded, Ok(1));
+        assert_eq!(cache.get(&"k"), None);
+        let reloaded = cache
+            .get_or_init("k", Duration::from_secs(60), || async {
+                Ok::<_, String>(2)
+            })
+            .await;
+        assert_eq!(reloaded, Ok(2));
+
+        cache.invalidate(&"k");
+        assert_eq!(cache.get(&"k"), None);
+    }
+
+    #[tokio::test]
+    async fn failed_loads_are_not_cached() {
+        let cache = AppCache::<&str, u32>::new();
+        let ttl = Duration::from_secs(60);
+        let failed = cache
+            .get_or_init("k", ttl, || async { Err("unavailable") })
+            .await;
+        assert_eq!(failed, Err("unavailable"));
+        let loaded = cache
+            .get_or_init("k", ttl, || async { Ok::<_, &str>(3) })
+            .await;
+        assert_eq!(loaded, Ok(3));
+    }
+
+    #[test]
+    fn evicts_the_soonest_expiring_when_full() {
+        let cache = AppCache::new().max_entries(2);
+        cache.insert("a", 1, Duration::from_secs(10));
+        cache.insert("b", 2, Duration::from_secs(60));
+        cache.insert("c", 3, Duration::from_secs(60));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(2));
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+}
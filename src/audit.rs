@@ -0,0 +1,351 @@
+//! Structured audit logging.
+//!
+//! [`AuditLog`] emits one [`AuditRecord`] per request: who made it, which
+//! route it hit, the outcome and how long it took. Sensitive headers and JSON
+//! body fields are redacted before the record leaves the middleware.
+//!
+//! # Example
+//! ```ignore
+//! use choko::audit::AuditLog;
+//!
+//! app.middleware(
+//!     AuditLog::new()
+//!         .include_body(true)
+//!         .redact_field("date_of_birth"),
+//! );
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Replacement for redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers redacted by default.
+pub(crate) const DEFAULT_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "x-api-key",
+    "x-signature",
+];
+
+/// JSON body fields redacted by default, at any depth.
+pub(crate) const DEFAULT_FIELDS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "card_number",
+    "ssn",
+];
+
+/// How a request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// 1xx–3xx.
+    Success,
+    /// 4xx, including rejections by earlier middleware.
+    Denied,
+    /// 5xx, or the handler returned an error.
+    Failure,
+}
+
+/// One audited request.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the Unix epoch when the request arrived.
+    pub timestamp_ms: u128,
+    pub request_id: Option<String>,
+    /// The correlation ID from [`Request::request_id`].
+    pub correlation_id: Option<String>,
+    /// The authenticated caller, if any.
+    pub actor: Option<String>,
+    pub source_ip: Option<String>,
+    pub method: String,
+    /// The matched route pattern, e.g. `/users/{user_id}`.
+    pub route: Option<String>,
+    pub path: String,
+    pub status: u16,
+    pub outcome: Outcome,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+type ActorFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+type SinkFn = Arc<dyn Fn(&AuditRecord) + Send + Sync>;
+
+/// Middleware that writes an [`AuditRecord`] for every request.
+///
+/// The actor is read when the request reaches this middleware, so register
+/// it after the authentication middleware whose identity it should record.
+///
+/// Records go to stdout as one JSON line each (picked up by CloudWatch
+/// Logs) unless a [`sink`](Self::sink) is set. Headers and the JSON body are
+/// only included when enabled, and always pass through redaction:
+/// `Authorization`, `Cookie`, API key and signature headers, and fields such
+/// as `password` and `token` are replaced with [`REDACTED`].
+pub struct AuditLog {
+    actor: ActorFn,
+    sink: SinkFn,
+    redact_headers: HashSet<String>,
+    redact_fields: HashSet<String>,
+    include_headers: bool,
+    include_body: bool,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuditLog {
+    /// Audit to stdout with the default redaction rules.
+    pub fn new() -> Self {
+        Self {
+            actor: Arc::new(default_actor),
+            sink: Arc::new(|record| match serde_json::to_string(record) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("Failed to serialize audit record: {e}"),
+            }),
+            redact_headers: DEFAULT_HEADERS.iter().map(|h| h.to_string()).collect(),
+            redact_fields: DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect(),
+            include_headers: false,
+            include_body: false,
+        }
+    }
+
+    /// Send records to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&AuditRecord) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    /// Identify the caller with `actor` instead of the built-in lookup of
+    /// authenticated identities.
+    pub fn actor<F>(mut self, actor: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.actor = Arc::new(actor);
+        self
+    }
+
+    /// Include request headers in the record.
+    pub fn include_headers(mut self, enabled: bool) -> Self {
+        self.include_headers = enabled;
+        self
+    }
+
+    /// Include the JSON request body in the record.
+    pub fn include_body(mut self, enabled: bool) -> Self {
+        self.include_body = enabled;
+        self
+    }
+
+    /// Also redact the header `name` (case-insensitive).
+    pub fn redact_header(mut self, name: &str) -> Self {
+        self.redact_headers.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Also redact JSON fields called `name` (case-insensitive, at any
+    /// depth).
+    pub fn redact_field(mut self, name: &str) -> Self {
+        self.redact_fields.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    fn headers(&self, req: &Request) -> BTreeMap<String, String> {
+        if !self.include_headers {
+            return BTreeMap::new();
+        }
+        req.headers
+            .iter()
+            .map(|(k, v)| {
+                let k = k.to_ascii_lowercase();
+                let v = if self.redact_headers.contains(&k) {
+                    REDACTED.to_string()
+                } else {
+                    v.clone()
+                };
+                (k, v)
+            })
+            .collect()
+    }
+
+    fn body(&self, req: &Request) -> Option<Value> {
+        if !self.include_body {
+            return None;
+        }
+        let mut body = req.json_body.clone()?;
+        redact(&mut body, &self.redact_fields);
+        Some(body)
+    }
+}
+
+/// Replace the values of `fields` anywhere in `value`.
+fn redact(value: &mut Value, fields: &HashSet<String>) {
+    match value {
+        Value::Object(map) => redact_map(map, fields),
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, fields)),
+        _ => {}
+    }
+}
+
+fn redact_map(map: &mut Map<String, Value>, fields: &HashSet<String>) {
+    for (key, value) in map.iter_mut() {
+        if fields.contains(&key.to_ascii_lowercase()) {
+            *value = Value::from(REDACTED);
+        } else {
+            redact(value, fields);
+        }
+    }
+}
+
+/// The caller from whichever authentication ran: JWT subject, Cognito
+/// user, API key, Basic user, or IAM principal.
+fn default_actor(req: &Request) -> Option<String> {
+    #[cfg(feature = "jwt")]
+    if let Some(sub) = req.jwt_claims().and_then(|c| c.subject()) {
+        return Some(sub.to_string());
+    }
+    if let Some(claims) = req.cognito_claims() {
+        return Some(claims.sub);
+    }
+    if let Some(identity) = req.api_key_identity() {
+        return Some(identity.id.clone());
+    }
+    if let Some(user) = req.basic_auth_user() {
+        return Some(user.to_string());
+    }
+    req.iam_identity().map(|iam| iam.user_arn.clone())
+}
+
+impl Middleware for AuditLog {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let started = Instant::now();
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let mut record = AuditRecord {
+            timestamp_ms,
+            request_id: req.request_context.request_id.clone(),
+            correlation_id: req.request_id().map(str::to_string),
+            actor: (self.actor)(&req),
+            source_ip: req.request_context.source_ip.clone(),
+            method: req.method().to_string(),
+            route: req.route().map(str::to_string),
+            path: req.path().to_string(),
+            status: 0,
+            outcome: Outcome::Success,
+            latency_ms: 0,
+            headers: self.headers(&req),
+            body: self.body(&req),
+        };
+        let sink = Arc::clone(&self.sink);
+        Box::pin(async move {
+            let result = next.run(req).await;
+            record.latency_ms = started.elapsed().as_millis() as u64;
+            record.status = match &result {
+                Ok(resp) => u16::try_from(resp.status_code).unwrap_or(500),
+                Err(_) => 500,
+            };
+            record.outcome = match record.status {
+                ..=399 => Outcome::Success,
+                400..=499 => Outcome::Denied,
+                _ => Outcome::Failure,
+            };
+            sink(&record);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::BasicAuthUser;
+    use crate::middleware::run_middleware;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    async fn audit(mw: AuditLog, req: Request, status: i64) -> AuditRecord {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&records);
+        let mw = mw.sink(move |r| captured.lock().unwrap().push(r.clone()));
+        let handler = move |_req| async move { Ok(Response::no_content().with_status(status)) };
+        run_middleware(mw, handler, req).await.unwrap();
+        let record = records.lock().unwrap().pop();
+        record.unwrap()
+    }
+
+    #[test]
+    fn redacts_nested_fields() {
+        let mut body = json!({
+            "user": { "name": "a", "Password": "hunter2" },
+            "cards": [{ "card_number": "4111" }],
+        });
+        redact(
+            &mut body,
+            &DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect(),
+        );
+        assert_eq!(body["user"]["Password"], REDACTED);
+        assert_eq!(body["user"]["name"], "a");
+        assert_eq!(body["cards"][0]["card_number"], REDACTED);
+    }
+
+    #[tokio::test]
+    async fn records_actor_outcome_and_redacted_headers() {
+        let mut req = Request {
+            headers: HashMap::from([
+                ("Authorization".to_string(), "Basic abc".to_string()),
+                ("X-Tenant".to_string(), "acme".to_string()),
+            ]),
+            json_body: Some(json!({ "email": "a@example.com", "dob": "2000-01-01" })),
+            ..Default::default()
+        };
+        req.extensions_mut()
+            .insert(BasicAuthUser("alice".to_string()));
+
+        let record = audit(
+            AuditLog::new()
+                .include_headers(true)
+                .include_body(true)
+                .redact_field("dob")
+                .redact_header("x-tenant"),
+            req,
+            403,
+        )
+        .await;
+        assert_eq!(record.actor.as_deref(), Some("alice"));
+        assert_eq!(record.status, 403);
+        assert_eq!(record.outcome, Outcome::Denied);
+        assert_eq!(record.headers["authorization"], REDACTED);
+        assert_eq!(record.headers["x-tenant"], REDACTED);
+        assert_eq!(record.body.unwrap()["dob"], REDACTED);
+    }
+
+    #[tokio::test]
+    async fn omits_headers_and_body_by_default() {
+        let record = audit(AuditLog::new(), Request::default(), 200).await;
+        assert_eq!(record.outcome, Outcome::Success);
+        assert!(record.headers.is_empty() && record.body.is_none());
+        assert_eq!(record.actor, None);
+    }
+}
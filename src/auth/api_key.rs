@@ -0,0 +1,206 @@
+//! `x-api-key` authentication.
+
+use super::{constant_time_eq, unauthorized};
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use serde_json::{Map, Value};
+use std::future::Future;
+use std::sync::Arc;
+
+/// The caller an API key belongs to.
+///
+/// Stored in the request extensions by [`ApiKeyAuth`]; read it with
+/// [`Request::api_key_identity`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKeyIdentity {
+    /// A stable name for the key's owner (never the key itself).
+    pub id: String,
+    /// Extra data the validator attached, e.g. a plan or tenant.
+    pub attributes: Map<String, Value>,
+}
+
+impl ApiKeyIdentity {
+    /// An identity with no attributes.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            attributes: Map::new(),
+        }
+    }
+}
+
+impl Request {
+    /// The identity resolved by [`ApiKeyAuth`], if it ran for this request.
+    pub fn api_key_identity(&self) -> Option<&ApiKeyIdentity> {
+        self.extensions().get::<ApiKeyIdentity>()
+    }
+}
+
+/// Resolves API keys to identities.
+pub trait ApiKeyValidator: Send + Sync + 'static {
+    /// Look up `key`: `Ok(None)` for unknown or revoked keys, `Err` for
+    /// lookup failures (answered with 500).
+    fn validate(&self, key: &str) -> BoxFuture<Result<Option<ApiKeyIdentity>, Error>>;
+}
+
+/// A fixed set of keys, compared in constant time.
+pub struct StaticKeys {
+    keys: Vec<(String, String)>,
+}
+
+impl StaticKeys {
+    /// Keys paired with the identity they resolve to.
+    pub fn new<K, I>(keys: impl IntoIterator<Item = (K, I)>) -> Self
+    where
+        K: Into<String>,
+        I: Into<String>,
+    {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|(k, i)| (k.into(), i.into()))
+                .collect(),
+        }
+    }
+}
+
+impl ApiKeyValidator for StaticKeys {
+    fn validate(&self, key: &str) -> BoxFuture<Result<Option<ApiKeyIdentity>, Error>> {
+        // Check every key so timing doesn't reveal which one matched
+        let mut found = None;
+        for (candidate, id) in &self.keys {
+            if constant_time_eq(candidate.as_bytes(), key.as_bytes()) {
+                found = Some(ApiKeyIdentity::new(id.clone()));
+            }
+        }
+        Box::pin(async move { Ok(found) })
+    }
+}
+
+/// A validator backed by an async closure.
+pub struct ValidateFn<F> {
+    f: F,
+}
+
+impl<F, Fut> ApiKeyValidator for ValidateFn<F>
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<ApiKeyIdentity>, Error>> + Send + 'static,
+{
+    fn validate(&self, key: &str) -> BoxFuture<Result<Option<ApiKeyIdentity>, Error>> {
+        Box::pin((self.f)(key.to_string()))
+    }
+}
+
+/// Build a validator from an async closure.
+pub fn validate_fn<F, Fut>(f: F) -> ValidateFn<F>
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<ApiKeyIdentity>, Error>> + Send + 'static,
+{
+    ValidateFn { f }
+}
+
+/// Middleware that requires a valid API key header.
+///
+/// Requests without the header get 401; keys the validator doesn't know get
+/// 403. On success the [`ApiKeyIdentity`] is inserted into the request
+/// extensions.
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::{ApiKeyAuth, StaticKeys};
+///
+/// app.middleware(ApiKeyAuth::new(StaticKeys::new([(ci_key, "ci"), (ops_key, "ops")])));
+/// ```
+pub struct ApiKeyAuth {
+    validator: Arc<dyn ApiKeyValidator>,
+    header: String,
+}
+
+impl ApiKeyAuth {
+    /// Check the `x-api-key` header with `validator`.
+    pub fn new(validator: impl ApiKeyValidator) -> Self {
+        Self {
+            validator: Arc::new(validator),
+            header: "x-api-key".to_string(),
+        }
+    }
+
+    /// Read the key from a different header.
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = name.to_ascii_lowercase();
+        self
+    }
+}
+
+impl Middleware for ApiKeyAuth {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let validator = Arc::clone(&self.validator);
+        let key = req.header(&self.header).map(str::to_string);
+        Box::pin(async move {
+            let Some(key) = key.filter(|k| !k.is_empty()) else {
+                return Ok(unauthorized("ApiKey", "Unauthorized"));
+            };
+            match validator.validate(&key).await? {
+                Some(identity) => {
+                    req.extensions_mut().insert(identity);
+                    next.run(req).await
+                }
+                None => Ok(crate::error_json(403, "Forbidden")),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use std::collections::HashMap;
+
+    async fn call(auth: ApiKeyAuth, headers: &[(&str, &str)]) -> Response {
+        let req = Request {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        };
+        let handler = |req: Request| async move {
+            let id = req.api_key_identity().map(|i| i.id.clone());
+            Ok(Response::text(id.unwrap_or_default()))
+        };
+        run_middleware(auth, handler, req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn static_keys_resolve_identity() {
+        let auth = ApiKeyAuth::new(StaticKeys::new([("k1", "ci"), ("k2", "ops")]));
+        let resp = call(auth, &[("x-api-key", "k2")]).await;
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.body, crate::ResponseBody::Text("ops".to_string()));
+    }
+
+    #[tokio::test]
+    async fn missing_key_is_401_and_unknown_key_is_403() {
+        let keys = || StaticKeys::new([("k1", "ci")]);
+        assert_eq!(call(ApiKeyAuth::new(keys()), &[]).await.status_code, 401);
+        assert_eq!(
+            call(ApiKeyAuth::new(keys()), &[("x-api-key", "nope")])
+                .await
+                .status_code,
+            403
+        );
+    }
+
+    #[tokio::test]
+    async fn closure_validator_and_custom_header() {
+        let auth = ApiKeyAuth::new(validate_fn(|key: String| async move {
+            Ok((key == "secret").then(|| ApiKeyIdentity::new("svc")))
+        }))
+        .header("X-Service-Key");
+        let resp = call(auth, &[("x-service-key", "secret")]).await;
+        assert_eq!(resp.body, crate::ResponseBody::Text("svc".to_string()));
+    }
+}
@@ -0,0 +1,134 @@
+//! HTTP Basic authentication.
+
+use super::{constant_time_eq, unauthorized};
+use crate::middleware::{Middleware, Next};
+use crate::{BasicCredentials, BoxFuture, Error, Request, Response};
+use std::sync::Arc;
+
+/// The username authenticated by [`BasicAuth`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicAuthUser(pub String);
+
+impl Request {
+    /// The username authenticated by [`BasicAuth`], if it ran for this
+    /// request.
+    pub fn basic_auth_user(&self) -> Option<&str> {
+        self.extensions()
+            .get::<BasicAuthUser>()
+            .map(|u| u.0.as_str())
+    }
+}
+
+type CheckFn = dyn Fn(&BasicCredentials) -> bool + Send + Sync;
+
+/// Middleware that requires `Authorization: Basic` credentials accepted by
+/// a check closure.
+///
+/// Failures get 401 with a `WWW-Authenticate: Basic realm="..."` challenge,
+/// so browsers show a login prompt. On success the username is inserted
+/// into the request extensions as [`BasicAuthUser`].
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::BasicAuth;
+///
+/// app.middleware(BasicAuth::single_user("staging", &user, &password));
+/// ```
+pub struct BasicAuth {
+    check: Arc<CheckFn>,
+    challenge: String,
+}
+
+impl BasicAuth {
+    /// Accept credentials for which `check` returns `true`.
+    pub fn new<F>(realm: &str, check: F) -> Self
+    where
+        F: Fn(&BasicCredentials) -> bool + Send + Sync + 'static,
+    {
+        let realm = realm.replace(['"', '\\'], "");
+        Self {
+            check: Arc::new(check),
+            challenge: format!("Basic realm=\"{realm}\", charset=\"UTF-8\""),
+        }
+    }
+
+    /// Accept one fixed username and password, compared in constant time.
+    pub fn single_user(realm: &str, username: &str, password: &str) -> Self {
+        let (username, password) = (username.to_string(), password.to_string());
+        Self::new(realm, move |creds| {
+            // Evaluate both comparisons so timing doesn't reveal which failed
+            let user_ok = constant_time_eq(creds.username.as_bytes(), username.as_bytes());
+            let pass_ok = constant_time_eq(creds.password.as_bytes(), password.as_bytes());
+            user_ok & pass_ok
+        })
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        match req.basic_credentials() {
+            Some(creds) if (self.check)(&creds) => {
+                req.extensions_mut().insert(BasicAuthUser(creds.username));
+                next.run(req)
+            }
+            _ => {
+                let resp = unauthorized(&self.challenge, "Unauthorized");
+                Box::pin(async move { Ok(resp) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use base64::Engine;
+    use std::collections::HashMap;
+
+    async fn call(auth: BasicAuth, credentials: Option<&str>) -> Response {
+        let mut headers = HashMap::new();
+        if let Some(creds) = credentials {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(creds);
+            headers.insert("authorization".to_string(), format!("Basic {encoded}"));
+        }
+        let req = Request {
+            headers,
+            ..Default::default()
+        };
+        let handler = |req: Request| async move {
+            let user = req.basic_auth_user().unwrap_or_default().to_string();
+            Ok(Response::text(user))
+        };
+        run_middleware(auth, handler, req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_credentials() {
+        let resp = call(
+            BasicAuth::single_user("staging", "admin", "pw"),
+            Some("admin:pw"),
+        )
+        .await;
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.body, crate::ResponseBody::Text("admin".to_string()));
+    }
+
+    #[tokio::test]
+    async fn challenges_missing_or_wrong_credentials() {
+        for creds in [None, Some("admin:wrong")] {
+            let resp = call(BasicAuth::single_user("staging", "admin", "pw"), creds).await;
+            assert_eq!(resp.status_code, 401);
+            assert_eq!(
+                resp.header("www-authenticate"),
+                Some("Basic realm=\"staging\", charset=\"UTF-8\"")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_check_closure() {
+        let auth = BasicAuth::new("ops", |creds| creds.username.starts_with("ops-"));
+        assert_eq!(call(auth, Some("ops-1:x")).await.status_code, 200);
+    }
+}
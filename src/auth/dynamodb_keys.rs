@@ -0,0 +1,87 @@
+//! API keys stored in a DynamoDB table (`dynamodb` feature).
+
+use super::api_key::{ApiKeyIdentity, ApiKeyValidator};
+use crate::{BoxFuture, Error};
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde_json::Value;
+
+/// Looks API keys up in a DynamoDB table whose partition key is the key.
+///
+/// The identity's `id` comes from the `id` attribute (configurable); other
+/// string, number and boolean attributes become identity attributes. Items
+/// with `enabled = false` are treated as revoked.
+///
+/// Consider storing a hash of the key as the partition key and hashing in a
+/// [`validate_fn`](super::validate_fn) instead if the table may be read by
+/// others.
+#[derive(Debug, Clone)]
+pub struct DynamoDbKeys {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    key_attribute: String,
+    id_attribute: String,
+}
+
+impl DynamoDbKeys {
+    /// Look keys up in `table`, partition key `api_key`.
+    pub fn new(client: aws_sdk_dynamodb::Client, table: impl Into<String>) -> Self {
+        Self {
+            client,
+            table: table.into(),
+            key_attribute: "api_key".to_string(),
+            id_attribute: "id".to_string(),
+        }
+    }
+
+    /// The partition key attribute name.
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        self.key_attribute = name.into();
+        self
+    }
+
+    /// The attribute holding the identity's id.
+    pub fn id_attribute(mut self, name: impl Into<String>) -> Self {
+        self.id_attribute = name.into();
+        self
+    }
+}
+
+impl ApiKeyValidator for DynamoDbKeys {
+    fn validate(&self, key: &str) -> BoxFuture<Result<Option<ApiKeyIdentity>, Error>> {
+        let this = self.clone();
+        let key = key.to_string();
+        Box::pin(async move {
+            let output = this
+                .client
+                .get_item()
+                .table_name(&this.table)
+                .key(&this.key_attribute, AttributeValue::S(key))
+                .consistent_read(true)
+                .send()
+                .await?;
+            let Some(item) = output.item else {
+                return Ok(None);
+            };
+            if matches!(item.get("enabled"), Some(AttributeValue::Bool(false))) {
+                return Ok(None);
+            }
+            let Some(AttributeValue::S(id)) = item.get(&this.id_attribute) else {
+                return Ok(None);
+            };
+            let mut identity = ApiKeyIdentity::new(id.clone());
+            for (name, value) in &item {
+                if *name == this.key_attribute || *name == this.id_attribute {
+                    continue;
+                }
+                let value = match value {
+                    AttributeValue::S(s) => Value::String(s.clone()),
+                    AttributeValue::N(n) => serde_json::from_str(n).unwrap_or(Value::Null),
+                    AttributeValue::Bool(b) => Value::Bool(*b),
+                    _ => continue,
+                };
+                identity.attributes.insert(name.clone(), value);
+            }
+            Ok(Some(identity))
+        })
+    }
+}
@@ -0,0 +1,176 @@
+//! Per-route scope and role requirements.
+
+use crate::cognito::parse_groups;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, Route};
+use serde_json::{Map, Value};
+
+/// The claim maps an authorization decision can draw on: verified JWT
+/// claims first, then the API Gateway authorizer's claims, then a custom
+/// authorizer's context.
+fn claim_sources(req: &Request) -> Vec<&Map<String, Value>> {
+    let mut sources = Vec::new();
+    #[cfg(feature = "jwt")]
+    if let Some(claims) = req.jwt_claims() {
+        sources.push(&claims.0);
+    }
+    if let Some(claims) = req.request_context.claims() {
+        sources.push(claims);
+    }
+    sources
+}
+
+/// The first of `names` present in any claim source, split into a list.
+fn list_claim(req: &Request, names: &[&str]) -> Vec<String> {
+    let authorizer = &req.request_context.authorizer;
+    for source in claim_sources(req) {
+        if let Some(value) = names.iter().find_map(|n| source.get(*n)) {
+            return parse_groups(value);
+        }
+    }
+    names
+        .iter()
+        .find_map(|n| authorizer.get(*n))
+        .map(parse_groups)
+        .unwrap_or_default()
+}
+
+impl Request {
+    /// The OAuth scopes granted to the caller, from the `scope` (or `scp`)
+    /// claim of a verified JWT or the API Gateway authorizer.
+    pub fn scopes(&self) -> Vec<String> {
+        list_claim(self, &["scope", "scp"])
+    }
+
+    /// The caller's roles, from `cognito:groups` or a `roles` claim.
+    pub fn roles(&self) -> Vec<String> {
+        list_claim(self, &["cognito:groups", "roles"])
+    }
+}
+
+enum Requirement {
+    /// Every listed scope must be granted.
+    Scopes(Vec<String>),
+    /// At least one listed role must be held.
+    AnyRole(Vec<String>),
+}
+
+/// Middleware that answers 403 unless the caller holds the required scopes
+/// or roles.
+///
+/// Usually attached through [`Route::require_scope`] and friends; use it
+/// directly to guard every route with [`Choko::middleware`](crate::Choko::middleware).
+/// It only reads claims, so it must run after the authentication that
+/// produces them (for example [`JwtAuth`](crate::auth::JwtAuth)).
+pub struct Guard {
+    requirement: Requirement,
+}
+
+impl Guard {
+    /// Require every scope in `scopes`.
+    pub fn scopes(scopes: &[&str]) -> Self {
+        Self {
+            requirement: Requirement::Scopes(scopes.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    /// Require at least one role in `roles`.
+    pub fn any_role(roles: &[&str]) -> Self {
+        Self {
+            requirement: Requirement::AnyRole(roles.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    /// Whether `req` satisfies the requirement.
+    pub fn permits(&self, req: &Request) -> bool {
+        match &self.requirement {
+            Requirement::Scopes(required) => {
+                let granted = req.scopes();
+                required.iter().all(|s| granted.contains(s))
+            }
+            Requirement::AnyRole(allowed) => req.roles().iter().any(|r| allowed.contains(r)),
+        }
+    }
+}
+
+impl Middleware for Guard {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if self.permits(&req) {
+            return next.run(req);
+        }
+        let mut resp = crate::error_json(403, "Forbidden");
+        if let Requirement::Scopes(scopes) = &self.requirement {
+            resp = resp.with_header(
+                "WWW-Authenticate",
+                format!(
+                    "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                    scopes.join(" ")
+                ),
+            );
+        }
+        Box::pin(async move { Ok(resp) })
+    }
+}
+
+impl Route {
+    /// Reject callers whose token lacks `scope` with 403. Repeat to require
+    /// several scopes.
+    pub fn require_scope(&mut self, scope: &str) -> &mut Self {
+        self.middleware(Guard::scopes(&[scope]))
+    }
+
+    /// Reject callers who don't hold `role` with 403. Repeat to require
+    /// several roles.
+    pub fn require_role(&mut self, role: &str) -> &mut Self {
+        self.middleware(Guard::any_role(&[role]))
+    }
+
+    /// Reject callers holding none of `roles` with 403.
+    pub fn require_any_role(&mut self, roles: &[&str]) -> &mut Self {
+        self.middleware(Guard::any_role(roles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request_with_claims(claims: Value) -> Request {
+        let mut req = Request::default();
+        req.request_context
+            .authorizer
+            .insert("claims".to_string(), claims);
+        req
+    }
+
+    #[test]
+    fn scopes_from_space_separated_claim() {
+        let req = request_with_claims(json!({ "scope": "orders:read orders:write" }));
+        assert_eq!(req.scopes(), vec!["orders:read", "orders:write"]);
+        assert!(Guard::scopes(&["orders:write"]).permits(&req));
+        assert!(!Guard::scopes(&["orders:write", "admin"]).permits(&req));
+    }
+
+    #[test]
+    fn roles_from_cognito_groups() {
+        let req = request_with_claims(json!({ "cognito:groups": "[admin ops]" }));
+        assert!(Guard::any_role(&["viewer", "ops"]).permits(&req));
+        assert!(!Guard::any_role(&["viewer"]).permits(&req));
+    }
+
+    #[test]
+    fn custom_authorizer_context() {
+        let mut req = Request::default();
+        req.request_context
+            .authorizer
+            .insert("roles".to_string(), json!("admin,billing"));
+        assert_eq!(req.roles(), vec!["admin", "billing"]);
+    }
+
+    #[test]
+    fn anonymous_requests_are_denied() {
+        assert!(!Guard::scopes(&["a"]).permits(&Request::default()));
+        assert!(!Guard::any_role(&["a"]).permits(&Request::default()));
+    }
+}
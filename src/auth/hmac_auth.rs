@@ -0,0 +1,243 @@
+//! HMAC-SHA256 signed requests for machine-to-machine calls (`hmac-auth`
+//! feature).
+
+use super::unauthorized;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+type ReplayFn = dyn Fn(String, u64) -> BoxFuture<Result<bool, Error>> + Send + Sync;
+
+/// Middleware verifying an HMAC-SHA256 signature over the request.
+///
+/// Clients send two headers:
+///
+/// - `X-Timestamp`: the Unix time in seconds when the request was signed
+/// - `X-Signature`: hex HMAC-SHA256 (optionally prefixed `sha256=`) of
+///   `"{timestamp}\n{METHOD}\n{path}\n"` followed by the raw body
+///
+/// The signature is compared in constant time and the timestamp must be
+/// within the tolerance (5 minutes by default). Failures get 401.
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::HmacAuth;
+///
+/// app.middleware(
+///     HmacAuth::new(secret).replay_guard(|signature, timestamp| async move {
+///         // e.g. a conditional put into a table with a TTL
+///         nonces::insert_if_absent(&signature, timestamp).await
+///     }),
+/// );
+/// ```
+pub struct HmacAuth {
+    secret: Vec<u8>,
+    tolerance: Duration,
+    signature_header: String,
+    timestamp_header: String,
+    replay_guard: Option<Arc<ReplayFn>>,
+}
+
+impl HmacAuth {
+    /// Verify signatures made with `secret`.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().to_vec(),
+            tolerance: Duration::from_secs(300),
+            signature_header: "x-signature".to_string(),
+            timestamp_header: "x-timestamp".to_string(),
+            replay_guard: None,
+        }
+    }
+
+    /// Maximum allowed difference between the signed timestamp and now.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Read the signature and timestamp from different headers.
+    pub fn headers(mut self, signature: &str, timestamp: &str) -> Self {
+        self.signature_header = signature.to_ascii_lowercase();
+        self.timestamp_header = timestamp.to_ascii_lowercase();
+        self
+    }
+
+    /// Reject replays: after a signature verifies, `guard` is called with
+    /// the signature (as lowercase hex, without any `sha256=` prefix) and
+    /// timestamp and must return `true` only the first time it sees them. Entries only need to be kept for the tolerance
+    /// window.
+    pub fn replay_guard<F, Fut>(mut self, guard: F) -> Self
+    where
+        F: Fn(String, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<bool, Error>> + Send + 'static,
+    {
+        self.replay_guard = Some(Arc::new(move |sig, ts| Box::pin(guard(sig, ts))));
+        self
+    }
+
+    /// The signature a client must send for the given request parts.
+    pub fn sign(&self, timestamp: u64, method: &str, path: &str, body: &[u8]) -> String {
+        let mac = self.mac(timestamp, method, path, body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn mac(&self, timestamp: u64, method: &str, path: &str, body: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(format!("{timestamp}\n{}\n{path}\n", method.to_ascii_uppercase()).as_bytes());
+        mac.update(body);
+        mac
+    }
+
+    /// Check the signature and timestamp, returning the signature in
+    /// canonical lowercase hex and the timestamp.
+    fn verify(&self, req: &Request) -> Result<(String, u64), &'static str> {
+        let timestamp: u64 = req
+            .header(&self.timestamp_header)
+            .and_then(|t| t.trim().parse().ok())
+            .ok_or("missing or malformed timestamp")?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+            return Err("timestamp outside tolerance");
+        }
+        let signature = req
+            .header(&self.signature_header)
+            .map(|s| s.trim().trim_start_matches("sha256="))
+            .ok_or("missing signature")?;
+        let expected = hex::decode(signature).map_err(|_| "invalid signature")?;
+        let body = req.body_bytes().unwrap_or_default();
+        self.mac(timestamp, req.method(), req.path(), body)
+            .verify_slice(&expected)
+            .map_err(|_| "invalid signature")?;
+        Ok((hex::encode(expected), timestamp))
+    }
+}
+
+impl Middleware for HmacAuth {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let (signature, timestamp) = match self.verify(&req) {
+            Ok(verified) => verified,
+            Err(reason) => {
+                let resp = unauthorized("HMAC-SHA256", reason);
+                return Box::pin(async move { Ok(resp) });
+            }
+        };
+        let Some(guard) = self.replay_guard.clone() else {
+            return next.run(req);
+        };
+        Box::pin(async move {
+            if !guard(signature, timestamp).await? {
+                return Ok(unauthorized("HMAC-SHA256", "request already processed"));
+            }
+            next.run(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn request(body: &str, signature: &str, timestamp: u64) -> Request {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::POST;
+        event.path = Some("/orders".to_string());
+        Request {
+            headers: HashMap::from([
+                ("x-signature".to_string(), signature.to_string()),
+                ("x-timestamp".to_string(), timestamp.to_string()),
+            ]),
+            body: Some(body.to_string()),
+            raw_event: Some(event),
+            ..Default::default()
+        }
+    }
+
+    async fn call(auth: HmacAuth, req: Request) -> Response {
+        run_middleware(auth, |_req| async { Ok(Response::text("ok")) }, req)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn accepts_valid_signature() {
+        let auth = HmacAuth::new("s3cret");
+        let ts = now();
+        let sig = auth.sign(ts, "POST", "/orders", br#"{"id":1}"#);
+        let resp = call(auth, request(r#"{"id":1}"#, &sig, ts)).await;
+        assert_eq!(resp.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_body_and_stale_timestamp() {
+        let signer = HmacAuth::new("s3cret");
+        let ts = now();
+        let sig = signer.sign(ts, "POST", "/orders", br#"{"id":1}"#);
+        let resp = call(HmacAuth::new("s3cret"), request(r#"{"id":2}"#, &sig, ts)).await;
+        assert_eq!(resp.status_code, 401);
+
+        let old = ts - 3600;
+        let sig = signer.sign(old, "POST", "/orders", b"");
+        let resp = call(HmacAuth::new("s3cret"), request("", &sig, old)).await;
+        assert_eq!(resp.status_code, 401);
+    }
+
+    #[tokio::test]
+    async fn replay_guard_rejects_repeats() {
+        let seen = Arc::new(Mutex::new(HashSet::new()));
+        let make = || {
+            let seen = Arc::clone(&seen);
+            HmacAuth::new("s3cret").replay_guard(move |sig, _ts| {
+                let fresh = seen.lock().unwrap().insert(sig);
+                async move { Ok(fresh) }
+            })
+        };
+        let ts = now();
+        let sig = make().sign(ts, "POST", "/orders", b"x");
+        assert_eq!(call(make(), request("x", &sig, ts)).await.status_code, 200);
+        assert_eq!(call(make(), request("x", &sig, ts)).await.status_code, 401);
+    }
+
+    #[tokio::test]
+    async fn replay_guard_sees_canonical_signature() {
+        let seen = Arc::new(Mutex::new(HashSet::new()));
+        let make = || {
+            let seen = Arc::clone(&seen);
+            HmacAuth::new("s3cret").replay_guard(move |sig, _ts| {
+                let fresh = seen.lock().unwrap().insert(sig);
+                async move { Ok(fresh) }
+            })
+        };
+        let ts = now();
+        let sig = make().sign(ts, "POST", "/orders", b"x");
+        assert_eq!(call(make(), request("x", &sig, ts)).await.status_code, 200);
+        for variant in [
+            format!("sha256={sig}"),
+            sig.to_ascii_uppercase(),
+            format!("  sha256={}  ", sig.to_ascii_uppercase()),
+        ] {
+            let resp = call(make(), request("x", &variant, ts)).await;
+            assert_eq!(resp.status_code, 401, "{variant}");
+        }
+    }
+}
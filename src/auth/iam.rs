@@ -0,0 +1,88 @@
+//! Authorizing `AWS_IAM` callers by ARN.
+
+use super::glob_match;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+
+/// Match `arn` against `pattern`, where `*` matches any run of characters
+/// and `?` a single character.
+///
+/// Assumed-role ARNs (`arn:aws:sts::<account>:assumed-role/<role>/<session>`)
+/// are matched as-is, so allow `.../assumed-role/<role>/*` to admit every
+/// session of a role.
+pub fn arn_matches(pattern: &str, arn: &str) -> bool {
+    glob_match(pattern, arn)
+}
+
+impl Request {
+    /// Whether the IAM caller's ARN matches any of `patterns` (see
+    /// [`arn_matches`]). `false` for requests without an IAM identity.
+    pub fn iam_caller_matches(&self, patterns: &[&str]) -> bool {
+        self.iam_identity()
+            .is_some_and(|iam| patterns.iter().any(|p| arn_matches(p, &iam.user_arn)))
+    }
+}
+
+/// Middleware admitting only IAM callers whose ARN matches one of the
+/// configured patterns; everyone else gets 403.
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::RequireIamCaller;
+///
+/// app.post("/internal/jobs", enqueue).middleware(RequireIamCaller::new([
+///     "arn:aws:sts::123456789012:assumed-role/scheduler/*",
+/// ]));
+/// ```
+pub struct RequireIamCaller {
+    patterns: Vec<String>,
+}
+
+impl RequireIamCaller {
+    /// Allow callers matching any of `patterns`.
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Middleware for RequireIamCaller {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let patterns: Vec<&str> = self.patterns.iter().map(String::as_str).collect();
+        if req.iam_caller_matches(&patterns) {
+            return next.run(req);
+        }
+        Box::pin(async move { Ok(crate::error_json(403, "Forbidden")) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let role = "arn:aws:sts::123456789012:assumed-role/scheduler/run-42";
+        assert!(arn_matches(
+            "arn:aws:sts::123456789012:assumed-role/scheduler/*",
+            role
+        ));
+        assert!(arn_matches("arn:aws:sts::*:assumed-role/sched?ler/*", role));
+        assert!(!arn_matches(
+            "arn:aws:sts::123456789012:assumed-role/billing/*",
+            role
+        ));
+        assert!(arn_matches("*", role));
+        assert!(!arn_matches("arn:aws:iam::123456789012:user/alice", role));
+    }
+
+    #[test]
+    fn request_without_iam_identity_never_matches() {
+        assert!(!Request::default().iam_caller_matches(&["*"]));
+    }
+}
@@ -0,0 +1,323 @@
+//! Bearer JWT validation against static keys or a JWKS endpoint
+//! (`jwt` feature).
+
+use super::unauthorized;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// How long a fetched JWKS is trusted before it is refetched.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
+/// Minimum interval between refetches triggered by an unknown `kid`, so
+/// tokens with bogus key ids can't hammer the issuer.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// Bounds on calls to the identity provider, so a hanging issuer fails the
+/// request instead of stalling it until the Lambda deadline.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An HTTP client for the identity provider's endpoints.
+pub(super) fn idp_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("the identity provider client configuration is valid")
+}
+
+/// The verified claims of a request's bearer token.
+///
+/// Stored in the request extensions by [`JwtAuth`]; read them with
+/// [`Request::jwt_claims`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct JwtClaims(pub Map<String, Value>);
+
+impl JwtClaims {
+    /// A single claim.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// The `sub` claim.
+    pub fn subject(&self) -> Option<&str> {
+        self.get("sub").and_then(Value::as_str)
+    }
+
+    /// Deserialize the claims into a typed struct.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_value(Value::Object(self.0.clone()))
+    }
+}
+
+impl Request {
+    /// The claims verified by [`JwtAuth`], if it ran for this request.
+    pub fn jwt_claims(&self) -> Option<&JwtClaims> {
+        self.extensions().get::<JwtClaims>()
+    }
+}
+
+enum Keys {
+    Static(DecodingKey),
+    Set(JwkSet),
+    Remote {
+        url: String,
+        client: reqwest::Client,
+        cache: RwLock<Option<(Instant, Arc<JwkSet>)>>,
+    },
+}
+
+struct Inner {
+    keys: Keys,
+    validation: Validation,
+}
+
+/// Middleware that requires a valid `Authorization: Bearer` JWT.
+///
+/// The signature is checked against a static key or a JWKS (fetched from a
+/// URL and cached, refetching when a token names an unknown `kid` so key
+/// rotation is picked up). `exp` is always required; `iss` and `aud` are
+/// checked when configured. On success the claims are inserted into the
+/// request extensions as [`JwtClaims`]; otherwise the request is rejected
+/// with 401.
+pub struct JwtAuth {
+    inner: Arc<Inner>,
+}
+
+impl JwtAuth {
+    /// Validate tokens against the JWKS served at `url` (RS256 by default).
+    /// Fetching the key set times out after 5 seconds, and the request then
+    /// fails with 401.
+    pub fn jwks(url: impl Into<String>) -> Self {
+        Self::from_keys(
+            Keys::Remote {
+                url: url.into(),
+                client: idp_client(),
+                cache: RwLock::new(None),
+            },
+            Algorithm::RS256,
+        )
+    }
+
+    /// Validate tokens against a fixed key set (RS256 by default).
+    pub fn jwk_set(set: JwkSet) -> Self {
+        Self::from_keys(Keys::Set(set), Algorithm::RS256)
+    }
+
+    /// Validate tokens against a single key using `algorithm`.
+    pub fn key(key: DecodingKey, algorithm: Algorithm) -> Self {
+        Self::from_keys(Keys::Static(key), algorithm)
+    }
+
+    /// Validate HS256 tokens signed with a shared secret.
+    pub fn secret(secret: &[u8]) -> Self {
+        Self::key(DecodingKey::from_secret(secret), Algorithm::HS256)
+    }
+
+    fn from_keys(keys: Keys, algorithm: Algorithm) -> Self {
+        let mut validation = Validation::new(algorithm);
+        // Only check `aud` once an audience is configured
+        validation.validate_aud = false;
+        Self {
+            inner: Arc::new(Inner { keys, validation }),
+        }
+    }
+
+    fn validation_mut(&mut self) -> &mut Validation {
+        &mut Arc::get_mut(&mut self.inner)
+            .expect("JwtAuth is configured before use")
+            .validation
+    }
+
+    /// Require the `iss` claim to equal `issuer`.
+    pub fn issuer(mut self, issuer: &str) -> Self {
+        self.validation_mut().set_issuer(&[issuer]);
+        self
+    }
+
+    /// Require the `aud` claim to contain `audience`.
+    pub fn audience(mut self, audience: &str) -> Self {
+        let validation = self.validation_mut();
+        validation.set_audience(&[audience]);
+        validation.validate_aud = true;
+        self
+    }
+
+    /// Accept tokens signed with any of `algorithms`.
+    pub fn algorithms(mut self, algorithms: &[Algorithm]) -> Self {
+        self.validation_mut().algorithms = algorithms.to_vec();
+        self
+    }
+
+    /// Allowed clock skew for `exp` and `nbf`. Defaults to 60 seconds.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.validation_mut().leeway = leeway.as_secs();
+        self
+    }
+
+    /// Verify `token` directly, outside the middleware.
+    pub async fn verify(&self, token: &str) -> Result<JwtClaims, Error> {
+        self.inner.verify(token).await
+    }
+}
+
+impl Inner {
+    async fn verify(&self, token: &str) -> Result<JwtClaims, Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key = match &self.keys {
+            Keys::Static(key) => key.clone(),
+            Keys::Set(set) => key_from_set(set, header.kid.as_deref())?,
+            Keys::Remote { .. } => {
+                let set = self.remote_keys(header.kid.as_deref()).await?;
+                key_from_set(&set, header.kid.as_deref())?
+            }
+        };
+        let data = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &self.validation)?;
+        Ok(JwtClaims(data.claims))
+    }
+
+    /// The cached JWKS, refetched when stale or when it lacks `kid`.
+    async fn remote_keys(&self, kid: Option<&str>) -> Result<Arc<JwkSet>, Error> {
+        let Keys::Remote { url, client, cache } = &self.keys else {
+            unreachable!("remote_keys is only called for JWKS URLs");
+        };
+        let cached = cache.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some((fetched_at, set)) = &cached {
+            let fresh = fetched_at.elapsed() < JWKS_TTL;
+            let has_kid = kid.is_none_or(|kid| set.find(kid).is_some());
+            if fresh && (has_kid || fetched_at.elapsed() < JWKS_MIN_REFRESH) {
+                return Ok(Arc::clone(set));
+            }
+        }
+        let set: JwkSet = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let set = Arc::new(set);
+        *cache.write().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), Arc::clone(&set)));
+        Ok(set)
+    }
+}
+
+fn key_from_set(set: &JwkSet, kid: Option<&str>) -> Result<DecodingKey, Error> {
+    let jwk = match kid {
+        Some(kid) => set.find(kid),
+        None if set.keys.len() == 1 => set.keys.first(),
+        None => None,
+    }
+    .ok_or("no matching key in JWKS")?;
+    Ok(DecodingKey::from_jwk(jwk)?)
+}
+
+impl Middleware for JwtAuth {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let inner = Arc::clone(&self.inner);
+        Box::pin(async move {
+            let Some(token) = req.bearer_token() else {
+                return Ok(unauthorized("Bearer", "Unauthorized"));
+            };
+            match inner.verify(&token).await {
+                Ok(claims) => {
+                    req.extensions_mut().insert(claims);
+                    next.run(req).await
+                }
+                Err(e) => {
+                    eprintln!("JWT rejected: {e}");
+                    Ok(unauthorized(
+                        "Bearer error=\"invalid_token\"",
+                        "Unauthorized",
+                    ))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn token(claims: Value) -> String {
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    fn exp(offset: i64) -> i64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        now + offset
+    }
+
+    async fn call(auth: JwtAuth, authorization: Option<String>) -> Response {
+        let mut headers = HashMap::new();
+        if let Some(value) = authorization {
+            headers.insert("authorization".to_string(), value);
+        }
+        let req = Request {
+            headers,
+            ..Default::default()
+        };
+        let handler = |req: Request| async move {
+            let sub = req.jwt_claims().and_then(|c| c.subject()).unwrap_or("");
+            Ok(Response::text(sub.to_string()))
+        };
+        run_middleware(auth, handler, req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_token_exposes_claims() {
+        let auth = JwtAuth::secret(SECRET).issuer("me").audience("api");
+        let jwt = token(json!({"sub": "user-1", "iss": "me", "aud": "api", "exp": exp(60)}));
+        let resp = call(auth, Some(format!("Bearer {jwt}"))).await;
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.body, crate::ResponseBody::Text("user-1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_expired_and_wrong_audience() {
+        let resp = call(JwtAuth::secret(SECRET), None).await;
+        assert_eq!(resp.status_code, 401);
+        assert_eq!(resp.header("www-authenticate"), Some("Bearer"));
+
+        let expired = token(json!({"sub": "u", "exp": exp(-3600)}));
+        let resp = call(JwtAuth::secret(SECRET), Some(format!("Bearer {expired}"))).await;
+        assert_eq!(resp.status_code, 401);
+
+        let wrong_aud = token(json!({"sub": "u", "aud": "other", "exp": exp(60)}));
+        let resp = call(
+            JwtAuth::secret(SECRET).audience("api"),
+            Some(format!("Bearer {wrong_aud}")),
+        )
+        .await;
+        assert_eq!(resp.status_code, 401);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_signature() {
+        let jwt = token(json!({"sub": "u", "exp": exp(60)}));
+        let resp = call(JwtAuth::secret(b"other"), Some(format!("Bearer {jwt}"))).await;
+        assert_eq!(resp.status_code, 401);
+    }
+}
@@ -0,0 +1,100 @@
+//! Authentication middleware.
+//!
+//! Each authenticator is middleware: attach it app-wide or to individual
+//! routes. Requests without valid credentials are rejected with 401 before
+//! the handler runs; on success the authenticated identity is stored in the
+//! request's extensions for handlers to read.
+//!
+//! # Example
+//! ```ignore
+//! use choko::auth::JwtAuth;
+//!
+//! app.middleware(
+//!     JwtAuth::jwks("https://issuer.example.com/.well-known/jwks.json")
+//!         .issuer("https://issuer.example.com/")
+//!         .audience("my-api"),
+//! );
+//! ```
+
+mod api_key;
+mod basic;
+#[cfg(feature = "dynamodb")]
+mod dynamodb_keys;
+mod guard;
+#[cfg(feature = "hmac-auth")]
+mod hmac_auth;
+mod iam;
+#[cfg(feature = "jwt")]
+mod jwt;
+mod mtls;
+#[cfg(feature = "oidc")]
+mod oidc;
+
+pub use api_key::{
+    validate_fn, ApiKeyAuth, ApiKeyIdentity, ApiKeyValidator, StaticKeys, ValidateFn,
+};
+pub use basic::{BasicAuth, BasicAuthUser};
+#[cfg(feature = "dynamodb")]
+pub use dynamodb_keys::DynamoDbKeys;
+pub use guard::Guard;
+#[cfg(feature = "hmac-auth")]
+pub use hmac_auth::HmacAuth;
+pub use iam::{arn_matches, RequireIamCaller};
+#[cfg(feature = "jwt")]
+pub use jwt::{JwtAuth, JwtClaims};
+pub use mtls::{CertificateValidity, ClientCertificate, RequireClientCert};
+#[cfg(feature = "oidc")]
+pub use oidc::{Login, OAuthClient, Provider, TokenResponse};
+
+use crate::Response;
+
+/// A 401 response carrying a `WWW-Authenticate` challenge.
+pub(crate) fn unauthorized(challenge: &str, message: &str) -> Response {
+    crate::error_json(401, message).with_header("WWW-Authenticate", challenge)
+}
+
+/// Compare two byte strings without short-circuiting on the first
+/// difference.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+/// and `?` a single character.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    // Iterative wildcard match with single-star backtracking
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_time_eq_compares_bytes() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+}
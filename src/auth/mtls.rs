@@ -0,0 +1,206 @@
+//! Client certificates from API Gateway mutual TLS.
+
+use super::glob_match;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// The validity period of a client certificate, as API Gateway formats it
+/// (e.g. `May 28 12:30:02 2019 GMT`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateValidity {
+    pub not_before: String,
+    pub not_after: String,
+}
+
+/// The client certificate presented over mutual TLS
+/// (`requestContext.identity.clientCert`).
+///
+/// API Gateway has already verified it against the domain's truststore,
+/// including its validity period.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientCertificate {
+    /// The certificate in PEM form.
+    #[serde(rename = "clientCertPem")]
+    pub pem: String,
+    /// The subject distinguished name, e.g. `CN=billing,O=Acme`.
+    #[serde(rename = "subjectDN")]
+    pub subject_dn: String,
+    /// The issuer distinguished name.
+    #[serde(rename = "issuerDN")]
+    pub issuer_dn: String,
+    pub serial_number: String,
+    pub validity: CertificateValidity,
+}
+
+impl ClientCertificate {
+    /// Read the certificate from the `clientCert` member of a request
+    /// identity.
+    pub(crate) fn from_identity(identity: &Map<String, Value>) -> Option<Self> {
+        serde_json::from_value(identity.get("clientCert")?.clone()).ok()
+    }
+
+    /// The value of attribute `name` (e.g. `CN`, `O`) in the subject DN,
+    /// as written there (escapes such as `\,` are kept).
+    pub fn subject_attribute(&self, name: &str) -> Option<&str> {
+        rdns(&self.subject_dn).into_iter().find_map(|rdn| {
+            let (key, value) = rdn.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then_some(value.trim())
+        })
+    }
+
+    /// The subject common name.
+    pub fn common_name(&self) -> Option<&str> {
+        self.subject_attribute("CN")
+    }
+}
+
+impl Request {
+    /// The client certificate, when the custom domain requires mutual TLS.
+    pub fn client_certificate(&self) -> Option<&ClientCertificate> {
+        self.request_context.client_certificate.as_ref()
+    }
+}
+
+/// Middleware admitting only clients whose certificate subject DN matches
+/// one of the configured patterns; other requests get 403.
+///
+/// Patterns are matched RDN by RDN: the subject must have the same RDNs in
+/// the same order, and the `*` and `?` wildcards never match across an RDN
+/// separator.
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::RequireClientCert;
+///
+/// app.middleware(RequireClientCert::new(["CN=*.partners.example.com,O=Acme"]));
+/// ```
+pub struct RequireClientCert {
+    subjects: Vec<String>,
+}
+
+impl RequireClientCert {
+    /// Allow subject DNs matching any of `subjects`.
+    pub fn new<I, S>(subjects: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            subjects: subjects.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Whether `req` presented an allowed certificate.
+    pub fn permits(&self, req: &Request) -> bool {
+        req.client_certificate().is_some_and(|cert| {
+            let subject = rdns(&cert.subject_dn);
+            self.subjects.iter().any(|p| dn_matches(p, &subject))
+        })
+    }
+}
+
+/// The RDNs of `dn`, split on commas that aren't escaped (`\,`) or quoted,
+/// without surrounding whitespace.
+fn rdns(dn: &str) -> Vec<&str> {
+    let mut rdns = Vec::new();
+    let (mut start, mut escaped, mut quoted) = (0, false, false);
+    for (i, c) in dn.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                rdns.push(dn[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    rdns.push(dn[start..].trim());
+    rdns
+}
+
+/// Whether `pattern` matches the `subject` RDNs one for one.
+fn dn_matches(pattern: &str, subject: &[&str]) -> bool {
+    let pattern = rdns(pattern);
+    pattern.len() == subject.len() && pattern.iter().zip(subject).all(|(p, s)| glob_match(p, s))
+}
+
+impl Middleware for RequireClientCert {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if self.permits(&req) {
+            return next.run(req);
+        }
+        Box::pin(async move { Ok(crate::error_json(403, "Forbidden")) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn certificate() -> ClientCertificate {
+        let identity = json!({
+            "clientCert": {
+                "clientCertPem": "-----BEGIN CERTIFICATE-----\n...",
+                "subjectDN": "CN=billing.partners.example.com, O=Acme",
+                "issuerDN": "CN=Acme Internal CA",
+                "serialNumber": "a1:b2:c3",
+                "validity": {
+                    "notBefore": "May 28 12:30:02 2019 GMT",
+                    "notAfter": "Aug  5 09:36:04 2031 GMT"
+                }
+            }
+        });
+        ClientCertificate::from_identity(identity.as_object().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_client_cert() {
+        let cert = certificate();
+        assert_eq!(cert.serial_number, "a1:b2:c3");
+        assert_eq!(cert.common_name(), Some("billing.partners.example.com"));
+        assert_eq!(cert.subject_attribute("o"), Some("Acme"));
+        assert_eq!(cert.validity.not_after, "Aug  5 09:36:04 2031 GMT");
+    }
+
+    #[test]
+    fn guard_matches_subject_patterns() {
+        let mut req = Request::default();
+        assert!(!RequireClientCert::new(["*"]).permits(&req));
+
+        req.request_context.client_certificate = Some(certificate());
+        assert!(RequireClientCert::new(["CN=*.partners.example.com,O=Acme"]).permits(&req));
+        assert!(!RequireClientCert::new(["CN=*.partners.example.com,O=Other"]).permits(&req));
+    }
+
+    #[test]
+    fn wildcards_stay_within_one_rdn() {
+        let mut req = Request::default();
+        let mut cert = certificate();
+        cert.subject_dn = "CN=x,O=Other,OU=a.partners.example.com,O=Acme".to_string();
+        req.request_context.client_certificate = Some(cert);
+        assert!(!RequireClientCert::new(["CN=*.partners.example.com,O=Acme"]).permits(&req));
+        assert!(RequireClientCert::new(["CN=*,O=*,OU=*,O=Acme"]).permits(&req));
+    }
+
+    #[test]
+    fn escaped_commas_do_not_split_rdns() {
+        let mut cert = certificate();
+        cert.subject_dn = r"CN=billing, O=Acme\, Inc., C=US".to_string();
+        assert_eq!(cert.subject_attribute("O"), Some(r"Acme\, Inc."));
+        assert_eq!(cert.subject_attribute("C"), Some("US"));
+
+        let mut req = Request::default();
+        req.request_context.client_certificate = Some(cert);
+        assert!(RequireClientCert::new([r"CN=billing,O=Acme\, *,C=US"]).permits(&req));
+        assert!(!RequireClientCert::new(["CN=billing,O=Acme,C=US"]).permits(&req));
+    }
+}
@@ -0,0 +1,417 @@
+//! OAuth 2.0 authorization-code login with PKCE and OpenID Connect ID token
+//! validation (`oidc` feature).
+
+use super::jwt::idp_client;
+use super::{constant_time_eq, JwtAuth, JwtClaims};
+use crate::{ChokoError, Error, Request, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Session key holding the state of a login in progress.
+const PENDING_KEY: &str = "oauth_pending";
+
+/// An OAuth 2.0 authorization server, optionally speaking OpenID Connect.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    authorize_url: String,
+    token_url: String,
+    issuer: Option<String>,
+    jwks_url: Option<String>,
+    scopes: Vec<String>,
+}
+
+impl Provider {
+    /// A plain OAuth 2.0 provider with no default scopes.
+    pub fn new(authorize_url: impl Into<String>, token_url: impl Into<String>) -> Self {
+        Self {
+            authorize_url: authorize_url.into(),
+            token_url: token_url.into(),
+            issuer: None,
+            jwks_url: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Treat the provider as OpenID Connect: request the `openid` scope and
+    /// validate the returned ID token against `issuer` and the JWKS at
+    /// `jwks_url`.
+    pub fn openid(mut self, issuer: impl Into<String>, jwks_url: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self.jwks_url = Some(jwks_url.into());
+        if !self.scopes.iter().any(|s| s == "openid") {
+            self.scopes.insert(0, "openid".to_string());
+        }
+        self
+    }
+
+    /// Replace the requested scopes.
+    pub fn scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// A Cognito user pool's hosted UI. `domain` is the hosted UI base URL,
+    /// e.g. `https://myapp.auth.eu-west-1.amazoncognito.com`.
+    pub fn cognito(domain: &str, region: &str, user_pool_id: &str) -> Self {
+        let domain = domain.trim_end_matches('/');
+        let issuer = format!("https://cognito-idp.{region}.amazonaws.com/{user_pool_id}");
+        let jwks_url = format!("{issuer}/.well-known/jwks.json");
+        Self::new(
+            format!("{domain}/oauth2/authorize"),
+            format!("{domain}/oauth2/token"),
+        )
+        .openid(issuer, jwks_url)
+        .scopes(&["openid", "email", "profile"])
+    }
+
+    /// Google accounts.
+    pub fn google() -> Self {
+        Self::new(
+            "https://accounts.google.com/o/oauth2/v2/auth",
+            "https://oauth2.googleapis.com/token",
+        )
+        .openid(
+            "https://accounts.google.com",
+            "https://www.googleapis.com/oauth2/v3/certs",
+        )
+        .scopes(&["openid", "email", "profile"])
+    }
+
+    /// GitHub. GitHub is OAuth 2.0 only, so logins carry an access token but
+    /// no ID token claims.
+    pub fn github() -> Self {
+        Self::new(
+            "https://github.com/login/oauth/authorize",
+            "https://github.com/login/oauth/access_token",
+        )
+        .scopes(&["read:user", "user:email"])
+    }
+}
+
+/// The token endpoint's response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub id_token: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// A completed login.
+#[derive(Debug, Clone)]
+pub struct Login {
+    pub tokens: TokenResponse,
+    /// The validated ID token claims, for OpenID Connect providers.
+    pub claims: Option<JwtClaims>,
+}
+
+/// What is remembered in the session between redirect and callback.
+#[derive(Debug, Serialize, Deserialize)]
+struct Pending {
+    state: String,
+    verifier: String,
+    nonce: String,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// Drives the authorization-code flow for one provider and client.
+///
+/// [`login`](Self::login) redirects to the provider, keeping a random
+/// `state`, PKCE verifier and `nonce` in the session, so the
+/// [`Sessions`](crate::session::Sessions) middleware must run on both
+/// routes. [`callback`](Self::callback) checks `state`, exchanges the code
+/// and validates the ID token.
+///
+/// # Example
+/// ```ignore
+/// use choko::auth::{OAuthClient, Provider};
+///
+/// let google = Arc::new(
+///     OAuthClient::new(Provider::google(), client_id, "https://app.example.com/callback")
+///         .client_secret(client_secret),
+/// );
+///
+/// let client = Arc::clone(&google);
+/// app.get("/login", move |req| {
+///     let client = Arc::clone(&client);
+///     async move { client.login(&req) }
+/// });
+/// app.get("/callback", move |req| {
+///     let client = Arc::clone(&google);
+///     async move {
+///         let login = client.callback(&req).await?;
+///         let session = req.session().unwrap();
+///         session.set("sub", &login.claims.unwrap().subject())?;
+///         Ok(Response::see_other("/"))
+///     }
+/// });
+/// ```
+pub struct OAuthClient {
+    provider: Provider,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    http: reqwest::Client,
+    id_tokens: Option<JwtAuth>,
+}
+
+impl OAuthClient {
+    /// A public client (no secret) redirecting back to `redirect_uri`.
+    pub fn new(
+        provider: Provider,
+        client_id: impl Into<String>,
+        redirect_uri: impl Into<String>,
+    ) -> Self {
+        let client_id = client_id.into();
+        let id_tokens = match (&provider.issuer, &provider.jwks_url) {
+            (Some(issuer), Some(jwks_url)) => {
+                Some(JwtAuth::jwks(jwks_url).issuer(issuer).audience(&client_id))
+            }
+            _ => None,
+        };
+        Self {
+            provider,
+            client_id,
+            client_secret: None,
+            redirect_uri: redirect_uri.into(),
+            http: idp_client(),
+            id_tokens,
+        }
+    }
+
+    /// Authenticate to the token endpoint with a client secret (HTTP Basic).
+    pub fn client_secret(mut self, secret: impl Into<String>) -> Self {
+        self.client_secret = Some(secret.into());
+        self
+    }
+
+    /// The provider's authorization URL for a new login, remembering its
+    /// state in `req`'s session.
+    pub fn authorization_url(&self, req: &Request) -> Result<String, Error> {
+        let session = req
+            .session()
+            .ok_or("OAuthClient requires the Sessions middleware")?;
+        let pending = Pending {
+            state: random_token(),
+            verifier: random_token(),
+            nonce: random_token(),
+        };
+        let scope = self.provider.scopes.join(" ");
+        let challenge = pkce_challenge(&pending.verifier);
+        let mut params = vec![
+            ("response_type", "code"),
+            ("client_id", self.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("state", pending.state.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ];
+        if !scope.is_empty() {
+            params.push(("scope", scope.as_str()));
+        }
+        if self.id_tokens.is_some() {
+            params.push(("nonce", pending.nonce.as_str()));
+        }
+        let query = serde_urlencoded::to_string(&params)?;
+        session.set(PENDING_KEY, &pending)?;
+        Ok(format!("{}?{query}", self.provider.authorize_url))
+    }
+
+    /// A 302 redirect to the provider's login page.
+    pub fn login(&self, req: &Request) -> Result<Response, Error> {
+        Ok(Response::redirect(self.authorization_url(req)?))
+    }
+
+    /// Complete the login on the redirect URI.
+    ///
+    /// Fails with 401 if the provider reported an error or `state` doesn't
+    /// match the session, and with 500 if the token exchange (which times
+    /// out after 5 seconds) or ID token validation fails.
+    pub async fn callback(&self, req: &Request) -> Result<Login, ChokoError> {
+        let param = |name: &str| req.query_params.get(name).and_then(|v| v.first());
+        if let Some(error) = param("error") {
+            return Err(ChokoError::unauthorized(format!("login failed: {error}")));
+        }
+        let session = req
+            .session()
+            .ok_or_else(|| ChokoError::internal("OAuthClient requires the Sessions middleware"))?;
+        let pending: Pending = session
+            .get(PENDING_KEY)
+            .ok_or_else(|| ChokoError::unauthorized("no login in progress"))?;
+        session.remove(PENDING_KEY);
+        let state_ok = param("state")
+            .is_some_and(|s| constant_time_eq(s.as_bytes(), pending.state.as_bytes()));
+        if !state_ok {
+            return Err(ChokoError::unauthorized("login state mismatch"));
+        }
+        let code = param("code").ok_or_else(|| ChokoError::bad_request("missing code"))?;
+
+        let tokens = self
+            .exchange(code, &pending.verifier)
+            .await
+            .map_err(ChokoError::internal)?;
+        let claims = match (&self.id_tokens, &tokens.id_token) {
+            (Some(validator), Some(id_token)) => {
+                let claims = validator
+                    .verify(id_token)
+                    .await
+                    .map_err(ChokoError::internal)?;
+                if claims.get("nonce").and_then(|n| n.as_str()) != Some(pending.nonce.as_str()) {
+                    return Err(ChokoError::internal("ID token nonce mismatch"));
+                }
+                Some(claims)
+            }
+            (Some(_), None) => return Err(ChokoError::internal("token response lacks id_token")),
+            (None, _) => None,
+        };
+        Ok(Login { tokens, claims })
+    }
+
+    async fn exchange(&self, code: &str, verifier: &str) -> Result<TokenResponse, Error> {
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", verifier),
+        ];
+        let mut request = self
+            .http
+            .post(&self.provider.token_url)
+            .header("Accept", "application/json")
+            .form(&form);
+        if let Some(secret) = &self.client_secret {
+            request = request.basic_auth(&self.client_id, Some(secret));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            return Err(match serde_json::from_slice::<TokenError>(&body) {
+                Ok(e) => format!(
+                    "token exchange failed: {} {}",
+                    e.error,
+                    e.error_description.unwrap_or_default()
+                )
+                .into(),
+                Err(_) => format!("token exchange failed with status {status}").into(),
+            });
+        }
+        // GitHub reports errors with a 200 status
+        if let Ok(e) = serde_json::from_slice::<TokenError>(&body) {
+            return Err(format!("token exchange failed: {}", e.error).into());
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+/// 256 bits of randomness, base64url-encoded.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).expect("OS random source is available");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 PKCE challenge for `verifier`.
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Session;
+    use std::collections::HashMap;
+
+    fn client() -> OAuthClient {
+        OAuthClient::new(Provider::google(), "client-1", "https://app.test/callback")
+    }
+
+    fn request_with_session(query: &[(&str, &str)], session: &Session) -> Request {
+        let mut req = Request {
+            query_params: query
+                .iter()
+                .map(|(k, v)| (k.to_string(), vec![v.to_string()]))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        };
+        req.extensions_mut().insert(session.clone());
+        req
+    }
+
+    #[test]
+    fn pkce_challenge_matches_rfc7636() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn authorization_url_remembers_state() {
+        let session = Session::default();
+        let url = client()
+            .authorization_url(&request_with_session(&[], &session))
+            .unwrap();
+        let pending: Pending = session.get(PENDING_KEY).unwrap();
+        assert!(url.starts_with("https://accounts.google.com/o/oauth2/v2/auth?"));
+        assert!(url.contains(&format!("state={}", pending.state)));
+        assert!(url.contains(&format!("nonce={}", pending.nonce)));
+        assert!(url.contains(&format!(
+            "code_challenge={}",
+            pkce_challenge(&pending.verifier)
+        )));
+        assert!(url.contains("scope=openid+email+profile"));
+    }
+
+    #[test]
+    fn github_has_no_id_token_or_nonce() {
+        let session = Session::default();
+        let client = OAuthClient::new(Provider::github(), "gh", "https://app.test/cb");
+        let url = client
+            .authorization_url(&request_with_session(&[], &session))
+            .unwrap();
+        assert!(!url.contains("nonce="));
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_state_mismatch_and_provider_errors() {
+        let session = Session::default();
+        client()
+            .authorization_url(&request_with_session(&[], &session))
+            .unwrap();
+        let err = client()
+            .callback(&request_with_session(
+                &[("code", "abc"), ("state", "forged")],
+                &session,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), 401);
+        // The pending login is consumed even on failure
+        assert!(session.get::<Pending>(PENDING_KEY).is_none());
+
+        let err = client()
+            .callback(&request_with_session(
+                &[("error", "access_denied")],
+                &session,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), 401);
+    }
+}
@@ -0,0 +1,611 @@
+//! Circuit breakers for downstream calls.
+//!
+//! When a dependency is failing or hanging, every request still waits on
+//! it, and Lambda timeouts pile up behind the slowest call. A
+//! [`CircuitBreaker`] tracks the failure rate of calls to one dependency
+//! and, once it crosses a threshold, *opens*: calls fail immediately with
+//! [`CircuitOpen`] instead of reaching the dependency. After a cool-down it
+//! lets a few trial calls through (*half-open*); if they succeed the circuit
+//! closes again, otherwise it reopens.
+//!
+//! Breakers are kept in app state, so their counts last as long as the warm
+//! container. Each container tracks its own failures. State changes and
+//! rejected calls are logged as JSON and, with
+//! [`CircuitBreaker::metrics`], published as EMF metrics.
+//!
+//! # Example
+//! ```ignore
+//! use choko::circuit_breaker::{CircuitBreaker, CircuitBreakers};
+//!
+//! app.state(CircuitBreakers::new().configure(|name| {
+//!     CircuitBreaker::new(name)
+//!         .failure_rate(0.5)
+//!         .open_for(Duration::from_secs(15))
+//!         .metrics("Orders")
+//! }));
+//!
+//! app.get("/quotes/{id}", |req| async move {
+//!     let breakers = req.state::<CircuitBreakers>().ok_or("breakers not configured")?;
+//!     let http = req.state::<HttpClient>().ok_or("http client not configured")?;
+//!     let quote = breakers
+//!         .get("pricing")
+//!         .call(|| async {
+//!             http.get("https://pricing.internal/quotes")
+//!                 .send()
+//!                 .await?
+//!                 .error_for_status()?
+//!                 .json::<Value>()
+//!                 .await
+//!         })
+//!         .await?;
+//!     Ok(Response::json(quote))
+//! });
+//! ```
+
+use crate::metrics::{self, Unit};
+use crate::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The state of a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through and their failures are counted.
+    Closed,
+    /// Calls are rejected until the cool-down ends.
+    Open,
+    /// A limited number of trial calls go through.
+    HalfOpen,
+}
+
+/// What happened to a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitEventKind {
+    Opened,
+    HalfOpened,
+    Closed,
+    Rejected,
+}
+
+impl CircuitEventKind {
+    fn message(self) -> &'static str {
+        match self {
+            CircuitEventKind::Opened => "circuit opened",
+            CircuitEventKind::HalfOpened => "circuit half-open",
+            CircuitEventKind::Closed => "circuit closed",
+            CircuitEventKind::Rejected => "call rejected by open circuit",
+        }
+    }
+
+    /// The EMF metric counting this event.
+    fn metric(self) -> &'static str {
+        match self {
+            CircuitEventKind::Opened => "CircuitOpened",
+            CircuitEventKind::HalfOpened => "CircuitHalfOpened",
+            CircuitEventKind::Closed => "CircuitClosed",
+            CircuitEventKind::Rejected => "CircuitRejected",
+        }
+    }
+}
+
+/// One circuit state change or rejected call.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CircuitEvent {
+    /// `WARN` when the circuit opens or rejects a call, else `INFO`.
+    pub level: &'static str,
+    pub message: &'static str,
+    pub event: CircuitEventKind,
+    /// The breaker's name.
+    pub dependency: String,
+    /// The circuit's state after the event.
+    pub state: CircuitState,
+    /// The failure rate that opened the circuit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure_rate: Option<f64>,
+}
+
+/// The error returned for calls rejected by an open circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitOpen {
+    pub dependency: String,
+    /// How long until trial calls are let through.
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "circuit for {} is open, retry in {}ms",
+            self.dependency,
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+type SinkFn = Arc<dyn Fn(&CircuitEvent) + Send + Sync>;
+
+#[derive(Debug)]
+enum Circuit {
+    Closed {
+        window_started: Instant,
+        calls: u32,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        in_flight: u32,
+        successes: u32,
+    },
+}
+
+impl Circuit {
+    fn closed() -> Self {
+        Circuit::Closed {
+            window_started: Instant::now(),
+            calls: 0,
+            failures: 0,
+        }
+    }
+}
+
+/// The circuit and a generation counter, bumped on every state change so
+/// calls started before it aren't counted after it.
+struct Shared {
+    circuit: Circuit,
+    generation: u64,
+}
+
+/// A circuit breaker for one dependency. Cheap to clone; clones share the
+/// circuit.
+///
+/// The circuit opens when at least half of the calls in a 30 second
+/// window fail, once the window has 10 calls. It stays open for 30
+/// seconds, then lets one trial call through.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    name: Arc<str>,
+    failure_rate: f64,
+    minimum_calls: u32,
+    window: Duration,
+    open_for: Duration,
+    trial_calls: u32,
+    namespace: Option<Arc<str>>,
+    sink: SinkFn,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl CircuitBreaker {
+    /// A closed circuit for the dependency `name`, used in logs and as the
+    /// metrics dimension.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into().into(),
+            failure_rate: 0.5,
+            minimum_calls: 10,
+            window: Duration::from_secs(30),
+            open_for: Duration::from_secs(30),
+            trial_calls: 1,
+            namespace: None,
+            sink: Arc::new(|event| match serde_json::to_string(event) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("Failed to serialize circuit event: {e}"),
+            }),
+            shared: Arc::new(Mutex::new(Shared {
+                circuit: Circuit::closed(),
+                generation: 0,
+            })),
+        }
+    }
+
+    /// The fraction of failed calls (0.0–1.0) that opens the circuit.
+    pub fn failure_rate(mut self, rate: f64) -> Self {
+        self.failure_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How many calls a window needs before its failure rate counts.
+    pub fn minimum_calls(mut self, calls: u32) -> Self {
+        self.minimum_calls = calls.max(1);
+        self
+    }
+
+    /// How long calls are counted before the counts start over.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// How long the circuit stays open before trial calls.
+    pub fn open_for(mut self, duration: Duration) -> Self {
+        self.open_for = duration;
+        self
+    }
+
+    /// How many trial calls a half-open circuit lets through; all must
+    /// succeed to close it.
+    pub fn trial_calls(mut self, calls: u32) -> Self {
+        self.trial_calls = calls.max(1);
+        self
+    }
+
+    /// Also publish events as EMF metrics under the CloudWatch `namespace`
+    /// (`CircuitOpened`, `CircuitHalfOpened`, `CircuitClosed` and
+    /// `CircuitRejected`, dimensioned by `Dependency`).
+    pub fn metrics(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into().into());
+        self
+    }
+
+    /// Send events to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&CircuitEvent) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The circuit's current state.
+    pub fn state(&self) -> CircuitState {
+        match self.lock().circuit {
+            Circuit::Open { until } if until <= Instant::now() => CircuitState::HalfOpen,
+            Circuit::Open { .. } => CircuitState::Open,
+            Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+            Circuit::Closed { .. } => CircuitState::Closed,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `call` through the circuit: an error counts as a failure, and
+    /// an open circuit returns [`CircuitOpen`] without running it.
+    ///
+    /// A call that is cancelled (e.g. by a timeout) also counts as a
+    /// failure.
+    pub async fn call<F, Fut, T, E>(&self, call: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<Error>,
+    {
+        let permit = self.acquire()?;
+        match call().await {
+            Ok(value) => {
+                permit.success();
+                Ok(value)
+            }
+            Err(e) => {
+                permit.failure();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Ask to make a call, for outcomes [`call`](Self::call) can't judge
+    /// (e.g. a 503 response). Report the outcome on the returned
+    /// [`Permit`]; a permit dropped without one counts as a failure.
+    pub fn acquire(&self) -> Result<Permit, CircuitOpen> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        let result = {
+            let mut shared = self.lock();
+            if let Circuit::Open { until } = shared.circuit {
+                if until <= now {
+                    shared.circuit = Circuit::HalfOpen {
+                        in_flight: 0,
+                        successes: 0,
+                    };
+                    shared.generation += 1;
+                    events.push((CircuitEventKind::HalfOpened, None));
+                }
+            }
+            let generation = shared.generation;
+            match &mut shared.circuit {
+                Circuit::Closed {
+                    window_started,
+                    calls,
+                    failures,
+                } => {
+                    if now.duration_since(*window_started) >= self.window {
+                        *window_started = now;
+                        *calls = 0;
+                        *failures = 0;
+                    }
+                    Ok(generation)
+                }
+                Circuit::HalfOpen { in_flight, .. } if *in_flight < self.trial_calls => {
+                    *in_flight += 1;
+                    Ok(generation)
+                }
+                Circuit::HalfOpen { .. } => Err(Duration::ZERO),
+                Circuit::Open { until } => Err(until.saturating_duration_since(now)),
+            }
+        };
+        let result = result
+            .map(|generation| Permit {
+                breaker: self.clone(),
+                generation,
+                reported: false,
+            })
+            .map_err(|retry_after| {
+                events.push((CircuitEventKind::Rejected, None));
+                CircuitOpen {
+                    dependency: self.name.to_string(),
+                    retry_after,
+                }
+            });
+        self.emit(events);
+        result
+    }
+
+    fn record(&self, generation: u64, success: bool) {
+        let mut events = Vec::new();
+        {
+            let mut shared = self.lock();
+            if shared.generation != generation {
+                return;
+            }
+            let next = match &mut shared.circuit {
+                Circuit::Closed {
+                    calls, failures, ..
+                } => {
+                    *calls += 1;
+                    *failures += u32::from(!success);
+                    let rate = f64::from(*failures) / f64::from(*calls);
+                    (*calls >= self.minimum_calls && *failures > 0 && rate >= self.failure_rate)
+                        .then(|| {
+                            events.push((CircuitEventKind::Opened, Some(rate)));
+                            Circuit::Open {
+                                until: Instant::now() + self.open_for,
+                            }
+                        })
+                }
+                Circuit::HalfOpen {
+                    in_flight,
+                    successes,
+                } => {
+                    *in_flight = in_flight.saturating_sub(1);
+                    if !success {
+                        events.push((CircuitEventKind::Opened, None));
+                        Some(Circuit::Open {
+                            until: Instant::now() + self.open_for,
+                        })
+                    } else {
+                        *successes += 1;
+                        (*successes >= self.trial_calls).then(|| {
+                            events.push((CircuitEventKind::Closed, None));
+                            Circuit::closed()
+                        })
+                    }
+                }
+                Circuit::Open { .. } => None,
+            };
+            if let Some(next) = next {
+                shared.circuit = next;
+                shared.generation += 1;
+            }
+        }
+        self.emit(events);
+    }
+
+    fn emit(&self, events: Vec<(CircuitEventKind, Option<f64>)>) {
+        for (kind, failure_rate) in events {
+            let (level, state) = match kind {
+                CircuitEventKind::Opened | CircuitEventKind::Rejected => {
+                    ("WARN", CircuitState::Open)
+                }
+                CircuitEventKind::HalfOpened => ("INFO", CircuitState::HalfOpen),
+                CircuitEventKind::Closed => ("INFO", CircuitState::Closed),
+            };
+            (self.sink)(&CircuitEvent {
+                level,
+                message: kind.message(),
+                event: kind,
+                dependency: self.name.to_string(),
+                state,
+                failure_rate,
+            });
+            if let Some(namespace) = &self.namespace {
+                let timestamp_ms = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64);
+                let doc = metrics::emf_document(
+                    namespace,
+                    &[("Dependency", &*self.name)],
+                    &[(kind.metric().to_string(), 1.0, Unit::Count)],
+                    timestamp_ms,
+                );
+                println!("{doc}");
+            }
+        }
+    }
+}
+
+/// Permission to make one call through a [`CircuitBreaker`].
+pub struct Permit {
+    breaker: CircuitBreaker,
+    generation: u64,
+    reported: bool,
+}
+
+impl Permit {
+    /// The call succeeded.
+    pub fn success(mut self) {
+        self.reported = true;
+        self.breaker.record(self.generation, true);
+    }
+
+    /// The call failed.
+    pub fn failure(mut self) {
+        self.reported = true;
+        self.breaker.record(self.generation, false);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.reported {
+            self.breaker.record(self.generation, false);
+        }
+    }
+}
+
+type FactoryFn = Arc<dyn Fn(&str) -> CircuitBreaker + Send + Sync>;
+
+/// Circuit breakers by dependency name, created on first use. Cheap to
+/// clone; clones share the breakers.
+#[derive(Clone)]
+pub struct CircuitBreakers {
+    factory: FactoryFn,
+    breakers: Arc<Mutex<HashMap<String, CircuitBreaker>>>,
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreakers {
+    /// Breakers created with [`CircuitBreaker::new`]'s defaults.
+    pub fn new() -> Self {
+        Self {
+            factory: Arc::new(|name| CircuitBreaker::new(name)),
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Create breakers with `factory`, given the dependency name.
+    pub fn configure<F>(mut self, factory: F) -> Self
+    where
+        F: Fn(&str) -> CircuitBreaker + Send + Sync + 'static,
+    {
+        self.factory = Arc::new(factory);
+        self
+    }
+
+    /// Use `breaker` for its dependency instead of the factory's.
+    pub fn with(self, breaker: CircuitBreaker) -> Self {
+        self.breakers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(breaker.name().to_string(), breaker);
+        self
+    }
+
+    /// The breaker for the dependency `name`.
+    pub fn get(&self, name: &str) -> CircuitBreaker {
+        let mut breakers = self.breakers.lock().unwrap_or_else(|e| e.into_inner());
+        match breakers.get(name) {
+            Some(breaker) => breaker.clone(),
+            None => {
+                let breaker = (self.factory)(name);
+                breakers.insert(name.to_string(), breaker.clone());
+                breaker
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(events: Arc<Mutex<Vec<CircuitEventKind>>>) -> CircuitBreaker {
+        CircuitBreaker::new("payments")
+            .minimum_calls(4)
+            .failure_rate(0.5)
+            .open_for(Duration::from_millis(20))
+            .sink(move |event| events.lock().unwrap().push(event.event))
+    }
+
+    async fn fail(breaker: &CircuitBreaker) -> Result<(), Error> {
+        breaker.call(|| async { Err::<(), _>("unavailable") }).await
+    }
+
+    async fn succeed(breaker: &CircuitBreaker) -> Result<(), Error> {
+        breaker.call(|| async { Ok::<_, Error>(()) }).await
+    }
+
+    #[tokio::test]
+    async fn opens_on_failure_rate_and_recovers() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let breaker = breaker(Arc::clone(&events));
+        succeed(&breaker).await.unwrap();
+        succeed(&breaker).await.unwrap();
+        fail(&breaker).await.unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        fail(&breaker).await.unwrap_err();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        let e = succeed(&breaker).await.unwrap_err();
+        let open = e.downcast_ref::<CircuitOpen>().unwrap();
+        assert_eq!(open.dependency, "payments");
+
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        succeed(&breaker).await.unwrap();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                CircuitEventKind::Opened,
+                CircuitEventKind::Rejected,
+                CircuitEventKind::HalfOpened,
+                CircuitEventKind::Closed
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_trial_reopens() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let breaker = breaker(Arc::clone(&events));
+        for _ in 0..4 {
+            fail(&breaker).await.unwrap_err();
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+
+        // Only one trial call at a time
+        let trial = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_err());
+        drop(trial);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn calls_from_an_earlier_state_are_ignored() {
+        let breaker = CircuitBreaker::new("search").minimum_calls(1).sink(|_| {});
+        let early = breaker.acquire().unwrap();
+        breaker.acquire().unwrap().failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        early.success();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn registry_shares_breakers() {
+        let breakers = CircuitBreakers::new()
+            .configure(|name| CircuitBreaker::new(name).minimum_calls(1).sink(|_| {}));
+        breakers.get("pricing").acquire().unwrap().failure();
+        assert_eq!(breakers.get("pricing").state(), CircuitState::Open);
+        assert_eq!(breakers.get("search").state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,203 @@
+//! Body (de)serialization selected by `Content-Type` / `Accept`: JSON, plus
+//! MessagePack (`msgpack` feature) and CBOR (`cbor` feature).
+
+use crate::{Error, Request, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The media type used for MessagePack bodies.
+#[cfg(feature = "msgpack")]
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// The media type used for CBOR bodies.
+#[cfg(feature = "cbor")]
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+
+#[cfg(feature = "msgpack")]
+fn is_msgpack(media_type: &str) -> bool {
+    matches!(
+        media_type,
+        "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack"
+    )
+}
+
+/// Media types [`Response::serialized`] can produce, in order of preference.
+fn available_types() -> Vec<&'static str> {
+    [
+        Some("application/json"),
+        cfg!(feature = "msgpack").then_some("application/msgpack"),
+        cfg!(feature = "cbor").then_some("application/cbor"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+impl Request {
+    /// Deserialize the request body as MessagePack.
+    #[cfg(feature = "msgpack")]
+    pub fn msgpack<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let bytes = self.body_bytes().ok_or("request body is empty")?;
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+
+    /// Deserialize the request body as CBOR.
+    #[cfg(feature = "cbor")]
+    pub fn cbor<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let bytes = self.body_bytes().ok_or("request body is empty")?;
+        Ok(ciborium::from_reader(bytes)?)
+    }
+
+    /// Deserialize the request body according to its `Content-Type`.
+    ///
+    /// MessagePack and CBOR are recognised when their features are enabled;
+    /// anything else is parsed as JSON.
+    pub fn body_as<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let media_type = self
+            .header("content-type")
+            .map(crate::media_type)
+            .unwrap_or_default();
+        #[cfg(feature = "msgpack")]
+        if is_msgpack(&media_type) {
+            return self.msgpack();
+        }
+        #[cfg(feature = "cbor")]
+        if media_type == CBOR_CONTENT_TYPE {
+            return self.cbor();
+        }
+        let _ = media_type;
+        let bytes = self.body_bytes().ok_or("request body is empty")?;
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl Response {
+    /// Serialize `value` as an `application/msgpack` response with status 200.
+    ///
+    /// Structs are encoded as maps with field names, for interoperability
+    /// with non-Rust clients.
+    #[cfg(feature = "msgpack")]
+    pub fn msgpack<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let body = rmp_serde::to_vec_named(value)?;
+        Ok(Response::binary(body, MSGPACK_CONTENT_TYPE))
+    }
+
+    /// Serialize `value` as an `application/cbor` response with status 200.
+    #[cfg(feature = "cbor")]
+    pub fn cbor<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let mut body = Vec::new();
+        ciborium::into_writer(value, &mut body)?;
+        Ok(Response::binary(body, CBOR_CONTENT_TYPE))
+    }
+
+    /// Serialize `value` in the format preferred by the request's `Accept`
+    /// header: JSON, MessagePack, or CBOR (depending on enabled features).
+    ///
+    /// Returns a 406 response if none of them is acceptable.
+    pub fn serialized<T: Serialize>(req: &Request, value: &T) -> Result<Self, Error> {
+        let available = available_types();
+        let Some(chosen) = req.preferred_type(&available) else {
+            return Ok(Response::not_acceptable(&available));
+        };
+        let resp = match chosen {
+            #[cfg(feature = "msgpack")]
+            MSGPACK_CONTENT_TYPE => Response::msgpack(value)?,
+            #[cfg(feature = "cbor")]
+            CBOR_CONTENT_TYPE => Response::cbor(value)?,
+            _ => Response::json(serde_json::to_value(value)?),
+        };
+        Ok(resp.with_header("Vary", "Accept"))
+    }
+}
+
+/// Deserialize a queue or topic message, treating text that isn't JSON as
+/// a JSON string so `String` accepts any message.
+#[cfg(any(feature = "sqs", feature = "sns"))]
+pub(crate) fn parse_message<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(text).or_else(|e| {
+        serde_json::from_value(serde_json::Value::String(text.to_string())).map_err(|_| e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    use crate::ResponseBody;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+    }
+
+    fn reading() -> Reading {
+        Reading {
+            sensor: "t1".to_string(),
+            value: 21.5,
+        }
+    }
+
+    fn request(content_type: Option<&str>, accept: Option<&str>, body: Vec<u8>) -> Request {
+        let mut req = Request::default();
+        if let Some(ct) = content_type {
+            req.headers.insert("content-type".into(), ct.into());
+        }
+        if let Some(a) = accept {
+            req.headers.insert("accept".into(), a.into());
+        }
+        match String::from_utf8(body) {
+            Ok(text) => req.body = Some(text),
+            Err(e) => req.binary_body = Some(e.into_bytes()),
+        }
+        req
+    }
+
+    #[test]
+    fn body_as_defaults_to_json() {
+        let req = request(None, None, br#"{"sensor":"t1","value":21.5}"#.to_vec());
+        assert_eq!(req.body_as::<Reading>().unwrap(), reading());
+    }
+
+    #[test]
+    fn serialized_falls_back_to_json_and_406() {
+        let resp = Response::serialized(&request(None, None, vec![]), &reading()).unwrap();
+        assert_eq!(
+            resp.body,
+            serde_json::json!({"sensor": "t1", "value": 21.5})
+        );
+
+        let req = request(None, Some("application/xml"), vec![]);
+        let resp = Response::serialized(&req, &reading()).unwrap();
+        assert_eq!(resp.status_code, 406);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip_via_negotiation() {
+        let req = request(None, Some("application/msgpack"), vec![]);
+        let resp = Response::serialized(&req, &reading()).unwrap();
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            MSGPACK_CONTENT_TYPE
+        );
+        let ResponseBody::Binary(bytes) = resp.body else {
+            panic!("expected binary body");
+        };
+        let req = request(Some("application/x-msgpack"), None, bytes);
+        assert_eq!(req.body_as::<Reading>().unwrap(), reading());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_round_trip_via_negotiation() {
+        let req = request(None, Some("application/cbor"), vec![]);
+        let resp = Response::serialized(&req, &reading()).unwrap();
+        let ResponseBody::Binary(bytes) = resp.body else {
+            panic!("expected binary body");
+        };
+        let req = request(Some("application/cbor"), None, bytes);
+        assert_eq!(req.body_as::<Reading>().unwrap(), reading());
+    }
+}
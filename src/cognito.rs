@@ -0,0 +1,123 @@
+//! Claims from an API Gateway Cognito user pool authorizer.
+
+use crate::Request;
+use serde_json::{Map, Value};
+
+/// The identity claims API Gateway passes on from a Cognito authorizer.
+///
+/// REST APIs deliver every claim as a string, so booleans and group lists
+/// are parsed from their string forms as well as native JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CognitoClaims {
+    /// The user's unique, immutable ID.
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    /// `cognito:username`.
+    pub username: Option<String>,
+    /// `cognito:groups`.
+    pub groups: Vec<String>,
+    /// Every claim, including custom attributes, as delivered.
+    pub raw: Map<String, Value>,
+}
+
+impl CognitoClaims {
+    /// Parse claims from the authorizer's `claims` map. Returns `None` if
+    /// `sub` is missing.
+    pub fn from_claims(raw: &Map<String, Value>) -> Option<Self> {
+        let string = |name: &str| raw.get(name).and_then(Value::as_str).map(str::to_string);
+        let email_verified = match raw.get("email_verified") {
+            Some(Value::Bool(b)) => Some(*b),
+            Some(Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        Some(Self {
+            sub: string("sub")?,
+            email: string("email"),
+            email_verified,
+            username: string("cognito:username"),
+            groups: raw
+                .get("cognito:groups")
+                .map(parse_groups)
+                .unwrap_or_default(),
+            raw: raw.clone(),
+        })
+    }
+
+    /// Whether the user belongs to `group`.
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+
+    /// A custom user pool attribute, e.g. `custom("tenant")` reads
+    /// `custom:tenant`.
+    pub fn custom(&self, attribute: &str) -> Option<&str> {
+        self.raw
+            .get(&format!("custom:{attribute}"))
+            .and_then(Value::as_str)
+    }
+}
+
+/// Groups arrive as a JSON array, `"a,b"`, or `"[a b]"` depending on the
+/// API type.
+pub(crate) fn parse_groups(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Value::String(s) => s
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split([',', ' '])
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Request {
+    /// The claims of the Cognito user who made the request, when the route
+    /// is protected by a Cognito user pool authorizer.
+    pub fn cognito_claims(&self) -> Option<CognitoClaims> {
+        CognitoClaims::from_claims(self.request_context.claims()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_rest_api_string_claims() {
+        let raw = json!({
+            "sub": "abc-123",
+            "email": "a@example.com",
+            "email_verified": "true",
+            "cognito:username": "alice",
+            "cognito:groups": "admin,staff",
+            "custom:tenant": "t-1"
+        });
+        let claims = CognitoClaims::from_claims(raw.as_object().unwrap()).unwrap();
+        assert_eq!(claims.sub, "abc-123");
+        assert_eq!(claims.email.as_deref(), Some("a@example.com"));
+        assert_eq!(claims.email_verified, Some(true));
+        assert_eq!(claims.username.as_deref(), Some("alice"));
+        assert!(claims.in_group("staff"));
+        assert_eq!(claims.custom("tenant"), Some("t-1"));
+    }
+
+    #[test]
+    fn parses_group_list_forms() {
+        assert_eq!(parse_groups(&json!(["a", "b"])), ["a", "b"]);
+        assert_eq!(parse_groups(&json!("[a b]")), ["a", "b"]);
+    }
+
+    #[test]
+    fn request_without_claims_has_none() {
+        assert!(Request::default().cognito_claims().is_none());
+    }
+}
@@ -0,0 +1,433 @@
+//! Cognito user pool triggers (`cognito-triggers` feature).
+//!
+//! Each trigger has a typed event ([`PreSignUp`], [`PostConfirmation`],
+//! [`PreTokenGeneration`] and the custom authentication challenges).
+//! Handlers registered with [`Choko::cognito_trigger`] receive it, fill in
+//! its `response` and return it; Cognito reads the answer from the echoed
+//! event. [`Choko::run_cognito`] starts the app as the pool's trigger
+//! function.
+//!
+//! # Example
+//! ```ignore
+//! use choko::cognito_triggers::{PreSignUp, PreTokenGeneration};
+//!
+//! app.cognito_trigger(|mut event: PreSignUp| async move {
+//!     let email = event.request.user_attributes.get("email").cloned().unwrap_or_default();
+//!     event.response.auto_confirm_user = email.ends_with("@example.com");
+//!     Ok(event)
+//! });
+//! app.cognito_trigger(|mut event: PreTokenGeneration| async move {
+//!     let tenant = tenant_of(&event.user_name).await?;
+//!     event.response.add_claim("tenant", tenant);
+//!     Ok(event)
+//! });
+//! app.run_cognito().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type TriggerFn =
+    Arc<dyn Fn(Value, Option<LambdaContext>) -> BoxFuture<Result<Value, Error>> + Send + Sync>;
+
+/// A trigger event, generic over the trigger's request and response.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerEvent<Req, Resp> {
+    pub version: String,
+    /// The trigger and the flow that fired it, e.g. `PreSignUp_SignUp` or
+    /// `TokenGeneration_RefreshTokens`.
+    pub trigger_source: String,
+    pub region: String,
+    pub user_pool_id: String,
+    pub user_name: Option<String>,
+    pub caller_context: CallerContext,
+    pub request: Req,
+    pub response: Resp,
+    #[serde(skip)]
+    lambda_context: Option<LambdaContext>,
+}
+
+impl<Req, Resp> TriggerEvent<Req, Resp> {
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallerContext {
+    pub aws_sdk_version: Option<String>,
+    pub client_id: Option<String>,
+}
+
+/// A trigger event type handled by [`Choko::cognito_trigger`].
+pub trait Trigger: Serialize + DeserializeOwned + Send + 'static {
+    /// The `triggerSource` prefix of the trigger, e.g. `PreSignUp`.
+    const SOURCE: &'static str;
+
+    #[doc(hidden)]
+    fn set_lambda_context(&mut self, context: Option<LambdaContext>);
+}
+
+macro_rules! trigger {
+    ($(#[$doc:meta])* $name:ident = $req:ident, $resp:ident, $source:literal) => {
+        $(#[$doc])*
+        pub type $name = TriggerEvent<$req, $resp>;
+
+        impl Trigger for $name {
+            const SOURCE: &'static str = $source;
+
+            fn set_lambda_context(&mut self, context: Option<LambdaContext>) {
+                self.lambda_context = context;
+            }
+        }
+    };
+}
+
+trigger!(
+    /// Before a user is registered: validate, auto-confirm or auto-verify.
+    PreSignUp = PreSignUpRequest, PreSignUpResponse, "PreSignUp"
+);
+trigger!(
+    /// After a user confirms sign-up or a forgotten-password reset.
+    PostConfirmation = PostConfirmationRequest, Empty, "PostConfirmation"
+);
+trigger!(
+    /// Before tokens are issued: add, override or suppress claims.
+    PreTokenGeneration = PreTokenGenerationRequest, PreTokenGenerationResponse, "TokenGeneration"
+);
+trigger!(
+    /// Decide the next custom authentication challenge.
+    DefineAuthChallenge = DefineAuthChallengeRequest, DefineAuthChallengeResponse, "DefineAuthChallenge"
+);
+trigger!(
+    /// Create a custom authentication challenge.
+    CreateAuthChallenge = CreateAuthChallengeRequest, CreateAuthChallengeResponse, "CreateAuthChallenge"
+);
+trigger!(
+    /// Check the answer to a custom authentication challenge.
+    VerifyAuthChallenge = VerifyAuthChallengeRequest, VerifyAuthChallengeResponse, "VerifyAuthChallengeResponse"
+);
+
+/// The response of triggers Cognito doesn't read one from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Empty {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreSignUpRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    pub validation_data: Option<HashMap<String, String>>,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreSignUpResponse {
+    #[serde(default)]
+    pub auto_confirm_user: bool,
+    #[serde(default)]
+    pub auto_verify_email: bool,
+    #[serde(default)]
+    pub auto_verify_phone: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostConfirmationRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreTokenGenerationRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    #[serde(default)]
+    pub group_configuration: GroupConfiguration,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupConfiguration {
+    #[serde(default)]
+    pub groups_to_override: Vec<String>,
+    #[serde(default)]
+    pub iam_roles_to_override: Vec<String>,
+    pub preferred_role: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreTokenGenerationResponse {
+    pub claims_override_details: Option<ClaimsOverrideDetails>,
+}
+
+impl PreTokenGenerationResponse {
+    fn details(&mut self) -> &mut ClaimsOverrideDetails {
+        self.claims_override_details
+            .get_or_insert_with(Default::default)
+    }
+
+    /// Add a claim to the ID token, or override an existing one.
+    pub fn add_claim(&mut self, name: &str, value: impl Into<String>) -> &mut Self {
+        self.details()
+            .claims_to_add_or_override
+            .insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Remove a claim from the ID token.
+    pub fn suppress_claim(&mut self, name: &str) -> &mut Self {
+        self.details().claims_to_suppress.push(name.to_string());
+        self
+    }
+
+    /// Replace the user's `cognito:groups`.
+    pub fn override_groups(&mut self, groups: Vec<String>) -> &mut Self {
+        self.details()
+            .group_override_details
+            .get_or_insert_with(Default::default)
+            .groups_to_override = groups;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimsOverrideDetails {
+    #[serde(default)]
+    pub claims_to_add_or_override: HashMap<String, String>,
+    #[serde(default)]
+    pub claims_to_suppress: Vec<String>,
+    pub group_override_details: Option<GroupConfiguration>,
+}
+
+/// One round of a custom authentication flow.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeResult {
+    /// `CUSTOM_CHALLENGE`, `PASSWORD_VERIFIER`, `SRP_A`, ...
+    pub challenge_name: String,
+    pub challenge_result: bool,
+    pub challenge_metadata: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefineAuthChallengeRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    #[serde(default)]
+    pub session: Vec<ChallengeResult>,
+    #[serde(default)]
+    pub user_not_found: bool,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefineAuthChallengeResponse {
+    pub challenge_name: Option<String>,
+    #[serde(default)]
+    pub issue_tokens: bool,
+    #[serde(default)]
+    pub fail_authentication: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAuthChallengeRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    pub challenge_name: String,
+    #[serde(default)]
+    pub session: Vec<ChallengeResult>,
+    #[serde(default)]
+    pub user_not_found: bool,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAuthChallengeResponse {
+    /// Sent to the client, e.g. a masked phone number.
+    #[serde(default)]
+    pub public_challenge_parameters: HashMap<String, String>,
+    /// Passed on to the verify trigger, e.g. the expected answer.
+    #[serde(default)]
+    pub private_challenge_parameters: HashMap<String, String>,
+    pub challenge_metadata: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyAuthChallengeRequest {
+    #[serde(default)]
+    pub user_attributes: HashMap<String, String>,
+    #[serde(default)]
+    pub private_challenge_parameters: HashMap<String, String>,
+    pub challenge_answer: Option<String>,
+    #[serde(default)]
+    pub user_not_found: bool,
+    pub client_metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyAuthChallengeResponse {
+    #[serde(default)]
+    pub answer_correct: bool,
+}
+
+impl Choko {
+    /// Handle the Cognito trigger `T`, e.g. `|event: PreSignUp| ...`.
+    ///
+    /// The handler returns the event with its `response` filled in;
+    /// returning an error makes Cognito reject the operation, with the
+    /// error message shown to the user.
+    pub fn cognito_trigger<T, F, Fut>(&mut self, handler: F) -> &mut Self
+    where
+        T: Trigger,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.cognito_triggers.insert(
+            T::SOURCE,
+            Arc::new(move |event, lambda_context| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move {
+                    let mut event: T = serde_json::from_value(event)?;
+                    event.set_lambda_context(lambda_context);
+                    Ok(serde_json::to_value(handler(event).await?)?)
+                })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the trigger function of a Cognito user pool.
+    pub async fn run_cognito(self) -> Result<(), Error> {
+        self.serve(|app, event: Value, context| async move {
+            app.dispatch_cognito(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_cognito(
+        &self,
+        event: Value,
+        context: Option<LambdaContext>,
+    ) -> Result<Value, Error> {
+        let source = event
+            .get("triggerSource")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let prefix = source.split('_').next().unwrap_or_default();
+        let handler = self
+            .cognito_triggers
+            .get(prefix)
+            .cloned()
+            .ok_or_else(|| format!("no handler for Cognito trigger {source:?}"))?;
+        handler(event, context).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(source: &str, request: Value) -> Value {
+        json!({
+            "version": "1",
+            "triggerSource": source,
+            "region": "eu-west-1",
+            "userPoolId": "eu-west-1_abc",
+            "userName": "alice",
+            "callerContext": { "awsSdkVersion": "aws-sdk-js-3", "clientId": "client" },
+            "request": request,
+            "response": {}
+        })
+    }
+
+    #[tokio::test]
+    async fn echoes_the_modified_event() {
+        let mut app = Choko::new("test");
+        app.cognito_trigger(|mut event: PreSignUp| async move {
+            let email = &event.request.user_attributes["email"];
+            if !email.ends_with("@example.com") {
+                return Err("Sign-up is restricted".into());
+            }
+            event.response.auto_confirm_user = true;
+            Ok(event)
+        });
+        app.cognito_trigger(|mut event: PreTokenGeneration| async move {
+            event
+                .response
+                .add_claim("tenant", "acme")
+                .suppress_claim("email");
+            Ok(event)
+        });
+
+        let out = app
+            .dispatch_cognito(
+                event(
+                    "PreSignUp_SignUp",
+                    json!({ "userAttributes": { "email": "a@example.com" } }),
+                ),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(out["response"]["autoConfirmUser"], true);
+        assert_eq!(out["userName"], "alice");
+        assert_eq!(out["request"]["userAttributes"]["email"], "a@example.com");
+
+        let denied = app
+            .dispatch_cognito(
+                event(
+                    "PreSignUp_SignUp",
+                    json!({ "userAttributes": { "email": "a@other.com" } }),
+                ),
+                None,
+            )
+            .await;
+        assert_eq!(denied.unwrap_err().to_string(), "Sign-up is restricted");
+
+        let out = app
+            .dispatch_cognito(
+                event(
+                    "TokenGeneration_RefreshTokens",
+                    json!({ "userAttributes": {}, "groupConfiguration": {} }),
+                ),
+                None,
+            )
+            .await
+            .unwrap();
+        let details = &out["response"]["claimsOverrideDetails"];
+        assert_eq!(details["claimsToAddOrOverride"]["tenant"], "acme");
+        assert_eq!(details["claimsToSuppress"], json!(["email"]));
+    }
+
+    #[tokio::test]
+    async fn rejects_unhandled_triggers() {
+        let app = Choko::new("test");
+        let result = app
+            .dispatch_cognito(event("PostConfirmation_ConfirmSignUp", json!({})), None)
+            .await;
+        assert!(result.is_err());
+    }
+}
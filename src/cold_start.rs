@@ -0,0 +1,69 @@
+//! Cold start detection.
+
+use crate::metrics::{self, Unit};
+use crate::Request;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static INIT_STARTED: OnceLock<Instant> = OnceLock::new();
+static COLD: AtomicBool = AtomicBool::new(true);
+
+/// Note the start of initialization; only the first call counts.
+pub(crate) fn mark_init() {
+    INIT_STARTED.get_or_init(Instant::now);
+}
+
+/// `Some(init duration)` for the first invocation of this execution
+/// environment, `None` afterwards. The duration runs from the first
+/// [`Choko::new`](crate::Choko::new) to the first event.
+pub(crate) fn take() -> Option<Duration> {
+    COLD.swap(false, Ordering::Relaxed)
+        .then(|| INIT_STARTED.get().map_or(Duration::ZERO, Instant::elapsed))
+}
+
+/// Print an EMF line with `ColdStart` (1) and `InitDuration` (ms) under
+/// `namespace`, dimensioned by function name.
+pub(crate) fn emit(namespace: &str, init_duration: Duration) {
+    let function = std::env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_default();
+    let values = [
+        ("ColdStart".to_string(), 1.0, Unit::Count),
+        (
+            "InitDuration".to_string(),
+            init_duration.as_secs_f64() * 1000.0,
+            Unit::Milliseconds,
+        ),
+    ];
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let doc = metrics::emf_document(
+        namespace,
+        &[("FunctionName", &function)],
+        &values,
+        timestamp_ms,
+    );
+    println!("{doc}");
+}
+
+impl Request {
+    /// Whether this request arrived in the first invocation of a fresh
+    /// execution environment. `false` outside the Lambda runtime.
+    pub fn is_cold_start(&self) -> bool {
+        self.lambda_context()
+            .is_some_and(|ctx| ctx.init_duration().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_first_invocation_is_cold() {
+        mark_init();
+        assert!(take().is_some());
+        assert!(take().is_none());
+        assert!(!Request::default().is_cold_start());
+    }
+}
@@ -0,0 +1,218 @@
+//! Response compression negotiated from `Accept-Encoding` (`compression` feature).
+
+use crate::{Response, ResponseBody};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+/// A content coding we can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+    fn name(self) -> &'static str {
+        match self {
+            Coding::Brotli => "br",
+            Coding::Gzip => "gzip",
+        }
+    }
+}
+
+/// Pick the coding to use for an `Accept-Encoding` value, preferring brotli
+/// over gzip at equal weight. Codings with `q=0` are excluded.
+fn choose_coding(accept_encoding: &str) -> Option<Coding> {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut wildcard = None;
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "*" => wildcard = Some(quality),
+            _ => {}
+        }
+    }
+    // `*` covers any coding not listed explicitly
+    let brotli = brotli.or(wildcard).unwrap_or(0.0);
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    match (brotli, gzip) {
+        (b, g) if b > 0.0 && b >= g => Some(Coding::Brotli),
+        (_, g) if g > 0.0 => Some(Coding::Gzip),
+        _ => None,
+    }
+}
+
+/// Whether a media type benefits from compression.
+fn is_compressible(content_type: &str) -> bool {
+    let media_type = crate::media_type(content_type);
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || matches!(
+            media_type.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-ndjson"
+                | "image/svg+xml"
+        )
+}
+
+fn encode(coding: Coding, data: &[u8]) -> std::io::Result<Vec<u8>> {
+    match coding {
+        Coding::Gzip => {
+            let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+            enc.write_all(data)?;
+            enc.finish()
+        }
+        Coding::Brotli => {
+            let mut enc = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            enc.write_all(data)?;
+            Ok(enc.into_inner())
+        }
+    }
+}
+
+/// Compress `resp` if the client accepts a supported coding, the body is at
+/// least `min_size` bytes, and its content type is compressible.
+pub(crate) fn compress_response(
+    mut resp: Response,
+    accept_encoding: Option<&str>,
+    min_size: usize,
+) -> Response {
+    let Some(coding) = accept_encoding.and_then(choose_coding) else {
+        return resp;
+    };
+    if resp.header("content-encoding").is_some() {
+        return resp;
+    }
+    let Some(content_type) = resp
+        .header("content-type")
+        .map(str::to_string)
+        .or_else(|| resp.body.default_content_type().map(str::to_string))
+    else {
+        return resp;
+    };
+    if !is_compressible(&content_type) {
+        return resp;
+    }
+    let data = match &resp.body {
+        ResponseBody::Json(v) => v.to_string().into_bytes(),
+        ResponseBody::Text(t) => t.as_bytes().to_vec(),
+        ResponseBody::Binary(b) => b.clone(),
+        ResponseBody::Empty | ResponseBody::Stream(_) => return resp,
+    };
+    if data.len() < min_size {
+        return resp;
+    }
+    let compressed = match encode(coding, &data) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Response compression failed: {e}");
+            return resp;
+        }
+    };
+    resp.headers
+        .retain(|k, _| !k.eq_ignore_ascii_case("content-type"));
+    resp.body = ResponseBody::Binary(compressed);
+    let resp = resp
+        .with_header("Content-Type", content_type)
+        .with_header("Content-Encoding", coding.name());
+    vary_on_encoding(resp)
+}
+
+/// Add `Accept-Encoding` to the response's `Vary`, keeping the headers it
+/// already varies on (e.g. `Accept` after content negotiation).
+fn vary_on_encoding(mut resp: Response) -> Response {
+    let existing = resp
+        .headers
+        .keys()
+        .find(|k| k.eq_ignore_ascii_case("vary"))
+        .cloned()
+        .and_then(|k| resp.headers.remove(&k));
+    let vary = match existing {
+        Some(v)
+            if v.split(',').any(|field| {
+                let field = field.trim();
+                field == "*" || field.eq_ignore_ascii_case("accept-encoding")
+            }) =>
+        {
+            v
+        }
+        Some(v) if !v.trim().is_empty() => format!("{v}, Accept-Encoding"),
+        _ => "Accept-Encoding".to_string(),
+    };
+    resp.with_header("Vary", vary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+    use flate2::read::GzDecoder;
+    use serde_json::json;
+    use std::io::Read;
+
+    #[test]
+    fn choose_coding_prefers_brotli_and_honours_q() {
+        assert_eq!(choose_coding("gzip, deflate, br"), Some(Coding::Brotli));
+        assert_eq!(choose_coding("br;q=0.5, gzip"), Some(Coding::Gzip));
+        assert_eq!(choose_coding("br;q=0, gzip;q=0"), None);
+        assert_eq!(choose_coding("identity"), None);
+        assert_eq!(choose_coding("gzip;q=0, *"), Some(Coding::Brotli));
+    }
+
+    #[test]
+    fn compresses_large_json_with_gzip() {
+        let items: Vec<_> = (0..200).map(|i| json!({"id": i, "name": "item"})).collect();
+        let resp = compress_response(Response::json(json!(items)), Some("gzip"), 1024);
+
+        assert_eq!(resp.header("content-encoding"), Some("gzip"));
+        assert_eq!(resp.header("content-type"), Some("application/json"));
+        let ResponseBody::Binary(bytes) = resp.body else {
+            panic!("expected binary body");
+        };
+        let mut out = String::new();
+        GzDecoder::new(&bytes[..]).read_to_string(&mut out).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&out).unwrap(),
+            json!(items)
+        );
+    }
+
+    #[test]
+    fn keeps_the_negotiated_vary() {
+        let req = Request {
+            headers: [("accept".to_string(), "application/json".to_string())].into(),
+            ..Default::default()
+        };
+        let items: Vec<_> = (0..200).map(|i| json!({"id": i})).collect();
+        let resp = Response::serialized(&req, &items).unwrap();
+        let resp = compress_response(resp, Some("gzip"), 1024);
+        assert_eq!(resp.header("content-encoding"), Some("gzip"));
+        assert_eq!(resp.header("vary"), Some("Accept, Accept-Encoding"));
+
+        let resp = Response::json(json!(items)).with_header("Vary", "accept-encoding");
+        let resp = compress_response(resp, Some("gzip"), 1024);
+        assert_eq!(resp.header("vary"), Some("accept-encoding"));
+    }
+
+    #[test]
+    fn skips_small_or_incompressible_bodies() {
+        let resp = compress_response(Response::json(json!({"a": 1})), Some("gzip"), 1024);
+        assert!(resp.header("content-encoding").is_none());
+
+        let png = Response::binary(vec![0u8; 4096], "image/png");
+        let resp = compress_response(png, Some("br"), 10);
+        assert!(resp.header("content-encoding").is_none());
+    }
+}
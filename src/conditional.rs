@@ -0,0 +1,235 @@
+//! Conditional request helpers for optimistic concurrency
+//! (`If-Match` / `If-Unmodified-Since`).
+
+use crate::{Request, Response};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An entity tag from an `ETag` or `If-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    /// The opaque tag value, without quotes.
+    pub tag: String,
+    /// Whether the tag was marked weak (`W/"..."`).
+    pub weak: bool,
+}
+
+impl EntityTag {
+    /// A strong entity tag.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self {
+            tag: tag.into(),
+            weak: false,
+        }
+    }
+
+    /// Strong comparison as required for `If-Match` (RFC 9110 §8.8.3.2).
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (weak, rest) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let tag = rest.strip_prefix('"')?.strip_suffix('"')?;
+        Some(Self {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// The parsed value of an `If-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// `If-Match: *` — matches any current representation.
+    Any,
+    /// A list of entity tags.
+    Tags(Vec<EntityTag>),
+}
+
+/// A precondition (`If-Match` / `If-Unmodified-Since`) did not hold.
+///
+/// Returning this error from a handler (e.g. via `?`) produces a 412 response.
+#[derive(Debug)]
+pub struct PreconditionFailed;
+
+impl fmt::Display for PreconditionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Precondition Failed")
+    }
+}
+
+impl std::error::Error for PreconditionFailed {}
+
+impl Request {
+    /// The parsed `If-Match` header, if present and well-formed.
+    pub fn if_match(&self) -> Option<IfMatch> {
+        let value = self.headers.get("if-match")?.trim();
+        if value == "*" {
+            return Some(IfMatch::Any);
+        }
+        let tags: Vec<EntityTag> = value.split(',').filter_map(EntityTag::parse).collect();
+        if tags.is_empty() {
+            None
+        } else {
+            Some(IfMatch::Tags(tags))
+        }
+    }
+
+    /// The parsed `If-Unmodified-Since` header, if present and a valid HTTP date.
+    pub fn if_unmodified_since(&self) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.headers.get("if-unmodified-since")?).ok()
+    }
+
+    /// Check the request's preconditions against the current version of a
+    /// resource.
+    ///
+    /// `If-Match` takes precedence; `If-Unmodified-Since` is only evaluated
+    /// when `If-Match` is absent and `last_modified` is known. Requests with
+    /// neither header pass; an `If-Match` without any valid entity tag (e.g.
+    /// the unquoted `If-Match: 5`) fails.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let item = repo.get(id).await?;
+    /// req.check_preconditions(&EntityTag::strong(item.version.to_string()), None)?;
+    /// ```
+    pub fn check_preconditions(
+        &self,
+        current: &EntityTag,
+        last_modified: Option<SystemTime>,
+    ) -> Result<(), PreconditionFailed> {
+        if self.headers.contains_key("if-match") {
+            return match self.if_match() {
+                Some(IfMatch::Any) => Ok(()),
+                Some(IfMatch::Tags(tags)) if tags.iter().any(|t| t.strong_eq(current)) => Ok(()),
+                _ => Err(PreconditionFailed),
+            };
+        }
+        if let (Some(since), Some(modified)) = (self.if_unmodified_since(), last_modified) {
+            // HTTP dates have one-second resolution
+            if unix_secs(modified) > unix_secs(since) {
+                return Err(PreconditionFailed);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl Response {
+    /// Create a 412 Precondition Failed response.
+    pub fn precondition_failed() -> Self {
+        Response::json(serde_json::json!({ "error": "Precondition Failed" })).with_status(412)
+    }
+
+    /// Set the `ETag` header.
+    pub fn with_etag(self, etag: &EntityTag) -> Self {
+        self.with_header("ETag", etag.to_string())
+    }
+
+    /// Set the `Last-Modified` header.
+    pub fn with_last_modified(self, time: SystemTime) -> Self {
+        self.with_header("Last-Modified", httpdate::fmt_http_date(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request_with(headers: &[(&str, &str)]) -> Request {
+        Request {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn if_match_parses_tags_and_wildcard() {
+        let req = request_with(&[("if-match", r#""a", W/"b""#)]);
+        assert_eq!(
+            req.if_match(),
+            Some(IfMatch::Tags(vec![
+                EntityTag::strong("a"),
+                EntityTag {
+                    tag: "b".to_string(),
+                    weak: true
+                }
+            ]))
+        );
+        assert_eq!(
+            request_with(&[("if-match", "*")]).if_match(),
+            Some(IfMatch::Any)
+        );
+        assert_eq!(request_with(&[]).if_match(), None);
+    }
+
+    #[test]
+    fn check_preconditions_uses_strong_comparison() {
+        let current = EntityTag::strong("v2");
+        assert!(request_with(&[("if-match", r#""v2""#)])
+            .check_preconditions(&current, None)
+            .is_ok());
+        assert!(request_with(&[("if-match", r#""v1""#)])
+            .check_preconditions(&current, None)
+            .is_err());
+        assert!(request_with(&[("if-match", r#"W/"v2""#)])
+            .check_preconditions(&current, None)
+            .is_err());
+        assert!(request_with(&[])
+            .check_preconditions(&current, None)
+            .is_ok());
+    }
+
+    #[test]
+    fn check_preconditions_rejects_malformed_if_match() {
+        let current = EntityTag::strong("5");
+        assert_eq!(request_with(&[("if-match", "5")]).if_match(), None);
+        assert!(request_with(&[("if-match", "5")])
+            .check_preconditions(&current, None)
+            .is_err());
+        assert!(request_with(&[("if-match", "")])
+            .check_preconditions(&current, None)
+            .is_err());
+    }
+
+    #[test]
+    fn check_preconditions_if_unmodified_since() {
+        let since = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let req = request_with(&[("if-unmodified-since", &httpdate::fmt_http_date(since))]);
+        let tag = EntityTag::strong("x");
+        assert!(req.check_preconditions(&tag, Some(since)).is_ok());
+        assert!(req
+            .check_preconditions(&tag, Some(since + Duration::from_secs(5)))
+            .is_err());
+    }
+
+    #[test]
+    fn response_etag_is_quoted() {
+        let resp = Response::json(serde_json::json!({})).with_etag(&EntityTag::strong("v1"));
+        assert_eq!(resp.headers.get("ETag").unwrap(), "\"v1\"");
+        assert_eq!(Response::precondition_failed().status_code, 412);
+    }
+}
@@ -0,0 +1,744 @@
+//! Typed configuration from defaults, environment variables, SSM and Secrets
+//! Manager (`config` feature).
+//!
+//! [`ChokoConfig`] merges layers of settings into one config struct, later
+//! layers overriding earlier ones field by field, and deserializes it once
+//! during the cold start. The struct is an ordinary
+//! `#[derive(Deserialize)]`; serde's `#[serde(default)]` attributes supply
+//! per-field defaults.
+//!
+//! - Environment variables under a prefix map to fields with `__` for
+//!   nesting: with the prefix `APP`, `APP_DB__HOST` sets `db.host` and
+//!   `APP_LOG_LEVEL` sets `log_level`.
+//! - Parameter Store paths nest the same way as with
+//!   [`Parameters`](crate::parameters::Parameters) (`ssm` feature).
+//! - A Secrets Manager secret holding a JSON object is merged in as-is, or
+//!   placed under a field (`secrets-manager` feature).
+//!
+//! Values are read leniently, since environment variables and parameters
+//! are all strings: `"5432"` fills a `u16`, `"true"` a `bool`, an empty
+//! value a `None`, and `"a,b"` or `["a","b"]` a `Vec`. A number still
+//! fills a `String` field, so an account ID stays intact.
+//!
+//! Errors name the field and where its value came from, e.g. `invalid
+//! configuration db.port: invalid value: string "abc", expected u16 (from
+//! env APP_DB__PORT)`, or the variable to set for a missing one. Returning
+//! the error from `main` fails the cold start with the message in the
+//! function's logs.
+//!
+//! # Example
+//! ```ignore
+//! use choko::config::ChokoConfig;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     db: DbConfig,                  // APP_DB__HOST, APP_DB__PORT, ...
+//!     #[serde(default)]
+//!     allowed_origins: Vec<String>,  // APP_ALLOWED_ORIGINS=a.com,b.com
+//! }
+//!
+//! let aws = aws_config::load_from_env().await;
+//! let config: Config = ChokoConfig::new()
+//!     .defaults(json!({ "db": { "port": 5432, "pool_size": 5 } }))
+//!     .env("APP")
+//!     .ssm(aws_sdk_ssm::Client::new(&aws), "/myapp/prod/")
+//!     .secret_at("db", aws_sdk_secretsmanager::Client::new(&aws), "myapp/prod/db")
+//!     .validate(|c: &Config| match c.db.pool_size {
+//!         0 => Err("db.pool_size must be at least 1".into()),
+//!         _ => Ok(()),
+//!     })
+//!     .load()
+//!     .await?;
+//! app.state(config);
+//! ```
+
+use crate::Error;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// Where a layer of settings came from, to name it in errors.
+#[derive(Debug, Clone)]
+enum Origin {
+    Defaults,
+    Env(String),
+    #[cfg_attr(not(feature = "ssm"), allow(dead_code))]
+    Ssm(String),
+    #[cfg_attr(not(feature = "secrets-manager"), allow(dead_code))]
+    Secret(String),
+}
+
+impl Origin {
+    /// The source of the value for `field`, e.g. `env APP_DB__PORT`.
+    fn describe(&self, field: &str) -> String {
+        match self {
+            Origin::Defaults => "defaults".to_string(),
+            Origin::Env(prefix) => format!("env {}", env_name(prefix, field)),
+            Origin::Ssm(path) => format!("SSM parameter {path}{}", field.replace('.', "/")),
+            Origin::Secret(name) => format!("secret {name}"),
+        }
+    }
+}
+
+enum Source {
+    Defaults(Value),
+    Env(String),
+    #[cfg(feature = "ssm")]
+    Ssm {
+        client: aws_sdk_ssm::Client,
+        path: String,
+    },
+    #[cfg(feature = "secrets-manager")]
+    Secret {
+        client: aws_sdk_secretsmanager::Client,
+        name: String,
+        field: Option<String>,
+    },
+}
+
+/// Loads a config struct of type `T` from layers of settings.
+pub struct ChokoConfig<T> {
+    sources: Vec<Source>,
+    validators: Vec<Validator<T>>,
+}
+
+impl<T: DeserializeOwned> Default for ChokoConfig<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: DeserializeOwned> ChokoConfig<T> {
+    /// A loader with no layers yet. Layers apply in the order they're
+    /// added, each overriding the fields it sets.
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            validators: Vec::new(),
+        }
+    }
+
+    /// Default values, as a JSON object shaped like the config.
+    pub fn defaults(mut self, defaults: Value) -> Self {
+        self.sources.push(Source::Defaults(defaults));
+        self
+    }
+
+    /// Environment variables starting with `prefix` and `_`. The rest of
+    /// the name, lowercased, is the field, with `__` separating nested
+    /// fields. An empty prefix reads every variable.
+    pub fn env(mut self, prefix: impl Into<String>) -> Self {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() && !prefix.ends_with('_') {
+            prefix.push('_');
+        }
+        self.sources.push(Source::Env(prefix));
+        self
+    }
+
+    /// The Parameter Store parameters under `path`, decrypting
+    /// `SecureString`s. `/myapp/prod/db/host` sets `db.host`.
+    #[cfg(feature = "ssm")]
+    pub fn ssm(mut self, client: aws_sdk_ssm::Client, path: impl Into<String>) -> Self {
+        let mut path = path.into();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        self.sources.push(Source::Ssm { client, path });
+        self
+    }
+
+    /// A secret holding a JSON object, merged into the config.
+    #[cfg(feature = "secrets-manager")]
+    pub fn secret(
+        mut self,
+        client: aws_sdk_secretsmanager::Client,
+        name: impl Into<String>,
+    ) -> Self {
+        self.sources.push(Source::Secret {
+            client,
+            name: name.into(),
+            field: None,
+        });
+        self
+    }
+
+    /// A secret placed under `field` (e.g. `db`, or `db.password` for a
+    /// plain string secret).
+    #[cfg(feature = "secrets-manager")]
+    pub fn secret_at(
+        mut self,
+        field: impl Into<String>,
+        client: aws_sdk_secretsmanager::Client,
+        name: impl Into<String>,
+    ) -> Self {
+        self.sources.push(Source::Secret {
+            client,
+            name: name.into(),
+            field: Some(field.into()),
+        });
+        self
+    }
+
+    /// Check the loaded config, e.g. that values are in range or consistent
+    /// with each other. An `Err` fails the load with its message.
+    pub fn validate<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.push(Box::new(check));
+        self
+    }
+
+    /// Read every layer, then deserialize and validate the config. A value
+    /// that doesn't fit its field, a missing field or a failed check is a
+    /// [`ConfigError`].
+    pub async fn load(self) -> Result<T, Error> {
+        let mut layers = Vec::new();
+        for source in &self.sources {
+            layers.push(read(source).await?);
+        }
+        Ok(self.build(layers)?)
+    }
+
+    fn build(&self, layers: Vec<(Origin, Value)>) -> Result<T, ConfigError> {
+        let mut root = Value::Object(Map::new());
+        let mut origins = HashMap::new();
+        let mut env_prefix = None;
+        for (origin, layer) in layers {
+            record(&layer, "", &origin, &mut origins);
+            merge(&mut root, layer);
+            if let Origin::Env(prefix) = origin {
+                env_prefix = Some(prefix);
+            }
+        }
+
+        let config = serde_path_to_error::deserialize(Lenient(root)).map_err(|e| {
+            let path = e.path().to_string();
+            let path = if path == "." { String::new() } else { path };
+            let message = e.inner().to_string();
+            if let Some(missing) = message
+                .strip_prefix("missing field `")
+                .and_then(|m| m.strip_suffix('`'))
+            {
+                let field = join(&path, missing);
+                return ConfigError {
+                    origin: env_prefix
+                        .as_deref()
+                        .map(|prefix| format!("set {}", env_name(prefix, &field))),
+                    field: Some(field),
+                    message: "missing".to_string(),
+                };
+            }
+            let leaf = path.split('[').next().unwrap_or_default();
+            ConfigError {
+                origin: origins.get(leaf).cloned(),
+                field: (!path.is_empty()).then_some(path),
+                message,
+            }
+        })?;
+
+        for check in &self.validators {
+            check(&config).map_err(|message| ConfigError {
+                field: None,
+                origin: None,
+                message,
+            })?;
+        }
+        Ok(config)
+    }
+}
+
+/// Why a config failed to load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The field at fault, e.g. `db.port`.
+    pub field: Option<String>,
+    /// Where its value came from, or how to set a missing one.
+    pub origin: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.field, self.message.as_str()) {
+            (Some(field), "missing") => write!(f, "missing configuration {field}")?,
+            (Some(field), message) => write!(f, "invalid configuration {field}: {message}")?,
+            (None, message) => write!(f, "invalid configuration: {message}")?,
+        }
+        match (&self.origin, self.message.as_str()) {
+            (Some(hint), "missing") => write!(f, " ({hint})"),
+            (Some(origin), _) => write!(f, " (from {origin})"),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The settings of one layer as a nested object.
+async fn read(source: &Source) -> Result<(Origin, Value), Error> {
+    Ok(match source {
+        Source::Defaults(value) => (Origin::Defaults, value.clone()),
+        Source::Env(prefix) => (
+            Origin::Env(prefix.clone()),
+            env_tree(prefix, std::env::vars()),
+        ),
+        #[cfg(feature = "ssm")]
+        Source::Ssm { client, path } => {
+            let tree = crate::parameters::fetch_tree(client, path)
+                .await
+                .map_err(|e| format!("failed to load configuration from SSM {path}: {e}"))?;
+            (Origin::Ssm(path.clone()), tree)
+        }
+        #[cfg(feature = "secrets-manager")]
+        Source::Secret {
+            client,
+            name,
+            field,
+        } => {
+            let output = client
+                .get_secret_value()
+                .secret_id(name)
+                .send()
+                .await
+                .map_err(|e| format!("failed to load configuration from secret {name}: {e}"))?;
+            let text = match (output.secret_string(), output.secret_binary()) {
+                (Some(text), _) => text.to_string(),
+                (None, Some(binary)) => String::from_utf8(binary.as_ref().to_vec())?,
+                (None, None) => return Err(format!("secret {name} has no value").into()),
+            };
+            let value = match serde_json::from_str(&text) {
+                Ok(object @ Value::Object(_)) => object,
+                _ => Value::String(text),
+            };
+            let tree = match field {
+                Some(field) => nest_at(field, value),
+                None if value.is_object() => value,
+                None => {
+                    return Err(format!(
+                    "secret {name} is not a JSON object; use secret_at to place it under a field"
+                )
+                    .into())
+                }
+            };
+            (Origin::Secret(name.clone()), tree)
+        }
+    })
+}
+
+/// The environment variable that sets `field`.
+fn env_name(prefix: &str, field: &str) -> String {
+    format!("{prefix}{}", field.replace('.', "__").to_ascii_uppercase())
+}
+
+/// A nested object from the variables in `vars` starting with `prefix`.
+fn env_tree(prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Value {
+    let mut root = Value::Object(Map::new());
+    for (name, value) in vars {
+        let Some(field) = name.strip_prefix(prefix).filter(|f| !f.is_empty()) else {
+            continue;
+        };
+        let field = field.to_ascii_lowercase().replace("__", ".");
+        merge(&mut root, nest_at(&field, Value::String(value)));
+    }
+    root
+}
+
+/// `value` wrapped in objects so it sits at the dotted `field`.
+fn nest_at(field: &str, value: Value) -> Value {
+    field
+        .rsplit('.')
+        .filter(|segment| !segment.is_empty())
+        .fold(value, |value, segment| {
+            Value::Object(Map::from_iter([(segment.to_string(), value)]))
+        })
+}
+
+/// Merge `layer` into `base`: objects field by field, anything else
+/// replaced.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Note `origin` as the source of every value in `layer`.
+fn record(layer: &Value, path: &str, origin: &Origin, origins: &mut HashMap<String, String>) {
+    match layer {
+        Value::Object(map) => {
+            for (key, value) in map {
+                record(value, &join(path, key), origin, origins);
+            }
+        }
+        _ => {
+            origins.insert(path.to_string(), origin.describe(path));
+        }
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{path}.{field}")
+    }
+}
+
+/// Deserializes a JSON value, parsing strings into the numbers, booleans,
+/// options and lists the target type asks for.
+struct Lenient(Value);
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for Lenient {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_array<'de, V: Visitor<'de>>(
+    items: Vec<Value>,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    let mut seq = SeqDeserializer::new(items.into_iter().map(Lenient));
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_object<'de, V: Visitor<'de>>(
+    map: Map<String, Value>,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    let mut map = MapDeserializer::new(map.into_iter().map(|(k, v)| (k, Lenient(v))));
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+macro_rules! parse_number {
+    ($($method:ident => $visit:ident($ty:ty)),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            match self.0 {
+                Value::String(s) => match s.trim().parse::<$ty>() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+                },
+                other => other.$method(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Array(items) => visit_array(items, visitor),
+            Value::Object(map) => visit_object(map, visitor),
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    parse_number! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64),
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+                "true" => visitor.visit_bool(true),
+                "false" => visitor.visit_bool(false),
+                _ => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+            },
+            other => other.deserialize_bool(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Number(n) => visitor.visit_string(n.to_string()),
+            Value::Bool(b) => visitor.visit_string(b.to_string()),
+            other => other.deserialize_string(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match &self.0 {
+            Value::Null => visitor.visit_none(),
+            Value::String(s) if s.is_empty() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Array(items) => visit_array(items, visitor),
+            Value::String(s) if s.trim_start().starts_with('[') => {
+                Lenient(serde_json::from_str(&s)?).deserialize_seq(visitor)
+            }
+            Value::String(s) => {
+                let items = s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect();
+                visit_array(items, visitor)
+            }
+            other => other.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Object(map) => visit_object(map, visitor),
+            Value::String(s) if s.trim_start().starts_with('{') => {
+                Lenient(serde_json::from_str(&s)?).deserialize_map(visitor)
+            }
+            other => other.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct Db {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Level {
+        Info,
+        Debug,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        db: Db,
+        debug: bool,
+        account_id: String,
+        #[serde(default)]
+        origins: Vec<String>,
+        timeout_ms: Option<u64>,
+        log_level: Level,
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Value {
+        env_tree(
+            "APP_",
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+    }
+
+    fn defaults() -> (Origin, Value) {
+        let defaults = json!({
+            "db": { "host": "localhost", "port": 5432 },
+            "debug": false,
+            "log_level": "info"
+        });
+        (Origin::Defaults, defaults)
+    }
+
+    #[test]
+    fn maps_env_vars_to_fields() {
+        let tree = vars(&[
+            ("APP_DB__HOST", "db.internal"),
+            ("APP_LOG_LEVEL", "debug"),
+            ("OTHER", "x"),
+        ]);
+        assert_eq!(
+            tree,
+            json!({ "db": { "host": "db.internal" }, "log_level": "debug" })
+        );
+        assert_eq!(env_name("APP_", "db.host"), "APP_DB__HOST");
+    }
+
+    #[test]
+    fn layers_override_defaults() {
+        let env = vars(&[
+            ("APP_DB__HOST", "db.internal"),
+            ("APP_DEBUG", "true"),
+            ("APP_ACCOUNT_ID", "012345678901"),
+            ("APP_ORIGINS", "a.com, b.com"),
+            ("APP_TIMEOUT_MS", ""),
+        ]);
+        let parameters = json!({ "db": { "port": 6432 }, "log_level": "debug" });
+        let config = ChokoConfig::<Config>::new()
+            .build(vec![
+                defaults(),
+                (Origin::Env("APP_".to_string()), env),
+                (Origin::Ssm("/app/prod/".to_string()), parameters),
+            ])
+            .unwrap();
+        assert_eq!(
+            (config.db.host.as_str(), config.db.port),
+            ("db.internal", 6432)
+        );
+        assert!(config.debug);
+        assert_eq!(config.account_id, "012345678901");
+        assert_eq!(config.origins, ["a.com", "b.com"]);
+        assert_eq!(config.timeout_ms, None);
+        assert!(matches!(config.log_level, Level::Debug));
+    }
+
+    #[test]
+    fn reads_strings_and_numbers_leniently() {
+        let layer = json!({
+            "db": { "host": "h", "port": " 15 " },
+            "debug": "FALSE",
+            "account_id": 123456789012u64,
+            "origins": "[\"a.com\"]",
+            "timeout_ms": "2500",
+            "log_level": "info"
+        });
+        let config = ChokoConfig::<Config>::new()
+            .build(vec![(Origin::Defaults, layer)])
+            .unwrap();
+        assert_eq!(config.db.port, 15);
+        assert!(!config.debug);
+        assert_eq!(config.account_id, "123456789012");
+        assert_eq!(config.origins, ["a.com"]);
+        assert_eq!(config.timeout_ms, Some(2500));
+    }
+
+    #[test]
+    fn errors_name_the_field_and_its_source() {
+        let env = vars(&[("APP_DB__PORT", "abc"), ("APP_ACCOUNT_ID", "1")]);
+        let e = ChokoConfig::<Config>::new()
+            .build(vec![defaults(), (Origin::Env("APP_".to_string()), env)])
+            .unwrap_err();
+        assert_eq!(e.field.as_deref(), Some("db.port"));
+        assert_eq!(e.origin.as_deref(), Some("env APP_DB__PORT"));
+        assert!(e
+            .to_string()
+            .starts_with("invalid configuration db.port: invalid value"));
+        assert!(e.to_string().ends_with("(from env APP_DB__PORT)"));
+
+        let e = ChokoConfig::<Config>::new()
+            .build(vec![
+                (
+                    Origin::Ssm("/app/".to_string()),
+                    json!({ "db": { "port": 1 } }),
+                ),
+                (Origin::Env("APP_".to_string()), json!({})),
+            ])
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "missing configuration db.host (set APP_DB__HOST)"
+        );
+    }
+
+    #[test]
+    fn runs_validators() {
+        let env = vars(&[("APP_ACCOUNT_ID", "1"), ("APP_DB__PORT", "0")]);
+        let loader = ChokoConfig::<Config>::new().validate(|c: &Config| match c.db.port {
+            0 => Err("db.port must not be 0".to_string()),
+            _ => Ok(()),
+        });
+        let e = loader
+            .build(vec![defaults(), (Origin::Env("APP_".to_string()), env)])
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "invalid configuration: db.port must not be 0"
+        );
+    }
+
+    #[test]
+    fn nests_secrets_under_a_field() {
+        assert_eq!(
+            nest_at("db.password", json!("s3cret")),
+            json!({ "db": { "password": "s3cret" } })
+        );
+        assert_eq!(
+            Origin::Ssm("/app/prod/".to_string()).describe("db.port"),
+            "SSM parameter /app/prod/db/port"
+        );
+    }
+}
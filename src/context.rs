@@ -0,0 +1,302 @@
+//! Invocation metadata exposed to handlers.
+
+use crate::auth::ClientCertificate;
+use crate::{Error, Request};
+use aws_lambda_events::event::apigw::ApiGatewayProxyRequestContext;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Request metadata supplied by API Gateway (`requestContext`).
+///
+/// Available to handlers through [`Request::request_context`].
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// The IP address of the client as seen by API Gateway.
+    pub source_ip: Option<String>,
+    /// The `User-Agent` reported by API Gateway.
+    pub user_agent: Option<String>,
+    /// The API Gateway stage name (e.g. `prod`).
+    pub stage: Option<String>,
+    /// The API Gateway request ID (distinct from the Lambda request ID).
+    pub request_id: Option<String>,
+    /// Values attached by the authorizer: Cognito claims under `claims`, or the
+    /// context map of a custom Lambda authorizer.
+    pub authorizer: HashMap<String, Value>,
+    /// The AWS principal that signed the request, when the method uses
+    /// `AWS_IAM` authorization.
+    pub iam: Option<IamIdentity>,
+    /// The client certificate, when the custom domain requires mutual TLS.
+    pub client_certificate: Option<ClientCertificate>,
+}
+
+/// The IAM caller of an `AWS_IAM`-authorized request.
+///
+/// API Gateway verifies the SigV4 signature before invoking the function;
+/// these fields describe who signed it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IamIdentity {
+    /// The caller's AWS account ID.
+    pub account_id: Option<String>,
+    /// The principal ARN, e.g. `arn:aws:sts::123456789012:assumed-role/app/session`.
+    pub user_arn: String,
+    /// The principal identifier (`caller`), e.g. `AROA...:session`.
+    pub caller: Option<String>,
+    /// The user identifier (`user`).
+    pub user: Option<String>,
+    /// The access key ID used to sign the request.
+    pub access_key: Option<String>,
+}
+
+impl RequestContext {
+    /// The claims attached by a Cognito/JWT authorizer (`authorizer.claims`).
+    pub fn claims(&self) -> Option<&Map<String, Value>> {
+        self.authorizer.get("claims").and_then(Value::as_object)
+    }
+}
+
+impl From<&ApiGatewayProxyRequestContext> for RequestContext {
+    fn from(ctx: &ApiGatewayProxyRequestContext) -> Self {
+        Self {
+            source_ip: ctx.identity.source_ip.clone(),
+            user_agent: ctx.identity.user_agent.clone(),
+            stage: ctx.stage.clone(),
+            request_id: ctx.request_id.clone(),
+            authorizer: ctx.authorizer.fields.clone(),
+            iam: ctx.identity.user_arn.clone().map(|user_arn| IamIdentity {
+                account_id: ctx.identity.account_id.clone(),
+                user_arn,
+                caller: ctx.identity.caller.clone(),
+                user: ctx.identity.user.clone(),
+                access_key: ctx.identity.access_key.clone(),
+            }),
+            client_certificate: ClientCertificate::from_identity(&ctx.identity.other),
+        }
+    }
+}
+
+/// Metadata about the current Lambda invocation.
+///
+/// Available to handlers through [`Request::lambda_context`].
+#[derive(Debug, Clone, Default)]
+pub struct LambdaContext {
+    request_id: String,
+    function_arn: String,
+    memory_limit_mb: i32,
+    deadline_ms: u64,
+    xray_trace_id: Option<String>,
+    pub(crate) init_duration: Option<Duration>,
+}
+
+impl LambdaContext {
+    /// The AWS request ID of this invocation.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// The ARN used to invoke the function (may include an alias or version).
+    pub fn function_arn(&self) -> &str {
+        &self.function_arn
+    }
+
+    /// The memory configured for the function, in MB.
+    pub fn memory_limit_mb(&self) -> i32 {
+        self.memory_limit_mb
+    }
+
+    /// The point in time at which the runtime will kill this invocation.
+    pub fn deadline(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.deadline_ms)
+    }
+
+    /// Time left before [`deadline`](Self::deadline), or zero if it has passed.
+    pub fn remaining_time(&self) -> Duration {
+        self.deadline()
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// The X-Ray trace header for this invocation, if tracing is active.
+    pub fn xray_trace_id(&self) -> Option<&str> {
+        self.xray_trace_id.as_deref()
+    }
+
+    /// For the first invocation of this execution environment (a cold
+    /// start), the time from [`Choko::new`](crate::Choko::new) to the
+    /// event's arrival; `None` for warm invocations.
+    pub fn init_duration(&self) -> Option<Duration> {
+        self.init_duration
+    }
+}
+
+impl From<&lambda_runtime::Context> for LambdaContext {
+    fn from(ctx: &lambda_runtime::Context) -> Self {
+        Self {
+            request_id: ctx.request_id.clone(),
+            function_arn: ctx.invoked_function_arn.clone(),
+            memory_limit_mb: ctx.env_config.memory,
+            deadline_ms: ctx.deadline,
+            xray_trace_id: ctx.xray_trace_id.clone(),
+            init_duration: None,
+        }
+    }
+}
+
+impl Request {
+    /// Metadata supplied by API Gateway: client identity, stage, and
+    /// authorizer output.
+    pub fn request_context(&self) -> &RequestContext {
+        &self.request_context
+    }
+
+    /// Metadata about the Lambda invocation that carried this request.
+    ///
+    /// Returns `None` when the request did not come through the Lambda
+    /// runtime (e.g. in unit tests).
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    /// The IAM principal that signed the request, for `AWS_IAM`-authorized
+    /// methods.
+    pub fn iam_identity(&self) -> Option<&IamIdentity> {
+        self.request_context.iam.as_ref()
+    }
+
+    /// Deserialize the context a custom Lambda authorizer attached to the
+    /// request (`requestContext.authorizer`).
+    ///
+    /// API Gateway may deliver numbers and booleans in the context as
+    /// strings; if the values don't deserialize as-is, they are parsed from
+    /// their string form like query parameters.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(serde::Deserialize)]
+    /// #[serde(rename_all = "camelCase")]
+    /// struct Tenant { tenant_id: String, can_write: bool }
+    ///
+    /// let tenant: Tenant = req.authorizer_context()?;
+    /// ```
+    pub fn authorizer_context<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let map: Map<String, Value> = self
+            .request_context
+            .authorizer
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        match serde_json::from_value(Value::Object(map)) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                let pairs: Vec<(&str, String)> = self
+                    .request_context
+                    .authorizer
+                    .iter()
+                    .filter_map(|(k, v)| match v {
+                        Value::String(s) => Some((k.as_str(), s.clone())),
+                        Value::Number(_) | Value::Bool(_) => Some((k.as_str(), v.to_string())),
+                        _ => None,
+                    })
+                    .collect();
+                let encoded = serde_urlencoded::to_string(&pairs)?;
+                serde_urlencoded::from_str(&encoded).map_err(|_| e.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn request_context_from_apigw_context() {
+        let mut ctx = ApiGatewayProxyRequestContext::default();
+        ctx.stage = Some("prod".to_string());
+        ctx.request_id = Some("apigw-1".to_string());
+        ctx.identity.source_ip = Some("203.0.113.7".to_string());
+        ctx.authorizer
+            .fields
+            .insert("claims".to_string(), json!({"sub": "user-1"}));
+
+        let rc = RequestContext::from(&ctx);
+        assert_eq!(rc.stage.as_deref(), Some("prod"));
+        assert_eq!(rc.request_id.as_deref(), Some("apigw-1"));
+        assert_eq!(rc.source_ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(rc.claims().unwrap()["sub"], "user-1");
+        assert!(rc.iam.is_none());
+    }
+
+    #[test]
+    fn iam_identity_from_apigw_context() {
+        let mut ctx = ApiGatewayProxyRequestContext::default();
+        ctx.identity.account_id = Some("123456789012".to_string());
+        ctx.identity.user_arn =
+            Some("arn:aws:sts::123456789012:assumed-role/billing/job-1".to_string());
+        ctx.identity.access_key = Some("ASIAEXAMPLE".to_string());
+
+        let iam = RequestContext::from(&ctx).iam.unwrap();
+        assert_eq!(iam.account_id.as_deref(), Some("123456789012"));
+        assert_eq!(
+            iam.user_arn,
+            "arn:aws:sts::123456789012:assumed-role/billing/job-1"
+        );
+        assert_eq!(iam.access_key.as_deref(), Some("ASIAEXAMPLE"));
+    }
+
+    #[test]
+    fn authorizer_context_deserializes_stringified_values() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "camelCase")]
+        struct Tenant {
+            principal_id: String,
+            tenant_id: String,
+            max_items: u32,
+            can_write: bool,
+        }
+
+        let mut ctx = ApiGatewayProxyRequestContext::default();
+        for (k, v) in [
+            ("principalId", json!("user-9")),
+            ("tenantId", json!("t-1")),
+            ("maxItems", json!("25")),
+            ("canWrite", json!("true")),
+        ] {
+            ctx.authorizer.fields.insert(k.to_string(), v);
+        }
+        let req = Request {
+            request_context: RequestContext::from(&ctx),
+            ..Default::default()
+        };
+        let tenant: Tenant = req.authorizer_context().unwrap();
+        assert_eq!(
+            tenant,
+            Tenant {
+                principal_id: "user-9".to_string(),
+                tenant_id: "t-1".to_string(),
+                max_items: 25,
+                can_write: true,
+            }
+        );
+    }
+
+    #[test]
+    fn lambda_context_from_runtime_context() {
+        let mut ctx = lambda_runtime::Context::default();
+        ctx.request_id = "req-1".to_string();
+        ctx.invoked_function_arn = "arn:aws:lambda:ap-northeast-1:123:function:f".to_string();
+        ctx.deadline = 1_000;
+
+        let lc = LambdaContext::from(&ctx);
+        assert_eq!(lc.request_id(), "req-1");
+        assert_eq!(
+            lc.function_arn(),
+            "arn:aws:lambda:ap-northeast-1:123:function:f"
+        );
+        assert_eq!(lc.deadline(), UNIX_EPOCH + Duration::from_secs(1));
+        // The deadline is long past
+        assert_eq!(lc.remaining_time(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,290 @@
+//! Reading request cookies and building `Set-Cookie` headers.
+
+use crate::{Request, Response};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// The `SameSite` cookie attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too. Browsers require `Secure` with this,
+    /// so it is always added.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+/// A cookie to set on the client, rendered as a `Set-Cookie` header.
+///
+/// Values are percent-encoded where needed, so arbitrary strings round-trip
+/// through [`Request::cookie`].
+///
+/// # Example
+/// ```ignore
+/// let cookie = Cookie::new("session", token)
+///     .http_only(true)
+///     .secure(true)
+///     .same_site(SameSite::Lax)
+///     .max_age(Duration::from_secs(3600));
+/// Ok(Response::no_content().with_cookie(cookie))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<SameSite>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    path: Option<String>,
+    domain: Option<String>,
+}
+
+impl Cookie {
+    /// A session cookie with the given name and value and no attributes.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            http_only: false,
+            secure: false,
+            same_site: None,
+            max_age: None,
+            expires: None,
+            path: None,
+            domain: None,
+        }
+    }
+
+    /// A cookie that deletes `name` on the client.
+    ///
+    /// `Path` and `Domain` must match the original cookie's; set them on the
+    /// returned value if the cookie was scoped.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    /// The cookie name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The cookie value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Hide the cookie from JavaScript.
+    pub fn http_only(mut self, enabled: bool) -> Self {
+        self.http_only = enabled;
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self, enabled: bool) -> Self {
+        self.secure = enabled;
+        self
+    }
+
+    /// Set the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Expire the cookie after `max_age` (whole seconds).
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Expire the cookie at a fixed time.
+    pub fn expires(mut self, at: SystemTime) -> Self {
+        self.expires = Some(at);
+        self
+    }
+
+    /// Restrict the cookie to a path prefix.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Send the cookie to a domain and its subdomains.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, encode_value(&self.value))?;
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if self.secure || self.same_site == Some(SameSite::None) {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Percent-encode bytes outside RFC 6265 `cookie-octet`, plus `%` itself.
+fn encode_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            0x21 | 0x23..=0x24 | 0x26..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Reverse [`encode_value`]; malformed escapes are kept verbatim.
+pub(crate) fn decode_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = value
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+impl Request {
+    /// All cookies sent with the request, as name/value pairs in header
+    /// order.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        let Some(header) = self.header("cookie") else {
+            return Vec::new();
+        };
+        header
+            .split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                let value = value.trim().trim_matches('"');
+                Some((name.trim().to_string(), decode_value(value)))
+            })
+            .collect()
+    }
+
+    /// The value of the cookie `name`, if the client sent it.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies()
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+}
+
+impl Response {
+    /// Add a `Set-Cookie` header. Can be called repeatedly.
+    pub fn with_cookie(self, cookie: Cookie) -> Self {
+        self.append_header("Set-Cookie", cookie.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn display_renders_attributes() {
+        let cookie = Cookie::new("session", "abc123")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::from_secs(3600))
+            .path("/")
+            .domain("example.com");
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc123; Max-Age=3600; Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    fn removal_expires_immediately() {
+        assert_eq!(
+            Cookie::removal("session").to_string(),
+            "session=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn same_site_none_implies_secure() {
+        let cookie = Cookie::new("a", "b").same_site(SameSite::None);
+        assert_eq!(cookie.to_string(), "a=b; Secure; SameSite=None");
+    }
+
+    #[test]
+    fn values_round_trip_through_request() {
+        let set = Cookie::new("prefs", "theme=dark; lang=日本").to_string();
+        let (_, encoded) = set.split_once('=').unwrap();
+        let req = Request {
+            headers: HashMap::from([("cookie".to_string(), format!("other=1; prefs={encoded}"))]),
+            ..Default::default()
+        };
+        assert_eq!(
+            req.cookie("prefs").as_deref(),
+            Some("theme=dark; lang=日本")
+        );
+        assert_eq!(req.cookie("other").as_deref(), Some("1"));
+        assert_eq!(req.cookie("missing"), None);
+    }
+
+    #[test]
+    fn with_cookie_appends_set_cookie_headers() {
+        let resp = Response::no_content()
+            .with_cookie(Cookie::new("a", "1"))
+            .with_cookie(Cookie::new("b", "2"));
+        assert_eq!(
+            resp.multi_value_headers["Set-Cookie"],
+            vec!["a=1".to_string(), "b=2".to_string()]
+        );
+    }
+}
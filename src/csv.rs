@@ -0,0 +1,72 @@
+//! CSV response bodies (`csv` feature).
+
+use crate::{Error, Response};
+use serde::Serialize;
+
+impl Response {
+    /// Serialize `rows` as a `text/csv` response with status 200.
+    ///
+    /// Struct rows produce a header record from their field names; fields are
+    /// quoted as needed. Chain [`with_attachment`](Response::with_attachment)
+    /// to offer the body as a download.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let orders: Vec<Order> = load_orders().await?;
+    /// Ok(Response::csv(&orders)?.with_attachment("orders.csv"))
+    /// ```
+    pub fn csv<I>(rows: I) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let mut writer = ::csv::Writer::from_writer(Vec::new());
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(Response::text(String::from_utf8(bytes)?)
+            .with_header("Content-Type", "text/csv; charset=utf-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+
+    #[derive(Serialize)]
+    struct Row {
+        id: u32,
+        name: &'static str,
+    }
+
+    #[test]
+    fn csv_writes_header_and_quotes_fields() {
+        let rows = [
+            Row {
+                id: 1,
+                name: "plain",
+            },
+            Row {
+                id: 2,
+                name: "with, comma",
+            },
+            Row {
+                id: 3,
+                name: "say \"hi\"",
+            },
+        ];
+        let resp = Response::csv(&rows).unwrap();
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            "text/csv; charset=utf-8"
+        );
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text(
+                "id,name\n1,plain\n2,\"with, comma\"\n3,\"say \"\"hi\"\"\"\n".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Transparent decoding of `Content-Encoding: gzip` / `deflate` request bodies.
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::Read;
+
+/// Why a compressed request body could not be decoded.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DecodeError {
+    /// The encoding is not one we can decode (maps to 415).
+    Unsupported,
+    /// The body is corrupt (maps to 400).
+    Invalid,
+    /// The decoded body exceeds the configured limit (maps to 413).
+    TooLarge,
+}
+
+/// Decode `body` according to the `Content-Encoding` header value, inflating
+/// at most `limit` bytes.
+pub(crate) fn decode_body(
+    encoding: &str,
+    body: Vec<u8>,
+    limit: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    let encoding = encoding.trim().to_ascii_lowercase();
+    match encoding.as_str() {
+        "" | "identity" => Ok(body),
+        "gzip" | "x-gzip" => inflate(GzDecoder::new(&body[..]), limit),
+        // HTTP "deflate" is zlib-wrapped, but many clients send raw deflate
+        "deflate" => match inflate(ZlibDecoder::new(&body[..]), limit) {
+            Err(DecodeError::Invalid) => inflate(DeflateDecoder::new(&body[..]), limit),
+            other => other,
+        },
+        _ => Err(DecodeError::Unsupported),
+    }
+}
+
+fn inflate(reader: impl Read, limit: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut out = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|_| DecodeError::Invalid)?;
+    if out.len() > limit {
+        return Err(DecodeError::TooLarge);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn decodes_gzip_body() {
+        let body = gzip(br#"{"a":1}"#);
+        assert_eq!(decode_body("gzip", body, 1024).unwrap(), br#"{"a":1}"#);
+    }
+
+    #[test]
+    fn decodes_zlib_deflate_body() {
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(b"hello").unwrap();
+        let body = enc.finish().unwrap();
+        assert_eq!(decode_body("deflate", body, 1024).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_bodies_over_limit() {
+        let body = gzip(&[b'a'; 2048]);
+        assert_eq!(decode_body("gzip", body, 1024), Err(DecodeError::TooLarge));
+    }
+
+    #[test]
+    fn rejects_unknown_encoding_and_corrupt_data() {
+        assert_eq!(
+            decode_body("br", b"xx".to_vec(), 1024),
+            Err(DecodeError::Unsupported)
+        );
+        assert_eq!(
+            decode_body("gzip", b"not gzip".to_vec(), 1024),
+            Err(DecodeError::Invalid)
+        );
+        assert_eq!(
+            decode_body("identity", b"plain".to_vec(), 1024).unwrap(),
+            b"plain"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Work deferred until after the response.
+//!
+//! [`Request::defer`] queues a future, such as warming a cache or sending an
+//! analytics event, to run once the handler and middleware have produced
+//! the response. Deferred tasks run concurrently before the invocation
+//! completes, for at most the deferred budget (see
+//! [`Choko::deferred_budget`](crate::Choko::deferred_budget)) and never
+//! past the Lambda deadline; tasks still running then are cancelled and
+//! logged.
+//!
+//! Lambda returns the response when the invocation completes, so deferred
+//! work still adds to the latency the client sees, but it can't fail or
+//! change the response. Keep it cheap; enqueue anything heavier (see
+//! `SqsSender`).
+
+use crate::timeout::DEFAULT_MARGIN;
+use crate::{BoxFuture, Request};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinSet;
+
+/// The deferred budget when none is configured.
+pub(crate) const DEFAULT_BUDGET: Duration = Duration::from_secs(2);
+
+/// The tasks deferred by one request.
+#[derive(Clone, Default)]
+pub(crate) struct DeferredTasks(Arc<Mutex<Vec<BoxFuture<()>>>>);
+
+impl DeferredTasks {
+    fn push(&self, task: BoxFuture<()>) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(task);
+    }
+
+    fn take(&self) -> Vec<BoxFuture<()>> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Run the queued tasks for up to `budget`, and never past `deadline`
+    /// less the safety margin.
+    pub(crate) async fn run(&self, budget: Duration, deadline: Option<SystemTime>) {
+        let tasks = self.take();
+        if tasks.is_empty() {
+            return;
+        }
+        let budget = match deadline {
+            Some(deadline) => {
+                let remaining = deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO);
+                budget.min(remaining.saturating_sub(DEFAULT_MARGIN))
+            }
+            None => budget,
+        };
+        let mut set = JoinSet::new();
+        for task in tasks {
+            set.spawn(task);
+        }
+        let finished = tokio::time::timeout(budget, async {
+            while let Some(result) = set.join_next().await {
+                if let Err(e) = result {
+                    eprintln!("Deferred task failed: {e}");
+                }
+            }
+        })
+        .await;
+        if finished.is_err() {
+            eprintln!(
+                "Cancelled {} deferred task(s) still running after {}ms",
+                set.len(),
+                budget.as_millis()
+            );
+            set.abort_all();
+        }
+    }
+}
+
+impl Request {
+    /// Run `task` after the response has been produced, before the
+    /// invocation completes.
+    ///
+    /// Outside a dispatched request (e.g. a `Request` built in a test) the
+    /// task is spawned instead.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.post("/orders", |req| async move {
+    ///     let order = place_order(&req).await?;
+    ///     let analytics = analytics_client.clone();
+    ///     let id = order.id.clone();
+    ///     req.defer(async move {
+    ///         if let Err(e) = analytics.track("order_placed", &id).await {
+    ///             eprintln!("analytics failed: {e}");
+    ///         }
+    ///     });
+    ///     Ok(Response::created(format!("/orders/{}", order.id), json!(order)))
+    /// });
+    /// ```
+    pub fn defer<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self.extensions().get::<DeferredTasks>() {
+            Some(tasks) => tasks.push(Box::pin(task)),
+            None => {
+                tokio::spawn(task);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Choko, Response};
+    use aws_lambda_events::event::apigw::ApiGatewayProxyRequest;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn runs_after_the_response_is_produced() {
+        let responded = Arc::new(AtomicBool::new(false));
+        let ran = Arc::new(AtomicUsize::new(0));
+        let mut app = Choko::new("test");
+        let (flag, counter) = (Arc::clone(&responded), Arc::clone(&ran));
+        app.get("/", move |req| {
+            let (flag, counter) = (Arc::clone(&flag), Arc::clone(&counter));
+            async move {
+                let seen = Arc::clone(&flag);
+                req.defer(async move {
+                    assert!(seen.load(Ordering::SeqCst));
+                    counter.fetch_add(1, Ordering::SeqCst);
+                });
+                flag.store(true, Ordering::SeqCst);
+                Ok(Response::text("ok"))
+            }
+        });
+
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some("/".to_string());
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cancels_tasks_over_budget() {
+        let tasks = DeferredTasks::default();
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&finished);
+        tasks.push(Box::pin(async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            flag.store(true, Ordering::SeqCst);
+        }));
+        tasks.push(Box::pin(async { panic!("boom") }));
+        tasks.run(Duration::from_millis(20), None).await;
+        assert!(!finished.load(Ordering::SeqCst));
+
+        // Past the Lambda deadline nothing runs at all
+        let counter = Arc::new(AtomicUsize::new(0));
+        let c = Arc::clone(&counter);
+        tasks.push(Box::pin(async move {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            c.fetch_add(1, Ordering::SeqCst);
+        }));
+        tasks
+            .run(Duration::from_secs(1), Some(SystemTime::now()))
+            .await;
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
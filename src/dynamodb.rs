@@ -0,0 +1,609 @@
+//! Typed DynamoDB items (`dynamodb` feature).
+//!
+//! [`Table`] wraps an `aws_sdk_dynamodb::Client` with get, put, update,
+//! delete and query operations that take and return serde types, converted
+//! with [`to_item`] and [`from_item`]. Create the table once when the
+//! function starts and clone it into handlers, so the client and its
+//! connections are reused across invocations.
+//!
+//! Query results come a page at a time; [`Page::cursor`] and
+//! [`Request::cursor`](crate::Request::cursor) carry the position between
+//! requests.
+//!
+//! # Example
+//! ```ignore
+//! use choko::dynamodb::{key, Key, Table};
+//!
+//! let client = aws_sdk_dynamodb::Client::new(&aws_config::load_from_env().await);
+//! let table = Table::new(client, "app").sort_key("sk");
+//!
+//! let orders = table.clone();
+//! app.get("/users/{id}/orders", move |req| {
+//!     let table = orders.clone();
+//!     async move {
+//!         let page = table
+//!             .query(key("USER", &req.path_params["id"]))
+//!             .begins_with("ORDER#")
+//!             .limit(20)
+//!             .start_after(req.cursor()?)
+//!             .page::<Order>()
+//!             .await?;
+//!         Ok(Response::json(json!({ "orders": page.items, "next": page.cursor() })))
+//!     }
+//! });
+//! ```
+
+use crate::pagination::encode_cursor;
+use crate::Error;
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+
+/// An item as the SDK represents it.
+pub type Item = HashMap<String, AttributeValue>;
+
+/// A single-table design key such as `USER#42`.
+pub fn key(prefix: &str, id: impl std::fmt::Display) -> String {
+    format!("{prefix}#{id}")
+}
+
+/// Convert a serializable value to an item. The value must serialize to a
+/// JSON object.
+pub fn to_item<T: Serialize + ?Sized>(value: &T) -> Result<Item, Error> {
+    match serde_json::to_value(value)? {
+        Value::Object(map) => Ok(map.into_iter().map(|(k, v)| (k, to_attribute(v))).collect()),
+        other => Err(format!("items must serialize to an object, not {other}").into()),
+    }
+}
+
+/// Convert an item to a deserializable value.
+///
+/// Binary attributes become base64 strings, sets become arrays.
+pub fn from_item<T: DeserializeOwned>(item: &Item) -> Result<T, Error> {
+    let map: Map<String, Value> = item
+        .iter()
+        .map(|(k, v)| (k.clone(), from_attribute(v)))
+        .collect();
+    Ok(serde_json::from_value(Value::Object(map))?)
+}
+
+/// The attribute value for a JSON value.
+pub fn to_attribute(value: Value) -> AttributeValue {
+    match value {
+        Value::Null => AttributeValue::Null(true),
+        Value::Bool(b) => AttributeValue::Bool(b),
+        Value::Number(n) => AttributeValue::N(n.to_string()),
+        Value::String(s) => AttributeValue::S(s),
+        Value::Array(values) => AttributeValue::L(values.into_iter().map(to_attribute).collect()),
+        Value::Object(map) => {
+            AttributeValue::M(map.into_iter().map(|(k, v)| (k, to_attribute(v))).collect())
+        }
+    }
+}
+
+/// The JSON value for an attribute value.
+pub fn from_attribute(attr: &AttributeValue) -> Value {
+    let number = |n: &str| {
+        n.parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| n.parse::<u64>().map(Value::from))
+            .ok()
+            .or_else(|| {
+                n.parse::<f64>()
+                    .ok()
+                    .and_then(Number::from_f64)
+                    .map(Value::Number)
+            })
+            .unwrap_or_else(|| Value::String(n.to_string()))
+    };
+    let binary = |b: &Blob| Value::String(base64::engine::general_purpose::STANDARD.encode(b));
+    match attr {
+        AttributeValue::S(s) => Value::String(s.clone()),
+        AttributeValue::N(n) => number(n),
+        AttributeValue::Bool(b) => Value::Bool(*b),
+        AttributeValue::B(b) => binary(b),
+        AttributeValue::L(values) => Value::Array(values.iter().map(from_attribute).collect()),
+        AttributeValue::M(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), from_attribute(v)))
+                .collect(),
+        ),
+        AttributeValue::Ss(values) => values.iter().cloned().map(Value::String).collect(),
+        AttributeValue::Ns(values) => values.iter().map(|n| number(n)).collect(),
+        AttributeValue::Bs(values) => values.iter().map(binary).collect(),
+        _ => Value::Null,
+    }
+}
+
+/// The primary key of an item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    partition: AttributeValue,
+    sort: Option<AttributeValue>,
+}
+
+impl Key {
+    /// A key with a string partition key value.
+    pub fn new(partition: impl Into<String>) -> Self {
+        Self {
+            partition: AttributeValue::S(partition.into()),
+            sort: None,
+        }
+    }
+
+    /// Add a string sort key value.
+    pub fn sort(mut self, sort: impl Into<String>) -> Self {
+        self.sort = Some(AttributeValue::S(sort.into()));
+        self
+    }
+
+    /// A key with arbitrary attribute values, e.g. numbers.
+    pub fn from_attributes(partition: AttributeValue, sort: Option<AttributeValue>) -> Self {
+        Self { partition, sort }
+    }
+}
+
+impl From<&str> for Key {
+    fn from(partition: &str) -> Self {
+        Key::new(partition)
+    }
+}
+
+impl From<String> for Key {
+    fn from(partition: String) -> Self {
+        Key::new(partition)
+    }
+}
+
+impl From<(&str, &str)> for Key {
+    fn from((partition, sort): (&str, &str)) -> Self {
+        Key::new(partition).sort(sort)
+    }
+}
+
+/// A DynamoDB table with typed items.
+#[derive(Debug, Clone)]
+pub struct Table {
+    client: aws_sdk_dynamodb::Client,
+    name: String,
+    partition_key: String,
+    sort_key: Option<String>,
+}
+
+impl Table {
+    /// The table `name`, with partition key attribute `pk` and no sort key.
+    pub fn new(client: aws_sdk_dynamodb::Client, name: impl Into<String>) -> Self {
+        Self {
+            client,
+            name: name.into(),
+            partition_key: "pk".to_string(),
+            sort_key: None,
+        }
+    }
+
+    /// The partition key attribute name.
+    pub fn partition_key(mut self, name: impl Into<String>) -> Self {
+        self.partition_key = name.into();
+        self
+    }
+
+    /// The sort key attribute name.
+    pub fn sort_key(mut self, name: impl Into<String>) -> Self {
+        self.sort_key = Some(name.into());
+        self
+    }
+
+    /// The underlying client, for operations not covered here.
+    pub fn client(&self) -> &aws_sdk_dynamodb::Client {
+        &self.client
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn key_item(&self, key: Key) -> Item {
+        let mut item = Item::from([(self.partition_key.clone(), key.partition)]);
+        if let (Some(name), Some(sort)) = (&self.sort_key, key.sort) {
+            item.insert(name.clone(), sort);
+        }
+        item
+    }
+
+    /// Fetch an item, with a strongly consistent read.
+    pub async fn get<T: DeserializeOwned>(&self, key: impl Into<Key>) -> Result<Option<T>, Error> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.name)
+            .set_key(Some(self.key_item(key.into())))
+            .consistent_read(true)
+            .send()
+            .await?;
+        output.item().map(from_item).transpose()
+    }
+
+    /// Write an item, replacing any item with the same key. `item` must
+    /// include its key attributes.
+    pub async fn put<T: Serialize + ?Sized>(&self, item: &T) -> Result<(), Error> {
+        self.client
+            .put_item()
+            .table_name(&self.name)
+            .set_item(Some(to_item(item)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Set the attributes of `changes` on an item, creating it if needed,
+    /// and return the item as updated. `null` fields are removed.
+    pub async fn update<T: DeserializeOwned>(
+        &self,
+        key: impl Into<Key>,
+        changes: &impl Serialize,
+    ) -> Result<T, Error> {
+        let (expression, names, values) = update_expression(to_item(changes)?);
+        let output = self
+            .client
+            .update_item()
+            .table_name(&self.name)
+            .set_key(Some(self.key_item(key.into())))
+            .update_expression(expression)
+            .set_expression_attribute_names(Some(names))
+            .set_expression_attribute_values((!values.is_empty()).then_some(values))
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await?;
+        from_item(output.attributes().unwrap_or(&Item::new()))
+    }
+
+    /// Delete an item. Deleting a missing item is not an error.
+    pub async fn delete(&self, key: impl Into<Key>) -> Result<(), Error> {
+        self.client
+            .delete_item()
+            .table_name(&self.name)
+            .set_key(Some(self.key_item(key.into())))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Query the items with partition key value `partition`.
+    pub fn query(&self, partition: impl Into<String>) -> Query<'_> {
+        Query {
+            table: self,
+            index: None,
+            partition: (
+                self.partition_key.clone(),
+                AttributeValue::S(partition.into()),
+            ),
+            sort_key: self.sort_key.clone(),
+            sort: None,
+            limit: None,
+            forward: true,
+            start: None,
+        }
+    }
+
+    /// Query the secondary index `index`, whose partition key attribute is
+    /// `partition_key`. Set its sort key with [`Query::sort_key`] before
+    /// adding sort key conditions.
+    pub fn query_index(
+        &self,
+        index: &str,
+        partition_key: &str,
+        partition: impl Into<String>,
+    ) -> Query<'_> {
+        Query {
+            index: Some(index.to_string()),
+            partition: (
+                partition_key.to_string(),
+                AttributeValue::S(partition.into()),
+            ),
+            sort_key: None,
+            ..self.query("")
+        }
+    }
+}
+
+/// `SET`/`REMOVE` expression for the attributes of `changes`.
+fn update_expression(changes: Item) -> (String, HashMap<String, String>, Item) {
+    let mut changes: Vec<_> = changes.into_iter().collect();
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut names = HashMap::new();
+    let mut values = Item::new();
+    let (mut set, mut remove) = (Vec::new(), Vec::new());
+    for (i, (name, value)) in changes.into_iter().enumerate() {
+        names.insert(format!("#f{i}"), name);
+        if value == AttributeValue::Null(true) {
+            remove.push(format!("#f{i}"));
+        } else {
+            set.push(format!("#f{i} = :f{i}"));
+            values.insert(format!(":f{i}"), value);
+        }
+    }
+    let mut expression = Vec::new();
+    if !set.is_empty() {
+        expression.push(format!("SET {}", set.join(", ")));
+    }
+    if !remove.is_empty() {
+        expression.push(format!("REMOVE {}", remove.join(", ")));
+    }
+    (expression.join(" "), names, values)
+}
+
+enum SortCondition {
+    BeginsWith(String),
+    Between(String, String),
+    Compare(&'static str, String),
+}
+
+/// A query being built. Run it with [`Query::page`] or [`Query::all`].
+pub struct Query<'a> {
+    table: &'a Table,
+    index: Option<String>,
+    partition: (String, AttributeValue),
+    sort_key: Option<String>,
+    sort: Option<SortCondition>,
+    limit: Option<i32>,
+    forward: bool,
+    start: Option<Item>,
+}
+
+/// One page of query results.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The key to continue after, if there are more results.
+    pub last_key: Option<Value>,
+}
+
+impl<T> Page<T> {
+    /// An opaque cursor for the next page, for a `cursor` query parameter.
+    pub fn cursor(&self) -> Option<String> {
+        self.last_key.as_ref().map(encode_cursor)
+    }
+}
+
+impl Query<'_> {
+    /// The sort key attribute of the index being queried.
+    pub fn sort_key(mut self, name: &str) -> Self {
+        self.sort_key = Some(name.to_string());
+        self
+    }
+
+    /// Only items whose sort key starts with `prefix`.
+    pub fn begins_with(mut self, prefix: impl Into<String>) -> Self {
+        self.sort = Some(SortCondition::BeginsWith(prefix.into()));
+        self
+    }
+
+    /// Only items whose sort key is between `low` and `high`, inclusive.
+    pub fn between(mut self, low: impl Into<String>, high: impl Into<String>) -> Self {
+        self.sort = Some(SortCondition::Between(low.into(), high.into()));
+        self
+    }
+
+    /// Only items whose sort key is greater than `value`.
+    pub fn after(mut self, value: impl Into<String>) -> Self {
+        self.sort = Some(SortCondition::Compare(">", value.into()));
+        self
+    }
+
+    /// Only items whose sort key is less than `value`.
+    pub fn before(mut self, value: impl Into<String>) -> Self {
+        self.sort = Some(SortCondition::Compare("<", value.into()));
+        self
+    }
+
+    /// At most `limit` items per page.
+    pub fn limit(mut self, limit: i32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Return items in descending sort key order.
+    pub fn descending(mut self) -> Self {
+        self.forward = false;
+        self
+    }
+
+    /// Continue after a page's [`last_key`](Page::last_key), e.g. decoded
+    /// from a cursor with [`Request::cursor`](crate::Request::cursor).
+    pub fn start_after(mut self, last_key: Option<Value>) -> Self {
+        self.start = last_key.and_then(|key| match to_attribute(key) {
+            AttributeValue::M(item) => Some(item),
+            _ => None,
+        });
+        self
+    }
+
+    fn key_condition(&self) -> Result<(String, HashMap<String, String>, Item), Error> {
+        let mut names = HashMap::from([("#pk".to_string(), self.partition.0.clone())]);
+        let mut values = Item::from([(":pk".to_string(), self.partition.1.clone())]);
+        let mut expression = "#pk = :pk".to_string();
+        if let Some(sort) = &self.sort {
+            let name = self
+                .sort_key
+                .clone()
+                .ok_or("sort key conditions need a sort key attribute")?;
+            names.insert("#sk".to_string(), name);
+            let condition = match sort {
+                SortCondition::BeginsWith(prefix) => {
+                    values.insert(":sk".to_string(), AttributeValue::S(prefix.clone()));
+                    "begins_with(#sk, :sk)".to_string()
+                }
+                SortCondition::Between(low, high) => {
+                    values.insert(":sk".to_string(), AttributeValue::S(low.clone()));
+                    values.insert(":sk2".to_string(), AttributeValue::S(high.clone()));
+                    "#sk BETWEEN :sk AND :sk2".to_string()
+                }
+                SortCondition::Compare(op, value) => {
+                    values.insert(":sk".to_string(), AttributeValue::S(value.clone()));
+                    format!("#sk {op} :sk")
+                }
+            };
+            expression = format!("{expression} AND {condition}");
+        }
+        Ok((expression, names, values))
+    }
+
+    /// Fetch one page of results.
+    pub async fn page<T: DeserializeOwned>(&self) -> Result<Page<T>, Error> {
+        self.fetch(self.start.clone()).await
+    }
+
+    /// Fetch every page, following the last evaluated key until the end.
+    pub async fn all<T: DeserializeOwned>(&self) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+        let mut start = self.start.clone();
+        loop {
+            let page = self.fetch::<T>(start).await?;
+            items.extend(page.items);
+            match page.last_key.map(to_attribute) {
+                Some(AttributeValue::M(key)) => start = Some(key),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, start: Option<Item>) -> Result<Page<T>, Error> {
+        let (expression, names, values) = self.key_condition()?;
+        let output = self
+            .table
+            .client
+            .query()
+            .table_name(&self.table.name)
+            .set_index_name(self.index.clone())
+            .key_condition_expression(expression)
+            .set_expression_attribute_names(Some(names))
+            .set_expression_attribute_values(Some(values))
+            .set_limit(self.limit)
+            .scan_index_forward(self.forward)
+            .set_exclusive_start_key(start)
+            .send()
+            .await?;
+        Ok(Page {
+            items: output
+                .items()
+                .iter()
+                .map(from_item)
+                .collect::<Result<_, _>>()?,
+            last_key: output.last_evaluated_key().map(|key| {
+                Value::Object(
+                    key.iter()
+                        .map(|(k, v)| (k.clone(), from_attribute(v)))
+                        .collect(),
+                )
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        pk: String,
+        sk: String,
+        total: f64,
+        qty: u32,
+        tags: Vec<String>,
+        note: Option<String>,
+    }
+
+    fn table() -> Table {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(aws_sdk_dynamodb::config::BehaviorVersion::latest())
+            .build();
+        Table::new(aws_sdk_dynamodb::Client::from_conf(config), "app").sort_key("sk")
+    }
+
+    #[test]
+    fn items_round_trip_through_serde() {
+        let order = Order {
+            pk: key("USER", 42),
+            sk: key("ORDER", "2024-05-01"),
+            total: 12.5,
+            qty: 3,
+            tags: vec!["gift".to_string()],
+            note: None,
+        };
+        let item = to_item(&order).unwrap();
+        assert_eq!(item["pk"], AttributeValue::S("USER#42".to_string()));
+        assert_eq!(item["qty"], AttributeValue::N("3".to_string()));
+        assert_eq!(item["note"], AttributeValue::Null(true));
+        assert_eq!(from_item::<Order>(&item).unwrap(), order);
+
+        let set = Item::from([
+            ("pk".to_string(), AttributeValue::S("x".to_string())),
+            (
+                "ids".to_string(),
+                AttributeValue::Ns(vec!["1".to_string(), "2.5".to_string()]),
+            ),
+            (
+                "raw".to_string(),
+                AttributeValue::B(Blob::new(b"hi".to_vec())),
+            ),
+        ]);
+        assert_eq!(
+            from_item::<Value>(&set).unwrap(),
+            json!({ "pk": "x", "ids": [1, 2.5], "raw": "aGk=" })
+        );
+        assert!(to_item(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn builds_update_expressions() {
+        let (expression, names, values) =
+            update_expression(to_item(&json!({ "status": "shipped", "note": null })).unwrap());
+        assert_eq!(expression, "SET #f1 = :f1 REMOVE #f0");
+        assert_eq!(names["#f0"], "note");
+        assert_eq!(names["#f1"], "status");
+        assert_eq!(values.len(), 1);
+    }
+
+    #[test]
+    fn builds_key_conditions() {
+        let table = table();
+        let query = table.query("USER#42").begins_with("ORDER#");
+        let (expression, names, values) = query.key_condition().unwrap();
+        assert_eq!(expression, "#pk = :pk AND begins_with(#sk, :sk)");
+        assert_eq!((names["#pk"].as_str(), names["#sk"].as_str()), ("pk", "sk"));
+        assert_eq!(values[":sk"], AttributeValue::S("ORDER#".to_string()));
+
+        let query = table
+            .query_index("gsi1", "email", "a@example.com")
+            .after("x");
+        assert!(query.key_condition().is_err());
+        let (expression, names, _) = query.sort_key("created").key_condition().unwrap();
+        assert_eq!(expression, "#pk = :pk AND #sk > :sk");
+        assert_eq!(names["#pk"], "email");
+
+        let key = table.key_item(("USER#42", "PROFILE").into());
+        assert_eq!(key.len(), 2);
+    }
+
+    #[test]
+    fn cursors_round_trip_last_keys() {
+        let page: Page<Order> = Page {
+            items: Vec::new(),
+            last_key: Some(json!({ "pk": "USER#42", "sk": "ORDER#9" })),
+        };
+        let last_key: Value = crate::decode_cursor(&page.cursor().unwrap()).unwrap();
+        let table = table();
+        let query = table.query("USER#42").start_after(Some(last_key));
+        assert_eq!(
+            query.start.unwrap()["sk"],
+            AttributeValue::S("ORDER#9".to_string())
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! DynamoDB Streams consumers (`dynamodb-streams` feature).
+//!
+//! [`Choko::dynamodb_table`] registers a handler for the change records of
+//! one table, and [`Choko::run_dynamodb_streams`] starts the app as the
+//! stream's Lambda consumer. Keys and images arrive in DynamoDB's
+//! attribute-value format (`{"S": "..."}`, `{"N": "42"}`, ...) and are
+//! converted to plain JSON before being deserialized into your types.
+//!
+//! Records are handled in order. The first one that fails is reported as
+//! the batch item failure and the rest of the batch is skipped, so Lambda
+//! retries from that record and per-key ordering holds. Enable
+//! *ReportBatchItemFailures* on the event source mapping.
+//!
+//! # Example
+//! ```ignore
+//! use choko::dynamodb_streams::{Change, ChangeKind};
+//!
+//! app.dynamodb_table("orders", |change: Change<Order>| async move {
+//!     match change.kind {
+//!         ChangeKind::Remove => search.delete(&change.keys::<OrderKey>()?.id).await,
+//!         _ => search.index(change.new_image.unwrap()).await,
+//!     }
+//! });
+//! app.run_dynamodb_streams().await
+//! ```
+
+use crate::streams::StreamBatchResponse;
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type TableHandlerFn =
+    Arc<dyn Fn(StreamRecord, Option<LambdaContext>) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// A DynamoDB Streams invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DynamoDbEvent {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<StreamRecord>,
+}
+
+/// One change record, with keys and images still in attribute-value
+/// format.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamRecord {
+    #[serde(rename = "eventID")]
+    pub event_id: String,
+    /// `INSERT`, `MODIFY` or `REMOVE`.
+    pub event_name: String,
+    #[serde(rename = "eventSourceARN", default)]
+    pub event_source_arn: String,
+    pub dynamodb: StreamData,
+}
+
+/// The `dynamodb` section of a change record.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StreamData {
+    #[serde(default)]
+    pub keys: Map<String, Value>,
+    pub new_image: Option<Map<String, Value>>,
+    pub old_image: Option<Map<String, Value>>,
+    #[serde(default)]
+    pub sequence_number: String,
+    /// Seconds since the Unix epoch.
+    pub approximate_creation_date_time: Option<f64>,
+}
+
+/// What happened to the item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Modify,
+    Remove,
+}
+
+/// A change to an item, with its images deserialized as `T`.
+///
+/// Which images are present depends on the stream view type: `new_image`
+/// is `None` for removals and `old_image` for inserts.
+#[derive(Debug, Clone)]
+pub struct Change<T> {
+    pub kind: ChangeKind,
+    pub event_id: String,
+    pub sequence_number: String,
+    pub new_image: Option<T>,
+    pub old_image: Option<T>,
+    /// Seconds since the Unix epoch.
+    pub approximate_creation_time: Option<f64>,
+    keys: Value,
+    table_name: String,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl<T> Change<T> {
+    /// The item's key attributes, deserialized as `K`.
+    pub fn keys<K: DeserializeOwned>(&self) -> Result<K, serde_json::Error> {
+        K::deserialize(&self.keys)
+    }
+
+    /// The name of the table the change was made to.
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+}
+
+impl<T: DeserializeOwned> Change<T> {
+    fn from_record(
+        record: StreamRecord,
+        lambda_context: Option<LambdaContext>,
+    ) -> Result<Self, Error> {
+        let kind = match record.event_name.as_str() {
+            "INSERT" => ChangeKind::Insert,
+            "MODIFY" => ChangeKind::Modify,
+            "REMOVE" => ChangeKind::Remove,
+            other => return Err(format!("unknown stream event {other:?}").into()),
+        };
+        let image = |image: Option<Map<String, Value>>| -> Result<Option<T>, Error> {
+            image
+                .map(|item| Ok(serde_json::from_value(from_item(item)?)?))
+                .transpose()
+        };
+        Ok(Self {
+            kind,
+            new_image: image(record.dynamodb.new_image)?,
+            old_image: image(record.dynamodb.old_image)?,
+            keys: from_item(record.dynamodb.keys)?,
+            table_name: table_name(&record.event_source_arn).to_string(),
+            event_id: record.event_id,
+            sequence_number: record.dynamodb.sequence_number,
+            approximate_creation_time: record.dynamodb.approximate_creation_date_time,
+            lambda_context,
+        })
+    }
+}
+
+/// Convert an item in attribute-value format to a JSON object.
+///
+/// Numbers become JSON numbers (strings if out of range), binary values
+/// their base64 text, and sets arrays.
+pub fn from_item(item: Map<String, Value>) -> Result<Value, Error> {
+    item.into_iter()
+        .map(|(name, value)| Ok((name, from_attribute(value)?)))
+        .collect::<Result<Map<_, _>, Error>>()
+        .map(Value::Object)
+}
+
+fn from_attribute(value: Value) -> Result<Value, Error> {
+    let attr = match value {
+        Value::Object(attr) => attr,
+        other => return Err(format!("invalid attribute value {other}").into()),
+    };
+    let Some((kind, value)) = attr.into_iter().next() else {
+        return Err("empty attribute value".into());
+    };
+    Ok(match (kind.as_str(), value) {
+        ("S" | "B", value @ Value::String(_)) => value,
+        ("N", Value::String(n)) => number(n),
+        ("BOOL", value @ Value::Bool(_)) => value,
+        ("NULL", _) => Value::Null,
+        ("M", Value::Object(map)) => from_item(map)?,
+        ("L", Value::Array(items)) => Value::Array(
+            items
+                .into_iter()
+                .map(from_attribute)
+                .collect::<Result<_, _>>()?,
+        ),
+        ("SS" | "BS", value @ Value::Array(_)) => value,
+        ("NS", Value::Array(items)) => Value::Array(
+            items
+                .into_iter()
+                .map(|n| match n {
+                    Value::String(n) => number(n),
+                    other => other,
+                })
+                .collect(),
+        ),
+        (kind, value) => return Err(format!("invalid {kind} attribute {value}").into()),
+    })
+}
+
+fn number(n: String) -> Value {
+    if let Ok(i) = n.parse::<i64>() {
+        return Value::from(i);
+    }
+    n.parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map_or(Value::String(n), Value::Number)
+}
+
+/// Decode a binary (`B`) attribute converted by [`from_item`].
+pub fn decode_binary(value: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    base64::engine::general_purpose::STANDARD.decode(value)
+}
+
+/// The table name from a stream ARN
+/// (`arn:aws:dynamodb:region:account:table/name/stream/label`).
+fn table_name(arn: &str) -> &str {
+    arn.split('/').nth(1).unwrap_or("")
+}
+
+impl Choko {
+    /// Handle the change records of the DynamoDB table `table`.
+    ///
+    /// A record fails if its images don't deserialize as `T` or the
+    /// handler returns an error.
+    pub fn dynamodb_table<T, F, Fut>(&mut self, table: &str, handler: F) -> &mut Self
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(Change<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.dynamodb_tables.insert(
+            table.to_string(),
+            Arc::new(move |record, lambda_context| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(Change::from_record(record, lambda_context)?).await })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the Lambda consumer of its tables' streams.
+    pub async fn run_dynamodb_streams(self) -> Result<(), Error> {
+        self.serve(|app, event: DynamoDbEvent, context| async move {
+            Ok(app.dispatch_dynamodb(event, Some(context)).await)
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_dynamodb(
+        &self,
+        event: DynamoDbEvent,
+        context: Option<LambdaContext>,
+    ) -> StreamBatchResponse {
+        for record in event.records {
+            let sequence_number = record.dynamodb.sequence_number.clone();
+            let table = table_name(&record.event_source_arn).to_string();
+            let outcome = match self.dynamodb_tables.get(&table) {
+                Some(handler) => handler(record, context.clone()).await,
+                None => Err(format!("no handler for table {table:?}").into()),
+            };
+            if let Err(e) = outcome {
+                eprintln!("DynamoDB stream record {sequence_number} from {table} failed: {e}");
+                return StreamBatchResponse::failed_at(Some(sequence_number));
+            }
+        }
+        StreamBatchResponse::failed_at(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: String,
+        total: f64,
+        items: Vec<String>,
+        paid: bool,
+    }
+
+    fn record(seq: &str, name: &str, new_image: Value) -> StreamRecord {
+        serde_json::from_value(json!({
+            "eventID": format!("ev-{seq}"),
+            "eventName": name,
+            "eventSource": "aws:dynamodb",
+            "eventSourceARN": "arn:aws:dynamodb:eu-west-1:123456789012:table/orders/stream/2024-05-01T00:00:00.000",
+            "dynamodb": {
+                "Keys": { "id": { "S": "o-1" } },
+                "NewImage": new_image,
+                "SequenceNumber": seq,
+                "StreamViewType": "NEW_AND_OLD_IMAGES"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn converts_attribute_values() {
+        let item = json!({
+            "id": { "S": "o-1" },
+            "total": { "N": "12.5" },
+            "qty": { "N": "3" },
+            "tags": { "SS": ["a", "b"] },
+            "sizes": { "NS": ["1", "2"] },
+            "meta": { "M": { "gift": { "BOOL": true }, "note": { "NULL": true } } },
+            "lines": { "L": [{ "S": "x" }, { "N": "7" }] }
+        });
+        let Value::Object(item) = item else {
+            unreachable!()
+        };
+        assert_eq!(
+            from_item(item).unwrap(),
+            json!({
+                "id": "o-1",
+                "total": 12.5,
+                "qty": 3,
+                "tags": ["a", "b"],
+                "sizes": [1, 2],
+                "meta": { "gift": true, "note": null },
+                "lines": ["x", 7]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_failed_record() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let mut app = Choko::new("test");
+        app.dynamodb_table("orders", move |change: Change<Order>| {
+            let log = Arc::clone(&log);
+            async move {
+                #[derive(Deserialize)]
+                struct Key {
+                    id: String,
+                }
+                assert_eq!(change.keys::<Key>()?.id, "o-1");
+                assert_eq!(change.table_name(), "orders");
+                let Order {
+                    id,
+                    total,
+                    items,
+                    paid,
+                } = change.new_image.ok_or("no new image")?;
+                log.lock().unwrap().push(format!(
+                    "{} {id} {total} {items:?} {paid}",
+                    change.sequence_number
+                ));
+                Ok(())
+            }
+        });
+
+        let order = json!({
+            "id": { "S": "o-1" },
+            "total": { "N": "9.99" },
+            "items": { "L": [{ "S": "book" }] },
+            "paid": { "BOOL": false }
+        });
+        let event = DynamoDbEvent {
+            records: vec![
+                record("100", "INSERT", order.clone()),
+                record("200", "MODIFY", json!({ "id": { "S": "o-1" } })),
+                record("300", "MODIFY", order),
+            ],
+        };
+        let resp = app.dispatch_dynamodb(event, None).await;
+        assert_eq!(resp, StreamBatchResponse::failed_at(Some("200".into())));
+        assert_eq!(*seen.lock().unwrap(), [r#"100 o-1 9.99 ["book"] false"#]);
+    }
+}
@@ -0,0 +1,626 @@
+//! CloudFront Lambda@Edge support (`edge` feature).
+//!
+//! For viewer-request and origin-request events, the CloudFront request is
+//! translated into the API Gateway form the router works on, so routes and
+//! middleware run at the edge unchanged. A route can answer the viewer
+//! directly with any [`Response`], or let the request continue to the
+//! origin with [`forward`] or [`rewrite`] (changing the URI), adding request
+//! headers with [`Forward::with_header`]. Requests no route matches continue
+//! unchanged.
+//!
+//! Viewer-response and origin-response events go to the hook registered
+//! with [`Choko::edge_response`], or pass through unchanged.
+//!
+//! # Example
+//! ```ignore
+//! use choko::edge;
+//!
+//! app.get("/admin/{path}", |req| async move {
+//!     match session_user(&req) {
+//!         Some(user) => Ok(edge::forward().with_header("x-user", &user).into()),
+//!         None => Ok(Response::redirect("/login")),
+//!     }
+//! });
+//! app.get("/", |_req| async { Ok(edge::rewrite("/index.html").into()) });
+//! app.edge_response(|_req, mut resp| async move {
+//!     resp.set_header("strict-transport-security", "max-age=63072000");
+//!     Ok(resp)
+//! });
+//! app.run_edge().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext, Response};
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::query_map::QueryMap;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Marks a response as "continue to the origin"; its value is the
+/// [`Forward`], as base64-encoded JSON.
+const FORWARD_HEADER: &str = "x-choko-edge-forward";
+
+/// Headers CloudFront doesn't let functions add or change.
+const DISALLOWED_HEADERS: &[&str] = &[
+    "connection",
+    "expect",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "trailer",
+    "upgrade",
+    "x-accel-buffering",
+    "x-accel-charset",
+    "x-accel-limit-rate",
+    "x-accel-redirect",
+    "x-amzn-auth",
+    "x-amzn-cf-billing",
+    "x-amzn-cf-id",
+    "x-amzn-cf-xff",
+    "x-amzn-errortype",
+    "x-amzn-fle-profile",
+    "x-amzn-header-count",
+    "x-amzn-header-order",
+    "x-amzn-lambda-integration-tag",
+    "x-amzn-requestid",
+    "x-cache",
+    "x-forwarded-proto",
+    "x-real-ip",
+];
+
+/// Prefixes of further disallowed headers.
+const DISALLOWED_PREFIXES: &[&str] = &["x-amz-cf-", "x-edge-"];
+
+/// Read-only headers of viewer-request events.
+const VIEWER_REQUEST_READ_ONLY: &[&str] = &["content-length", "host", "transfer-encoding", "via"];
+
+/// Read-only headers of origin-request events.
+const ORIGIN_REQUEST_READ_ONLY: &[&str] = &[
+    "accept-encoding",
+    "content-length",
+    "if-modified-since",
+    "if-none-match",
+    "if-range",
+    "if-unmodified-since",
+    "transfer-encoding",
+    "via",
+];
+
+/// Read-only headers of responses generated at the edge.
+const RESPONSE_READ_ONLY: &[&str] = &["content-length", "transfer-encoding", "via"];
+
+/// Whether a function may set header `name` (lowercase), given the
+/// event's `read_only` headers.
+fn settable(name: &str, read_only: &[&str]) -> bool {
+    !DISALLOWED_HEADERS.contains(&name)
+        && !DISALLOWED_PREFIXES.iter().any(|p| name.starts_with(p))
+        && !read_only.contains(&name)
+}
+
+pub(crate) type ResponseHookFn =
+    Arc<dyn Fn(CfRequest, CfResponse) -> BoxFuture<Result<CfResponse, Error>> + Send + Sync>;
+
+/// A request continuing to the origin, created by [`forward`] or
+/// [`rewrite`] and returned from a route with `.into()`.
+///
+/// Only the headers added with [`with_header`](Self::with_header) reach the
+/// origin request; headers set on the response by middleware or hooks do
+/// not.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Forward {
+    uri: Option<String>,
+    headers: Vec<(String, String)>,
+}
+
+impl Forward {
+    /// Set the request header `name` for the origin.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let json = base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+impl From<Forward> for Response {
+    fn from(forward: Forward) -> Self {
+        let json = serde_json::to_vec(&forward).unwrap_or_default();
+        Response::no_content().with_header(
+            FORWARD_HEADER,
+            base64::engine::general_purpose::STANDARD.encode(json),
+        )
+    }
+}
+
+/// Let the request continue to the origin.
+pub fn forward() -> Forward {
+    Forward::default()
+}
+
+/// Let the request continue to the origin with its URI replaced by `uri`.
+pub fn rewrite(uri: &str) -> Forward {
+    Forward {
+        uri: Some(uri.to_string()),
+        ..Forward::default()
+    }
+}
+
+/// A Lambda@Edge invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CloudFrontEvent {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<CloudFrontRecord>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CloudFrontRecord {
+    pub cf: Cf,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Cf {
+    pub config: CfConfig,
+    pub request: CfRequest,
+    pub response: Option<CfResponse>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfConfig {
+    pub distribution_domain_name: String,
+    pub distribution_id: String,
+    /// `viewer-request`, `origin-request`, `origin-response` or
+    /// `viewer-response`.
+    pub event_type: String,
+    pub request_id: Option<String>,
+}
+
+/// A header as CloudFront represents it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CfHeader {
+    /// The header name in its original case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub value: String,
+}
+
+/// Headers by lowercase name.
+pub type CfHeaders = BTreeMap<String, Vec<CfHeader>>;
+
+fn set_header(headers: &mut CfHeaders, name: &str, value: &str) {
+    headers.insert(
+        name.to_ascii_lowercase(),
+        vec![CfHeader {
+            key: Some(name.to_string()),
+            value: value.to_string(),
+        }],
+    );
+}
+
+/// The request, as CloudFront passes and expects it back.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfRequest {
+    pub client_ip: String,
+    pub method: String,
+    pub uri: String,
+    #[serde(default)]
+    pub querystring: String,
+    #[serde(default)]
+    pub headers: CfHeaders,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<CfBody>,
+    /// Fields passed back untouched, such as `origin`.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl CfRequest {
+    /// Set the header `name`, replacing any values.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        set_header(&mut self.headers, name, value);
+    }
+}
+
+/// The request body, when the behaviour includes it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfBody {
+    #[serde(default)]
+    pub input_truncated: bool,
+    /// `read-only` or `replace`.
+    pub action: String,
+    /// `base64` or `text`.
+    pub encoding: String,
+    pub data: String,
+}
+
+/// A response, generated at the edge or received from the origin.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CfResponse {
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_description: Option<String>,
+    #[serde(default)]
+    pub headers: CfHeaders,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// `text` or `base64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_encoding: Option<String>,
+}
+
+impl CfResponse {
+    /// Set the header `name`, replacing any values.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        set_header(&mut self.headers, name, value);
+    }
+}
+
+fn parse_query(query: &str) -> QueryMap {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |s: &str| crate::cookie::decode_value(&s.replace('+', " "));
+        params.entry(decode(k)).or_default().push(decode(v));
+    }
+    QueryMap::from(params)
+}
+
+/// The API Gateway event equivalent to `request`.
+fn to_apigw_request(request: &CfRequest) -> ApiGatewayProxyRequest {
+    let mut req = ApiGatewayProxyRequest::default();
+    req.http_method = request.method.parse().unwrap_or_default();
+    req.path = Some(request.uri.clone());
+    for (name, values) in &request.headers {
+        let separator = if name == "cookie" { "; " } else { ", " };
+        let joined = values
+            .iter()
+            .map(|h| h.value.as_str())
+            .collect::<Vec<_>>()
+            .join(separator);
+        if let (Ok(name), Ok(value)) = (
+            http::HeaderName::from_bytes(name.as_bytes()),
+            http::HeaderValue::from_str(&joined),
+        ) {
+            req.headers.insert(name, value);
+        }
+    }
+    let query = parse_query(&request.querystring);
+    req.query_string_parameters = query.clone();
+    req.multi_value_query_string_parameters = query;
+    if let Some(body) = &request.body {
+        req.body = Some(body.data.clone());
+        req.is_base64_encoded = body.encoding == "base64";
+    }
+    req.request_context.identity.source_ip = Some(request.client_ip.clone());
+    req
+}
+
+/// The CloudFront response equivalent to `resp`.
+fn to_cf_response(resp: ApiGatewayProxyResponse) -> CfResponse {
+    let mut out = CfResponse {
+        status: resp.status_code.to_string(),
+        ..Default::default()
+    };
+    for (name, value) in resp.headers.iter().chain(resp.multi_value_headers.iter()) {
+        if !settable(name.as_str(), RESPONSE_READ_ONLY) {
+            continue;
+        }
+        if let Ok(value) = value.to_str() {
+            out.headers
+                .entry(name.as_str().to_string())
+                .or_default()
+                .push(CfHeader {
+                    key: None,
+                    value: value.to_string(),
+                });
+        }
+    }
+    match resp.body {
+        Some(Body::Text(text)) => {
+            out.body = Some(text);
+            out.body_encoding = Some("text".to_string());
+        }
+        Some(Body::Binary(bytes)) => {
+            out.body = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+            out.body_encoding = Some("base64".to_string());
+        }
+        _ => {}
+    }
+    out
+}
+
+impl Choko {
+    /// Run `hook` on viewer-response and origin-response events, e.g. to
+    /// add security headers. It receives the request and the response and
+    /// returns the response to send on.
+    pub fn edge_response<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(CfRequest, CfResponse) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<CfResponse, Error>> + Send + 'static,
+    {
+        self.edge_response_hook = Some(Arc::new(move |req, resp| Box::pin(hook(req, resp))));
+        self
+    }
+
+    /// Run the application as a Lambda@Edge function.
+    pub async fn run_edge(self) -> Result<(), Error> {
+        self.serve(|app, event: CloudFrontEvent, context| async move {
+            app.dispatch_edge(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_edge(
+        &self,
+        event: CloudFrontEvent,
+        context: Option<LambdaContext>,
+    ) -> Result<Value, Error> {
+        let cf = event
+            .records
+            .into_iter()
+            .next()
+            .ok_or("CloudFront event without records")?
+            .cf;
+        if let Some(response) = cf.response {
+            let response = match &self.edge_response_hook {
+                Some(hook) => hook(cf.request, response).await?,
+                None => response,
+            };
+            return Ok(serde_json::to_value(response)?);
+        }
+
+        let read_only = match cf.config.event_type.as_str() {
+            "origin-request" => ORIGIN_REQUEST_READ_ONLY,
+            _ => VIEWER_REQUEST_READ_ONLY,
+        };
+        let mut request = cf.request;
+        let method = request.method.to_uppercase();
+        let routed = self.routes.iter().any(|route| {
+            route.methods.contains(&method)
+                && crate::match_path(&route.segments, &request.uri).is_some()
+        });
+        if !routed {
+            return Ok(serde_json::to_value(request)?);
+        }
+        let mut resp = self
+            .dispatch_with_context(to_apigw_request(&request), context)
+            .await?;
+        let Some(forward) = resp.headers.remove(FORWARD_HEADER) else {
+            return Ok(serde_json::to_value(to_cf_response(resp))?);
+        };
+        let forward = forward
+            .to_str()
+            .ok()
+            .and_then(Forward::decode)
+            .ok_or("malformed edge forward marker")?;
+        if let Some(uri) = forward.uri {
+            request.uri = uri;
+        }
+        for (name, value) in &forward.headers {
+            if settable(&name.to_ascii_lowercase(), read_only) {
+                request.set_header(name, value);
+            } else {
+                eprintln!("CloudFront doesn't allow setting the {name} header; not forwarded");
+            }
+        }
+        Ok(serde_json::to_value(request)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request_event(uri: &str, query: &str) -> CloudFrontEvent {
+        serde_json::from_value(json!({
+            "Records": [{
+                "cf": {
+                    "config": {
+                        "distributionDomainName": "d111111abcdef8.cloudfront.net",
+                        "distributionId": "EDFDVBD6EXAMPLE",
+                        "eventType": "viewer-request",
+                        "requestId": "4TyzHTaYWb1GX1qTfsHhEqV6HUDd_BzoBZnwfnvQc_1oF26ClkoUSEQ=="
+                    },
+                    "request": {
+                        "clientIp": "203.0.113.178",
+                        "headers": {
+                            "host": [{ "key": "Host", "value": "d111111abcdef8.cloudfront.net" }],
+                            "cookie": [{ "key": "Cookie", "value": "a=1" }, { "key": "Cookie", "value": "b=2" }]
+                        },
+                        "method": "GET",
+                        "querystring": query,
+                        "uri": uri,
+                        "origin": { "s3": { "domainName": "bucket.s3.amazonaws.com" } }
+                    }
+                }
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn app() -> Choko {
+        let mut app = Choko::new("test");
+        app.get("/admin", |req| async move {
+            let token = req.query_params.get("token").and_then(|v| v.first());
+            if token.map(String::as_str) == Some("secret") {
+                Ok(forward()
+                    .with_header("X-User", "alice")
+                    .with_header("Via", "edge")
+                    .into())
+            } else {
+                Ok(crate::error_json(403, "Forbidden"))
+            }
+        });
+        app.get("/", |req| async move {
+            assert_eq!(req.header("cookie"), Some("a=1; b=2"));
+            Ok(rewrite("/index.html").into())
+        });
+        // Response headers from hooks don't reach the origin request
+        app.after_response(|_req, resp| {
+            resp.headers
+                .insert("X-Cache".to_string(), "MISS".to_string());
+            resp.headers
+                .insert("X-Frame-Options".to_string(), "DENY".to_string());
+        });
+        app.request_id(crate::REQUEST_ID_HEADER);
+        app
+    }
+
+    #[tokio::test]
+    async fn answers_forwards_or_rewrites_requests() {
+        let app = app();
+        let out = app
+            .dispatch_edge(request_event("/admin", "token=nope"), None)
+            .await
+            .unwrap();
+        assert_eq!(out["status"], "403");
+        assert_eq!(
+            out["headers"]["content-type"][0]["value"],
+            "application/json"
+        );
+        assert_eq!(out["headers"]["x-frame-options"][0]["value"], "DENY");
+        assert!(out["headers"].get("x-cache").is_none());
+
+        let out = app
+            .dispatch_edge(request_event("/admin", "token=secret"), None)
+            .await
+            .unwrap();
+        assert_eq!(out["uri"], "/admin");
+        assert_eq!(out["headers"]["x-user"][0]["value"], "alice");
+        assert_eq!(out["origin"]["s3"]["domainName"], "bucket.s3.amazonaws.com");
+        let headers: Vec<&String> = out["headers"].as_object().unwrap().keys().collect();
+        assert_eq!(headers, ["cookie", "host", "x-user"]);
+
+        let out = app
+            .dispatch_edge(request_event("/", ""), None)
+            .await
+            .unwrap();
+        assert_eq!(out["uri"], "/index.html");
+
+        let out = app
+            .dispatch_edge(request_event("/static/app.js", ""), None)
+            .await
+            .unwrap();
+        assert_eq!(out["uri"], "/static/app.js");
+    }
+
+    #[tokio::test]
+    async fn response_events_go_to_the_hook() {
+        let mut app = Choko::new("test");
+        app.edge_response(|_req, mut resp| async move {
+            resp.set_header("Strict-Transport-Security", "max-age=63072000");
+            Ok(resp)
+        });
+        let mut event = request_event("/", "");
+        event.records[0].cf.response = Some(CfResponse {
+            status: "200".to_string(),
+            ..Default::default()
+        });
+        let out = app.dispatch_edge(event, None).await.unwrap();
+        assert_eq!(out["status"], "200");
+        assert_eq!(
+            out["headers"]["strict-transport-security"][0]["value"],
+            "max-age=63072000"
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_only_headers_cloudfront_allows() {
+        let mut app = Choko::new("test");
+        app.get("/", |_req| async move {
+            Ok(forward()
+                .with_header("X-User", "alice")
+                .with_header("Connection", "close")
+                .with_header("X-Amz-Cf-Pop", "NRT57")
+                .with_header("X-Edge-Location", "NRT")
+                .with_header("Accept-Encoding", "identity")
+                .with_header("Host", "origin.example.com")
+                .into())
+        });
+
+        // Host is read-only for viewer requests, Accept-Encoding for origin
+        // requests
+        let out = app
+            .dispatch_edge(request_event("/", ""), None)
+            .await
+            .unwrap();
+        let headers: Vec<&String> = out["headers"].as_object().unwrap().keys().collect();
+        assert_eq!(headers, ["accept-encoding", "cookie", "host", "x-user"]);
+        assert_eq!(
+            out["headers"]["host"][0]["value"],
+            "d111111abcdef8.cloudfront.net"
+        );
+
+        let mut event = request_event("/", "");
+        event.records[0].cf.config.event_type = "origin-request".to_string();
+        let out = app.dispatch_edge(event, None).await.unwrap();
+        let headers: Vec<&String> = out["headers"].as_object().unwrap().keys().collect();
+        assert_eq!(headers, ["cookie", "host", "x-user"]);
+        assert_eq!(out["headers"]["host"][0]["value"], "origin.example.com");
+    }
+
+    #[tokio::test]
+    async fn binary_bodies_round_trip_as_base64() {
+        let mut app = Choko::new("test");
+        app.post("/upload", |req| async move {
+            let mut bytes = req.body_bytes().unwrap_or_default().to_vec();
+            bytes.reverse();
+            Ok(Response::binary(bytes, "application/octet-stream"))
+        });
+        let mut event = request_event("/upload", "");
+        let request = &mut event.records[0].cf.request;
+        request.method = "POST".to_string();
+        request.body = Some(CfBody {
+            action: "read-only".to_string(),
+            encoding: "base64".to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode([0, 159, 146, 150]),
+            ..Default::default()
+        });
+
+        let out = app.dispatch_edge(event, None).await.unwrap();
+        assert_eq!(out["status"], "200");
+        assert_eq!(out["bodyEncoding"], "base64");
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(out["body"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(body, [150, 146, 159, 0]);
+    }
+
+    #[tokio::test]
+    async fn unrouted_requests_pass_through_unchanged() {
+        let mut app = Choko::new("test");
+        app.get("/admin", |_req| async move {
+            Ok(crate::error_json(404, "No such page"))
+        });
+
+        for (method, uri) in [("GET", "/missing"), ("POST", "/admin")] {
+            let mut event = request_event(uri, "a=1");
+            event.records[0].cf.request.method = method.to_string();
+            let original = serde_json::to_value(&event.records[0].cf.request).unwrap();
+            let out = app.dispatch_edge(event, None).await.unwrap();
+            assert_eq!(out, original);
+        }
+
+        // A route's own 404 is an answer to the viewer
+        let out = app
+            .dispatch_edge(request_event("/admin", ""), None)
+            .await
+            .unwrap();
+        assert_eq!(out["status"], "404");
+        assert!(out.get("uri").is_none());
+    }
+}
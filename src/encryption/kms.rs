@@ -0,0 +1,123 @@
+//! Data keys from AWS KMS (`kms` feature).
+
+use super::{DataKey, KeyProvider};
+use crate::{BoxFuture, Error};
+use aws_sdk_kms::primitives::Blob;
+use aws_sdk_kms::types::DataKeySpec;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a generated data key is reused for new ciphertexts.
+const DATA_KEY_TTL: Duration = Duration::from_secs(300);
+
+/// Unwrapped keys kept per container before the cache is reset.
+const MAX_CACHED_KEYS: usize = 1_000;
+
+#[derive(Default)]
+struct Cache {
+    current: Option<(Instant, DataKey)>,
+    unwrapped: HashMap<Vec<u8>, [u8; 32]>,
+}
+
+/// A KMS key as master key.
+///
+/// Data keys come from `GenerateDataKey` and are reused for five minutes;
+/// unwrapped keys are cached in the container, so warm invocations rarely
+/// call KMS.
+#[derive(Clone)]
+pub struct KmsKey {
+    client: aws_sdk_kms::Client,
+    key_id: String,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl KmsKey {
+    /// Use the KMS key `key_id` (ID, ARN or alias).
+    pub fn new(client: aws_sdk_kms::Client, key_id: impl Into<String>) -> Self {
+        Self {
+            client,
+            key_id: key_id.into(),
+            cache: Arc::default(),
+        }
+    }
+
+    fn cached_data_key(&self) -> Option<DataKey> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .current
+            .as_ref()
+            .filter(|(created, _)| created.elapsed() < DATA_KEY_TTL)
+            .map(|(_, key)| key.clone())
+    }
+
+    fn remember(&self, wrapped: Vec<u8>, plaintext: [u8; 32]) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.unwrapped.len() >= MAX_CACHED_KEYS {
+            cache.unwrapped.clear();
+        }
+        cache.unwrapped.insert(wrapped, plaintext);
+    }
+}
+
+fn to_key(blob: Option<&Blob>) -> Result<[u8; 32], Error> {
+    let bytes = blob.ok_or("KMS returned no plaintext key")?.as_ref();
+    Ok(<[u8; 32]>::try_from(bytes).map_err(|_| "KMS data key must be 32 bytes")?)
+}
+
+impl KeyProvider for KmsKey {
+    fn data_key(&self) -> BoxFuture<Result<DataKey, Error>> {
+        let this = self.clone();
+        Box::pin(async move {
+            if let Some(key) = this.cached_data_key() {
+                return Ok(key);
+            }
+            let output = this
+                .client
+                .generate_data_key()
+                .key_id(&this.key_id)
+                .key_spec(DataKeySpec::Aes256)
+                .send()
+                .await?;
+            let key = DataKey {
+                plaintext: to_key(output.plaintext())?,
+                wrapped: output
+                    .ciphertext_blob()
+                    .ok_or("KMS returned no wrapped key")?
+                    .as_ref()
+                    .to_vec(),
+            };
+            this.remember(key.wrapped.clone(), key.plaintext);
+            this.cache.lock().unwrap_or_else(|e| e.into_inner()).current =
+                Some((Instant::now(), key.clone()));
+            Ok(key)
+        })
+    }
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> BoxFuture<Result<[u8; 32], Error>> {
+        let this = self.clone();
+        let wrapped = wrapped.to_vec();
+        Box::pin(async move {
+            let cached = this
+                .cache
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrapped
+                .get(&wrapped)
+                .copied();
+            if let Some(key) = cached {
+                return Ok(key);
+            }
+            let output = this
+                .client
+                .decrypt()
+                .key_id(&this.key_id)
+                .ciphertext_blob(Blob::new(wrapped.clone()))
+                .send()
+                .await?;
+            let key = to_key(output.plaintext())?;
+            this.remember(wrapped, key);
+            Ok(key)
+        })
+    }
+}
@@ -0,0 +1,386 @@
+//! Field-level envelope encryption for JSON bodies (`field-encryption`
+//! feature).
+//!
+//! [`FieldEncryptor`] encrypts selected fields of a JSON document with
+//! AES-256-GCM under a data key from a [`KeyProvider`]: a local master key
+//! ([`LocalKey`]) or AWS KMS (`kms` feature). Each encrypted field becomes a
+//! self-describing string carrying its wrapped data key, so any holder of the
+//! master key can decrypt it later. [`FieldEncryption`] applies this per
+//! route: designated request fields are encrypted before the handler sees
+//! them, so handlers store and log only ciphertext, and the same fields are
+//! decrypted in JSON responses.
+//!
+//! # Example
+//! ```ignore
+//! use choko::encryption::{FieldEncryption, FieldEncryptor, LocalKey};
+//!
+//! let encryptor = FieldEncryptor::new(LocalKey::from_base64(&master_key)?);
+//! app.post("/customers", create_customer)
+//!     .middleware(FieldEncryption::new(encryptor, &["ssn", "contact.email"]));
+//! ```
+
+#[cfg(feature = "kms")]
+mod kms;
+
+#[cfg(feature = "kms")]
+pub use kms::KmsKey;
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, ResponseBody};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prefix of encrypted field values.
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const NONCE_LEN: usize = 12;
+
+/// A data key: the AES-256 key used for fields and its wrapped
+/// (master-key-encrypted) form stored alongside them.
+#[derive(Clone)]
+pub struct DataKey {
+    pub plaintext: [u8; 32],
+    pub wrapped: Vec<u8>,
+}
+
+/// Issues and unwraps data keys under a master key.
+pub trait KeyProvider: Send + Sync + 'static {
+    /// A data key for new ciphertexts.
+    fn data_key(&self) -> BoxFuture<Result<DataKey, Error>>;
+
+    /// Recover the plaintext of a wrapped data key.
+    fn unwrap_key(&self, wrapped: &[u8]) -> BoxFuture<Result<[u8; 32], Error>>;
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).expect("OS random source is available");
+    bytes
+}
+
+/// AES-256-GCM `plaintext` under `key`, returned as `nonce || ciphertext`.
+fn seal(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = random_bytes::<NONCE_LEN>();
+    let mut sealed = nonce.to_vec();
+    sealed.extend(
+        cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| "encryption failed")?,
+    );
+    Ok(sealed)
+}
+
+/// The inverse of [`seal`].
+fn open(key: &[u8; 32], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    if sealed.len() < NONCE_LEN {
+        return Err("ciphertext is truncated".into());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    Ok(cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| "decryption failed: wrong key or tampered ciphertext")?)
+}
+
+/// A master key held by the application (e.g. loaded from Secrets Manager).
+///
+/// Each [`data_key`](KeyProvider::data_key) call generates a fresh random
+/// data key wrapped with AES-256-GCM under the master key.
+pub struct LocalKey {
+    master: [u8; 32],
+}
+
+impl LocalKey {
+    /// Use a 32-byte master key.
+    pub fn new(master: [u8; 32]) -> Self {
+        Self { master }
+    }
+
+    /// Use a base64-encoded 32-byte master key.
+    pub fn from_base64(master: &str) -> Result<Self, Error> {
+        let bytes = STANDARD.decode(master.trim())?;
+        let master: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| "master key must be 32 bytes")?;
+        Ok(Self::new(master))
+    }
+}
+
+impl KeyProvider for LocalKey {
+    fn data_key(&self) -> BoxFuture<Result<DataKey, Error>> {
+        let plaintext = random_bytes::<32>();
+        let key = seal(&self.master, &plaintext, b"data-key")
+            .map(|wrapped| DataKey { plaintext, wrapped });
+        Box::pin(async move { key })
+    }
+
+    fn unwrap_key(&self, wrapped: &[u8]) -> BoxFuture<Result<[u8; 32], Error>> {
+        let key = open(&self.master, wrapped, b"data-key").and_then(|bytes| {
+            <[u8; 32]>::try_from(bytes).map_err(|_| Error::from("data key must be 32 bytes"))
+        });
+        Box::pin(async move { key })
+    }
+}
+
+/// A decoded encrypted field: `(wrapped data key, nonce || ciphertext)`.
+fn parse_field(value: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(value.strip_prefix(ENCRYPTED_PREFIX)?)
+        .ok()?;
+    let len = usize::from(u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]));
+    let rest = bytes.get(2..)?;
+    if rest.len() < len {
+        return None;
+    }
+    let (wrapped, sealed) = rest.split_at(len);
+    Some((wrapped.to_vec(), sealed.to_vec()))
+}
+
+/// Call `f` on every value at `path` (dotted, with `*` matching every
+/// array element or object member).
+fn visit<F>(value: &mut Value, path: &[&str], f: &mut F) -> Result<(), Error>
+where
+    F: FnMut(&mut Value) -> Result<(), Error>,
+{
+    let Some((head, rest)) = path.split_first() else {
+        return f(value);
+    };
+    match (value, *head) {
+        (Value::Array(items), "*") => items.iter_mut().try_for_each(|v| visit(v, rest, f)),
+        (Value::Object(map), "*") => map.values_mut().try_for_each(|v| visit(v, rest, f)),
+        (Value::Array(items), index) => {
+            match index.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                Some(v) => visit(v, rest, f),
+                None => Ok(()),
+            }
+        }
+        (Value::Object(map), key) => match map.get_mut(key) {
+            Some(v) => visit(v, rest, f),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Encrypts and decrypts designated JSON fields.
+///
+/// Paths are dotted (`contact.email`) and may use `*` for every array
+/// element or object member (`cards.*.number`). Field values of any JSON
+/// type are encrypted as a whole and restored with their original type. The
+/// path is bound to the ciphertext as associated data, so a value can't be
+/// moved to another field. Cheap to clone.
+#[derive(Clone)]
+pub struct FieldEncryptor {
+    provider: Arc<dyn KeyProvider>,
+}
+
+impl FieldEncryptor {
+    /// Encrypt under keys from `provider`.
+    pub fn new(provider: impl KeyProvider) -> Self {
+        Self {
+            provider: Arc::new(provider),
+        }
+    }
+
+    /// Encrypt the fields at `paths` in place. Missing fields, `null`s and
+    /// already encrypted values are left alone.
+    pub async fn encrypt(&self, value: &mut Value, paths: &[&str]) -> Result<(), Error> {
+        let key = self.provider.data_key().await?;
+        let len = u16::try_from(key.wrapped.len()).map_err(|_| "wrapped data key is too long")?;
+        for path in paths {
+            let segments: Vec<&str> = path.split('.').collect();
+            visit(value, &segments, &mut |field| {
+                if field.is_null() || is_encrypted(field) {
+                    return Ok(());
+                }
+                let sealed = seal(&key.plaintext, &serde_json::to_vec(field)?, path.as_bytes())?;
+                let mut bytes = len.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&key.wrapped);
+                bytes.extend(sealed);
+                *field = Value::String(format!(
+                    "{ENCRYPTED_PREFIX}{}",
+                    URL_SAFE_NO_PAD.encode(bytes)
+                ));
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Decrypt the fields at `paths` in place. Values that aren't encrypted
+    /// are left alone.
+    pub async fn decrypt(&self, value: &mut Value, paths: &[&str]) -> Result<(), Error> {
+        // Unwrap each distinct data key once
+        let mut keys: HashMap<Vec<u8>, [u8; 32]> = HashMap::new();
+        let mut wrapped_keys = Vec::new();
+        for path in paths {
+            let segments: Vec<&str> = path.split('.').collect();
+            visit(value, &segments, &mut |field| {
+                if let Some((wrapped, _)) = field.as_str().and_then(parse_field) {
+                    wrapped_keys.push(wrapped);
+                }
+                Ok(())
+            })?;
+        }
+        for wrapped in wrapped_keys {
+            if let Entry::Vacant(entry) = keys.entry(wrapped) {
+                let key = self.provider.unwrap_key(entry.key()).await?;
+                entry.insert(key);
+            }
+        }
+
+        for path in paths {
+            let segments: Vec<&str> = path.split('.').collect();
+            visit(value, &segments, &mut |field| {
+                let Some((wrapped, sealed)) = field.as_str().and_then(parse_field) else {
+                    return Ok(());
+                };
+                let plaintext = open(&keys[&wrapped], &sealed, path.as_bytes())?;
+                *field = serde_json::from_slice(&plaintext)?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `value` is a field encrypted by [`FieldEncryptor`].
+pub fn is_encrypted(value: &Value) -> bool {
+    value
+        .as_str()
+        .is_some_and(|s| s.starts_with(ENCRYPTED_PREFIX))
+}
+
+/// Middleware encrypting designated fields of JSON request bodies before
+/// the handler runs, and decrypting them in JSON responses.
+pub struct FieldEncryption {
+    encryptor: FieldEncryptor,
+    paths: Arc<Vec<String>>,
+    decrypt_responses: bool,
+}
+
+impl FieldEncryption {
+    /// Protect the fields at `paths` (see [`FieldEncryptor`] for the
+    /// syntax).
+    pub fn new(encryptor: FieldEncryptor, paths: &[&str]) -> Self {
+        Self {
+            encryptor,
+            paths: Arc::new(paths.iter().map(|p| p.to_string()).collect()),
+            decrypt_responses: true,
+        }
+    }
+
+    /// Whether to decrypt the fields in responses. Disable to hand
+    /// ciphertext back to clients.
+    pub fn decrypt_responses(mut self, enabled: bool) -> Self {
+        self.decrypt_responses = enabled;
+        self
+    }
+}
+
+impl Middleware for FieldEncryption {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let encryptor = self.encryptor.clone();
+        let paths = Arc::clone(&self.paths);
+        let decrypt_responses = self.decrypt_responses;
+        Box::pin(async move {
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            if let Some(mut body) = req.json_body.take() {
+                encryptor.encrypt(&mut body, &paths).await?;
+                req.body = Some(body.to_string());
+                req.json_body = Some(body);
+            }
+            let mut resp = next.run(req).await?;
+            if decrypt_responses {
+                if let ResponseBody::Json(body) = &mut resp.body {
+                    encryptor.decrypt(body, &paths).await?;
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use serde_json::json;
+
+    fn encryptor() -> FieldEncryptor {
+        FieldEncryptor::new(LocalKey::new([7; 32]))
+    }
+
+    #[tokio::test]
+    async fn round_trips_nested_and_wildcard_fields() {
+        let original = json!({
+            "name": "Ann",
+            "contact": { "email": "ann@example.com" },
+            "cards": [{ "number": "4111", "exp": "12/30" }, { "number": 4242 }],
+            "ssn": null,
+        });
+        let paths = ["contact.email", "cards.*.number", "ssn"];
+        let mut doc = original.clone();
+        encryptor().encrypt(&mut doc, &paths).await.unwrap();
+
+        assert!(is_encrypted(&doc["contact"]["email"]));
+        assert!(is_encrypted(&doc["cards"][1]["number"]));
+        assert_eq!(doc["cards"][0]["exp"], "12/30");
+        assert_eq!(doc["ssn"], Value::Null);
+        assert!(!doc.to_string().contains("ann@example.com"));
+
+        encryptor().decrypt(&mut doc, &paths).await.unwrap();
+        assert_eq!(doc, original);
+    }
+
+    #[tokio::test]
+    async fn ciphertext_is_bound_to_key_and_path() {
+        let mut doc = json!({ "a": "secret", "b": null });
+        encryptor().encrypt(&mut doc, &["a"]).await.unwrap();
+
+        let other = FieldEncryptor::new(LocalKey::new([8; 32]));
+        assert!(other.decrypt(&mut doc.clone(), &["a"]).await.is_err());
+
+        doc["b"] = doc["a"].clone();
+        assert!(encryptor().decrypt(&mut doc, &["b"]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn middleware_encrypts_requests_and_decrypts_responses() {
+        let mw = FieldEncryption::new(encryptor(), &["ssn"]);
+        let handler = |req: Request| async move {
+            let body = req.json_body.unwrap();
+            assert!(is_encrypted(&body["ssn"]));
+            assert!(!req.body.unwrap().contains("123-45-6789"));
+            Ok(Response::json(body))
+        };
+        let req = Request {
+            json_body: Some(json!({ "ssn": "123-45-6789" })),
+            ..Default::default()
+        };
+        let resp = run_middleware(mw, handler, req).await.unwrap();
+        assert_eq!(
+            resp.body,
+            ResponseBody::Json(json!({ "ssn": "123-45-6789" }))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Status-aware handler errors.
+
+use crate::Error;
+use std::fmt;
+
+/// An error that carries the HTTP status it should be answered with.
+///
+/// Returning it from a handler (e.g. via `?`) produces a response with the
+/// matching status and the error's message. [`ChokoError::Internal`] is the
+/// exception: its source is logged and clients only see a generic 500.
+///
+/// # Example
+/// ```ignore
+/// app.get("/users/{id}", |req| async move {
+///     let user = find_user(&req.path_params["id"])
+///         .await
+///         .map_err(ChokoError::internal)?
+///         .ok_or_else(|| ChokoError::not_found("user"))?;
+///     Ok(Response::json(json!(user)))
+/// });
+/// ```
+#[derive(Debug)]
+pub enum ChokoError {
+    /// 400 with a message describing what was wrong with the request.
+    BadRequest(String),
+    /// 401 with a message.
+    Unauthorized(String),
+    /// 404 naming the missing resource.
+    NotFound(String),
+    /// 500; the source is logged but never sent to the client.
+    Internal(Error),
+    /// Any other status with a message.
+    Status(u16, String),
+}
+
+impl ChokoError {
+    /// A 400 error.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ChokoError::BadRequest(message.into())
+    }
+
+    /// A 401 error.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        ChokoError::Unauthorized(message.into())
+    }
+
+    /// A 404 error for `resource`, rendered as "`resource` not found".
+    pub fn not_found(resource: impl Into<String>) -> Self {
+        ChokoError::NotFound(resource.into())
+    }
+
+    /// A 500 error wrapping `source`.
+    pub fn internal(source: impl Into<Error>) -> Self {
+        ChokoError::Internal(source.into())
+    }
+
+    /// An error with an arbitrary status.
+    pub fn status(status: u16, message: impl Into<String>) -> Self {
+        ChokoError::Status(status, message.into())
+    }
+
+    /// The HTTP status this error maps to.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ChokoError::BadRequest(_) => 400,
+            ChokoError::Unauthorized(_) => 401,
+            ChokoError::NotFound(_) => 404,
+            ChokoError::Internal(_) => 500,
+            ChokoError::Status(status, _) => *status,
+        }
+    }
+
+    /// The message sent to the client.
+    pub(crate) fn public_message(&self) -> String {
+        match self {
+            ChokoError::Internal(_) => "Internal Server Error".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ChokoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChokoError::BadRequest(msg)
+            | ChokoError::Unauthorized(msg)
+            | ChokoError::Status(_, msg) => f.write_str(msg),
+            ChokoError::NotFound(resource) => write!(f, "{resource} not found"),
+            ChokoError::Internal(source) => write!(f, "internal error: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ChokoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChokoError::Internal(source) => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(ChokoError::bad_request("x").status_code(), 400);
+        assert_eq!(ChokoError::unauthorized("x").status_code(), 401);
+        assert_eq!(ChokoError::not_found("x").status_code(), 404);
+        assert_eq!(ChokoError::internal("boom").status_code(), 500);
+        assert_eq!(ChokoError::status(409, "conflict").status_code(), 409);
+    }
+
+    #[test]
+    fn internal_message_is_not_exposed() {
+        let err = ChokoError::internal("db password rejected");
+        assert_eq!(err.public_message(), "Internal Server Error");
+        assert!(err.to_string().contains("db password rejected"));
+        assert_eq!(
+            ChokoError::not_found("user").public_message(),
+            "user not found"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Publishing domain events to EventBridge (`eventbridge` feature).
+//!
+//! [`EventPublisher`] puts events on one bus under one source, e.g.
+//! `shop.orders`. Detail types name what happened in PascalCase
+//! (`OrderPlaced`). Each event's detail wraps the payload with metadata:
+//!
+//! ```json
+//! {
+//!   "metadata": { "traceparent": "00-...", "request_id": "..." },
+//!   "data": { "order_id": "o-1" }
+//! }
+//! ```
+//!
+//! When published with a request, its W3C trace context and request ID are
+//! filled in, so consumers can continue the trace.
+//!
+//! # Example
+//! ```ignore
+//! use choko::eventbridge::EventPublisher;
+//!
+//! let client = aws_sdk_eventbridge::Client::new(&aws_config::load_from_env().await);
+//! app.state(EventPublisher::new(client, "shop", "shop.orders"));
+//!
+//! app.post("/orders", |req| async move {
+//!     let order = place_order(&req).await?;
+//!     let events = req.state::<EventPublisher>().ok_or("events not configured")?;
+//!     events.publish(&req, "OrderPlaced", &order).await?;
+//!     Ok(Response::created(format!("/orders/{}", order.id), json!(order)))
+//! });
+//! ```
+
+use crate::{Error, Request};
+use aws_sdk_eventbridge::types::PutEventsRequestEntry;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// EventBridge's limit on entries per `PutEvents` request.
+const BATCH_SIZE: usize = 10;
+
+/// An event to publish with [`EventPublisher::publish_all`].
+#[derive(Debug, Clone)]
+pub struct Event {
+    detail_type: String,
+    data: Value,
+    resources: Vec<String>,
+}
+
+impl Event {
+    /// An event of type `detail_type` carrying `data`.
+    pub fn new(detail_type: impl Into<String>, data: &impl Serialize) -> Result<Self, Error> {
+        Ok(Self {
+            detail_type: detail_type.into(),
+            data: serde_json::to_value(data)?,
+            resources: Vec::new(),
+        })
+    }
+
+    /// An ARN the event concerns.
+    pub fn resource(mut self, arn: impl Into<String>) -> Self {
+        self.resources.push(arn.into());
+        self
+    }
+}
+
+/// Publishes events to one bus under one source. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct EventPublisher {
+    client: aws_sdk_eventbridge::Client,
+    bus: String,
+    source: String,
+}
+
+impl EventPublisher {
+    /// Publish to `bus` (a name or ARN) as `source`.
+    ///
+    /// # Panics
+    /// Panics if `source` is empty or starts with `aws.`, which is reserved
+    /// for AWS services.
+    pub fn new(
+        client: aws_sdk_eventbridge::Client,
+        bus: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Self {
+        let source = source.into();
+        assert!(
+            !source.is_empty() && !source.starts_with("aws."),
+            "invalid event source {source:?}"
+        );
+        Self {
+            client,
+            bus: bus.into(),
+            source,
+        }
+    }
+
+    /// Publish one event in the context of `req`.
+    pub async fn publish(
+        &self,
+        req: &Request,
+        detail_type: &str,
+        data: &impl Serialize,
+    ) -> Result<(), Error> {
+        self.publish_all(Some(req), vec![Event::new(detail_type, data)?])
+            .await
+    }
+
+    /// Publish `events`, ten per request. `req`, if any, supplies the trace
+    /// context and request ID. Fails if EventBridge rejects any entry.
+    pub async fn publish_all(
+        &self,
+        req: Option<&Request>,
+        events: Vec<Event>,
+    ) -> Result<(), Error> {
+        let metadata = metadata(req);
+        let entries = events
+            .into_iter()
+            .map(|event| self.entry(&metadata, event))
+            .collect::<Result<Vec<_>, _>>()?;
+        for chunk in entries.chunks(BATCH_SIZE) {
+            let output = self
+                .client
+                .put_events()
+                .set_entries(Some(chunk.to_vec()))
+                .send()
+                .await?;
+            if output.failed_entry_count() > 0 {
+                let reasons: Vec<&str> = output
+                    .entries()
+                    .iter()
+                    .filter_map(|e| e.error_message())
+                    .collect();
+                return Err(format!(
+                    "EventBridge rejected {} event(s): {}",
+                    output.failed_entry_count(),
+                    reasons.join("; ")
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(
+        &self,
+        metadata: &Map<String, Value>,
+        event: Event,
+    ) -> Result<PutEventsRequestEntry, Error> {
+        if event.detail_type.is_empty() {
+            return Err("events need a detail type".into());
+        }
+        let detail = json!({ "metadata": metadata, "data": event.data });
+        Ok(PutEventsRequestEntry::builder()
+            .event_bus_name(&self.bus)
+            .source(&self.source)
+            .detail_type(event.detail_type)
+            .detail(detail.to_string())
+            .set_resources((!event.resources.is_empty()).then_some(event.resources))
+            .build())
+    }
+}
+
+/// The metadata recorded with events published for `req`.
+fn metadata(req: Option<&Request>) -> Map<String, Value> {
+    let mut metadata = Map::new();
+    let Some(req) = req else {
+        return metadata;
+    };
+    if let Some(trace) = req.trace_context() {
+        for (name, value) in trace.headers() {
+            metadata.insert(name.to_string(), Value::String(value));
+        }
+    }
+    if let Some(id) = req.request_id() {
+        metadata.insert("request_id".to_string(), Value::String(id.to_string()));
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace_context::TraceContext;
+
+    fn publisher() -> EventPublisher {
+        let config = aws_sdk_eventbridge::Config::builder()
+            .behavior_version(aws_sdk_eventbridge::config::BehaviorVersion::latest())
+            .build();
+        EventPublisher::new(
+            aws_sdk_eventbridge::Client::from_conf(config),
+            "shop",
+            "shop.orders",
+        )
+    }
+
+    #[test]
+    fn wraps_data_with_trace_metadata() {
+        let mut req = Request::default();
+        req.extensions_mut()
+            .insert(TraceContext::from_headers(|name| {
+                (name == "traceparent")
+                    .then_some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+            }));
+        let event = Event::new("OrderPlaced", &json!({ "order_id": "o-1" }))
+            .unwrap()
+            .resource("arn:aws:dynamodb:eu-west-1:123456789012:table/orders");
+        let entry = publisher().entry(&metadata(Some(&req)), event).unwrap();
+
+        assert_eq!(entry.source(), Some("shop.orders"));
+        assert_eq!(entry.event_bus_name(), Some("shop"));
+        assert_eq!(entry.detail_type(), Some("OrderPlaced"));
+        assert_eq!(entry.resources().len(), 1);
+        let detail: Value = serde_json::from_str(entry.detail().unwrap()).unwrap();
+        assert_eq!(detail["data"], json!({ "order_id": "o-1" }));
+        assert!(detail["metadata"]["traceparent"]
+            .as_str()
+            .unwrap()
+            .starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+    }
+
+    #[test]
+    fn rejects_events_without_a_type() {
+        let event = Event::new("", &json!({})).unwrap();
+        assert!(publisher().entry(&Map::new(), event).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid event source")]
+    fn rejects_reserved_sources() {
+        let config = aws_sdk_eventbridge::Config::builder()
+            .behavior_version(aws_sdk_eventbridge::config::BehaviorVersion::latest())
+            .build();
+        EventPublisher::new(
+            aws_sdk_eventbridge::Client::from_conf(config),
+            "default",
+            "aws.s3",
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Routing an invocation to the subsystem its event is for.
+//!
+//! [`Choko::run`] accepts any event: it recognizes the payload's shape and
+//! hands it to the HTTP router, the WebSocket routes or the handlers of an
+//! event source feature, so one function can serve several triggers.
+//! Other event types can be routed through an [`EventAdapter`].
+
+use crate::{Choko, Error, LambdaContext};
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Translates events choko doesn't know into requests for the routes, the
+/// way the ALB and Function URL support does.
+///
+/// Requests go through the same pipeline as API Gateway ones: middleware,
+/// routing, error handling and logging all apply. Register adapters with
+/// [`Choko::event_adapter`]; they are consulted, in registration order,
+/// before the built-in event types.
+///
+/// # Example
+/// ```ignore
+/// /// Direct invocations like `{"action": "get_user", "id": "42"}`.
+/// struct Rpc;
+///
+/// impl EventAdapter for Rpc {
+///     fn matches(&self, event: &Value) -> bool {
+///         event.get("action").is_some()
+///     }
+///
+///     fn to_request(&self, event: Value) -> Result<ApiGatewayProxyRequest, Error> {
+///         let mut req = ApiGatewayProxyRequest::default();
+///         req.http_method = http::Method::POST;
+///         req.path = Some(format!("/rpc/{}", event["action"].as_str().unwrap_or_default()));
+///         req.body = Some(event.to_string());
+///         Ok(req)
+///     }
+/// }
+///
+/// app.event_adapter(Rpc);
+/// ```
+pub trait EventAdapter: Send + Sync + 'static {
+    /// Whether this adapter handles `event`.
+    fn matches(&self, event: &Value) -> bool;
+
+    /// The API Gateway request equivalent to `event`.
+    fn to_request(&self, event: Value) -> Result<ApiGatewayProxyRequest, Error>;
+
+    /// What to return to the invoker for `response`. Defaults to the API
+    /// Gateway response itself.
+    fn to_response(&self, response: ApiGatewayProxyResponse) -> Result<Value, Error> {
+        Ok(serde_json::to_value(response)?)
+    }
+}
+
+/// The kind of event an invocation carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    /// API Gateway REST API (proxy integration).
+    Http,
+    Alb,
+    /// Lambda Function URL, or API Gateway HTTP API (payload 2.0).
+    FunctionUrl,
+    WebSocket,
+    /// CloudFront Lambda@Edge.
+    Edge,
+    Sqs,
+    Sns,
+    DynamoDb,
+    Kinesis,
+    Kafka,
+    S3,
+    Ses,
+    Schedule,
+    Cognito,
+}
+
+impl EventKind {
+    /// Recognize `event` by the fields that set each shape apart.
+    pub(crate) fn detect(event: &Value) -> Option<Self> {
+        if let Some(records) = event.get("Records").and_then(Value::as_array) {
+            let record = records.first()?;
+            if record.get("cf").is_some() {
+                return Some(Self::Edge);
+            }
+            let source = record
+                .get("eventSource")
+                .or_else(|| record.get("EventSource"))
+                .and_then(Value::as_str)?;
+            return match source {
+                "aws:sqs" => Some(Self::Sqs),
+                "aws:sns" => Some(Self::Sns),
+                "aws:dynamodb" => Some(Self::DynamoDb),
+                "aws:kinesis" => Some(Self::Kinesis),
+                "aws:s3" => Some(Self::S3),
+                "aws:ses" => Some(Self::Ses),
+                _ => None,
+            };
+        }
+        if matches!(
+            event.get("eventSource").and_then(Value::as_str),
+            Some("aws:kafka" | "SelfManagedKafka")
+        ) {
+            return Some(Self::Kafka);
+        }
+        if let Some(context) = event.get("requestContext") {
+            if context.get("elb").is_some() {
+                return Some(Self::Alb);
+            }
+            if context.get("connectionId").is_some() {
+                return Some(Self::WebSocket);
+            }
+            if context.get("http").is_some() {
+                return Some(Self::FunctionUrl);
+            }
+        }
+        if event.get("httpMethod").is_some() {
+            return Some(Self::Http);
+        }
+        if event.get("detail-type").and_then(Value::as_str) == Some("Scheduled Event") {
+            return Some(Self::Schedule);
+        }
+        if event.get("triggerSource").is_some() && event.get("userPoolId").is_some() {
+            return Some(Self::Cognito);
+        }
+        None
+    }
+
+    /// The Cargo feature that handles this kind of event.
+    fn feature(self) -> &'static str {
+        match self {
+            Self::Http | Self::WebSocket => "",
+            Self::Alb => "alb",
+            Self::FunctionUrl => "function-url",
+            Self::Edge => "edge",
+            Self::Sqs => "sqs",
+            Self::Sns => "sns",
+            Self::DynamoDb => "dynamodb-streams",
+            Self::Kinesis => "kinesis",
+            Self::Kafka => "kafka",
+            Self::S3 => "s3-events",
+            Self::Ses => "ses",
+            Self::Schedule => "schedule",
+            Self::Cognito => "cognito-triggers",
+        }
+    }
+}
+
+impl Choko {
+    /// Route events matched by `adapter` through the HTTP pipeline.
+    pub fn event_adapter(&mut self, adapter: impl EventAdapter) -> &mut Self {
+        self.event_adapters.push(Arc::new(adapter));
+        self
+    }
+
+    /// Dispatch `event` to the subsystem for its kind and serialize the
+    /// response that kind of invocation expects.
+    pub(crate) async fn dispatch_event(
+        &self,
+        event: Value,
+        context: Option<LambdaContext>,
+    ) -> Result<Value, Error> {
+        if let Some(adapter) = self.event_adapters.iter().find(|a| a.matches(&event)) {
+            let request = adapter.to_request(event)?;
+            let response = self.dispatch_with_context(request, context).await?;
+            return adapter.to_response(response);
+        }
+        let kind = EventKind::detect(&event).ok_or("unrecognized event payload")?;
+        let response = match kind {
+            EventKind::Http => serde_json::to_value(
+                self.dispatch_with_context(serde_json::from_value(event)?, context)
+                    .await?,
+            )?,
+            EventKind::WebSocket => serde_json::to_value(
+                self.dispatch_ws(serde_json::from_value(event)?, context)
+                    .await?,
+            )?,
+            #[cfg(feature = "alb")]
+            EventKind::Alb => serde_json::to_value(
+                self.dispatch_alb(serde_json::from_value(event)?, context)
+                    .await?,
+            )?,
+            #[cfg(feature = "function-url")]
+            EventKind::FunctionUrl => serde_json::to_value(
+                self.dispatch_function_url(serde_json::from_value(event)?, context)
+                    .await?,
+            )?,
+            #[cfg(feature = "edge")]
+            EventKind::Edge => {
+                self.dispatch_edge(serde_json::from_value(event)?, context)
+                    .await?
+            }
+            #[cfg(feature = "sqs")]
+            EventKind::Sqs => serde_json::to_value(
+                self.dispatch_sqs(serde_json::from_value(event)?, context)
+                    .await,
+            )?,
+            #[cfg(feature = "sns")]
+            EventKind::Sns => {
+                self.dispatch_sns(serde_json::from_value(event)?, context)
+                    .await?;
+                Value::Null
+            }
+            #[cfg(feature = "dynamodb-streams")]
+            EventKind::DynamoDb => serde_json::to_value(
+                self.dispatch_dynamodb(serde_json::from_value(event)?, context)
+                    .await,
+            )?,
+            #[cfg(feature = "kinesis")]
+            EventKind::Kinesis => serde_json::to_value(
+                self.dispatch_kinesis(serde_json::from_value(event)?, context)
+                    .await,
+            )?,
+            #[cfg(feature = "kafka")]
+            EventKind::Kafka => {
+                self.dispatch_kafka(serde_json::from_value(event)?, context)
+                    .await?;
+                Value::Null
+            }
+            #[cfg(feature = "s3-events")]
+            EventKind::S3 => {
+                self.dispatch_s3(serde_json::from_value(event)?, context)
+                    .await?;
+                Value::Null
+            }
+            #[cfg(feature = "ses")]
+            EventKind::Ses => {
+                self.dispatch_ses(serde_json::from_value(event)?, context)
+                    .await?;
+                Value::Null
+            }
+            #[cfg(feature = "schedule")]
+            EventKind::Schedule => {
+                self.dispatch_schedule(serde_json::from_value(event)?, context)
+                    .await?;
+                Value::Null
+            }
+            #[cfg(feature = "cognito-triggers")]
+            EventKind::Cognito => self.dispatch_cognito(event, context).await?,
+            #[allow(unreachable_patterns)]
+            kind => {
+                return Err(format!("{kind:?} events need the `{}` feature", kind.feature()).into())
+            }
+        };
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn detects_event_shapes() {
+        let cases = [
+            (
+                json!({ "httpMethod": "GET", "path": "/", "requestContext": {} }),
+                EventKind::Http,
+            ),
+            (
+                json!({ "httpMethod": "GET", "requestContext": { "elb": {} } }),
+                EventKind::Alb,
+            ),
+            (
+                json!({ "version": "2.0", "rawPath": "/", "requestContext": { "http": {} } }),
+                EventKind::FunctionUrl,
+            ),
+            (
+                json!({ "requestContext": { "connectionId": "abc", "routeKey": "$connect" } }),
+                EventKind::WebSocket,
+            ),
+            (
+                json!({ "Records": [{ "eventSource": "aws:sqs" }] }),
+                EventKind::Sqs,
+            ),
+            (
+                json!({ "Records": [{ "cf": { "config": {}, "request": {} } }] }),
+                EventKind::Edge,
+            ),
+            (
+                json!({ "Records": [{ "EventSource": "aws:sns" }] }),
+                EventKind::Sns,
+            ),
+            (
+                json!({ "Records": [{ "eventSource": "aws:s3" }] }),
+                EventKind::S3,
+            ),
+            (
+                json!({ "eventSource": "SelfManagedKafka", "records": {} }),
+                EventKind::Kafka,
+            ),
+            (
+                json!({ "detail-type": "Scheduled Event", "source": "aws.events" }),
+                EventKind::Schedule,
+            ),
+            (
+                json!({ "triggerSource": "PreSignUp_SignUp", "userPoolId": "p" }),
+                EventKind::Cognito,
+            ),
+        ];
+        for (event, kind) in cases {
+            assert_eq!(EventKind::detect(&event), Some(kind), "{event}");
+        }
+        assert_eq!(EventKind::detect(&json!({ "foo": 1 })), None);
+        assert_eq!(EventKind::detect(&json!({ "Records": [] })), None);
+    }
+
+    #[tokio::test]
+    async fn dispatches_http_events_to_routes() {
+        let mut app = Choko::new("test");
+        app.get("/ping", |_req| async {
+            Ok(crate::Response::json(json!("pong")))
+        });
+        let mut req = aws_lambda_events::event::apigw::ApiGatewayProxyRequest::default();
+        req.http_method = http::Method::GET;
+        req.path = Some("/ping".to_string());
+        let event = serde_json::to_value(req).unwrap();
+        let resp = app.dispatch_event(event, None).await.unwrap();
+        assert_eq!(resp["statusCode"], 200);
+        assert!(app.dispatch_event(json!({}), None).await.is_err());
+    }
+
+    struct Rpc;
+
+    impl EventAdapter for Rpc {
+        fn matches(&self, event: &Value) -> bool {
+            event.get("action").is_some()
+        }
+
+        fn to_request(&self, event: Value) -> Result<ApiGatewayProxyRequest, Error> {
+            let action = event["action"].as_str().ok_or("action must be a string")?;
+            let mut req = ApiGatewayProxyRequest::default();
+            req.http_method = http::Method::POST;
+            req.path = Some(format!("/rpc/{action}"));
+            req.headers.insert(
+                http::header::CONTENT_TYPE,
+                http::HeaderValue::from_static("application/json"),
+            );
+            req.body = Some(event.to_string());
+            Ok(req)
+        }
+
+        fn to_response(&self, response: ApiGatewayProxyResponse) -> Result<Value, Error> {
+            Ok(json!({ "status": response.status_code }))
+        }
+    }
+
+    #[tokio::test]
+    async fn adapters_route_custom_events() {
+        let mut app = Choko::new("test");
+        app.event_adapter(Rpc);
+        app.post("/rpc/echo", |req| async move {
+            let id = req.json_body.as_ref().map(|b| b["id"].clone());
+            Ok(crate::Response::json(json!({ "id": id })).with_status(202))
+        });
+
+        let out = app
+            .dispatch_event(json!({ "action": "echo", "id": 7 }), None)
+            .await
+            .unwrap();
+        assert_eq!(out, json!({ "status": 202 }));
+        let out = app
+            .dispatch_event(json!({ "action": "missing" }), None)
+            .await
+            .unwrap();
+        assert_eq!(out, json!({ "status": 404 }));
+        assert!(app
+            .dispatch_event(json!({ "action": 1 }), None)
+            .await
+            .is_err());
+    }
+}
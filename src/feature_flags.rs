@@ -0,0 +1,300 @@
+//! Feature flags from AWS AppConfig (`appconfig` feature).
+//!
+//! [`FeatureFlags`] is middleware that reads a feature flag configuration
+//! profile through the AppConfig Lambda extension's local endpoint and
+//! attaches the flags to each request, where
+//! [`Request::feature_enabled`] checks them. [`Route::require_flag`] hides
+//! a route (404) while its flag is off.
+//!
+//! The extension polls AppConfig and serves its cache locally; the flags
+//! are additionally cached in-process for a short TTL. When a fetch fails
+//! the last known flags stay in effect, and before any fetch has succeeded
+//! every flag is off.
+//!
+//! # Example
+//! ```ignore
+//! use choko::feature_flags::FeatureFlags;
+//!
+//! app.middleware(FeatureFlags::appconfig("shop", "prod", "flags"));
+//! app.post("/checkout", checkout_v2).require_flag("new-checkout");
+//! app.get("/cart", |req| async move {
+//!     let layout = if req.feature_enabled("compact-cart") { "compact" } else { "full" };
+//!     ...
+//! });
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, Route};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The AppConfig extension's default port.
+const EXTENSION_PORT: u16 = 2772;
+
+/// One feature flag.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Flag {
+    pub enabled: bool,
+    /// The flag's attributes, e.g. a rollout percentage or variant name.
+    pub attributes: Map<String, Value>,
+}
+
+/// The flags in effect for a request.
+#[derive(Debug, Clone, Default)]
+pub struct FlagSet(Arc<HashMap<String, Flag>>);
+
+impl FlagSet {
+    /// Parse a feature flag profile as returned by AppConfig:
+    /// `{"name": {"enabled": true, ...attributes}}`.
+    pub fn from_json(value: &Value) -> Self {
+        let flags = value
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, flag)| {
+                let mut attributes = flag.as_object().cloned().unwrap_or_default();
+                let enabled = attributes.remove("enabled").and_then(|v| v.as_bool());
+                let flag = Flag {
+                    enabled: enabled.unwrap_or(false),
+                    attributes,
+                };
+                (name.clone(), flag)
+            })
+            .collect();
+        Self(Arc::new(flags))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Flag> {
+        self.0.get(name)
+    }
+
+    /// Whether `name` is on. Unknown flags are off.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|f| f.enabled)
+    }
+}
+
+impl Request {
+    /// The flags attached by [`FeatureFlags`].
+    pub fn feature_flags(&self) -> Option<&FlagSet> {
+        self.extensions().get::<FlagSet>()
+    }
+
+    /// The flag `name`, if known.
+    pub fn feature_flag(&self, name: &str) -> Option<&Flag> {
+        self.feature_flags()?.get(name)
+    }
+
+    /// Whether the flag `name` is on. Off when the flag is unknown or no
+    /// [`FeatureFlags`] middleware ran.
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        self.feature_flags().is_some_and(|f| f.is_enabled(name))
+    }
+}
+
+enum Source {
+    Fixed(FlagSet),
+    AppConfig {
+        client: reqwest::Client,
+        url: String,
+        ttl: Duration,
+        cache: Mutex<Option<(Instant, FlagSet)>>,
+    },
+}
+
+impl Source {
+    async fn flags(&self) -> FlagSet {
+        match self {
+            Source::Fixed(flags) => flags.clone(),
+            Source::AppConfig {
+                client,
+                url,
+                ttl,
+                cache,
+            } => {
+                let cached = cache.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                if let Some((fetched_at, flags)) = &cached {
+                    if fetched_at.elapsed() < *ttl {
+                        return flags.clone();
+                    }
+                }
+                let flags = match fetch(client, url).await {
+                    Ok(flags) => flags,
+                    Err(e) => {
+                        eprintln!("Failed to read feature flags from {url}: {e}");
+                        cached.map(|(_, flags)| flags).unwrap_or_default()
+                    }
+                };
+                *cache.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some((Instant::now(), flags.clone()));
+                flags
+            }
+        }
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<FlagSet, Error> {
+    let value: Value = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(FlagSet::from_json(&value))
+}
+
+/// Middleware that attaches the current feature flags to every request.
+pub struct FeatureFlags {
+    source: Arc<Source>,
+}
+
+impl FeatureFlags {
+    /// Read the feature flag profile `profile` of `application` in
+    /// `environment` from the AppConfig Lambda extension, caching it for 10
+    /// seconds. The port comes from `AWS_APPCONFIG_EXTENSION_HTTP_PORT`.
+    pub fn appconfig(application: &str, environment: &str, profile: &str) -> Self {
+        let port = std::env::var("AWS_APPCONFIG_EXTENSION_HTTP_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(EXTENSION_PORT);
+        Self::url(format!(
+            "http://localhost:{port}/applications/{application}/environments/{environment}/configurations/{profile}"
+        ))
+    }
+
+    /// Read flags from `url`, any endpoint serving the AppConfig feature
+    /// flag format.
+    pub fn url(url: impl Into<String>) -> Self {
+        Self {
+            source: Arc::new(Source::AppConfig {
+                client: reqwest::Client::new(),
+                url: url.into(),
+                ttl: Duration::from_secs(10),
+                cache: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Fixed flags, for local development and tests.
+    pub fn fixed(flags: Value) -> Self {
+        Self {
+            source: Arc::new(Source::Fixed(FlagSet::from_json(&flags))),
+        }
+    }
+
+    /// How long fetched flags are reused. No effect for fixed flags.
+    ///
+    /// # Panics
+    /// Panics if called after the middleware has been shared.
+    pub fn cache_ttl(mut self, new_ttl: Duration) -> Self {
+        if let Source::AppConfig { ttl, .. } =
+            Arc::get_mut(&mut self.source).expect("FeatureFlags is configured before use")
+        {
+            *ttl = new_ttl;
+        }
+        self
+    }
+}
+
+impl Middleware for FeatureFlags {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let source = Arc::clone(&self.source);
+        Box::pin(async move {
+            req.extensions_mut().insert(source.flags().await);
+            next.run(req).await
+        })
+    }
+}
+
+/// Middleware that answers 404 while a flag is off, as if the route didn't
+/// exist. Usually attached with [`Route::require_flag`].
+pub struct FlagGuard {
+    flag: String,
+}
+
+impl FlagGuard {
+    pub fn new(flag: impl Into<String>) -> Self {
+        Self { flag: flag.into() }
+    }
+}
+
+impl Middleware for FlagGuard {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if req.feature_enabled(&self.flag) {
+            return next.run(req);
+        }
+        Box::pin(async move { Ok(crate::error_json(404, "Not Found")) })
+    }
+}
+
+impl Route {
+    /// Answer 404 unless the feature flag `flag` is on. Needs the
+    /// [`FeatureFlags`] middleware.
+    pub fn require_flag(&mut self, flag: &str) -> &mut Self {
+        self.middleware(FlagGuard::new(flag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Choko;
+    use aws_lambda_events::event::apigw::ApiGatewayProxyRequest;
+    use serde_json::json;
+
+    fn get(path: &str) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some(path.to_string());
+        event
+    }
+
+    #[test]
+    fn parses_appconfig_flags() {
+        let flags = FlagSet::from_json(&json!({
+            "new-checkout": { "enabled": true, "variant": "b" },
+            "dark-mode": { "enabled": false }
+        }));
+        assert!(flags.is_enabled("new-checkout"));
+        assert!(!flags.is_enabled("dark-mode"));
+        assert!(!flags.is_enabled("unknown"));
+        assert_eq!(
+            flags.get("new-checkout").unwrap().attributes["variant"],
+            "b"
+        );
+    }
+
+    #[tokio::test]
+    async fn guards_routes_and_exposes_flags() {
+        let mut app = Choko::new("test");
+        app.middleware(FeatureFlags::fixed(json!({
+            "new-checkout": { "enabled": true },
+            "beta": { "enabled": false }
+        })));
+        app.get("/checkout", |req| async move {
+            Ok(Response::text(
+                req.feature_enabled("new-checkout").to_string(),
+            ))
+        })
+        .require_flag("new-checkout");
+        app.get("/beta", |_req| async move { Ok(Response::text("beta")) })
+            .require_flag("beta");
+
+        let resp = app.dispatch(get("/checkout")).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(
+            resp.body,
+            Some(aws_lambda_events::encodings::Body::Text("true".to_string()))
+        );
+        assert_eq!(app.dispatch(get("/beta")).await.unwrap().status_code, 404);
+    }
+
+    #[tokio::test]
+    async fn unreachable_endpoint_turns_flags_off() {
+        let flags = FeatureFlags::url("http://127.0.0.1:9/flags");
+        assert!(!flags.source.flags().await.is_enabled("anything"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Client IP and scheme resolution from `Forwarded` / `X-Forwarded-*`
+//! headers and the API Gateway source IP.
+
+use crate::Request;
+use std::net::IpAddr;
+
+/// Parse a node from `Forwarded: for=` or `X-Forwarded-For`, dropping quotes,
+/// IPv6 brackets, and ports. Obfuscated identifiers (`unknown`, `_hidden`)
+/// yield `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    // IPv4 with a port
+    let (host, _port) = node.rsplit_once(':')?;
+    host.parse().ok()
+}
+
+/// `for=` values from every `Forwarded` element, in order.
+fn forwarded_for(header: &str) -> Vec<Option<IpAddr>> {
+    header
+        .split(',')
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (k, v) = pair.split_once('=')?;
+                k.trim().eq_ignore_ascii_case("for").then(|| parse_node(v))
+            })
+        })
+        .collect()
+}
+
+/// The first `proto=` value in a `Forwarded` header.
+fn forwarded_proto(header: &str) -> Option<String> {
+    header.split([',', ';']).find_map(|pair| {
+        let (k, v) = pair.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case("proto")
+            .then(|| v.trim().trim_matches('"').to_ascii_lowercase())
+    })
+}
+
+impl Request {
+    /// The chain of client addresses, from the originating client to the
+    /// immediate peer.
+    ///
+    /// Built from `Forwarded` (or `X-Forwarded-For` when absent), followed by
+    /// the API Gateway `sourceIp` if it is not already the last hop.
+    /// Unparseable entries are kept as `None` so hop counts stay accurate.
+    pub fn forwarded_chain(&self) -> Vec<Option<IpAddr>> {
+        let mut chain = if let Some(fwd) = self.header("forwarded") {
+            forwarded_for(fwd)
+        } else if let Some(xff) = self.header("x-forwarded-for") {
+            xff.split(',').map(parse_node).collect()
+        } else {
+            Vec::new()
+        };
+        let source = self
+            .request_context()
+            .source_ip
+            .as_deref()
+            .and_then(parse_node);
+        if source.is_some() && chain.last() != Some(&source) {
+            chain.push(source);
+        }
+        chain
+    }
+
+    /// The client IP address.
+    ///
+    /// Uses the API Gateway `sourceIp`, which clients cannot spoof. When it is
+    /// unavailable, falls back to the left-most forwarded address. If other
+    /// proxies you control sit in front of API Gateway, use
+    /// [`client_ip_with_trusted_proxies`](Self::client_ip_with_trusted_proxies)
+    /// instead.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        let source = self
+            .request_context()
+            .source_ip
+            .as_deref()
+            .and_then(parse_node);
+        source.or_else(|| self.forwarded_chain().into_iter().flatten().next())
+    }
+
+    /// The client IP address, skipping `trusted` proxy hops at the end of
+    /// [`forwarded_chain`](Self::forwarded_chain).
+    ///
+    /// With `trusted = 0` this is the immediate peer; with `trusted = 1` it is
+    /// the address that peer reported, and so on. Returns `None` if the chain
+    /// is shorter than expected or the entry is unparseable.
+    pub fn client_ip_with_trusted_proxies(&self, trusted: usize) -> Option<IpAddr> {
+        let chain = self.forwarded_chain();
+        let index = chain.len().checked_sub(trusted + 1)?;
+        chain[index]
+    }
+
+    /// The scheme the client used (`https` unless a proxy reports otherwise).
+    ///
+    /// Read from `Forwarded: proto=` or `X-Forwarded-Proto`. API Gateway only
+    /// serves HTTPS, so that is the default.
+    pub fn scheme(&self) -> String {
+        self.header("forwarded")
+            .and_then(forwarded_proto)
+            .or_else(|| {
+                self.header("x-forwarded-proto").map(|p| {
+                    p.split(',')
+                        .next()
+                        .unwrap_or("")
+                        .trim()
+                        .to_ascii_lowercase()
+                })
+            })
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "https".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestContext;
+
+    fn request(headers: &[(&str, &str)], source_ip: Option<&str>) -> Request {
+        let mut req = Request {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+        req.request_context = RequestContext {
+            source_ip: source_ip.map(str::to_string),
+            ..Default::default()
+        };
+        req
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_node_handles_ports_and_brackets() {
+        assert_eq!(parse_node("192.0.2.1:8080"), Some(ip("192.0.2.1")));
+        assert_eq!(
+            parse_node("\"[2001:db8::1]:4711\""),
+            Some(ip("2001:db8::1"))
+        );
+        assert_eq!(parse_node("2001:db8::2"), Some(ip("2001:db8::2")));
+        assert_eq!(parse_node("unknown"), None);
+    }
+
+    #[test]
+    fn client_ip_prefers_source_ip() {
+        let req = request(&[("x-forwarded-for", "1.1.1.1, 2.2.2.2")], Some("2.2.2.2"));
+        assert_eq!(req.client_ip(), Some(ip("2.2.2.2")));
+        assert_eq!(req.client_ip_with_trusted_proxies(1), Some(ip("1.1.1.1")));
+        assert_eq!(req.client_ip_with_trusted_proxies(5), None);
+    }
+
+    #[test]
+    fn client_ip_falls_back_to_forwarded_headers() {
+        let req = request(
+            &[("forwarded", "for=192.0.2.60;proto=http, for=198.51.100.17")],
+            None,
+        );
+        assert_eq!(req.client_ip(), Some(ip("192.0.2.60")));
+        assert_eq!(req.scheme(), "http");
+
+        let req = request(&[("x-forwarded-for", "203.0.113.9")], None);
+        assert_eq!(req.client_ip(), Some(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn forwarded_chain_appends_source_ip() {
+        let req = request(&[("x-forwarded-for", "1.1.1.1")], Some("3.3.3.3"));
+        assert_eq!(
+            req.forwarded_chain(),
+            vec![Some(ip("1.1.1.1")), Some(ip("3.3.3.3"))]
+        );
+    }
+
+    #[test]
+    fn scheme_defaults_to_https() {
+        assert_eq!(request(&[], None).scheme(), "https");
+        assert_eq!(
+            request(&[("x-forwarded-proto", "HTTP")], None).scheme(),
+            "http"
+        );
+    }
+}
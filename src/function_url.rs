@@ -0,0 +1,323 @@
+//! Lambda Function URL support (`function-url` feature).
+//!
+//! Function URLs invoke the function with the HTTP API v2 payload, which
+//! differs from the REST API events the router works on:
+//!
+//! - cookies arrive in a separate `cookies` array, and `Set-Cookie` values
+//!   must be returned the same way;
+//! - repeated query parameters are comma-joined in `queryStringParameters`,
+//!   so the raw query string is parsed instead;
+//! - the method, source IP and IAM caller live in `requestContext.http` and
+//!   `requestContext.authorizer.iam`.
+//!
+//! [`Choko::run_function_url`] returns each response in one piece.
+//! [`Choko::run_function_url_streaming`] serves URLs configured with the
+//! `RESPONSE_STREAM` invoke mode, sending streamed bodies as they are
+//! produced.
+
+use crate::{BodyStream, Choko, Error, LambdaContext, ResponseBody};
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::event::lambda_function_urls::{
+    LambdaFunctionUrlRequest, LambdaFunctionUrlResponse,
+};
+use aws_lambda_events::query_map::QueryMap;
+use base64::Engine;
+use lambda_runtime::streaming;
+use lambda_runtime::{MetadataPrelude, StreamResponse};
+use std::collections::HashMap;
+
+/// The API Gateway event equivalent to `event`.
+pub(crate) fn to_apigw_request(event: LambdaFunctionUrlRequest) -> ApiGatewayProxyRequest {
+    let mut req = ApiGatewayProxyRequest::default();
+    let http = &event.request_context.http;
+    req.http_method = http
+        .method
+        .as_deref()
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(http::Method::GET);
+    req.path = event.raw_path.clone().or_else(|| http.path.clone());
+    req.headers = event.headers;
+    if let Some(cookies) = event.cookies.filter(|c| !c.is_empty()) {
+        if let Ok(value) = http::HeaderValue::from_str(&cookies.join("; ")) {
+            req.headers.insert(http::header::COOKIE, value);
+        }
+    }
+
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_str(event.raw_query_string.as_deref().unwrap_or(""))
+            .unwrap_or_default();
+    for (k, v) in pairs {
+        params.entry(k).or_default().push(v);
+    }
+    req.multi_value_query_string_parameters = QueryMap::from(params);
+
+    let ctx = &mut req.request_context;
+    ctx.request_id = event.request_context.request_id.clone();
+    ctx.identity.source_ip = http.source_ip.clone();
+    ctx.identity.user_agent = http.user_agent.clone();
+    if let Some(iam) = event
+        .request_context
+        .authorizer
+        .as_ref()
+        .and_then(|a| a.iam.as_ref())
+    {
+        ctx.identity.account_id = iam.account_id.clone();
+        ctx.identity.user_arn = iam.user_arn.clone();
+        ctx.identity.caller = iam.caller_id.clone();
+        ctx.identity.user = iam.user_id.clone();
+        ctx.identity.access_key = iam.access_key.clone();
+    }
+
+    req.body = event.body;
+    req.is_base64_encoded = event.is_base64_encoded;
+    req
+}
+
+/// The Function URL response equivalent to `resp`.
+pub(crate) fn to_function_url_response(resp: ApiGatewayProxyResponse) -> LambdaFunctionUrlResponse {
+    let mut out = LambdaFunctionUrlResponse::default();
+    out.status_code = resp.status_code;
+    let mut headers = resp.headers;
+    for (name, value) in resp.multi_value_headers.iter() {
+        headers.append(name.clone(), value.clone());
+    }
+    for name in headers.keys() {
+        let values: Vec<&str> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        if name == http::header::SET_COOKIE {
+            out.cookies = values.into_iter().map(str::to_string).collect();
+        } else if let Ok(value) = http::HeaderValue::from_str(&values.join(", ")) {
+            out.headers.insert(name.clone(), value);
+        }
+    }
+    match resp.body {
+        Some(Body::Text(text)) => out.body = Some(text),
+        Some(Body::Binary(bytes)) => {
+            out.body = Some(base64::engine::general_purpose::STANDARD.encode(bytes));
+            out.is_base64_encoded = true;
+        }
+        _ => {}
+    }
+    out
+}
+
+/// Send the chunks of `stream` until it ends, the client goes away or the
+/// producer fails.
+async fn forward(stream: &mut BodyStream, tx: &mut streaming::Sender) -> Result<(), Error> {
+    while let Some(chunk) = stream.next_chunk().await {
+        if tx.send_data(chunk?.into()).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+impl Choko {
+    /// Run the application behind a Lambda Function URL, with no API
+    /// Gateway in front.
+    ///
+    /// Routes, middleware and hooks behave as with [`run`](Self::run). With
+    /// `AWS_IAM` auth on the URL, the caller is available as
+    /// [`Request::iam_identity`](crate::Request::iam_identity).
+    pub async fn run_function_url(self) -> Result<(), Error> {
+        self.serve(|app, event: LambdaFunctionUrlRequest, context| async move {
+            app.dispatch_function_url(event, Some(context)).await
+        })
+        .await
+    }
+
+    /// Run the application behind a Lambda Function URL in response
+    /// streaming mode (`InvokeMode: RESPONSE_STREAM`).
+    ///
+    /// Streamed bodies, such as [`SseResponse`](crate::SseResponse) and
+    /// [`NdjsonResponse`](crate::NdjsonResponse), reach the client as they
+    /// are produced and aren't limited to the 6 MB buffered payload size.
+    /// Other responses are sent in one piece. Deferred work runs once the
+    /// body ends, before the invocation completes.
+    pub async fn run_function_url_streaming(self) -> Result<(), Error> {
+        self.serve(|app, event: LambdaFunctionUrlRequest, context| async move {
+            Ok::<_, Error>(app.stream_function_url(event, Some(context)).await)
+        })
+        .await
+    }
+
+    pub(crate) async fn stream_function_url(
+        &self,
+        event: LambdaFunctionUrlRequest,
+        context: Option<LambdaContext>,
+    ) -> StreamResponse<streaming::Body> {
+        let routed = self
+            .route_event(to_apigw_request(event), context, false)
+            .await;
+        let mut resp = routed.response;
+        let mut head = self.response_head(&resp);
+        let (mut tx, body) = streaming::channel();
+        let (deferred, deadline) = (routed.deferred, routed.deadline);
+        let budget = self.deferred_budget;
+        match resp.body {
+            ResponseBody::Stream(mut stream) => {
+                tokio::spawn(async move {
+                    let result = forward(&mut stream, &mut tx).await;
+                    // The invocation completes when the body ends
+                    deferred.run(budget, deadline).await;
+                    if let Err(e) = result {
+                        eprintln!("Handler error: {e}");
+                        tx.abort();
+                    }
+                });
+            }
+            body => {
+                resp.body = body;
+                head = self.build_apigw_response(resp);
+                let bytes = match head.body.take() {
+                    Some(Body::Text(text)) => text.into_bytes(),
+                    Some(Body::Binary(bytes)) => bytes,
+                    _ => Vec::new(),
+                };
+                head.is_base64_encoded = false;
+                tokio::spawn(async move {
+                    if !bytes.is_empty() {
+                        let _ = tx.send_data(bytes.into()).await;
+                    }
+                    deferred.run(budget, deadline).await;
+                });
+            }
+        }
+        let head = to_function_url_response(head);
+        StreamResponse {
+            metadata_prelude: MetadataPrelude {
+                status_code: http::StatusCode::from_u16(
+                    u16::try_from(head.status_code).unwrap_or(500),
+                )
+                .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR),
+                headers: head.headers,
+                cookies: head.cookies,
+            },
+            stream: body,
+        }
+    }
+
+    pub(crate) async fn dispatch_function_url(
+        &self,
+        event: LambdaFunctionUrlRequest,
+        context: Option<LambdaContext>,
+    ) -> Result<LambdaFunctionUrlResponse, Error> {
+        let resp = self
+            .dispatch_with_context(to_apigw_request(event), context)
+            .await?;
+        Ok(to_function_url_response(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cookie, Response};
+
+    fn url_request(method: &str, path: &str, query: &str) -> LambdaFunctionUrlRequest {
+        let mut event = LambdaFunctionUrlRequest::default();
+        event.raw_path = Some(path.to_string());
+        event.raw_query_string = Some(query.to_string());
+        event.request_context.http.method = Some(method.to_string());
+        event.request_context.http.source_ip = Some("198.51.100.4".to_string());
+        event
+    }
+
+    #[tokio::test]
+    async fn repeated_query_params_and_cookies() {
+        let mut app = Choko::new("test");
+        app.get("/tags", |req| async move {
+            let tags = req.query_params["tag"].join("|");
+            let session = req.cookie("session").unwrap_or_default();
+            Ok(Response::text(format!("{tags} {session}"))
+                .with_cookie(Cookie::new("a", "1"))
+                .with_cookie(Cookie::new("b", "2")))
+        });
+
+        let mut event = url_request("GET", "/tags", "tag=a%2Cb&tag=c");
+        event.cookies = Some(vec!["theme=dark".to_string(), "session=s1".to_string()]);
+        let resp = app.dispatch_function_url(event, None).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.body.as_deref(), Some("a,b|c s1"));
+        assert_eq!(resp.cookies.len(), 2);
+        assert!(resp.headers.get("set-cookie").is_none());
+    }
+
+    #[tokio::test]
+    async fn binary_bodies_are_base64_encoded() {
+        let mut app = Choko::new("test");
+        app.post("/echo", |req| async move {
+            Ok(Response::binary(
+                req.body_bytes().unwrap_or_default().to_vec(),
+                "application/octet-stream",
+            ))
+        });
+
+        let mut event = url_request("POST", "/echo", "");
+        event.body = Some("AP8=".to_string());
+        event.is_base64_encoded = true;
+        let resp = app.dispatch_function_url(event, None).await.unwrap();
+        assert_eq!(resp.body.as_deref(), Some("AP8="));
+        assert!(resp.is_base64_encoded);
+    }
+
+    #[tokio::test]
+    async fn streaming_mode_sends_stream_bodies_and_buffered_ones() {
+        let mut app = Choko::new("test");
+        app.get("/progress", |_req| async move {
+            let (sse, tx) = crate::SseResponse::channel();
+            tokio::spawn(async move {
+                for pct in [50, 100] {
+                    let _ = tx.send(crate::SseEvent::data(pct.to_string())).await;
+                }
+            });
+            Ok(sse.into())
+        });
+        app.get("/me", |_req| async move {
+            Ok(Response::json(serde_json::json!({ "id": 1 }))
+                .with_status(201)
+                .with_cookie(Cookie::new("a", "1")))
+        });
+
+        let resp = app
+            .stream_function_url(url_request("GET", "/progress", ""), None)
+            .await;
+        let prelude = &resp.metadata_prelude;
+        assert_eq!(prelude.status_code, 200);
+        assert_eq!(prelude.headers["content-type"], "text/event-stream");
+        let body = resp.stream.collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"data: 50\n\ndata: 100\n\n");
+
+        let resp = app
+            .stream_function_url(url_request("GET", "/me", ""), None)
+            .await;
+        let prelude = &resp.metadata_prelude;
+        assert_eq!(prelude.status_code, 201);
+        assert_eq!(prelude.headers["content-type"], "application/json");
+        assert_eq!(prelude.cookies, vec!["a=1".to_string()]);
+        let body = resp.stream.collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"id":1}"#);
+    }
+
+    #[tokio::test]
+    async fn streaming_mode_aborts_the_body_when_the_producer_fails() {
+        let mut app = Choko::new("test");
+        app.get("/export", |_req| async move {
+            let (tx, body) = crate::BodyStream::channel(1);
+            tokio::spawn(async move {
+                let _ = tx.send("partial").await;
+                tx.abort("database went away").await;
+            });
+            Ok(Response::stream(body))
+        });
+        let resp = app
+            .stream_function_url(url_request("GET", "/export", ""), None)
+            .await;
+        assert!(resp.stream.collect().await.is_err());
+    }
+}
@@ -0,0 +1,557 @@
+//! GraphQL endpoints with `async-graphql` (`graphql` feature).
+//!
+//! [`Choko::graphql`] mounts a schema at a route:
+//!
+//! - `POST` runs a query, mutation or batch sent as JSON;
+//! - `GET` runs a query from the `query`, `variables` and `operationName`
+//!   parameters, or a persisted query by hash. Mutations are refused, so
+//!   GET responses can be cached;
+//! - `GET` without a query serves GraphiQL or GraphQL Playground, when
+//!   enabled.
+//!
+//! Persisted queries use Apollo's protocol: the client sends
+//! `extensions={"persistedQuery":{"version":1,"sha256Hash":"..."}}` instead
+//! of the query text. Hashes are looked up in the queries registered with
+//! [`GraphQL::persisted_queries`] and, with
+//! [`GraphQL::automatic_persisted_queries`], in queries clients have sent
+//! along with their hash before. Learned queries are capped in number and
+//! length, evicting the least recently used. Unknown hashes get a
+//! `PersistedQueryNotFound` error, so the client can retry with the text.
+//!
+//! Resolvers can read the choko [`Request`] (headers, auth claims, state)
+//! from the context with `ctx.data::<choko::Request>()`.
+//!
+//! # Example
+//! ```ignore
+//! use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+//! use choko::graphql::GraphQL;
+//!
+//! struct Query;
+//!
+//! #[Object]
+//! impl Query {
+//!     async fn hello(&self) -> &str {
+//!         "world"
+//!     }
+//! }
+//!
+//! let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+//! app.graphql("/graphql", GraphQL::new(schema).graphiql());
+//! ```
+
+use crate::{Choko, Error, Request, Response, Route};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig, GraphiQLSource};
+use async_graphql::parser::types::OperationType;
+use async_graphql::{BatchRequest, Executor, Variables};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// The in-browser IDE served on `GET` requests without a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ide {
+    GraphiQL,
+    Playground,
+}
+
+/// A GraphQL schema and how to serve it, for [`Choko::graphql`].
+pub struct GraphQL<E> {
+    executor: E,
+    ide: Option<Ide>,
+    persisted: Arc<Mutex<HashMap<String, String>>>,
+    automatic_persisted_queries: bool,
+    learned: Mutex<HashMap<String, Learned>>,
+    max_learned_queries: usize,
+    max_learned_query_len: usize,
+}
+
+/// A query learned from a client, with when it was last used for eviction.
+struct Learned {
+    query: String,
+    used: Instant,
+}
+
+impl<E: Executor> GraphQL<E> {
+    /// Serve `executor`, usually an `async_graphql::Schema`.
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            ide: None,
+            persisted: Arc::default(),
+            automatic_persisted_queries: false,
+            learned: Mutex::default(),
+            max_learned_queries: 1_000,
+            max_learned_query_len: 10 * 1024,
+        }
+    }
+
+    /// Serve GraphiQL on `GET` requests without a query.
+    pub fn graphiql(mut self) -> Self {
+        self.ide = Some(Ide::GraphiQL);
+        self
+    }
+
+    /// Serve GraphQL Playground on `GET` requests without a query.
+    pub fn playground(mut self) -> Self {
+        self.ide = Some(Ide::Playground);
+        self
+    }
+
+    /// Accept these queries by their SHA-256 hash, e.g. the manifest
+    /// generated by the client build.
+    pub fn persisted_queries<I, S>(self, queries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        {
+            let mut persisted = self.persisted.lock().unwrap_or_else(|e| e.into_inner());
+            for query in queries {
+                let query = query.as_ref();
+                persisted.insert(hash(query), query.to_string());
+            }
+        }
+        self
+    }
+
+    /// Also remember queries clients send with their hash, for later
+    /// requests by hash alone. Each container learns them separately.
+    pub fn automatic_persisted_queries(mut self) -> Self {
+        self.automatic_persisted_queries = true;
+        self
+    }
+
+    /// How many automatic persisted queries to remember, evicting the least
+    /// recently used. Defaults to 1,000.
+    pub fn max_learned_queries(mut self, max: usize) -> Self {
+        self.max_learned_queries = max.max(1);
+        self
+    }
+
+    /// The longest automatic persisted query to remember, in bytes. Longer
+    /// queries still run but aren't learned. Defaults to 10 KiB.
+    pub fn max_learned_query_len(mut self, bytes: usize) -> Self {
+        self.max_learned_query_len = bytes;
+        self
+    }
+
+    /// Remember `query` under `digest`, evicting the least recently used
+    /// queries when full.
+    fn learn(&self, digest: String, query: &str) {
+        if query.len() > self.max_learned_query_len {
+            return;
+        }
+        let mut learned = self.learned.lock().unwrap_or_else(|e| e.into_inner());
+        while learned.len() >= self.max_learned_queries && !learned.contains_key(&digest) {
+            let oldest = learned
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(digest, _)| digest.clone());
+            match oldest {
+                Some(oldest) => learned.remove(&oldest),
+                None => break,
+            };
+        }
+        learned.insert(
+            digest,
+            Learned {
+                query: query.to_string(),
+                used: Instant::now(),
+            },
+        );
+    }
+
+    /// The query registered or learned for `digest`.
+    fn lookup(&self, digest: &str) -> Option<String> {
+        let persisted = self.persisted.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(query) = persisted.get(digest) {
+            return Some(query.clone());
+        }
+        drop(persisted);
+        let mut learned = self.learned.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = learned.get_mut(digest)?;
+        entry.used = Instant::now();
+        Some(entry.query.clone())
+    }
+
+    /// The query text for a request, from `query` or the persisted query
+    /// hash in `extensions`. The error is the response to send instead.
+    fn resolve(
+        &self,
+        query: Option<&str>,
+        extensions: Option<&Value>,
+    ) -> Result<String, Box<Response>> {
+        let hash_param = extensions
+            .and_then(|ext| ext.get("persistedQuery"))
+            .and_then(|pq| pq.get("sha256Hash"))
+            .and_then(Value::as_str);
+        match (query, hash_param) {
+            (Some(query), Some(expected)) => {
+                if hash(query) != expected.to_ascii_lowercase() {
+                    return Err(Box::new(crate::error_json(
+                        400,
+                        "persisted query hash doesn't match the query",
+                    )));
+                }
+                if self.automatic_persisted_queries {
+                    self.learn(hash(query), query);
+                }
+                Ok(query.to_string())
+            }
+            (Some(query), None) => Ok(query.to_string()),
+            (None, Some(digest)) => match self.lookup(&digest.to_ascii_lowercase()) {
+                Some(query) => Ok(query),
+                None => Err(Box::new(persisted_query_not_found())),
+            },
+            (None, None) => Err(Box::new(crate::error_json(400, "query is required"))),
+        }
+    }
+
+    async fn get(&self, req: Request, endpoint: &str) -> Result<Response, Error> {
+        let param = |name: &str| {
+            req.query_params
+                .get(name)
+                .and_then(|values| values.first())
+                .map(String::as_str)
+        };
+        if param("query").is_none() && param("extensions").is_none() {
+            return Ok(match self.ide {
+                Some(Ide::GraphiQL) => {
+                    Response::html(GraphiQLSource::build().endpoint(endpoint).finish())
+                }
+                Some(Ide::Playground) => {
+                    Response::html(playground_source(GraphQLPlaygroundConfig::new(endpoint)))
+                }
+                None => crate::error_json(400, "query is required"),
+            });
+        }
+        let json_param = |name: &str| match param(name) {
+            Some(raw) => serde_json::from_str::<Value>(raw)
+                .map(Some)
+                .map_err(|_| Box::new(crate::error_json(400, &format!("{name} must be JSON")))),
+            None => Ok(None),
+        };
+        let (variables, extensions) = match (json_param("variables"), json_param("extensions")) {
+            (Ok(variables), Ok(extensions)) => (variables, extensions),
+            (Err(resp), _) | (_, Err(resp)) => return Ok(*resp),
+        };
+        let query = match self.resolve(param("query"), extensions.as_ref()) {
+            Ok(query) => query,
+            Err(resp) => return Ok(*resp),
+        };
+        if !is_read_only(&query) {
+            return Ok(
+                crate::error_json(405, "mutations must use POST").with_header("Allow", "POST")
+            );
+        }
+
+        let mut request = async_graphql::Request::new(query);
+        if let Some(name) = param("operationName") {
+            request = request.operation_name(name);
+        }
+        if let Some(variables) = variables {
+            request = request.variables(Variables::from_json(variables));
+        }
+        let response = self.executor.execute(request.data(req.clone())).await;
+        let cache_control = response.cache_control.value();
+        let mut resp = json_response(&response)?;
+        if let Some(value) = cache_control {
+            resp = resp.with_header("Cache-Control", value);
+        }
+        Ok(resp)
+    }
+
+    async fn post(&self, req: Request) -> Result<Response, Error> {
+        let (items, batch) = match &req.json_body {
+            Some(Value::Array(items)) => (items.clone(), true),
+            Some(item @ Value::Object(_)) => (vec![item.clone()], false),
+            _ => {
+                return Ok(crate::error_json(
+                    400,
+                    "expected a GraphQL request as a JSON object or array",
+                ))
+            }
+        };
+        let mut requests = Vec::with_capacity(items.len());
+        for mut item in items {
+            let query = item.get("query").and_then(Value::as_str);
+            let query = match self.resolve(query, item.get("extensions")) {
+                Ok(query) => query,
+                Err(resp) => return Ok(*resp),
+            };
+            item["query"] = Value::String(query);
+            let request = match serde_json::from_value::<async_graphql::Request>(item) {
+                Ok(request) => request,
+                Err(e) => {
+                    return Ok(crate::error_json(
+                        400,
+                        &format!("invalid GraphQL request: {e}"),
+                    ))
+                }
+            };
+            requests.push(request.data(req.clone()));
+        }
+        if batch {
+            let response = self
+                .executor
+                .execute_batch(BatchRequest::Batch(requests))
+                .await;
+            return json_response(&response);
+        }
+        let request = requests.pop().expect("a single request was parsed");
+        json_response(&self.executor.execute(request).await)
+    }
+}
+
+impl Choko {
+    /// Serve `graphql` at `path`, on `GET` and `POST`.
+    pub fn graphql<E: Executor>(&mut self, path: &str, graphql: GraphQL<E>) -> &mut Route {
+        let graphql = Arc::new(graphql);
+        let endpoint: Arc<str> = path.into();
+        self.route(path, &["GET", "POST"], move |req| {
+            let graphql = Arc::clone(&graphql);
+            let endpoint = Arc::clone(&endpoint);
+            async move {
+                if req.method() == "GET" {
+                    graphql.get(req, &endpoint).await
+                } else {
+                    graphql.post(req).await
+                }
+            }
+        })
+    }
+}
+
+fn hash(query: &str) -> String {
+    hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+/// Apollo's answer to an unknown persisted query hash, asking the client
+/// to send the full query.
+fn persisted_query_not_found() -> Response {
+    Response::json(json!({
+        "errors": [{
+            "message": "PersistedQueryNotFound",
+            "extensions": { "code": "PERSISTED_QUERY_NOT_FOUND" },
+        }],
+    }))
+}
+
+/// Whether every operation in `query` is a query. Syntax errors pass, for
+/// the executor to report.
+fn is_read_only(query: &str) -> bool {
+    match async_graphql::parser::parse_query(query) {
+        Ok(document) => document
+            .operations
+            .iter()
+            .all(|(_, operation)| operation.node.ty == OperationType::Query),
+        Err(_) => true,
+    }
+}
+
+fn json_response<T: Serialize>(body: &T) -> Result<Response, Error> {
+    Ok(Response::json(serde_json::to_value(body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{Context, EmptySubscription, Object, Schema};
+    use aws_lambda_events::event::apigw::ApiGatewayProxyRequest;
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn hello(&self, name: Option<String>) -> String {
+            format!("hello {}", name.as_deref().unwrap_or("world"))
+        }
+
+        async fn agent(&self, ctx: &Context<'_>) -> Option<String> {
+            let req = ctx.data::<Request>().ok()?;
+            req.header("user-agent").map(str::to_string)
+        }
+    }
+
+    struct Mutation;
+
+    #[Object]
+    impl Mutation {
+        async fn ping(&self) -> bool {
+            true
+        }
+    }
+
+    type TestSchema = Schema<Query, Mutation, EmptySubscription>;
+
+    fn app(configure: impl FnOnce(GraphQL<TestSchema>) -> GraphQL<TestSchema>) -> Choko {
+        let schema = Schema::new(Query, Mutation, EmptySubscription);
+        let mut app = Choko::new("test");
+        app.graphql("/graphql", configure(GraphQL::new(schema)));
+        app
+    }
+
+    fn get(query: &[(&str, &str)]) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some("/graphql".to_string());
+        event.query_string_parameters = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>()
+            .into();
+        event
+    }
+
+    fn post(body: Value) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::POST;
+        event.path = Some("/graphql".to_string());
+        event.headers.insert("user-agent", "tests".parse().unwrap());
+        event.body = Some(body.to_string());
+        event
+    }
+
+    fn body(resp: &aws_lambda_events::event::apigw::ApiGatewayProxyResponse) -> Value {
+        match &resp.body {
+            Some(aws_lambda_events::encodings::Body::Text(text)) => {
+                serde_json::from_str(text).unwrap_or(Value::String(text.clone()))
+            }
+            _ => Value::Null,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_posted_queries_and_batches() {
+        let app = app(|g| g);
+        let resp = app
+            .dispatch(post(json!({
+                "query": "query($n: String) { hello(name: $n) agent }",
+                "variables": { "n": "choko" },
+            })))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(
+            body(&resp)["data"],
+            json!({ "hello": "hello choko", "agent": "tests" })
+        );
+
+        let resp = app
+            .dispatch(post(json!([
+                { "query": "{ hello }" },
+                { "query": "mutation { ping }" },
+            ])))
+            .await
+            .unwrap();
+        let results = body(&resp);
+        assert_eq!(results[0]["data"]["hello"], "hello world");
+        assert_eq!(results[1]["data"]["ping"], true);
+    }
+
+    #[tokio::test]
+    async fn runs_persisted_queries_over_get() {
+        let query = "{ hello }";
+        let extensions =
+            json!({ "persistedQuery": { "version": 1, "sha256Hash": hash(query) } }).to_string();
+        let app = app(|g| g.persisted_queries([query]));
+        let resp = app
+            .dispatch(get(&[("extensions", &extensions)]))
+            .await
+            .unwrap();
+        assert_eq!(body(&resp)["data"]["hello"], "hello world");
+
+        let unknown =
+            json!({ "persistedQuery": { "version": 1, "sha256Hash": hash("{ agent }") } })
+                .to_string();
+        let resp = app
+            .dispatch(get(&[("extensions", &unknown)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            body(&resp)["errors"][0]["message"],
+            "PersistedQueryNotFound"
+        );
+
+        let resp = app
+            .dispatch(get(&[("query", "mutation { ping }")]))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 405);
+    }
+
+    #[tokio::test]
+    async fn learns_automatic_persisted_queries() {
+        let query = "{ agent }";
+        let extensions = json!({ "persistedQuery": { "version": 1, "sha256Hash": hash(query) } });
+        let app = app(|g| g.automatic_persisted_queries());
+        let resp = app
+            .dispatch(get(&[("extensions", &extensions.to_string())]))
+            .await
+            .unwrap();
+        assert_eq!(
+            body(&resp)["errors"][0]["message"],
+            "PersistedQueryNotFound"
+        );
+
+        app.dispatch(post(json!({ "query": query, "extensions": extensions })))
+            .await
+            .unwrap();
+        let resp = app
+            .dispatch(get(&[("extensions", &extensions.to_string())]))
+            .await
+            .unwrap();
+        assert_eq!(body(&resp)["data"], json!({ "agent": null }));
+    }
+
+    #[tokio::test]
+    async fn caps_learned_queries() {
+        let app = app(|g| {
+            g.automatic_persisted_queries()
+                .max_learned_queries(2)
+                .max_learned_query_len(20)
+        });
+        let extensions =
+            |query: &str| json!({ "persistedQuery": { "version": 1, "sha256Hash": hash(query) } });
+        let learn = |query: &'static str| {
+            app.dispatch(post(
+                json!({ "query": query, "extensions": extensions(query) }),
+            ))
+        };
+        let known = |query: &'static str| {
+            let app = &app;
+            async move {
+                let resp = app
+                    .dispatch(get(&[("extensions", &extensions(query).to_string())]))
+                    .await
+                    .unwrap();
+                body(&resp)["errors"][0]["message"] != "PersistedQueryNotFound"
+            }
+        };
+
+        learn("{ hello }").await.unwrap();
+        learn("{ agent }").await.unwrap();
+        assert!(known("{ hello }").await);
+        learn("{ hello agent }").await.unwrap();
+        assert!(known("{ hello }").await);
+        assert!(!known("{ agent }").await);
+        assert!(known("{ hello agent }").await);
+
+        let long = "{ hello agent hello: hello }";
+        learn(long).await.unwrap();
+        assert!(!known(long).await);
+    }
+
+    #[tokio::test]
+    async fn serves_graphiql() {
+        let resp = app(GraphQL::graphiql).dispatch(get(&[])).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert!(matches!(body(&resp), Value::String(page) if page.contains("/graphql")));
+
+        let resp = app(|g| g).dispatch(get(&[])).await.unwrap();
+        assert_eq!(resp.status_code, 400);
+    }
+}
@@ -0,0 +1,159 @@
+//! Typed request header access, including `Authorization` parsing.
+
+use crate::Request;
+use base64::Engine;
+
+/// A header that can be decoded from its string value.
+///
+/// Implement this for application-specific headers to read them through
+/// [`Request::typed_header`].
+pub trait TypedHeader: Sized {
+    /// The header name, in lowercase.
+    const NAME: &'static str;
+
+    /// Decode the header value, returning `None` if it is malformed.
+    fn decode(value: &str) -> Option<Self>;
+}
+
+/// Username and password from `Authorization: Basic ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A parsed `Authorization` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// `Bearer <token>`
+    Bearer(String),
+    /// `Basic <base64(user:pass)>`
+    Basic(BasicCredentials),
+    /// Any other scheme, with the scheme lowercased and the rest untouched.
+    Other { scheme: String, credentials: String },
+}
+
+impl TypedHeader for Authorization {
+    const NAME: &'static str = "authorization";
+
+    fn decode(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (scheme, rest) = value.split_once(char::is_whitespace)?;
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return None;
+        }
+        let scheme = scheme.to_ascii_lowercase();
+        match scheme.as_str() {
+            "bearer" => Some(Authorization::Bearer(rest.to_string())),
+            "basic" => {
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(rest)
+                    .ok()?;
+                let decoded = String::from_utf8(decoded).ok()?;
+                let (username, password) = decoded.split_once(':')?;
+                Some(Authorization::Basic(BasicCredentials {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                }))
+            }
+            _ => Some(Authorization::Other {
+                scheme,
+                credentials: rest.to_string(),
+            }),
+        }
+    }
+}
+
+impl Request {
+    /// Look up a header value by name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        match self.headers.get(name) {
+            Some(v) => Some(v.as_str()),
+            None => self
+                .headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str()),
+        }
+    }
+
+    /// Decode a header into a [`TypedHeader`], returning `None` if it is
+    /// missing or malformed.
+    pub fn typed_header<H: TypedHeader>(&self) -> Option<H> {
+        self.header(H::NAME).and_then(H::decode)
+    }
+
+    /// The token from an `Authorization: Bearer <token>` header.
+    ///
+    /// The scheme is matched case-insensitively and surrounding whitespace is
+    /// ignored.
+    pub fn bearer_token(&self) -> Option<String> {
+        match self.typed_header::<Authorization>()? {
+            Authorization::Bearer(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// The decoded credentials from an `Authorization: Basic ...` header.
+    pub fn basic_credentials(&self) -> Option<BasicCredentials> {
+        match self.typed_header::<Authorization>()? {
+            Authorization::Basic(creds) => Some(creds),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_auth(value: &str) -> Request {
+        let mut req = Request::default();
+        req.headers
+            .insert("authorization".to_string(), value.to_string());
+        req
+    }
+
+    #[test]
+    fn bearer_token_is_case_and_whitespace_insensitive() {
+        assert_eq!(
+            request_with_auth("bearer   abc.def.ghi ").bearer_token(),
+            Some("abc.def.ghi".to_string())
+        );
+        assert_eq!(
+            request_with_auth("Bearer xyz").bearer_token(),
+            Some("xyz".to_string())
+        );
+        assert_eq!(request_with_auth("Bearer").bearer_token(), None);
+        assert_eq!(request_with_auth("Basic dTpw").bearer_token(), None);
+    }
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        // "alice:s3cr:et"
+        let creds = request_with_auth("BASIC YWxpY2U6czNjcjpldA==")
+            .basic_credentials()
+            .unwrap();
+        assert_eq!(creds.username, "alice");
+        assert_eq!(creds.password, "s3cr:et");
+        assert_eq!(request_with_auth("Basic !!!").basic_credentials(), None);
+    }
+
+    #[test]
+    fn typed_header_supports_custom_headers() {
+        struct Tenant(String);
+        impl TypedHeader for Tenant {
+            const NAME: &'static str = "x-tenant-id";
+            fn decode(value: &str) -> Option<Self> {
+                Some(Tenant(value.trim().to_string()))
+            }
+        }
+
+        let mut req = Request::default();
+        req.headers
+            .insert("X-Tenant-Id".to_string(), " acme ".to_string());
+        assert_eq!(req.typed_header::<Tenant>().unwrap().0, "acme");
+        assert_eq!(req.header("x-TENANT-id"), Some(" acme "));
+    }
+}
@@ -0,0 +1,170 @@
+//! Health check endpoint.
+//!
+//! [`Choko::health`] registers a `GET` route answering with a consistent
+//! JSON body: overall status plus, for each readiness check, its status,
+//! duration and error. With no checks it is a plain liveness probe.
+//!
+//! ```text
+//! {"status":"fail","checks":{"orders-table":{"status":"pass","duration_ms":14},"payments-api":{"status":"fail","duration_ms":2000,"error":"timed out"}}}
+//! ```
+//!
+//! # Example
+//! ```ignore
+//! use choko::health::Health;
+//!
+//! let ddb = dynamodb.clone();
+//! app.health(
+//!     "/healthz",
+//!     Health::new().check("orders-table", move || {
+//!         let ddb = ddb.clone();
+//!         async move {
+//!             ddb.describe_table().table_name("orders").send().await?;
+//!             Ok(())
+//!         }
+//!     }),
+//! );
+//! ```
+
+use crate::{BoxFuture, Choko, Error, Response, Route};
+use serde_json::{json, Map, Value};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+type CheckFn = Arc<dyn Fn() -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// The readiness checks served by a health route.
+#[derive(Clone)]
+pub struct Health {
+    checks: Vec<(String, CheckFn)>,
+    timeout: Duration,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    /// No checks: the route only reports that the function is alive.
+    pub fn new() -> Self {
+        Self {
+            checks: Vec::new(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Add a readiness check. It passes when `probe` returns `Ok`.
+    pub fn check<F, Fut>(mut self, name: &str, probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.checks
+            .push((name.to_string(), Arc::new(move || Box::pin(probe()))));
+        self
+    }
+
+    /// How long each check may take before it fails (default 2s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run every check concurrently and build the response: 200 if all
+    /// pass, otherwise 503.
+    pub async fn report(&self) -> Response {
+        let mut tasks = JoinSet::new();
+        for (index, (_, probe)) in self.checks.iter().enumerate() {
+            let probe = Arc::clone(probe);
+            let timeout = self.timeout;
+            tasks.spawn(async move {
+                let started = Instant::now();
+                let outcome = match tokio::time::timeout(timeout, probe()).await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(_) => Some("timed out".to_string()),
+                };
+                (index, outcome, started.elapsed())
+            });
+        }
+        let mut results = vec![None; self.checks.len()];
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((index, outcome, elapsed)) => results[index] = Some((outcome, elapsed)),
+                Err(e) => eprintln!("Health check panicked: {e}"),
+            }
+        }
+
+        let mut healthy = true;
+        let mut checks = Map::new();
+        for ((name, _), result) in self.checks.iter().zip(results) {
+            let (error, elapsed) = result.unwrap_or((Some("panicked".to_string()), Duration::ZERO));
+            let mut entry = json!({
+                "status": if error.is_none() { "pass" } else { "fail" },
+                "duration_ms": elapsed.as_millis() as u64,
+            });
+            if let Some(error) = error {
+                healthy = false;
+                entry["error"] = Value::String(error);
+            }
+            checks.insert(name.clone(), entry);
+        }
+
+        let mut body = json!({ "status": if healthy { "pass" } else { "fail" } });
+        if !checks.is_empty() {
+            body["checks"] = Value::Object(checks);
+        }
+        Response::json(body)
+            .with_status(if healthy { 200 } else { 503 })
+            .with_header("Cache-Control", "no-store")
+    }
+}
+
+impl Choko {
+    /// Register a `GET` health route at `path` running `health`'s checks.
+    ///
+    /// Pass [`Health::new`] for a liveness-only endpoint.
+    pub fn health(&mut self, path: &str, health: Health) -> &mut Route {
+        let health = Arc::new(health);
+        self.get(path, move |_req| {
+            let health = Arc::clone(&health);
+            async move { Ok(health.report().await) }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn liveness_only() {
+        let resp = Health::new().report().await;
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.body, json!({"status": "pass"}));
+    }
+
+    #[tokio::test]
+    async fn failing_and_slow_checks_give_503() {
+        let health = Health::new()
+            .timeout(Duration::from_millis(20))
+            .check("db", || async { Ok(()) })
+            .check("api", || async { Err("connection refused".into()) })
+            .check("slow", || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            });
+        let resp = health.report().await;
+        assert_eq!(resp.status_code, 503);
+        let crate::ResponseBody::Json(body) = resp.body else {
+            panic!("expected JSON body");
+        };
+        assert_eq!(body["status"], "fail");
+        assert_eq!(body["checks"]["db"]["status"], "pass");
+        assert_eq!(body["checks"]["api"]["error"], "connection refused");
+        assert_eq!(body["checks"]["slow"]["error"], "timed out");
+    }
+}
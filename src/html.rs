@@ -0,0 +1,92 @@
+//! HTML responses and template rendering (`askama` feature).
+
+#[cfg(feature = "askama")]
+use crate::Error;
+use crate::Response;
+
+impl Response {
+    /// A `text/html; charset=utf-8` response with status 200.
+    pub fn html(body: impl Into<String>) -> Self {
+        Response::text(body).with_header("Content-Type", "text/html; charset=utf-8")
+    }
+
+    /// Render an askama template as an HTML response.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(askama::Template)]
+    /// #[template(path = "callback.html")]
+    /// struct Callback<'a> { user: &'a str }
+    ///
+    /// Ok(Response::template(&Callback { user: "alice" })?)
+    /// ```
+    #[cfg(feature = "askama")]
+    pub fn template<T: askama::Template>(template: &T) -> Result<Self, Error> {
+        Ok(Response::html(template.render()?))
+    }
+}
+
+/// An HTML page, converted into a `text/html` [`Response`].
+///
+/// Wraps either a rendered string, or with the `askama` feature a template
+/// rendered by [`Html::render`]:
+///
+/// ```ignore
+/// Ok(Html("<h1>Signed in</h1>").into())
+/// Ok(Html(StatusPage { healthy: true }).render()?)
+/// ```
+#[derive(Debug, Clone)]
+pub struct Html<T>(pub T);
+
+impl From<Html<String>> for Response {
+    fn from(html: Html<String>) -> Self {
+        Response::html(html.0)
+    }
+}
+
+impl From<Html<&str>> for Response {
+    fn from(html: Html<&str>) -> Self {
+        Response::html(html.0)
+    }
+}
+
+#[cfg(feature = "askama")]
+impl<T: askama::Template> Html<T> {
+    /// Render the template into an HTML response.
+    pub fn render(&self) -> Result<Response, Error> {
+        Response::template(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+
+    #[test]
+    fn html_sets_content_type() {
+        let resp: Response = Html("<p>hi</p>").into();
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(resp.body, ResponseBody::Text("<p>hi</p>".to_string()));
+    }
+
+    #[cfg(feature = "askama")]
+    #[test]
+    fn template_renders_html() {
+        #[derive(askama::Template)]
+        #[template(source = "<h1>Hello, {{ name }}</h1>", ext = "html")]
+        struct Hello<'a> {
+            name: &'a str,
+        }
+
+        let resp = Html(Hello { name: "<tea>" }).render().unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text("<h1>Hello, &lt;tea&gt;</h1>".to_string())
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Outbound HTTP client with timeouts and retries (`http-client` feature).
+//!
+//! [`HttpClient`] wraps a `reqwest::Client` with defaults suited to Lambda:
+//! a 2-second connect timeout, a 5-second timeout per attempt, and retries
+//! with exponential backoff for idempotent requests that fail to connect,
+//! time out, or get 429, 502, 503 or 504 (honouring `Retry-After`).
+//!
+//! [`OutgoingRequest::propagate`] forwards the incoming request's W3C trace
+//! context and request ID, and keeps every attempt and backoff within the
+//! Lambda deadline. With the `tracing` feature each call runs in an
+//! `http.client` span recording the status and number of attempts.
+//!
+//! Keep one client in app state, so connections are pooled across
+//! invocations.
+//!
+//! # Example
+//! ```ignore
+//! use choko::http_client::HttpClient;
+//!
+//! app.state(HttpClient::new());
+//!
+//! app.get("/quotes/{id}", |req| async move {
+//!     let http = req.state::<HttpClient>().ok_or("http client not configured")?;
+//!     let quote: Value = http
+//!         .get(format!("https://pricing.internal/quotes/{}", req.path_params["id"]))
+//!         .propagate(&req)
+//!         .send()
+//!         .await?
+//!         .error_for_status()?
+//!         .json()
+//!         .await?;
+//!     Ok(Response::json(quote))
+//! });
+//! ```
+
+use crate::timeout::DEFAULT_MARGIN;
+use crate::{Error, Request, REQUEST_ID_HEADER};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// Statuses worth retrying: the upstream is overloaded or briefly down.
+const RETRYABLE_STATUSES: [StatusCode; 4] = [
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// An HTTP client for calling other services. Cheap to clone; clones share
+/// the connection pool.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    timeout: Duration,
+    retries: u32,
+    base_delay: Duration,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClient {
+    /// A client with a 2-second connect timeout, a 5-second timeout per
+    /// attempt and two retries.
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(2))
+            .build()
+            .expect("the default HTTP client configuration is valid");
+        Self::with_client(client)
+    }
+
+    /// Wrap a configured `reqwest::Client`, e.g. one with a user agent or
+    /// client certificate.
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            timeout: Duration::from_secs(5),
+            retries: 2,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+
+    /// The timeout for each attempt.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times an idempotent request is retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// The delay before the first retry; each further retry doubles it.
+    pub fn backoff(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// The underlying client.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub fn get(&self, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        self.request(Method::GET, url)
+    }
+
+    pub fn post(&self, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        self.request(Method::POST, url)
+    }
+
+    pub fn put(&self, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn patch(&self, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        self.request(Method::PATCH, url)
+    }
+
+    pub fn delete(&self, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        self.request(Method::DELETE, url)
+    }
+
+    /// Start a `method` request to `url`.
+    pub fn request(&self, method: Method, url: impl AsRef<str>) -> OutgoingRequest<'_> {
+        let url = url.as_ref();
+        let idempotent = matches!(
+            method,
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+        );
+        OutgoingRequest {
+            http: self,
+            builder: self.client.request(method.clone(), url),
+            method,
+            url: url.to_string(),
+            idempotent,
+            timeout: self.timeout,
+            deadline: None,
+        }
+    }
+}
+
+/// A request being built by [`HttpClient`].
+pub struct OutgoingRequest<'a> {
+    http: &'a HttpClient,
+    builder: reqwest::RequestBuilder,
+    method: Method,
+    url: String,
+    idempotent: bool,
+    timeout: Duration,
+    deadline: Option<Instant>,
+}
+
+impl OutgoingRequest<'_> {
+    pub fn header(mut self, name: &str, value: impl AsRef<str>) -> Self {
+        self.builder = self.builder.header(name, value.as_ref());
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: impl std::fmt::Display) -> Self {
+        self.builder = self.builder.bearer_auth(token);
+        self
+    }
+
+    /// Add query parameters.
+    pub fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.builder = self.builder.query(query);
+        self
+    }
+
+    /// Send `body` as JSON.
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.builder = self.builder.json(body);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<reqwest::Body>) -> Self {
+        self.builder = self.builder.body(body);
+        self
+    }
+
+    /// The timeout for each attempt of this request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Allow retries for a request that is safe to repeat although its
+    /// method isn't, e.g. a `POST` carrying an `Idempotency-Key`.
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Forward `req`'s `traceparent`, `tracestate` and request ID, and
+    /// finish before its Lambda deadline (less a 500ms margin).
+    pub fn propagate(mut self, req: &Request) -> Self {
+        if let Some(trace) = req.trace_context() {
+            for (name, value) in trace.headers() {
+                self.builder = self.builder.header(name, value);
+            }
+        }
+        if let Some(id) = req.request_id() {
+            self.builder = self.builder.header(REQUEST_ID_HEADER, id);
+        }
+        if let Some(ctx) = req.lambda_context() {
+            let remaining = ctx.remaining_time().saturating_sub(DEFAULT_MARGIN);
+            self.deadline = Some(Instant::now() + remaining);
+        }
+        self
+    }
+
+    /// Send the request, retrying as configured. Responses with error
+    /// statuses are returned as they are; see
+    /// `reqwest::Response::error_for_status`.
+    pub async fn send(self) -> Result<reqwest::Response, Error> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "http.client",
+            http.method = %self.method,
+            url.full = %self.url,
+            http.status_code = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+        );
+        let sent = self.send_with_retries();
+        #[cfg(feature = "tracing")]
+        let sent = tracing::Instrument::instrument(sent, span);
+        sent.await
+    }
+
+    async fn send_with_retries(self) -> Result<reqwest::Response, Error> {
+        let OutgoingRequest {
+            http,
+            builder,
+            method,
+            url,
+            idempotent,
+            timeout,
+            deadline,
+        } = self;
+        let retries = if idempotent { http.retries } else { 0 };
+        let mut builder = Some(builder);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let attempt_timeout = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(format!(
+                            "{method} {url}: no time left before the Lambda deadline"
+                        )
+                        .into());
+                    }
+                    timeout.min(left)
+                }
+                None => timeout,
+            };
+            let retry = builder
+                .as_ref()
+                .filter(|_| attempts <= retries)
+                .and_then(|b| b.try_clone());
+            let last = retry.is_none();
+            let current = match retry {
+                Some(current) => current,
+                None => builder
+                    .take()
+                    .expect("the request is kept until the last attempt"),
+            };
+
+            let result = current.timeout(attempt_timeout).send().await;
+            #[cfg(feature = "tracing")]
+            {
+                let span = tracing::Span::current();
+                span.record("attempts", attempts);
+                if let Ok(resp) = &result {
+                    span.record("http.status_code", resp.status().as_u16());
+                }
+            }
+            let wait = match &result {
+                Ok(resp) if RETRYABLE_STATUSES.contains(&resp.status()) => {
+                    Some(retry_after(resp).unwrap_or_else(|| backoff(http.base_delay, attempts)))
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    Some(backoff(http.base_delay, attempts))
+                }
+                _ => None,
+            };
+            let fits = |wait: Duration| deadline.is_none_or(|d| Instant::now() + wait < d);
+            match wait {
+                Some(wait) if !last && fits(wait) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        attempt = attempts,
+                        wait_ms = wait.as_millis() as u64,
+                        "retrying HTTP request"
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+}
+
+/// The delay before retry number `attempts`, with jitter.
+fn backoff(base_delay: Duration, attempts: u32) -> Duration {
+    let backoff = base_delay * 2u32.saturating_pow(attempts - 1);
+    let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    backoff.mul_f64(0.5 + jitter / 2.0)
+}
+
+/// The delay a `Retry-After: <seconds>` header asks for.
+fn retry_after(resp: &reqwest::Response) -> Option<Duration> {
+    let seconds: u64 = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A server answering each connection with the next of `statuses` and
+    /// recording the requests it received.
+    async fn serve(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                seen.lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&buf[..n]).to_string());
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn retries_idempotent_requests() {
+        let (url, requests) = serve(vec![503, 200]).await;
+        let http = HttpClient::new().backoff(Duration::ZERO);
+        let resp = http.get(&url).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_posts() {
+        let (url, requests) = serve(vec![503, 200]).await;
+        let http = HttpClient::new().backoff(Duration::ZERO);
+        let resp = http.post(&url).json(&[1]).send().await.unwrap();
+        assert_eq!(resp.status(), 503);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn propagates_trace_context() {
+        let (url, requests) = serve(vec![200]).await;
+        let mut req = Request::default();
+        req.extensions_mut()
+            .insert(crate::trace_context::TraceContext::from_headers(|name| {
+                (name == "traceparent")
+                    .then_some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+            }));
+        HttpClient::new()
+            .get(&url)
+            .propagate(&req)
+            .send()
+            .await
+            .unwrap();
+        let request = requests.lock().unwrap()[0].to_ascii_lowercase();
+        assert!(request.contains("traceparent: 00-0af7651916cd43dd8448eb211c80319c-"));
+    }
+}
@@ -0,0 +1,204 @@
+//! Conversions between plain `http` messages and the API Gateway events the
+//! router works on, for running outside Lambda or through `tower` layers.
+
+use crate::{Choko, Error, LambdaContext};
+use aws_lambda_events::encodings::Body;
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use aws_lambda_events::query_map::QueryMap;
+use base64::Engine;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// The API Gateway event equivalent to `req`, received from `peer`.
+///
+/// A request made by [`from_apigw_request`] carries its original event in
+/// its extensions; the request context and stage come from there, and the
+/// method, path, headers, query and body from `req`.
+pub(crate) fn to_apigw_request(
+    req: http::Request<Vec<u8>>,
+    peer: Option<SocketAddr>,
+) -> ApiGatewayProxyRequest {
+    let (mut parts, body) = req.into_parts();
+    let mut event = parts
+        .extensions
+        .remove::<ApiGatewayProxyRequest>()
+        .unwrap_or_default();
+    event.http_method = parts.method;
+    event.path = Some(parts.uri.path().to_string());
+    event.headers = parts.headers;
+    event.multi_value_headers = http::HeaderMap::new();
+    event.query_string_parameters = QueryMap::default();
+    event.body = None;
+    event.is_base64_encoded = false;
+
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_str(parts.uri.query().unwrap_or("")).unwrap_or_default();
+    for (k, v) in pairs {
+        params.entry(k).or_default().push(v);
+    }
+    event.multi_value_query_string_parameters = QueryMap::from(params);
+
+    if !body.is_empty() {
+        match String::from_utf8(body) {
+            Ok(text) => event.body = Some(text),
+            Err(e) => {
+                event.body = Some(base64::engine::general_purpose::STANDARD.encode(e.into_bytes()));
+                event.is_base64_encoded = true;
+            }
+        }
+    }
+    let ctx = &mut event.request_context;
+    ctx.http_method = event.http_method.clone();
+    if let Some(peer) = peer {
+        ctx.identity.source_ip = Some(peer.ip().to_string());
+    }
+    if let Some(agent) = event.headers.get(http::header::USER_AGENT) {
+        ctx.identity.user_agent = agent.to_str().ok().map(str::to_string);
+    }
+    event
+}
+
+/// The `http` request equivalent to `event`, with the event itself and
+/// `context` in its extensions.
+#[cfg(feature = "tower")]
+pub(crate) fn from_apigw_request(
+    event: ApiGatewayProxyRequest,
+    context: Option<LambdaContext>,
+) -> Result<http::Request<Vec<u8>>, Error> {
+    let mut uri = event.path.clone().unwrap_or_else(|| "/".to_string());
+    let mut query: Vec<(&str, &str)> = event.multi_value_query_string_parameters.iter().collect();
+    if query.is_empty() {
+        query = event.query_string_parameters.iter().collect();
+    }
+    if !query.is_empty() {
+        uri.push('?');
+        uri.push_str(&serde_urlencoded::to_string(&query)?);
+    }
+    let body = match event.body.as_deref() {
+        Some(body) if event.is_base64_encoded => {
+            base64::engine::general_purpose::STANDARD.decode(body)?
+        }
+        Some(body) => body.as_bytes().to_vec(),
+        None => Vec::new(),
+    };
+    let mut req = http::Request::new(body);
+    *req.method_mut() = event.http_method.clone();
+    *req.uri_mut() = uri.parse()?;
+    *req.headers_mut() = event.headers.clone();
+    for (name, value) in event.multi_value_headers.iter() {
+        if !event.headers.contains_key(name) {
+            req.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    if let Some(context) = context {
+        req.extensions_mut().insert(context);
+    }
+    req.extensions_mut().insert(event);
+    Ok(req)
+}
+
+/// The `http` response equivalent to `resp`.
+pub(crate) fn to_http_response(resp: ApiGatewayProxyResponse) -> http::Response<Vec<u8>> {
+    let body = match resp.body {
+        Some(Body::Text(text)) => text.into_bytes(),
+        Some(Body::Binary(bytes)) => bytes,
+        _ => Vec::new(),
+    };
+    let mut out = http::Response::new(body);
+    *out.status_mut() = http::StatusCode::from_u16(u16::try_from(resp.status_code).unwrap_or(500))
+        .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+    let headers = out.headers_mut();
+    *headers = resp.headers;
+    for (name, value) in resp.multi_value_headers.iter() {
+        headers.append(name.clone(), value.clone());
+    }
+    out
+}
+
+/// The API Gateway response equivalent to `resp`. Bodies that aren't valid
+/// UTF-8 are sent base64-encoded.
+#[cfg(feature = "tower")]
+pub(crate) fn to_apigw_response(resp: http::Response<Vec<u8>>) -> ApiGatewayProxyResponse {
+    let (parts, body) = resp.into_parts();
+    let mut out = ApiGatewayProxyResponse::default();
+    out.status_code = i64::from(parts.status.as_u16());
+    for name in parts.headers.keys() {
+        let values = parts.headers.get_all(name);
+        if values.iter().count() == 1 {
+            out.headers
+                .insert(name.clone(), parts.headers[name].clone());
+        } else {
+            for value in values {
+                out.multi_value_headers.append(name.clone(), value.clone());
+            }
+        }
+    }
+    if !body.is_empty() {
+        match String::from_utf8(body) {
+            Ok(text) => out.body = Some(Body::Text(text)),
+            Err(e) => {
+                out.body = Some(Body::Binary(e.into_bytes()));
+                out.is_base64_encoded = true;
+            }
+        }
+    }
+    out
+}
+
+impl Choko {
+    /// Handle a plain HTTP request with the routes, middleware and hooks,
+    /// exactly as an API Gateway request for the same path.
+    pub async fn call_http(
+        &self,
+        req: http::Request<Vec<u8>>,
+        peer: Option<SocketAddr>,
+    ) -> Result<http::Response<Vec<u8>>, Error> {
+        let context = req.extensions().get::<LambdaContext>().cloned();
+        let resp = self
+            .dispatch_with_context(to_apigw_request(req, peer), context)
+            .await?;
+        Ok(to_http_response(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn round_trips_through_the_router() {
+        let mut app = Choko::new("test");
+        app.post("/items/{id}", |req| async move {
+            Ok(Response::json(json!({
+                "id": req.path_params["id"],
+                "tag": req.query_params.get("tag"),
+                "body": req.json_body,
+                "ip": req.request_context.source_ip,
+            }))
+            .with_status(201)
+            .with_header("x-item", "1"))
+        });
+
+        let req = http::Request::post("/items/7?tag=a&tag=b")
+            .header("content-type", "application/json")
+            .body(br#"{"name":"lamp"}"#.to_vec())
+            .unwrap();
+        let resp = app
+            .call_http(req, Some("127.0.0.1:5000".parse().unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 201);
+        assert_eq!(resp.headers()["x-item"], "1");
+        let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert_eq!(
+            body,
+            json!({ "id": "7", "tag": ["a", "b"], "body": { "name": "lamp" }, "ip": "127.0.0.1" })
+        );
+
+        let req = http::Request::get("/missing").body(Vec::new()).unwrap();
+        assert_eq!(app.call_http(req, None).await.unwrap().status(), 404);
+    }
+}
@@ -0,0 +1,280 @@
+//! Idempotency records in DynamoDB (`dynamodb` feature).
+
+use super::{IdempotencyRecord, IdempotencyStore};
+use crate::{BoxFuture, Error};
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValuesOnConditionCheckFailure};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const IN_PROGRESS: &str = "IN_PROGRESS";
+const COMPLETED: &str = "COMPLETED";
+
+/// Records kept in a DynamoDB table, shared by every Lambda instance.
+///
+/// A key is claimed with one conditional `PutItem`, which only succeeds if
+/// the key is unused or its lock or record has expired, so two instances
+/// can't claim the same key. Each claim carries a random token, and the
+/// response is only recorded (or the claim released) while the item still
+/// holds that token: if the lock expired and another instance re-claimed
+/// the key, its record is left alone.
+///
+/// The table needs a string partition key (default `pk`); enable TTL on the
+/// `expires_at` attribute so old records are cleaned up.
+#[derive(Debug, Clone)]
+pub struct DynamoDbStore {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    key_attribute: String,
+    /// Tokens of the claims this store holds, by key.
+    claims: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl DynamoDbStore {
+    /// Store records in `table`.
+    pub fn new(client: aws_sdk_dynamodb::Client, table: impl Into<String>) -> Self {
+        Self {
+            client,
+            table: table.into(),
+            key_attribute: "pk".to_string(),
+            claims: Arc::default(),
+        }
+    }
+
+    /// The partition key attribute name.
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        self.key_attribute = name.into();
+        self
+    }
+
+    /// Take the token of this store's claim on `key`.
+    fn take_claim(&self, key: &str) -> Result<String, Error> {
+        self.claims
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key)
+            .ok_or_else(|| format!("idempotency key {key} was not claimed by this store").into())
+    }
+}
+
+/// 128 bits of randomness identifying one claim.
+fn claim_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("OS random source is available");
+    hex::encode(bytes)
+}
+
+/// Our claim's lock expired and another request claimed the key again.
+fn log_lost_claim(key: &str) {
+    eprintln!(
+        "Idempotency key {key} was claimed again after its lock expired; keeping the new claim"
+    );
+}
+
+/// The condition that `key` is still held by the claim with `token`.
+const STILL_CLAIMED: &str = "#status = :in_progress AND fingerprint = :fp AND #claim = :claim";
+
+fn now() -> Result<u64, Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+fn number(value: u64) -> AttributeValue {
+    AttributeValue::N(value.to_string())
+}
+
+/// The record in an item returned by DynamoDB.
+fn record(item: &HashMap<String, AttributeValue>) -> Result<IdempotencyRecord, Error> {
+    let string = |name: &str| match item.get(name) {
+        Some(AttributeValue::S(s)) => Ok(s.clone()),
+        _ => Err(format!("idempotency record is missing {name}")),
+    };
+    let fingerprint = string("fingerprint")?;
+    match string("status")?.as_str() {
+        COMPLETED => Ok(IdempotencyRecord::Completed {
+            fingerprint,
+            response: string("response")?.into_bytes(),
+        }),
+        _ => Ok(IdempotencyRecord::InProgress { fingerprint }),
+    }
+}
+
+impl IdempotencyStore for DynamoDbStore {
+    fn claim(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lock_for: Duration,
+    ) -> BoxFuture<Result<Option<IdempotencyRecord>, Error>> {
+        let this = self.clone();
+        let key = key.to_string();
+        let fingerprint = fingerprint.to_string();
+        Box::pin(async move {
+            let now = now()?;
+            let token = claim_token();
+            let result = this
+                .client
+                .put_item()
+                .table_name(&this.table)
+                .item(&this.key_attribute, AttributeValue::S(key.clone()))
+                .item("status", AttributeValue::S(IN_PROGRESS.to_string()))
+                .item("fingerprint", AttributeValue::S(fingerprint))
+                .item("claim", AttributeValue::S(token.clone()))
+                .item("expires_at", number(now + lock_for.as_secs().max(1)))
+                .condition_expression("attribute_not_exists(#pk) OR expires_at < :now")
+                .expression_attribute_names("#pk", &this.key_attribute)
+                .expression_attribute_values(":now", number(now))
+                .return_values_on_condition_check_failure(
+                    ReturnValuesOnConditionCheckFailure::AllOld,
+                )
+                .send()
+                .await;
+            let e = match result {
+                Ok(_) => {
+                    this.claims
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(key, token);
+                    return Ok(None);
+                }
+                Err(e) => e,
+            };
+            let taken = match e.as_service_error() {
+                Some(PutItemError::ConditionalCheckFailedException(failed)) => {
+                    failed.item().cloned()
+                }
+                _ => return Err(e.into()),
+            };
+            let item = taken.ok_or("DynamoDB returned no item for the taken idempotency key")?;
+            Ok(Some(record(&item)?))
+        })
+    }
+
+    fn complete(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        response: Vec<u8>,
+        keep_for: Duration,
+    ) -> BoxFuture<Result<(), Error>> {
+        let this = self.clone();
+        let key = key.to_string();
+        let fingerprint = fingerprint.to_string();
+        Box::pin(async move {
+            let token = this.take_claim(&key)?;
+            let response = String::from_utf8(response)?;
+            let result = this
+                .client
+                .put_item()
+                .table_name(&this.table)
+                .item(&this.key_attribute, AttributeValue::S(key.clone()))
+                .item("status", AttributeValue::S(COMPLETED.to_string()))
+                .item("fingerprint", AttributeValue::S(fingerprint.clone()))
+                .item("response", AttributeValue::S(response))
+                .item("expires_at", number(now()? + keep_for.as_secs()))
+                .condition_expression(STILL_CLAIMED)
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_names("#claim", "claim")
+                .expression_attribute_values(":in_progress", AttributeValue::S(IN_PROGRESS.into()))
+                .expression_attribute_values(":fp", AttributeValue::S(fingerprint))
+                .expression_attribute_values(":claim", AttributeValue::S(token))
+                .send()
+                .await;
+            match result {
+                Ok(_) => Ok(()),
+                Err(e) => match e.as_service_error() {
+                    Some(PutItemError::ConditionalCheckFailedException(_)) => {
+                        log_lost_claim(&key);
+                        Ok(())
+                    }
+                    _ => Err(e.into()),
+                },
+            }
+        })
+    }
+
+    fn release(&self, key: &str) -> BoxFuture<Result<(), Error>> {
+        let this = self.clone();
+        let key = key.to_string();
+        Box::pin(async move {
+            let token = this.take_claim(&key)?;
+            let result = this
+                .client
+                .delete_item()
+                .table_name(&this.table)
+                .key(&this.key_attribute, AttributeValue::S(key.clone()))
+                .condition_expression("#status = :in_progress AND #claim = :claim")
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_names("#claim", "claim")
+                .expression_attribute_values(":in_progress", AttributeValue::S(IN_PROGRESS.into()))
+                .expression_attribute_values(":claim", AttributeValue::S(token))
+                .send()
+                .await;
+            match result {
+                Ok(_) => Ok(()),
+                Err(e) => match e.as_service_error() {
+                    Some(DeleteItemError::ConditionalCheckFailedException(_)) => {
+                        log_lost_claim(&key);
+                        Ok(())
+                    }
+                    _ => Err(e.into()),
+                },
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_records() {
+        let item = HashMap::from([
+            (
+                "status".to_string(),
+                AttributeValue::S(COMPLETED.to_string()),
+            ),
+            (
+                "fingerprint".to_string(),
+                AttributeValue::S("f".to_string()),
+            ),
+            ("response".to_string(), AttributeValue::S("{}".to_string())),
+        ]);
+        assert_eq!(
+            record(&item).unwrap(),
+            IdempotencyRecord::Completed {
+                fingerprint: "f".to_string(),
+                response: b"{}".to_vec()
+            }
+        );
+        let item = HashMap::from([
+            (
+                "status".to_string(),
+                AttributeValue::S(IN_PROGRESS.to_string()),
+            ),
+            (
+                "fingerprint".to_string(),
+                AttributeValue::S("f".to_string()),
+            ),
+        ]);
+        assert!(matches!(
+            record(&item).unwrap(),
+            IdempotencyRecord::InProgress { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_settles_its_own_claims() {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(aws_sdk_dynamodb::config::BehaviorVersion::latest())
+            .build();
+        let store = DynamoDbStore::new(aws_sdk_dynamodb::Client::from_conf(config), "t");
+        assert!(store
+            .complete("k", "f", b"{}".to_vec(), Duration::from_secs(60))
+            .await
+            .is_err());
+        assert!(store.release("k").await.is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Per-container in-memory idempotency store.
+
+use super::{IdempotencyRecord, IdempotencyStore};
+use crate::{BoxFuture, Error};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Records held in the Lambda container's memory.
+///
+/// Each container has its own records, so a retry routed to another
+/// instance runs again. For tests and local development.
+#[derive(Default)]
+pub struct MemoryStore {
+    records: Mutex<HashMap<String, (Instant, IdempotencyRecord)>>,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, IdempotencyRecord)>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl IdempotencyStore for MemoryStore {
+    fn claim(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lock_for: Duration,
+    ) -> BoxFuture<Result<Option<IdempotencyRecord>, Error>> {
+        let now = Instant::now();
+        let mut records = self.lock();
+        records.retain(|_, (expires, _)| *expires > now);
+        let existing = match records.get(key) {
+            Some((_, record)) => Some(record.clone()),
+            None => {
+                let record = IdempotencyRecord::InProgress {
+                    fingerprint: fingerprint.to_string(),
+                };
+                records.insert(key.to_string(), (now + lock_for, record));
+                None
+            }
+        };
+        Box::pin(async move { Ok(existing) })
+    }
+
+    fn complete(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        response: Vec<u8>,
+        keep_for: Duration,
+    ) -> BoxFuture<Result<(), Error>> {
+        let record = IdempotencyRecord::Completed {
+            fingerprint: fingerprint.to_string(),
+            response,
+        };
+        self.lock()
+            .insert(key.to_string(), (Instant::now() + keep_for, record));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn release(&self, key: &str) -> BoxFuture<Result<(), Error>> {
+        self.lock().remove(key);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expired_locks_can_be_claimed_again() {
+        let store = MemoryStore::new();
+        assert_eq!(store.claim("k", "f", Duration::ZERO).await.unwrap(), None);
+        assert_eq!(
+            store
+                .claim("k", "f", Duration::from_secs(60))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            store
+                .claim("k", "f", Duration::from_secs(60))
+                .await
+                .unwrap(),
+            Some(IdempotencyRecord::InProgress {
+                fingerprint: "f".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Idempotency middleware (`idempotency` feature).
+//!
+//! Clients retry requests that time out, and for endpoints such as payments
+//! a retry must not repeat the side effect. With [`Idempotency`], a request
+//! carrying an `Idempotency-Key` header is executed once; retries with the
+//! same key get the recorded response back, marked
+//! `Idempotent-Replayed: true`.
+//!
+//! The first request claims the key in an [`IdempotencyStore`] before the
+//! handler runs, so concurrent retries can't both execute it:
+//!
+//! - while the first request is still running, retries get 409 with
+//!   `Retry-After`;
+//! - reusing a key with a different query string or body answers 422;
+//! - a handler error or 5xx response releases the key, so the request can
+//!   be retried;
+//! - a streamed response can't be recorded, so retries after it get 409
+//!   instead of a replay;
+//! - a claim whose request died mid-flight (e.g. a Lambda timeout) expires
+//!   after the lock timeout.
+//!
+//! Keys are scoped to the method, path and `Authorization` header, so two
+//! clients choosing the same key don't see each other's responses. If the
+//! store can't be reached the request fails rather than risk running twice.
+//!
+//! [`MemoryStore`] works for tests and local development; use the DynamoDB
+//! store (`dynamodb` feature) in production.
+//!
+//! # Example
+//! ```ignore
+//! use choko::idempotency::{DynamoDbStore, Idempotency};
+//!
+//! let store = DynamoDbStore::new(dynamodb_client, "idempotency");
+//! app.post("/payments", create_payment)
+//!     .middleware(Idempotency::new(store).required());
+//! ```
+
+#[cfg(feature = "dynamodb")]
+mod dynamodb;
+mod memory;
+
+#[cfg(feature = "dynamodb")]
+pub use dynamodb::DynamoDbStore;
+pub use memory::MemoryStore;
+
+use crate::middleware::{Middleware, Next};
+use crate::response_cache::CachedResponse;
+use crate::{BoxFuture, Error, Request, Response};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The request header carrying the client's key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// The longest key accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// What a store already holds for a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyRecord {
+    /// A request with this key is running.
+    InProgress { fingerprint: String },
+    /// A request with this key completed with `response`.
+    Completed {
+        fingerprint: String,
+        response: Vec<u8>,
+    },
+}
+
+impl IdempotencyRecord {
+    fn fingerprint(&self) -> &str {
+        match self {
+            IdempotencyRecord::InProgress { fingerprint }
+            | IdempotencyRecord::Completed { fingerprint, .. } => fingerprint,
+        }
+    }
+}
+
+/// Where idempotency records live.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Claim `key` for a request with `fingerprint`, locking it for
+    /// `lock_for`. Returns `None` once claimed, or the existing record if
+    /// the key is taken. Expired locks and records can be claimed again.
+    fn claim(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lock_for: Duration,
+    ) -> BoxFuture<Result<Option<IdempotencyRecord>, Error>>;
+
+    /// Record the response for a claimed `key`, kept for `keep_for`.
+    fn complete(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        response: Vec<u8>,
+        keep_for: Duration,
+    ) -> BoxFuture<Result<(), Error>>;
+
+    /// Give up a claim, so the key can be used again.
+    fn release(&self, key: &str) -> BoxFuture<Result<(), Error>>;
+}
+
+/// Middleware executing requests with the same `Idempotency-Key` once.
+///
+/// Applies to `POST` and `PATCH` requests by default; other methods pass
+/// through.
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    methods: Vec<String>,
+    required: bool,
+    lock_timeout: Duration,
+    expires_after: Duration,
+    prefix: String,
+}
+
+impl Idempotency {
+    /// Keep records in `store` for 24 hours, locking keys for up to 60
+    /// seconds while their request runs.
+    pub fn new(store: impl IdempotencyStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            methods: vec!["POST".to_string(), "PATCH".to_string()],
+            required: false,
+            lock_timeout: Duration::from_secs(60),
+            expires_after: Duration::from_secs(24 * 60 * 60),
+            prefix: String::new(),
+        }
+    }
+
+    /// Answer 400 to requests without an `Idempotency-Key`.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// The methods this applies to, e.g. `&["POST", "PUT"]`.
+    pub fn methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+        self
+    }
+
+    /// How long a running request holds its key. Set it above the
+    /// function timeout, so a slow request isn't executed twice.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// How long completed responses are replayed.
+    pub fn expires_after(mut self, duration: Duration) -> Self {
+        self.expires_after = duration;
+        self
+    }
+
+    /// Namespace keys, so several apps can share a store.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn key(&self, req: &Request, idempotency_key: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            req.method(),
+            req.path(),
+            req.header("authorization").unwrap_or(""),
+            idempotency_key,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{}{}", self.prefix, hex::encode(hasher.finalize()))
+    }
+}
+
+/// The replay of a request whose response was streamed.
+const STREAMED: &str =
+    "A request with this Idempotency-Key already completed; its streamed response can't be replayed";
+
+/// A hash of the query parameters (sorted by name) and body, which a
+/// retry must repeat.
+fn fingerprint(req: &Request) -> String {
+    let mut params: Vec<_> = req.query_params.iter().collect();
+    params.sort_by_key(|(name, _)| *name);
+    let mut hasher = Sha256::new();
+    for (name, values) in params {
+        for value in values {
+            hasher.update(name.as_bytes());
+            hasher.update([0]);
+            hasher.update(value.as_bytes());
+            hasher.update([0]);
+        }
+    }
+    hasher.update([0]);
+    hasher.update(req.body_bytes().unwrap_or_default());
+    hex::encode(hasher.finalize())
+}
+
+impl Middleware for Idempotency {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if !self.methods.iter().any(|m| m == req.method()) {
+            return next.run(req);
+        }
+        let idempotency_key = match req.header(IDEMPOTENCY_KEY_HEADER) {
+            Some(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key,
+            Some(_) => {
+                let message =
+                    format!("{IDEMPOTENCY_KEY_HEADER} must be 1-{MAX_KEY_LEN} characters");
+                return Box::pin(async move { Ok(crate::error_json(400, &message)) });
+            }
+            None if self.required => {
+                let message = format!("{IDEMPOTENCY_KEY_HEADER} header is required");
+                return Box::pin(async move { Ok(crate::error_json(400, &message)) });
+            }
+            None => return next.run(req),
+        };
+        let key = self.key(&req, idempotency_key);
+        let fingerprint = fingerprint(&req);
+        let store = Arc::clone(&self.store);
+        let lock_timeout = self.lock_timeout;
+        let expires_after = self.expires_after;
+        Box::pin(async move {
+            match store.claim(&key, &fingerprint, lock_timeout).await? {
+                None => {}
+                Some(record) if record.fingerprint() != fingerprint => {
+                    return Ok(crate::error_json(
+                        422,
+                        "Idempotency-Key was already used for a different request",
+                    ));
+                }
+                Some(IdempotencyRecord::InProgress { .. }) => {
+                    return Ok(crate::error_json(
+                        409,
+                        "A request with this Idempotency-Key is in progress",
+                    )
+                    .with_header("Retry-After", "1"));
+                }
+                Some(IdempotencyRecord::Completed { response, .. }) => {
+                    let resp = serde_json::from_slice::<CachedResponse>(&response)?;
+                    return Ok(resp
+                        .into_response()?
+                        .with_header("Idempotent-Replayed", "true"));
+                }
+            }
+
+            let result = next.run(req).await;
+            let recorded = match &result {
+                // A streamed body can't be stored, but the request did run:
+                // keep the key completed so a retry doesn't run it again
+                Ok(resp) if resp.status_code < 500 => CachedResponse::capture(resp)
+                    .or_else(|| CachedResponse::capture(&crate::error_json(409, STREAMED))),
+                _ => None,
+            };
+            let stored = match recorded {
+                Some(recorded) => {
+                    let bytes = serde_json::to_vec(&recorded)?;
+                    store
+                        .complete(&key, &fingerprint, bytes, expires_after)
+                        .await
+                }
+                None => store.release(&key).await,
+            };
+            if let Err(e) = stored {
+                eprintln!("Failed to update idempotency record {key}: {e}");
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Choko;
+    use aws_lambda_events::event::apigw::ApiGatewayProxyRequest;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn post(body: &str, key: Option<&str>) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::POST;
+        event.path = Some("/payments".to_string());
+        event.body = Some(body.to_string());
+        if let Some(key) = key {
+            event
+                .headers
+                .insert("idempotency-key", key.parse().unwrap());
+        }
+        event
+    }
+
+    fn app(store: MemoryStore, calls: Arc<AtomicUsize>) -> Choko {
+        let mut app = Choko::new("test");
+        app.post("/payments", move |req| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                if req.body.as_deref() == Some("fail") {
+                    return Err("card declined".into());
+                }
+                Ok(Response::created(
+                    format!("/payments/{n}"),
+                    serde_json::json!({ "id": n }),
+                ))
+            }
+        })
+        .middleware(Idempotency::new(store).required());
+        app
+    }
+
+    #[tokio::test]
+    async fn replays_completed_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(MemoryStore::new(), Arc::clone(&calls));
+
+        let first = app.dispatch(post("{}", Some("k1"))).await.unwrap();
+        assert_eq!(first.status_code, 201);
+        let retry = app.dispatch(post("{}", Some("k1"))).await.unwrap();
+        assert_eq!(retry.status_code, 201);
+        assert_eq!(retry.body, first.body);
+        assert_eq!(retry.headers.get("idempotent-replayed").unwrap(), "true");
+        assert_eq!(retry.headers.get("location"), first.headers.get("location"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let reused = app
+            .dispatch(post(r#"{"amount":5}"#, Some("k1")))
+            .await
+            .unwrap();
+        assert_eq!(reused.status_code, 422);
+        let missing = app.dispatch(post("{}", None)).await.unwrap();
+        assert_eq!(missing.status_code, 400);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn reusing_a_key_with_another_query_string_is_rejected() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(MemoryStore::new(), Arc::clone(&calls));
+        let with_query = |query: &[(&str, &str)]| {
+            let mut event = post("{}", Some("k5"));
+            event.query_string_parameters = query
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+                .into();
+            event
+        };
+
+        let first = app
+            .dispatch(with_query(&[("amount", "5"), ("currency", "EUR")]))
+            .await
+            .unwrap();
+        assert_eq!(first.status_code, 201);
+        let retry = app
+            .dispatch(with_query(&[("currency", "EUR"), ("amount", "5")]))
+            .await
+            .unwrap();
+        assert_eq!(retry.status_code, 201);
+        assert_eq!(retry.headers.get("idempotent-replayed").unwrap(), "true");
+        let reused = app
+            .dispatch(with_query(&[("amount", "500"), ("currency", "EUR")]))
+            .await
+            .unwrap();
+        assert_eq!(reused.status_code, 422);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_requests_release_their_key() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(MemoryStore::new(), Arc::clone(&calls));
+        let resp = app.dispatch(post("fail", Some("k2"))).await.unwrap();
+        assert_eq!(resp.status_code, 500);
+        app.dispatch(post("fail", Some("k2"))).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn streamed_responses_are_not_run_again() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut app = Choko::new("test");
+        let counter = Arc::clone(&calls);
+        app.post("/payments", move |_req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let (tx, body) = crate::BodyStream::channel(1);
+                tokio::spawn(async move { tx.send("charged").await });
+                Ok(Response::stream(body))
+            }
+        })
+        .middleware(Idempotency::new(MemoryStore::new()));
+
+        let first = app.dispatch(post("{}", Some("k4"))).await.unwrap();
+        assert_eq!(first.status_code, 200);
+        let retry = app.dispatch(post("{}", Some("k4"))).await.unwrap();
+        assert_eq!(retry.status_code, 409);
+        assert_eq!(retry.headers.get("idempotent-replayed").unwrap(), "true");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_retries_conflict() {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let mut app = Choko::new("test");
+        let gate = Arc::clone(&release);
+        app.post("/payments", move |_req| {
+            let gate = Arc::clone(&gate);
+            async move {
+                gate.notified().await;
+                Ok(Response::text("charged"))
+            }
+        })
+        .middleware(Idempotency::new(MemoryStore::new()));
+
+        let (first, retry) = tokio::join!(app.dispatch(post("{}", Some("k3"))), async {
+            let retry = app.dispatch(post("{}", Some("k3"))).await;
+            release.notify_one();
+            retry
+        });
+        assert_eq!(first.unwrap().status_code, 200);
+        let retry = retry.unwrap();
+        assert_eq!(retry.status_code, 409);
+        assert_eq!(retry.headers.get("retry-after").unwrap(), "1");
+    }
+}
@@ -0,0 +1,424 @@
+//! A typed service container.
+//!
+//! [`Services`] holds one instance per type: SDK clients, database pools,
+//! repositories. Each is registered once at startup, either as a value or
+//! as an async constructor that runs the first time the service is needed.
+//! Constructors resolve their own dependencies through the [`Resolver`]
+//! they're given, so a repository can ask for the pool it wraps.
+//!
+//! Handlers resolve services with [`Request::inject`] instead of capturing
+//! a clone of each in every closure. Calling [`Services::init`] in `main`
+//! builds everything during the cold start, where a failure stops the
+//! function from starting; otherwise each service is built on first use.
+//!
+//! Concurrent requests for a service that is still being built wait for
+//! the one build. A failed build isn't kept, so the next request retries
+//! it. Dependency cycles are reported as errors rather than deadlocking,
+//! including ones closed by two requests building different services of the
+//! cycle at the same time.
+//!
+//! # Example
+//! ```ignore
+//! use choko::inject::{Resolver, Services};
+//!
+//! let services = Services::new()
+//!     .value(aws_sdk_dynamodb::Client::new(&aws_config::load_from_env().await))
+//!     .provide(|_| async { Ok(PgPoolOptions::new().connect(&database_url()).await?) })
+//!     .provide(|r: Resolver| async move {
+//!         Ok(UserRepo::new(r.get::<PgPool>().await?, r.get::<aws_sdk_dynamodb::Client>().await?))
+//!     });
+//! services.init().await?;
+//! app.state(services);
+//!
+//! app.get("/users/{id}", |req| async move {
+//!     let users = req.inject::<UserRepo>().await?;
+//!     let user = users.find(&req.path_params["id"]).await?;
+//!     Ok(Response::json(json!(user)))
+//! });
+//! ```
+
+use crate::{BoxFuture, Error, Request};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+type Instance = Arc<dyn Any + Send + Sync>;
+type Factory = Box<dyn Fn(Resolver) -> BoxFuture<Result<Instance, Error>> + Send + Sync>;
+
+struct Entry {
+    name: &'static str,
+    instance: OnceCell<Instance>,
+    factory: Option<Factory>,
+}
+
+/// A container of services keyed by type. Cheap to clone; clones share the
+/// same instances.
+#[derive(Clone, Default)]
+pub struct Services {
+    entries: Arc<HashMap<TypeId, Entry>>,
+    waits: Arc<Mutex<WaitGraph>>,
+}
+
+/// The services each running constructor is waiting for, across every task
+/// building one. A constructor waiting on something that already waits on
+/// it would never finish.
+#[derive(Default)]
+struct WaitGraph {
+    edges: HashMap<&'static str, Vec<&'static str>>,
+}
+
+impl WaitGraph {
+    fn reaches(&self, from: &'static str, to: &'static str) -> bool {
+        let mut stack = vec![from];
+        let mut seen = Vec::new();
+        while let Some(name) = stack.pop() {
+            if name == to {
+                return true;
+            }
+            if !seen.contains(&name) {
+                seen.push(name);
+                stack.extend(self.edges.get(name).into_iter().flatten().copied());
+            }
+        }
+        false
+    }
+
+    fn remove(&mut self, dependent: &'static str, name: &'static str) {
+        if let Some(names) = self.edges.get_mut(dependent) {
+            if let Some(i) = names.iter().position(|&n| n == name) {
+                names.swap_remove(i);
+            }
+            if names.is_empty() {
+                self.edges.remove(dependent);
+            }
+        }
+    }
+}
+
+/// Removes a wait edge once the dependency has been resolved (or the wait
+/// abandoned).
+struct Waiting<'a> {
+    graph: &'a Mutex<WaitGraph>,
+    dependent: &'static str,
+    name: &'static str,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.graph
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.dependent, self.name);
+    }
+}
+
+impl Services {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an instance that is already built. Registering a second
+    /// service of the same type replaces the first.
+    ///
+    /// # Panics
+    /// Panics if called after the container has been cloned.
+    pub fn value<T: Send + Sync + 'static>(self, value: T) -> Self {
+        let instance: Instance = Arc::new(value);
+        self.register::<T>(Entry {
+            name: type_name::<T>(),
+            instance: OnceCell::new_with(Some(instance)),
+            factory: None,
+        })
+    }
+
+    /// Register an async constructor, run the first time the service is
+    /// resolved (or by [`Services::init`]).
+    ///
+    /// # Panics
+    /// Panics if called after the container has been cloned.
+    pub fn provide<T, F, Fut>(self, factory: F) -> Self
+    where
+        T: Send + Sync + 'static,
+        F: Fn(Resolver) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let factory: Factory = Box::new(move |resolver| {
+            let built = factory(resolver);
+            Box::pin(async move { Ok(Arc::new(built.await?) as Instance) })
+        });
+        self.register::<T>(Entry {
+            name: type_name::<T>(),
+            instance: OnceCell::new(),
+            factory: Some(factory),
+        })
+    }
+
+    fn register<T: 'static>(mut self, entry: Entry) -> Self {
+        Arc::get_mut(&mut self.entries)
+            .expect("services must be registered before the container is cloned")
+            .insert(TypeId::of::<T>(), entry);
+        self
+    }
+
+    /// Whether a service of type `T` is registered.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.entries.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The service of type `T`, built now if it hasn't been yet.
+    pub async fn get<T: Send + Sync + 'static>(&self) -> Result<Arc<T>, Error> {
+        Resolver::new(self.clone()).get().await
+    }
+
+    /// Build every registered service that hasn't been built yet, each
+    /// after the services it depends on. Call it during the cold start so
+    /// requests don't pay for it and a misconfiguration fails the function
+    /// before it serves anything.
+    pub async fn init(&self) -> Result<(), Error> {
+        for (&id, entry) in self.entries.iter() {
+            self.instance(id, entry.name, &[]).await?;
+        }
+        Ok(())
+    }
+
+    /// The instance registered under `id`, built with `chain` being the
+    /// services whose constructors are waiting for it.
+    async fn instance(
+        &self,
+        id: TypeId,
+        name: &'static str,
+        chain: &[&'static str],
+    ) -> Result<Instance, Error> {
+        let Some(entry) = self.entries.get(&id) else {
+            let needed_by = match chain.last() {
+                Some(dependent) => format!(" (needed by {dependent})"),
+                None => String::new(),
+            };
+            return Err(format!("no service of type {name} registered{needed_by}").into());
+        };
+        if chain.contains(&name) {
+            return Err(format!("dependency cycle: {} -> {name}", chain.join(" -> ")).into());
+        }
+        if let Some(instance) = entry.instance.get() {
+            return Ok(Arc::clone(instance));
+        }
+        let _waiting = match chain.last() {
+            Some(&dependent) => Some(self.wait(dependent, name)?),
+            None => None,
+        };
+        let instance = entry
+            .instance
+            .get_or_try_init(|| async {
+                let factory = entry
+                    .factory
+                    .as_ref()
+                    .expect("values are set when registered");
+                let resolver = Resolver {
+                    services: self.clone(),
+                    chain: chain.iter().copied().chain([name]).collect(),
+                };
+                factory(resolver)
+                    .await
+                    .map_err(|e| Error::from(format!("failed to initialize {name}: {e}")))
+            })
+            .await?;
+        Ok(Arc::clone(instance))
+    }
+
+    /// Record that `dependent`'s constructor is waiting for `name`, unless
+    /// `name` is already (transitively) waiting for `dependent`.
+    fn wait(&self, dependent: &'static str, name: &'static str) -> Result<Waiting<'_>, Error> {
+        let mut graph = self.waits.lock().unwrap_or_else(|e| e.into_inner());
+        if graph.reaches(name, dependent) {
+            return Err(format!("dependency cycle: {dependent} -> {name} -> {dependent}").into());
+        }
+        graph.edges.entry(dependent).or_default().push(name);
+        Ok(Waiting {
+            graph: &self.waits,
+            dependent,
+            name,
+        })
+    }
+}
+
+/// Resolves the dependencies of a service being built.
+#[derive(Clone)]
+pub struct Resolver {
+    services: Services,
+    chain: Vec<&'static str>,
+}
+
+impl Resolver {
+    fn new(services: Services) -> Self {
+        Self {
+            services,
+            chain: Vec::new(),
+        }
+    }
+
+    /// The service of type `T`, built now if it hasn't been yet.
+    pub async fn get<T: Send + Sync + 'static>(&self) -> Result<Arc<T>, Error> {
+        let name = type_name::<T>();
+        let instance = self
+            .services
+            .instance(TypeId::of::<T>(), name, &self.chain)
+            .await?;
+        Ok(instance
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("{name} is registered under its own type")))
+    }
+}
+
+/// A service resolved for a handler. Derefs to the service.
+pub struct Inject<T>(Arc<T>);
+
+impl<T> Inject<T> {
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> Clone for Inject<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Inject<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl Request {
+    /// The service of type `T` from the [`Services`] in app state, built
+    /// now if it hasn't been yet.
+    pub async fn inject<T: Send + Sync + 'static>(&self) -> Result<Inject<T>, Error> {
+        let services = self
+            .state::<Services>()
+            .ok_or("no Services in app state; register them with Choko::state")?;
+        Ok(Inject(services.get::<T>().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq)]
+    struct Pool(&'static str);
+
+    #[derive(Debug, PartialEq)]
+    struct Repo {
+        pool: Arc<Pool>,
+    }
+
+    #[tokio::test]
+    async fn builds_services_once_on_first_use() {
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&builds);
+        let services = Services::new()
+            .value(Pool("primary"))
+            .provide(move |r: Resolver| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    Ok(Repo {
+                        pool: r.get::<Pool>().await?,
+                    })
+                }
+            });
+        assert_eq!(builds.load(Ordering::SeqCst), 0);
+
+        let (a, b) = tokio::join!(services.get::<Repo>(), services.get::<Repo>());
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.pool.0, "primary");
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_builds() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&attempts);
+        let services = Services::new().provide(move |_| {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match attempt {
+                    0 => Err(Error::from("connection refused")),
+                    _ => Ok(Pool("primary")),
+                }
+            }
+        });
+        let e = services.init().await.unwrap_err();
+        assert!(e.to_string().contains("failed to initialize"));
+        assert!(e.to_string().ends_with("connection refused"));
+        assert_eq!(services.get::<Pool>().await.unwrap().0, "primary");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_missing_services_and_cycles() {
+        let services = Services::new().provide(|r: Resolver| async move {
+            Ok(Repo {
+                pool: r.get::<Pool>().await?,
+            })
+        });
+        let e = services.get::<Repo>().await.unwrap_err().to_string();
+        assert!(e.contains(&format!("no service of type {}", type_name::<Pool>())));
+        assert!(e.contains(&format!("needed by {}", type_name::<Repo>())));
+
+        let services = Services::new()
+            .provide(|r: Resolver| async move {
+                Ok(Repo {
+                    pool: r.get::<Pool>().await?,
+                })
+            })
+            .provide(|r: Resolver| async move {
+                r.get::<Repo>().await?;
+                Ok(Pool("never"))
+            });
+        let e = services.get::<Pool>().await.unwrap_err().to_string();
+        assert!(e.contains("dependency cycle"));
+    }
+
+    #[tokio::test]
+    async fn reports_cycles_closed_by_concurrent_builds() {
+        let services = Services::new()
+            .provide(|r: Resolver| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                Ok(Repo {
+                    pool: r.get::<Pool>().await?,
+                })
+            })
+            .provide(|r: Resolver| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                r.get::<Repo>().await?;
+                Ok(Pool("never"))
+            });
+        let (repo, pool) = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            tokio::join!(services.get::<Repo>(), services.get::<Pool>())
+        })
+        .await
+        .expect("concurrent builds of a cycle deadlocked");
+        assert!(repo.unwrap_err().to_string().contains("dependency cycle"));
+        assert!(pool.unwrap_err().to_string().contains("dependency cycle"));
+        assert!(services.waits.lock().unwrap().edges.is_empty());
+    }
+
+    #[tokio::test]
+    async fn injects_into_requests() {
+        let mut req = Request::default();
+        assert!(req.inject::<Pool>().await.is_err());
+
+        req.extensions_mut()
+            .insert(Services::new().value(Pool("primary")));
+        let pool = req.inject::<Pool>().await.unwrap();
+        assert_eq!(*pool, Pool("primary"));
+    }
+}
@@ -0,0 +1,182 @@
+//! CIDR-based IP allow and deny lists.
+//!
+//! # Example
+//! ```ignore
+//! use choko::ipfilter::IpFilter;
+//!
+//! app.get("/partner/orders", partner_orders)
+//!     .middleware(IpFilter::new().allow("203.0.113.0/24")?.allow("2001:db8::/32")?);
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8`. A bare address is a
+/// single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// A CIDR string could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCidr(String);
+
+impl fmt::Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CIDR: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCidr {}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCidr(s.to_string());
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl Cidr {
+    /// Whether `ip` lies in this network. IPv4-mapped IPv6 addresses match
+    /// IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_match(u32::from(net).into(), u32::from(ip).into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_match(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_match(net: u128, ip: u128, prefix: u8, bits: u32) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - u32::from(prefix);
+    (net >> shift) == (ip >> shift)
+}
+
+/// Middleware admitting or rejecting requests by the API Gateway source IP.
+///
+/// Deny rules win over allow rules. With at least one allow rule, only
+/// listed clients get through; with none, everyone not denied does.
+/// Rejected requests get 403.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    /// A filter with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Admit clients in `cidr`.
+    pub fn allow(mut self, cidr: &str) -> Result<Self, InvalidCidr> {
+        self.allow.push(cidr.parse()?);
+        Ok(self)
+    }
+
+    /// Reject clients in `cidr`.
+    pub fn deny(mut self, cidr: &str) -> Result<Self, InvalidCidr> {
+        self.deny.push(cidr.parse()?);
+        Ok(self)
+    }
+
+    /// Whether a client at `ip` (or an unknown address) may proceed.
+    pub fn permits(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return self.allow.is_empty();
+        };
+        if self.deny.iter().any(|c| c.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|c| c.contains(ip))
+    }
+}
+
+impl Middleware for IpFilter {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let source_ip = req
+            .request_context()
+            .source_ip
+            .as_deref()
+            .and_then(|ip| ip.parse().ok());
+        if self.permits(source_ip) {
+            return next.run(req);
+        }
+        Box::pin(async move { Ok(crate::error_json(403, "Forbidden")) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let net: Cidr = "203.0.113.0/24".parse().unwrap();
+        assert!(net.contains("203.0.113.200".parse().unwrap()));
+        assert!(!net.contains("203.0.114.1".parse().unwrap()));
+        assert!(net.contains("::ffff:203.0.113.9".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn deny_wins_and_allow_list_restricts() {
+        let filter = IpFilter::new()
+            .allow("10.0.0.0/8")
+            .unwrap()
+            .deny("10.0.0.13")
+            .unwrap();
+        assert!(filter.permits(ip("10.1.2.3")));
+        assert!(!filter.permits(ip("10.0.0.13")));
+        assert!(!filter.permits(ip("192.0.2.1")));
+        assert!(!filter.permits(None));
+
+        let deny_only = IpFilter::new().deny("192.0.2.0/24").unwrap();
+        assert!(deny_only.permits(ip("198.51.100.1")));
+        assert!(deny_only.permits(None));
+    }
+}
@@ -0,0 +1,426 @@
+//! JSON:API documents (`jsonapi` feature).
+//!
+//! Builds `application/vnd.api+json` responses from serde types and parses
+//! JSON:API request documents back into them.
+//!
+//! # Example
+//! ```ignore
+//! use choko::jsonapi::{Resource, ResourceIdentifier};
+//!
+//! app.get("/articles/{id}", |req| async move {
+//!     let article = db::article(&req.path_params["id"]).await?;
+//!     let resource = Resource::new("articles", article.id.to_string(), &article)?
+//!         .relationship("author", ResourceIdentifier::new("people", article.author_id.to_string()));
+//!     Ok(Response::jsonapi(resource))
+//! });
+//!
+//! app.post("/articles", |req| async move {
+//!     let input = req.jsonapi::<NewArticle>("articles")?;
+//!     let saved = db::insert(input.attributes).await?;
+//!     Ok(Response::jsonapi(Resource::new("articles", saved.id.to_string(), &saved)?).with_status(201))
+//! });
+//! ```
+
+use crate::{ChokoError, Error, Request, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The JSON:API media type.
+pub const JSONAPI_CONTENT_TYPE: &str = "application/vnd.api+json";
+
+/// A `{type, id}` pair referring to a resource.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceIdentifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+}
+
+impl ResourceIdentifier {
+    /// Identify the resource `id` of type `kind`.
+    pub fn new(kind: impl Into<String>, id: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            id: id.into(),
+        }
+    }
+}
+
+/// The linkage of a relationship: to-one (possibly empty) or to-many.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Linkage {
+    Many(Vec<ResourceIdentifier>),
+    One(Option<ResourceIdentifier>),
+}
+
+impl From<ResourceIdentifier> for Linkage {
+    fn from(id: ResourceIdentifier) -> Self {
+        Linkage::One(Some(id))
+    }
+}
+
+impl From<Option<ResourceIdentifier>> for Linkage {
+    fn from(id: Option<ResourceIdentifier>) -> Self {
+        Linkage::One(id)
+    }
+}
+
+impl From<Vec<ResourceIdentifier>> for Linkage {
+    fn from(ids: Vec<ResourceIdentifier>) -> Self {
+        Linkage::Many(ids)
+    }
+}
+
+/// A relationship object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relationship {
+    pub data: Linkage,
+}
+
+/// A resource object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Resource {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub relationships: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+impl Resource {
+    /// A resource whose attributes are the fields of `attributes`.
+    ///
+    /// `attributes` must serialize to a JSON object; an `id` or `type` field
+    /// in it is dropped, since those are top-level members in JSON:API.
+    pub fn new<T: Serialize + ?Sized>(
+        kind: impl Into<String>,
+        id: impl Into<String>,
+        attributes: &T,
+    ) -> Result<Self, Error> {
+        let Value::Object(mut attributes) = serde_json::to_value(attributes)? else {
+            return Err("JSON:API attributes must serialize to an object".into());
+        };
+        attributes.remove("id");
+        attributes.remove("type");
+        Ok(Self {
+            kind: kind.into(),
+            id: Some(id.into()),
+            attributes,
+            relationships: Map::new(),
+            meta: None,
+        })
+    }
+
+    /// Add a relationship.
+    pub fn relationship(mut self, name: impl Into<String>, linkage: impl Into<Linkage>) -> Self {
+        let relationship = Relationship {
+            data: linkage.into(),
+        };
+        self.relationships.insert(
+            name.into(),
+            serde_json::to_value(relationship).unwrap_or_default(),
+        );
+        self
+    }
+
+    /// Set the resource's `meta` object.
+    pub fn meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+}
+
+/// The primary data of a document: one resource, a collection, or null.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum PrimaryData {
+    One(Option<Resource>),
+    Many(Vec<Resource>),
+}
+
+impl From<Resource> for PrimaryData {
+    fn from(resource: Resource) -> Self {
+        PrimaryData::One(Some(resource))
+    }
+}
+
+impl From<Option<Resource>> for PrimaryData {
+    fn from(resource: Option<Resource>) -> Self {
+        PrimaryData::One(resource)
+    }
+}
+
+impl From<Vec<Resource>> for PrimaryData {
+    fn from(resources: Vec<Resource>) -> Self {
+        PrimaryData::Many(resources)
+    }
+}
+
+/// A top-level document carrying primary data.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Document {
+    pub data: PrimaryData,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub included: Vec<Resource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub links: Map<String, Value>,
+}
+
+impl Document {
+    /// A document with the given primary data.
+    pub fn new(data: impl Into<PrimaryData>) -> Self {
+        Self {
+            data: data.into(),
+            included: Vec::new(),
+            meta: None,
+            links: Map::new(),
+        }
+    }
+
+    /// Add compound-document resources to `included`.
+    pub fn include(mut self, resources: impl IntoIterator<Item = Resource>) -> Self {
+        self.included.extend(resources);
+        self
+    }
+
+    /// Set the top-level `meta` object.
+    pub fn meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Add a top-level link, e.g. `"next"`.
+    pub fn link(mut self, name: impl Into<String>, href: impl Into<String>) -> Self {
+        self.links.insert(name.into(), Value::String(href.into()));
+        self
+    }
+}
+
+impl From<Resource> for Document {
+    fn from(resource: Resource) -> Self {
+        Document::new(resource)
+    }
+}
+
+impl From<Option<Resource>> for Document {
+    fn from(resource: Option<Resource>) -> Self {
+        Document::new(resource)
+    }
+}
+
+impl From<Vec<Resource>> for Document {
+    fn from(resources: Vec<Resource>) -> Self {
+        Document::new(resources)
+    }
+}
+
+/// A JSON:API error object.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Value>,
+}
+
+impl ErrorObject {
+    /// An error with the given status and title.
+    pub fn new(status: u16, title: impl Into<String>) -> Self {
+        Self {
+            status: Some(status.to_string()),
+            title: Some(title.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Set the detail message.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Point at the offending member of the request document, e.g.
+    /// `"/data/attributes/title"`.
+    pub fn pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.source = Some(serde_json::json!({ "pointer": pointer.into() }));
+        self
+    }
+}
+
+/// A resource parsed from a JSON:API request document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceInput<T> {
+    pub kind: String,
+    /// Absent for client-created resources without client-generated ids.
+    pub id: Option<String>,
+    pub attributes: T,
+    pub relationships: Map<String, Value>,
+}
+
+impl<T> ResourceInput<T> {
+    /// The linkage of the relationship `name`, if present and well-formed.
+    pub fn relationship(&self, name: &str) -> Option<Linkage> {
+        let rel = self.relationships.get(name)?.get("data")?;
+        serde_json::from_value(rel.clone()).ok()
+    }
+}
+
+impl Request {
+    /// Parse a JSON:API request document whose primary data is a resource of
+    /// type `kind`, deserializing its attributes into `T`.
+    ///
+    /// A malformed document or wrong type is rejected with 400 (409 for a
+    /// type mismatch, as the specification requires).
+    pub fn jsonapi<T: DeserializeOwned>(&self, kind: &str) -> Result<ResourceInput<T>, ChokoError> {
+        #[derive(Deserialize)]
+        struct Input {
+            data: Resource,
+        }
+
+        let body = self
+            .body
+            .as_deref()
+            .ok_or_else(|| ChokoError::bad_request("JSON:API document required"))?;
+        let input: Input = serde_json::from_str(body)
+            .map_err(|e| ChokoError::bad_request(format!("Invalid JSON:API document: {e}")))?;
+        let resource = input.data;
+        if resource.kind != kind {
+            return Err(ChokoError::status(
+                409,
+                format!("Expected resource type {kind:?}, got {:?}", resource.kind),
+            ));
+        }
+        let attributes = serde_json::from_value(Value::Object(resource.attributes))
+            .map_err(|e| ChokoError::bad_request(format!("Invalid attributes: {e}")))?;
+        Ok(ResourceInput {
+            kind: resource.kind,
+            id: resource.id,
+            attributes,
+            relationships: resource.relationships,
+        })
+    }
+}
+
+impl Response {
+    /// A `200` JSON:API response carrying `document` (or just its primary
+    /// data).
+    pub fn jsonapi(document: impl Into<Document>) -> Self {
+        let body = serde_json::to_value(document.into()).unwrap_or_default();
+        Response::json(body).with_header("Content-Type", JSONAPI_CONTENT_TYPE)
+    }
+
+    /// A JSON:API error document with the given status.
+    pub fn jsonapi_errors(status: i64, errors: Vec<ErrorObject>) -> Self {
+        Response::json(serde_json::json!({ "errors": errors }))
+            .with_status(status)
+            .with_header("Content-Type", JSONAPI_CONTENT_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Article {
+        id: u32,
+        title: String,
+    }
+
+    #[test]
+    fn response_wraps_resource_in_document() {
+        let article = Article {
+            id: 1,
+            title: "Rails is Omakase".to_string(),
+        };
+        let resource = Resource::new("articles", "1", &article)
+            .unwrap()
+            .relationship("author", ResourceIdentifier::new("people", "9"));
+        let resp = Response::jsonapi(Document::new(resource).meta(json!({"total": 1})));
+
+        assert_eq!(resp.header("content-type"), Some(JSONAPI_CONTENT_TYPE));
+        assert_eq!(
+            resp.body,
+            ResponseBody::Json(json!({
+                "data": {
+                    "type": "articles",
+                    "id": "1",
+                    "attributes": {"title": "Rails is Omakase"},
+                    "relationships": {"author": {"data": {"type": "people", "id": "9"}}}
+                },
+                "meta": {"total": 1}
+            }))
+        );
+    }
+
+    #[test]
+    fn collections_and_errors() {
+        let resp = Response::jsonapi(Vec::<Resource>::new());
+        assert_eq!(resp.body, ResponseBody::Json(json!({"data": []})));
+
+        let resp = Response::jsonapi_errors(
+            422,
+            vec![ErrorObject::new(422, "Invalid Attribute").pointer("/data/attributes/title")],
+        );
+        assert_eq!(resp.status_code, 422);
+        assert_eq!(
+            resp.body,
+            ResponseBody::Json(json!({"errors": [{
+                "status": "422",
+                "title": "Invalid Attribute",
+                "source": {"pointer": "/data/attributes/title"}
+            }]}))
+        );
+    }
+
+    #[test]
+    fn request_parses_resource_document() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct NewArticle {
+            title: String,
+        }
+
+        let req = Request {
+            body: Some(
+                json!({"data": {
+                    "type": "articles",
+                    "attributes": {"title": "Hello"},
+                    "relationships": {"author": {"data": {"type": "people", "id": "9"}}}
+                }})
+                .to_string(),
+            ),
+            ..Default::default()
+        };
+        let input: ResourceInput<NewArticle> = req.jsonapi("articles").unwrap();
+        assert_eq!(input.id, None);
+        assert_eq!(input.attributes.title, "Hello");
+        assert_eq!(
+            input.relationship("author"),
+            Some(Linkage::One(Some(ResourceIdentifier::new("people", "9"))))
+        );
+
+        assert_eq!(
+            req.jsonapi::<NewArticle>("comments")
+                .unwrap_err()
+                .status_code(),
+            409
+        );
+    }
+}
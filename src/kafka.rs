@@ -0,0 +1,326 @@
+//! MSK and self-managed Kafka consumers (`kafka` feature).
+//!
+//! [`Choko::kafka_topic`] registers a handler for the records of one
+//! topic, and [`Choko::run_kafka`] starts the app as the consumer of an
+//! MSK or self-managed Kafka event source mapping. Keys, values and
+//! headers are decoded before they reach the handler.
+//!
+//! Each topic-partition's records are handled in offset order. Kafka event
+//! sources don't support partial batch responses, so the first failure
+//! fails the invocation and the whole batch is retried; handlers should be
+//! idempotent.
+//!
+//! # Example
+//! ```ignore
+//! use choko::kafka::Record;
+//!
+//! app.kafka_topic("orders", |record: Record| async move {
+//!     let order: Order = record.json()?;
+//!     ledger.apply(order).await
+//! });
+//! app.run_kafka().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type TopicHandlerFn = Arc<dyn Fn(Record) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// A Kafka invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaEvent {
+    /// `aws:kafka` for MSK, `SelfManagedKafka` otherwise.
+    pub event_source: String,
+    pub event_source_arn: Option<String>,
+    pub bootstrap_servers: Option<String>,
+    /// Records by `<topic>-<partition>`.
+    #[serde(default)]
+    pub records: BTreeMap<String, Vec<KafkaEventRecord>>,
+}
+
+/// One record as delivered by Lambda, key and value still base64-encoded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaEventRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: i64,
+    /// `CREATE_TIME` or `LOG_APPEND_TIME`.
+    pub timestamp_type: Option<String>,
+    pub key: Option<String>,
+    pub value: Option<String>,
+    /// Header values as byte arrays, one map per header.
+    #[serde(default)]
+    pub headers: Vec<HashMap<String, Vec<u8>>>,
+}
+
+/// A decoded Kafka record.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    /// Headers in record order; names may repeat.
+    pub headers: Vec<(String, Vec<u8>)>,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl Record {
+    /// Deserialize the value as JSON. A missing value (a tombstone)
+    /// deserializes as `null`.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(self.value.as_deref().unwrap_or(b"null"))
+    }
+
+    /// The value as text, if present and valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(self.value.as_deref()?).ok()
+    }
+
+    /// The key as text, if present and valid UTF-8.
+    pub fn key_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.key.as_deref()?).ok()
+    }
+
+    /// The first header named `name`.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    fn decode(
+        record: KafkaEventRecord,
+        lambda_context: Option<LambdaContext>,
+    ) -> Result<Self, Error> {
+        let decode = |data: Option<String>| {
+            data.map(|d| base64::engine::general_purpose::STANDARD.decode(d))
+                .transpose()
+        };
+        Ok(Self {
+            key: decode(record.key)?,
+            value: decode(record.value)?,
+            topic: record.topic,
+            partition: record.partition,
+            offset: record.offset,
+            timestamp: record.timestamp,
+            headers: record.headers.into_iter().flatten().collect(),
+            lambda_context,
+        })
+    }
+}
+
+impl Choko {
+    /// Handle the records of the Kafka topic `topic`.
+    pub fn kafka_topic<F, Fut>(&mut self, topic: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Record) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.kafka_topics.insert(
+            topic.to_string(),
+            Arc::new(move |record| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(record).await })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the consumer of its Kafka topics.
+    pub async fn run_kafka(self) -> Result<(), Error> {
+        self.serve(|app, event: KafkaEvent, context| async move {
+            app.dispatch_kafka(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_kafka(
+        &self,
+        event: KafkaEvent,
+        context: Option<LambdaContext>,
+    ) -> Result<(), Error> {
+        for (topic_partition, mut records) in event.records {
+            records.sort_by_key(|r| r.offset);
+            for record in records {
+                let offset = record.offset;
+                let handler = self
+                    .kafka_topics
+                    .get(&record.topic)
+                    .cloned()
+                    .ok_or_else(|| format!("no handler for topic {:?}", record.topic))?;
+                let outcome = match Record::decode(record, context.clone()) {
+                    Ok(record) => handler(record).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = outcome {
+                    return Err(format!("{topic_partition}@{offset}: {e}").into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn record(topic: &str, offset: i64, value: &str) -> serde_json::Value {
+        json!({
+            "topic": topic,
+            "partition": 0,
+            "offset": offset,
+            "timestamp": 1714564800000i64,
+            "timestampType": "CREATE_TIME",
+            "key": base64::engine::general_purpose::STANDARD.encode("k1"),
+            "value": base64::engine::general_purpose::STANDARD.encode(value),
+            "headers": [{ "trace": [97, 98] }]
+        })
+    }
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: u32,
+    }
+
+    #[tokio::test]
+    async fn decodes_records_in_offset_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let mut app = Choko::new("test");
+        app.kafka_topic("orders", move |record: Record| {
+            let log = Arc::clone(&log);
+            async move {
+                assert_eq!(record.key_str(), Some("k1"));
+                assert_eq!(record.header("trace"), Some(&b"ab"[..]));
+                let order: Order = record.json()?;
+                log.lock().unwrap().push(order.id);
+                Ok(())
+            }
+        });
+
+        let event: KafkaEvent = serde_json::from_value(json!({
+            "eventSource": "aws:kafka",
+            "eventSourceArn": "arn:aws:kafka:eu-west-1:123456789012:cluster/main/abc",
+            "records": {
+                "orders-0": [
+                    record("orders", 11, r#"{"id":2}"#),
+                    record("orders", 10, r#"{"id":1}"#)
+                ]
+            }
+        }))
+        .unwrap();
+        app.dispatch_kafka(event, None).await.unwrap();
+        assert_eq!(*seen.lock().unwrap(), [1, 2]);
+
+        let event: KafkaEvent = serde_json::from_value(json!({
+            "eventSource": "SelfManagedKafka",
+            "records": { "orders-0": [record("orders", 12, "oops")] }
+        }))
+        .unwrap();
+        let err = app.dispatch_kafka(event, None).await.unwrap_err();
+        assert!(err.to_string().starts_with("orders-0@12: "));
+    }
+
+    #[tokio::test]
+    async fn failures_stop_the_batch_at_the_failed_record() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let mut app = Choko::new("test");
+        app.kafka_topic("orders", move |record: Record| {
+            let log = Arc::clone(&log);
+            async move {
+                if record.text() == Some("reject") {
+                    return Err("order rejected".into());
+                }
+                log.lock().unwrap().push(record.offset);
+                Ok(())
+            }
+        });
+
+        // A value that isn't base64 fails before the handler runs
+        let mut undecodable = record("orders", 21, "");
+        undecodable["value"] = json!("not base64!");
+        let event: KafkaEvent = serde_json::from_value(json!({
+            "eventSource": "aws:kafka",
+            "records": {
+                "orders-0": [record("orders", 20, "a"), undecodable, record("orders", 22, "b")]
+            }
+        }))
+        .unwrap();
+        let err = app.dispatch_kafka(event, None).await.unwrap_err();
+        assert!(err.to_string().starts_with("orders-0@21: "), "{err}");
+        assert_eq!(*seen.lock().unwrap(), [20]);
+
+        let event: KafkaEvent = serde_json::from_value(json!({
+            "eventSource": "aws:kafka",
+            "records": {
+                "orders-0": [record("orders", 31, "b"), record("orders", 30, "reject")]
+            }
+        }))
+        .unwrap();
+        let err = app.dispatch_kafka(event, None).await.unwrap_err();
+        assert_eq!(err.to_string(), "orders-0@30: order rejected");
+        assert_eq!(*seen.lock().unwrap(), [20]);
+
+        let event: KafkaEvent = serde_json::from_value(json!({
+            "eventSource": "aws:kafka",
+            "records": { "payments-0": [record("payments", 1, "a")] }
+        }))
+        .unwrap();
+        let err = app.dispatch_kafka(event, None).await.unwrap_err();
+        assert_eq!(err.to_string(), r#"no handler for topic "payments""#);
+    }
+
+    #[test]
+    fn decodes_headers_and_tombstones() {
+        let mut raw = record("orders", 1, "");
+        raw["value"] = json!(null);
+        raw["key"] = json!(null);
+        raw["headers"] = json!([
+            { "trace": [116, 49] },
+            { "binary": [0, 159, 146, 150] },
+            { "trace": [116, 50] }
+        ]);
+        let raw: KafkaEventRecord = serde_json::from_value(raw).unwrap();
+        let record = Record::decode(raw, None).unwrap();
+
+        assert_eq!(
+            record.headers,
+            [
+                ("trace".to_string(), b"t1".to_vec()),
+                ("binary".to_string(), vec![0, 159, 146, 150]),
+                ("trace".to_string(), b"t2".to_vec()),
+            ]
+        );
+        assert_eq!(record.header("trace"), Some(&b"t1"[..]));
+        assert_eq!(record.header("binary"), Some(&[0, 159, 146, 150][..]));
+        assert_eq!(record.header("missing"), None);
+
+        assert_eq!(record.key_str(), None);
+        assert_eq!(record.text(), None);
+        assert_eq!(record.json::<Option<u32>>().unwrap(), None);
+    }
+}
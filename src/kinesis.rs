@@ -0,0 +1,238 @@
+//! Kinesis stream consumers (`kinesis` feature).
+//!
+//! [`Choko::kinesis_stream`] registers a handler for the records of one
+//! stream, and [`Choko::run_kinesis`] starts the app as its Lambda
+//! consumer. Record data is base64-decoded before it reaches the handler;
+//! [`Record::json`] deserializes it.
+//!
+//! Records are handled in order. The first one that fails is reported as
+//! the batch item failure and the rest of the batch is skipped, so Lambda
+//! checkpoints just before it and retries from there. Enable
+//! *ReportBatchItemFailures* on the event source mapping.
+//!
+//! # Example
+//! ```ignore
+//! use choko::kinesis::Record;
+//!
+//! app.kinesis_stream("clickstream", |record: Record| async move {
+//!     let click: Click = record.json()?;
+//!     warehouse.insert(click).await
+//! });
+//! app.run_kinesis().await
+//! ```
+
+use crate::streams::StreamBatchResponse;
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type StreamHandlerFn = Arc<dyn Fn(Record) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// A Kinesis invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct KinesisEvent {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<KinesisEventRecord>,
+}
+
+/// One record as delivered by Lambda, data still base64-encoded.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KinesisEventRecord {
+    #[serde(rename = "eventID", default)]
+    pub event_id: String,
+    #[serde(rename = "eventSourceARN", default)]
+    pub event_source_arn: String,
+    pub kinesis: KinesisData,
+}
+
+/// The `kinesis` section of a record.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KinesisData {
+    pub partition_key: String,
+    pub sequence_number: String,
+    pub data: String,
+    /// Seconds since the Unix epoch.
+    pub approximate_arrival_timestamp: Option<f64>,
+}
+
+/// A decoded Kinesis record.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub partition_key: String,
+    pub sequence_number: String,
+    pub data: Vec<u8>,
+    /// Seconds since the Unix epoch.
+    pub approximate_arrival_timestamp: Option<f64>,
+    stream_name: String,
+    shard_id: String,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl Record {
+    /// Deserialize the data as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.data)
+    }
+
+    /// The data as text, if it is valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// The name of the stream the record came from.
+    pub fn stream_name(&self) -> &str {
+        &self.stream_name
+    }
+
+    /// The shard the record came from, e.g. `shardId-000000000000`.
+    pub fn shard_id(&self) -> &str {
+        &self.shard_id
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    fn decode(
+        record: KinesisEventRecord,
+        lambda_context: Option<LambdaContext>,
+    ) -> Result<Self, Error> {
+        let data = base64::engine::general_purpose::STANDARD.decode(&record.kinesis.data)?;
+        Ok(Self {
+            partition_key: record.kinesis.partition_key,
+            sequence_number: record.kinesis.sequence_number,
+            data,
+            approximate_arrival_timestamp: record.kinesis.approximate_arrival_timestamp,
+            stream_name: stream_name(&record.event_source_arn).to_string(),
+            shard_id: record
+                .event_id
+                .split_once(':')
+                .map_or("", |(shard, _)| shard)
+                .to_string(),
+            lambda_context,
+        })
+    }
+}
+
+/// The stream name from an ARN (`arn:aws:kinesis:region:account:stream/name`).
+fn stream_name(arn: &str) -> &str {
+    arn.split('/').nth(1).unwrap_or("")
+}
+
+impl Choko {
+    /// Handle the records of the Kinesis stream `stream`.
+    pub fn kinesis_stream<F, Fut>(&mut self, stream: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Record) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.kinesis_streams.insert(
+            stream.to_string(),
+            Arc::new(move |record| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(record).await })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the Lambda consumer of its Kinesis streams.
+    pub async fn run_kinesis(self) -> Result<(), Error> {
+        self.serve(|app, event: KinesisEvent, context| async move {
+            Ok(app.dispatch_kinesis(event, Some(context)).await)
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_kinesis(
+        &self,
+        event: KinesisEvent,
+        context: Option<LambdaContext>,
+    ) -> StreamBatchResponse {
+        for record in event.records {
+            let sequence_number = record.kinesis.sequence_number.clone();
+            let stream = stream_name(&record.event_source_arn).to_string();
+            let outcome = match self.kinesis_streams.get(&stream) {
+                Some(handler) => match Record::decode(record, context.clone()) {
+                    Ok(record) => handler(record).await,
+                    Err(e) => Err(e),
+                },
+                None => Err(format!("no handler for stream {stream:?}").into()),
+            };
+            if let Err(e) = outcome {
+                eprintln!("Kinesis record {sequence_number} from {stream} failed: {e}");
+                return StreamBatchResponse::failed_at(Some(sequence_number));
+            }
+        }
+        StreamBatchResponse::failed_at(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn record(seq: &str, data: &[u8]) -> KinesisEventRecord {
+        serde_json::from_value(serde_json::json!({
+            "eventSource": "aws:kinesis",
+            "eventID": format!("shardId-000000000001:{seq}"),
+            "eventName": "aws:kinesis:record",
+            "eventSourceARN": "arn:aws:kinesis:eu-west-1:123456789012:stream/clicks",
+            "kinesis": {
+                "kinesisSchemaVersion": "1.0",
+                "partitionKey": "user-1",
+                "sequenceNumber": seq,
+                "data": base64::engine::general_purpose::STANDARD.encode(data),
+                "approximateArrivalTimestamp": 1714532400.5
+            }
+        }))
+        .unwrap()
+    }
+
+    #[derive(Deserialize)]
+    struct Click {
+        page: String,
+    }
+
+    #[tokio::test]
+    async fn decodes_and_checkpoints_at_the_first_failure() {
+        let pages = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&pages);
+        let mut app = Choko::new("test");
+        app.kinesis_stream("clicks", move |record: Record| {
+            let log = Arc::clone(&log);
+            async move {
+                assert_eq!(record.shard_id(), "shardId-000000000001");
+                let click: Click = record.json()?;
+                log.lock().unwrap().push(click.page);
+                Ok(())
+            }
+        });
+
+        let event = KinesisEvent {
+            records: vec![
+                record("1", br#"{"page":"/home"}"#),
+                record("2", br#"{"page":"/cart"}"#),
+                record("3", b"not json"),
+                record("4", br#"{"page":"/checkout"}"#),
+            ],
+        };
+        let resp = app.dispatch_kinesis(event, None).await;
+        assert_eq!(resp, StreamBatchResponse::failed_at(Some("3".into())));
+        assert_eq!(*pages.lock().unwrap(), ["/home", "/cart"]);
+
+        let event = KinesisEvent {
+            records: vec![record("5", br#"{"page":"/"}"#)],
+        };
+        let resp = app.dispatch_kinesis(event, None).await;
+        assert!(resp.batch_item_failures.is_empty());
+    }
+}
@@ -1,15 +1,173 @@
+pub use aws_lambda_events;
 use aws_lambda_events::encodings::Body;
 use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use base64::Engine;
+#[cfg(feature = "cbor")]
+pub use codec::CBOR_CONTENT_TYPE;
+#[cfg(feature = "msgpack")]
+pub use codec::MSGPACK_CONTENT_TYPE;
+pub use cognito::CognitoClaims;
+pub use conditional::{EntityTag, IfMatch, PreconditionFailed};
+pub use context::{IamIdentity, LambdaContext, RequestContext};
+pub use cookie::{Cookie, SameSite};
+pub use error::ChokoError;
+pub use events::EventAdapter;
+pub use headers::{Authorization, BasicCredentials, TypedHeader};
+pub use html::Html;
 pub use lambda_runtime::Error;
 use lambda_runtime::{service_fn, LambdaEvent};
+pub use middleware::{Middleware, Next};
+pub use ndjson::{NdjsonResponse, NdjsonSender};
+pub use negotiate::{MediaRange, Negotiate};
+#[cfg(feature = "s3-offload")]
+pub use offload::{OffloadMode, S3Offload};
+pub use pagination::{decode_cursor, encode_cursor, CURSOR_PARAM};
+pub use problem::{Problem, PROBLEM_CONTENT_TYPE};
+#[cfg(feature = "protobuf")]
+pub use protobuf::PROTOBUF_CONTENT_TYPE;
+pub use query::QueryRejection;
+pub use request_id::REQUEST_ID_HEADER;
 pub use serde_json;
 use serde_json::Value;
+#[cfg(feature = "tower")]
+pub use service::ChokoService;
+pub use sse::{SseEvent, SseResponse, SseSender};
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+pub use stream::{BodySender, BodyStream, StreamClosed};
+pub use trace_context::TraceContext;
+
+pub mod access_log;
+#[cfg(feature = "alb")]
+mod alb;
+pub mod app_cache;
+pub mod audit;
+pub mod auth;
+pub mod circuit_breaker;
+mod codec;
+mod cognito;
+#[cfg(feature = "cognito-triggers")]
+pub mod cognito_triggers;
+mod cold_start;
+#[cfg(feature = "compression")]
+mod compress;
+mod conditional;
+#[cfg(feature = "config")]
+pub mod config;
+mod context;
+mod cookie;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "compression")]
+mod decompress;
+mod defer;
+#[cfg(feature = "dynamodb")]
+pub mod dynamodb;
+#[cfg(feature = "dynamodb-streams")]
+pub mod dynamodb_streams;
+#[cfg(feature = "edge")]
+pub mod edge;
+#[cfg(feature = "field-encryption")]
+pub mod encryption;
+mod error;
+#[cfg(feature = "eventbridge")]
+pub mod eventbridge;
+mod events;
+#[cfg(feature = "appconfig")]
+pub mod feature_flags;
+mod forwarded;
+#[cfg(feature = "function-url")]
+mod function_url;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+mod headers;
+pub mod health;
+mod html;
+#[cfg(feature = "http-client")]
+pub mod http_client;
+#[cfg(any(feature = "server", feature = "tower"))]
+mod http_compat;
+#[cfg(feature = "idempotency")]
+pub mod idempotency;
+pub mod inject;
+pub mod ipfilter;
+#[cfg(feature = "jsonapi")]
+pub mod jsonapi;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "kinesis")]
+pub mod kinesis;
+pub mod maintenance;
+pub mod metrics;
+pub mod middleware;
+mod ndjson;
+mod negotiate;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+#[cfg(feature = "s3-offload")]
+mod offload;
+mod pagination;
+#[cfg(feature = "ssm")]
+pub mod parameters;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod problem;
+#[cfg(feature = "protobuf")]
+mod protobuf;
+mod query;
+pub mod ratelimit;
+mod request_id;
+pub mod response_cache;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "s3-events")]
+pub mod s3_events;
+#[cfg(feature = "s3-uploads")]
+pub mod s3_uploads;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+#[cfg(feature = "secrets-manager")]
+pub mod secrets;
+#[cfg(feature = "sentry")]
+pub mod sentry;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "ses")]
+pub mod ses;
+#[cfg(feature = "sessions")]
+pub mod session;
+pub mod slow_log;
+#[cfg(feature = "sns")]
+pub mod sns;
+#[cfg(feature = "sqs")]
+pub mod sqs;
+#[cfg(feature = "sqs-sender")]
+pub mod sqs_sender;
+mod sse;
+#[cfg(feature = "step-functions")]
+pub mod step_functions;
+mod stream;
+#[cfg(any(feature = "dynamodb-streams", feature = "kinesis"))]
+mod streams;
+#[cfg(feature = "tracing")]
+pub mod telemetry;
+pub mod timeout;
+mod trace_context;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+pub mod websocket;
+#[cfg(feature = "xml")]
+mod xml;
+#[cfg(feature = "xray")]
+pub mod xray;
 
 /// A request object passed to route handlers.
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct Request {
     /// Path parameters extracted from the URL pattern (e.g., `{user_id}` -> "123").
     pub path_params: HashMap<String, String>,
@@ -17,27 +175,254 @@ pub struct Request {
     pub query_params: HashMap<String, Vec<String>>,
     /// HTTP headers.
     pub headers: HashMap<String, String>,
-    /// The raw request body as a string.
+    /// The request body as a string (base64 and `Content-Encoding` already
+    /// decoded). `None` for binary bodies; see [`Request::body_bytes`].
     pub body: Option<String>,
+    binary_body: Option<Vec<u8>>,
     /// The parsed JSON body (if applicable).
     pub json_body: Option<Value>,
+    request_context: RequestContext,
+    lambda_context: Option<LambdaContext>,
+    extensions: http::Extensions,
+    raw_event: Option<ApiGatewayProxyRequest>,
+    route: Option<String>,
+    request_id: Option<String>,
+}
+
+impl Request {
+    /// Typed values attached to this request, e.g. by middleware.
+    pub fn extensions(&self) -> &http::Extensions {
+        &self.extensions
+    }
+
+    /// A value shared with [`Choko::state`].
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions.get::<T>()
+    }
+
+    /// Mutable access to the request's typed extensions.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(Clone)]
+    /// struct CurrentUser(String);
+    ///
+    /// req.extensions_mut().insert(CurrentUser("alice".into()));
+    /// let user = req.extensions().get::<CurrentUser>();
+    /// ```
+    pub fn extensions_mut(&mut self) -> &mut http::Extensions {
+        &mut self.extensions
+    }
+
+    /// The HTTP method, e.g. `GET`. Empty for requests not built from an
+    /// API Gateway event.
+    pub fn method(&self) -> &str {
+        self.raw_event
+            .as_ref()
+            .map_or("", |event| event.http_method.as_str())
+    }
+
+    /// The request path as delivered by API Gateway (`event.path`, before any
+    /// stage stripping).
+    pub fn path(&self) -> &str {
+        self.raw_event
+            .as_ref()
+            .and_then(|event| event.path.as_deref())
+            .unwrap_or("/")
+    }
+
+    /// The path pattern of the matched route, e.g. `/users/{user_id}`.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// The decoded request body as bytes, for both text and binary payloads.
+    pub fn body_bytes(&self) -> Option<&[u8]> {
+        self.binary_body
+            .as_deref()
+            .or_else(|| self.body.as_deref().map(str::as_bytes))
+    }
+
+    /// The original API Gateway event, for fields the typed API doesn't expose.
+    ///
+    /// Returns `None` for requests not built from an API Gateway event.
+    pub fn raw_event(&self) -> Option<&ApiGatewayProxyRequest> {
+        self.raw_event.as_ref()
+    }
+}
+
+/// The body of a [`Response`].
+#[derive(Debug)]
+pub enum ResponseBody {
+    /// A JSON document, served as `application/json` by default.
+    Json(Value),
+    /// A UTF-8 text body, served as `text/plain; charset=utf-8` by default.
+    Text(String),
+    /// A binary body, base64-encoded for API Gateway and served as
+    /// `application/octet-stream` by default.
+    Binary(Vec<u8>),
+    /// No body at all (e.g. 204 No Content or redirects).
+    Empty,
+    /// A body produced incrementally through a channel, served as
+    /// `application/octet-stream` unless a `Content-Type` is set.
+    Stream(BodyStream),
+}
+
+impl ResponseBody {
+    fn default_content_type(&self) -> Option<&'static str> {
+        match self {
+            ResponseBody::Json(_) => Some("application/json"),
+            ResponseBody::Text(_) => Some("text/plain; charset=utf-8"),
+            ResponseBody::Binary(_) => Some("application/octet-stream"),
+            ResponseBody::Empty => None,
+            ResponseBody::Stream(_) => Some("application/octet-stream"),
+        }
+    }
+}
+
+/// Streamed bodies never compare equal, since their contents are not yet known.
+impl PartialEq for ResponseBody {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ResponseBody::Json(a), ResponseBody::Json(b)) => a == b,
+            (ResponseBody::Text(a), ResponseBody::Text(b)) => a == b,
+            (ResponseBody::Binary(a), ResponseBody::Binary(b)) => a == b,
+            (ResponseBody::Empty, ResponseBody::Empty) => true,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<Value> for ResponseBody {
+    fn eq(&self, other: &Value) -> bool {
+        matches!(self, ResponseBody::Json(v) if v == other)
+    }
 }
 
 /// A response builder for route handlers.
 #[derive(Debug)]
 pub struct Response {
     pub status_code: i64,
-    pub body: Value,
+    pub body: ResponseBody,
     pub headers: HashMap<String, String>,
+    /// Headers that may appear more than once, such as `Set-Cookie`.
+    /// Sent as API Gateway `multiValueHeaders`.
+    pub multi_value_headers: HashMap<String, Vec<String>>,
 }
 
 impl Response {
     /// Create a JSON response with status 200.
     pub fn json(body: Value) -> Self {
+        Self::with_body(ResponseBody::Json(body))
+    }
+
+    /// Serialize any `Serialize` value into a JSON response with status 200.
+    ///
+    /// The value is written straight to the response body without building
+    /// an intermediate [`Value`].
+    ///
+    /// # Example
+    /// ```ignore
+    /// Ok(Response::json_of(&user)?.with_status(201))
+    /// ```
+    pub fn json_of<T: serde::Serialize + ?Sized>(value: &T) -> Result<Self, serde_json::Error> {
+        let body = serde_json::to_string(value)?;
+        Ok(Self::with_body(ResponseBody::Text(body))
+            .with_header("Content-Type", "application/json"))
+    }
+
+    /// Create a plain-text response with status 200.
+    ///
+    /// Override the `Content-Type` header to serve other textual formats such
+    /// as `text/csv` or `text/html`.
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::with_body(ResponseBody::Text(body.into()))
+    }
+
+    /// Create a binary response with status 200 and the given content type.
+    pub fn binary(body: impl Into<Vec<u8>>, content_type: impl Into<String>) -> Self {
+        Self::with_body(ResponseBody::Binary(body.into())).with_header("Content-Type", content_type)
+    }
+
+    /// Create a response whose body is produced through a channel.
+    ///
+    /// The response is sent once every [`BodySender`] has been dropped.
+    pub fn stream(body: BodyStream) -> Self {
+        Self::with_body(ResponseBody::Stream(body))
+    }
+
+    /// Collect a streamed body into a text (if valid UTF-8) or binary body.
+    /// Other bodies are returned unchanged.
+    pub(crate) async fn buffered(mut self) -> Result<Self, Error> {
+        if let ResponseBody::Stream(stream) = self.body {
+            self.body = match String::from_utf8(stream.collect().await?) {
+                Ok(text) => ResponseBody::Text(text),
+                Err(e) => ResponseBody::Binary(e.into_bytes()),
+            };
+        }
+        Ok(self)
+    }
+
+    /// Create a 302 Found redirect to `location`.
+    pub fn redirect(location: impl Into<String>) -> Self {
+        Self::redirect_with_status(302, location)
+    }
+
+    /// Create a 303 See Other redirect, telling the client to GET `location`.
+    pub fn see_other(location: impl Into<String>) -> Self {
+        Self::redirect_with_status(303, location)
+    }
+
+    /// Create a 307 Temporary Redirect, which preserves the request method.
+    pub fn temporary_redirect(location: impl Into<String>) -> Self {
+        Self::redirect_with_status(307, location)
+    }
+
+    /// Create a 308 Permanent Redirect, which preserves the request method.
+    pub fn permanent_redirect(location: impl Into<String>) -> Self {
+        Self::redirect_with_status(308, location)
+    }
+
+    fn redirect_with_status(status_code: i64, location: impl Into<String>) -> Self {
+        Self::with_body(ResponseBody::Empty)
+            .with_status(status_code)
+            .with_header("Location", location)
+    }
+
+    /// Create a 201 Created response pointing at the new resource.
+    pub fn created(location: impl Into<String>, body: Value) -> Self {
+        Self::json(body)
+            .with_status(201)
+            .with_header("Location", location)
+    }
+
+    /// Create a 202 Accepted response, for work that will be completed
+    /// asynchronously.
+    pub fn accepted(body: Value) -> Self {
+        Self::json(body).with_status(202)
+    }
+
+    /// Create an empty 204 No Content response.
+    pub fn no_content() -> Self {
+        Self::with_body(ResponseBody::Empty).with_status(204)
+    }
+
+    /// Create a 406 response listing the media types that could have
+    /// been served.
+    pub fn not_acceptable(available: &[&str]) -> Self {
+        Self::json(serde_json::json!({
+            "error": "Not Acceptable",
+            "available": available,
+        }))
+        .with_status(406)
+    }
+
+    fn with_body(body: ResponseBody) -> Self {
         Self {
             status_code: 200,
             body,
             headers: HashMap::new(),
+            multi_value_headers: HashMap::new(),
         }
     }
 
@@ -52,15 +437,213 @@ impl Response {
         self.headers.insert(key.into(), value.into());
         self
     }
+
+    /// Add a value to a header that may be repeated, such as `Set-Cookie`.
+    pub fn append_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.multi_value_headers
+            .entry(key.into())
+            .or_default()
+            .push(value.into());
+        self
+    }
+
+    /// Look up a response header by name, case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Mark the response as a download with `Content-Disposition: attachment`.
+    ///
+    /// Non-ASCII file names are sent as an RFC 5987 `filename*` parameter,
+    /// with an ASCII fallback in `filename`.
+    pub fn with_attachment(self, filename: &str) -> Self {
+        let fallback: String = filename
+            .chars()
+            .map(|c| match c {
+                '"' | '\\' => '_',
+                c if c.is_ascii() && !c.is_ascii_control() => c,
+                _ => '_',
+            })
+            .collect();
+        let mut value = format!("attachment; filename=\"{fallback}\"");
+        if !filename.is_ascii() {
+            let encoded: String = filename
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{b:02X}"),
+                })
+                .collect();
+            value.push_str(&format!("; filename*=UTF-8''{encoded}"));
+        }
+        self.with_header("Content-Disposition", value)
+    }
+}
+
+/// A boxed, sendable future, as returned by [`Middleware::handle`].
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+type HandlerFn = Arc<dyn Fn(Request) -> BoxFuture<Result<Response, Error>> + Send + Sync>;
+type AfterResponseFn = Arc<dyn Fn(&Request, &mut Response) + Send + Sync>;
+#[cfg(feature = "shutdown")]
+type ShutdownFn = Box<dyn FnOnce() -> BoxFuture<()> + Send + Sync>;
+
+/// A routed response, before conversion for the integration, and the work
+/// its handler deferred.
+pub(crate) struct Routed {
+    pub(crate) response: Response,
+    pub(crate) deferred: defer::DeferredTasks,
+    pub(crate) deadline: Option<std::time::SystemTime>,
+}
+
+/// Parse a response header, logging and skipping invalid names or values.
+fn header_pair(key: &str, value: &str) -> Option<(http::HeaderName, http::HeaderValue)> {
+    match (
+        http::HeaderName::from_bytes(key.as_bytes()),
+        http::HeaderValue::from_str(value),
+    ) {
+        (Ok(name), Ok(val)) => Some((name, val)),
+        (Err(e), _) => {
+            eprintln!("Invalid header name {key:?}: {e}");
+            None
+        }
+        (_, Err(e)) => {
+            eprintln!("Invalid header value for {key:?}: {e}");
+            None
+        }
+    }
 }
 
-type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
-type HandlerFn = Box<dyn Fn(Request) -> BoxFuture<Result<Response, Error>> + Send + Sync>;
+/// A JSON `{"error": message}` response with the given status.
+fn error_json(status_code: i64, message: &str) -> Response {
+    Response::json(serde_json::json!({ "error": message })).with_status(status_code)
+}
+
+/// A framework-generated error response: `{"error": message}`, or a
+/// [`Problem`] when `problem_details` is enabled.
+fn framework_error(status_code: i64, message: &str, problem_details: bool) -> Response {
+    if !problem_details {
+        return error_json(status_code, message);
+    }
+    let problem = Problem::new(u16::try_from(status_code).unwrap_or(500));
+    if problem.title == message {
+        problem.into()
+    } else {
+        problem.with_detail(message).into()
+    }
+}
 
-struct Route {
+/// A registered route.
+///
+/// Returned by [`Choko::route`] and the method shortcuts so per-route options
+/// can be chained after registration.
+pub struct Route {
+    pattern: String,
     methods: Vec<String>,
     handler: HandlerFn,
     segments: Vec<Segment>,
+    content_types: Option<Vec<String>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    #[cfg(feature = "json-schema")]
+    schema: Option<jsonschema::Validator>,
+}
+
+impl Route {
+    /// Add middleware that runs only for this route, after any app-wide
+    /// middleware.
+    pub fn middleware(&mut self, middleware: impl Middleware) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Restrict the media types this route accepts in request bodies.
+    ///
+    /// Requests with a non-empty body whose `Content-Type` does not match one
+    /// of `types` are rejected with 415. Entries may use a `type/*` wildcard.
+    /// Overrides any app-wide setting from [`Choko::accept_content_types`].
+    pub fn accepts(&mut self, types: &[&str]) -> &mut Self {
+        self.content_types = Some(types.iter().map(|t| t.to_ascii_lowercase()).collect());
+        self
+    }
+
+    /// Validate incoming JSON bodies against a JSON Schema before the handler runs.
+    ///
+    /// Requests whose body is missing, not JSON, or violates the schema are
+    /// rejected with 400 and a list of the failing instance paths.
+    ///
+    /// # Panics
+    /// Panics if `schema` is not a valid JSON Schema.
+    #[cfg(feature = "json-schema")]
+    pub fn json_schema(&mut self, schema: Value) -> &mut Self {
+        let validator = jsonschema::validator_for(&schema)
+            .unwrap_or_else(|e| panic!("invalid JSON Schema for route: {e}"));
+        self.schema = Some(validator);
+        self
+    }
+
+    /// Check the request against the route's schema, returning the list of
+    /// violations (as `{"path", "message"}` objects) on failure.
+    #[cfg(feature = "json-schema")]
+    fn validate_schema(&self, req: &Request) -> Result<(), Vec<Value>> {
+        let Some(validator) = &self.schema else {
+            return Ok(());
+        };
+        let Some(body) = &req.json_body else {
+            return Err(vec![serde_json::json!({
+                "path": "",
+                "message": "request body must be valid JSON",
+            })]);
+        };
+        let errors: Vec<Value> = validator
+            .iter_errors(body)
+            .map(|e| {
+                serde_json::json!({
+                    "path": e.instance_path.to_string(),
+                    "message": e.to_string(),
+                })
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Run the route's request checks, then its handler.
+    ///
+    /// `default_content_types` is the app-wide setting, used when the route
+    /// doesn't declare its own. `problem_details` selects the error body
+    /// format for rejected requests.
+    fn call(
+        &self,
+        req: Request,
+        default_content_types: Option<&[String]>,
+        problem_details: bool,
+    ) -> BoxFuture<Result<Response, Error>> {
+        let accepted = self.content_types.as_deref().or(default_content_types);
+        if let Some(accepted) = accepted {
+            if !content_type_allowed(accepted, &req) {
+                let resp = framework_error(415, "Unsupported Media Type", problem_details);
+                return Box::pin(async move { Ok(resp) });
+            }
+        }
+        #[cfg(feature = "json-schema")]
+        if let Err(errors) = self.validate_schema(&req) {
+            let resp = if problem_details {
+                Problem::new(400).with_extension("errors", errors).into()
+            } else {
+                let body = serde_json::json!({ "error": "Bad Request", "details": errors });
+                Response::json(body).with_status(400)
+            };
+            return Box::pin(async move { Ok(resp) });
+        }
+        (self.handler)(req)
+    }
 }
 
 #[derive(Clone)]
@@ -114,19 +697,314 @@ fn match_path(segments: &[Segment], path: &str) -> Option<HashMap<String, String
     Some(params)
 }
 
+/// Remove a leading `/{stage}` segment from `path`, if present.
+fn strip_stage_prefix<'a>(path: &'a str, stage: Option<&str>) -> &'a str {
+    let Some(stage) = stage.filter(|s| !s.is_empty()) else {
+        return path;
+    };
+    let Some(rest) = path.trim_start_matches('/').strip_prefix(stage) else {
+        return path;
+    };
+    if rest.is_empty() || rest.starts_with('/') {
+        rest
+    } else {
+        path
+    }
+}
+
+/// Extract the lowercased media type from a `Content-Type` value, dropping
+/// any parameters such as `charset`.
+fn media_type(value: &str) -> String {
+    value
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Check whether the request body's content type is one of `accepted`.
+///
+/// Requests without a body always pass, so GET/DELETE routes are unaffected.
+fn content_type_allowed(accepted: &[String], req: &Request) -> bool {
+    if req.body_bytes().is_none_or(<[u8]>::is_empty) {
+        return true;
+    }
+    let Some(ct) = req.headers.get("content-type") else {
+        return false;
+    };
+    let ct = media_type(ct);
+    accepted.iter().any(|a| match a.strip_suffix("/*") {
+        Some(prefix) => ct.split('/').next() == Some(prefix),
+        None => *a == ct,
+    })
+}
+
+/// Why a request body could not be decoded.
+#[derive(Debug)]
+enum BodyError {
+    BadBase64,
+    #[cfg(feature = "compression")]
+    Decode(decompress::DecodeError),
+}
+
+impl BodyError {
+    /// The status and message to answer with.
+    fn status(&self) -> (i64, &'static str) {
+        match self {
+            BodyError::BadBase64 => (400, "Bad Request"),
+            #[cfg(feature = "compression")]
+            BodyError::Decode(decompress::DecodeError::Unsupported) => {
+                (415, "Unsupported Media Type")
+            }
+            #[cfg(feature = "compression")]
+            BodyError::Decode(decompress::DecodeError::Invalid) => (400, "Bad Request"),
+            #[cfg(feature = "compression")]
+            BodyError::Decode(decompress::DecodeError::TooLarge) => (413, "Payload Too Large"),
+        }
+    }
+}
+
 /// The main application struct for the Choko framework.
 pub struct Choko {
-    routes: Vec<Route>,
+    routes: Vec<Arc<Route>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    after_response: Vec<AfterResponseFn>,
+    content_types: Option<Arc<[String]>>,
+    strip_stage: bool,
+    problem_details: bool,
+    debug: bool,
+    request_id_header: Option<String>,
+    deferred_budget: Duration,
+    cold_start_namespace: Option<String>,
+    state: http::Extensions,
+    ws_routes: HashMap<String, websocket::WsHandlerFn>,
+    event_adapters: Vec<Arc<dyn EventAdapter>>,
+    #[cfg(feature = "sqs")]
+    sqs_queues: HashMap<String, sqs::SqsHandlerFn>,
+    #[cfg(feature = "sns")]
+    sns_handlers: Vec<(sns::Matcher, sns::SnsHandlerFn)>,
+    #[cfg(feature = "dynamodb-streams")]
+    dynamodb_tables: HashMap<String, dynamodb_streams::TableHandlerFn>,
+    #[cfg(feature = "kinesis")]
+    kinesis_streams: HashMap<String, kinesis::StreamHandlerFn>,
+    #[cfg(feature = "cognito-triggers")]
+    cognito_triggers: HashMap<&'static str, cognito_triggers::TriggerFn>,
+    #[cfg(feature = "s3-events")]
+    s3_buckets: HashMap<String, s3_events::BucketHandlerFn>,
+    #[cfg(feature = "ses")]
+    ses_handlers: Vec<(String, ses::EmailHandlerFn)>,
+    #[cfg(feature = "kafka")]
+    kafka_topics: HashMap<String, kafka::TopicHandlerFn>,
+    #[cfg(feature = "edge")]
+    edge_response_hook: Option<edge::ResponseHookFn>,
+    #[cfg(feature = "schedule")]
+    scheduled_tasks: Vec<schedule::ScheduledTask>,
+    #[cfg(feature = "shutdown")]
+    shutdown_hooks: Vec<ShutdownFn>,
+    #[cfg(feature = "compression")]
+    max_decompressed_size: usize,
+    #[cfg(feature = "compression")]
+    compress_min_size: Option<usize>,
+    #[cfg(feature = "s3-offload")]
+    offload: Option<S3Offload>,
+    #[cfg(feature = "server")]
+    max_request_body_size: usize,
 }
 
 impl Choko {
     /// Create a new Choko application.
     pub fn new(_app_name: impl Into<String>) -> Self {
-        Self { routes: Vec::new() }
+        cold_start::mark_init();
+        Self {
+            routes: Vec::new(),
+            middleware: Vec::new(),
+            after_response: Vec::new(),
+            content_types: None,
+            strip_stage: false,
+            problem_details: false,
+            debug: false,
+            request_id_header: None,
+            deferred_budget: defer::DEFAULT_BUDGET,
+            cold_start_namespace: None,
+            state: http::Extensions::new(),
+            ws_routes: HashMap::new(),
+            event_adapters: Vec::new(),
+            #[cfg(feature = "sqs")]
+            sqs_queues: HashMap::new(),
+            #[cfg(feature = "sns")]
+            sns_handlers: Vec::new(),
+            #[cfg(feature = "dynamodb-streams")]
+            dynamodb_tables: HashMap::new(),
+            #[cfg(feature = "kinesis")]
+            kinesis_streams: HashMap::new(),
+            #[cfg(feature = "cognito-triggers")]
+            cognito_triggers: HashMap::new(),
+            #[cfg(feature = "s3-events")]
+            s3_buckets: HashMap::new(),
+            #[cfg(feature = "ses")]
+            ses_handlers: Vec::new(),
+            #[cfg(feature = "kafka")]
+            kafka_topics: HashMap::new(),
+            #[cfg(feature = "edge")]
+            edge_response_hook: None,
+            #[cfg(feature = "schedule")]
+            scheduled_tasks: Vec::new(),
+            #[cfg(feature = "shutdown")]
+            shutdown_hooks: Vec::new(),
+            #[cfg(feature = "compression")]
+            max_decompressed_size: 10 * 1024 * 1024,
+            #[cfg(feature = "compression")]
+            compress_min_size: None,
+            #[cfg(feature = "s3-offload")]
+            offload: None,
+            #[cfg(feature = "server")]
+            max_request_body_size: 10 * 1024 * 1024,
+        }
+    }
+
+    /// Limit the size of request bodies after `Content-Encoding` decompression.
+    ///
+    /// Larger bodies are rejected with 413. Defaults to 10 MiB.
+    #[cfg(feature = "compression")]
+    pub fn max_decompressed_body_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_decompressed_size = bytes;
+        self
+    }
+
+    /// Compress response bodies of at least `min_size` bytes with brotli or
+    /// gzip when the client's `Accept-Encoding` allows it.
+    ///
+    /// Only textual content types (JSON, XML, `text/*`, ...) are compressed.
+    /// Compressed bodies are returned base64-encoded with `Content-Encoding`
+    /// set, so API Gateway passes them through unchanged.
+    #[cfg(feature = "compression")]
+    pub fn compress_responses(&mut self, min_size: usize) -> &mut Self {
+        self.compress_min_size = Some(min_size);
+        self
+    }
+
+    /// Upload responses that exceed Lambda's 6 MB payload limit to S3 and
+    /// answer with a presigned URL instead (see [`S3Offload`]).
+    #[cfg(feature = "s3-offload")]
+    pub fn offload_large_responses(&mut self, offload: S3Offload) -> &mut Self {
+        self.offload = Some(offload);
+        self
+    }
+
+    /// Strip a leading `/{stage}` segment from request paths before routing.
+    ///
+    /// The stage is taken from `requestContext.stage`. Useful when the API is
+    /// invoked through a URL that includes the stage (e.g. `/prod/users/5`).
+    pub fn strip_stage_prefix(&mut self, enabled: bool) -> &mut Self {
+        self.strip_stage = enabled;
+        self
+    }
+
+    /// Emit the framework's own error responses (404, 405, 415, 500, ...) as
+    /// RFC 7807 `application/problem+json` instead of `{"error": "..."}`.
+    pub fn problem_details(&mut self, enabled: bool) -> &mut Self {
+        self.problem_details = enabled;
+        self
+    }
+
+    /// Give every request a correlation ID, carried in `header` (usually
+    /// [`REQUEST_ID_HEADER`]).
+    ///
+    /// A valid incoming value is reused; otherwise the API Gateway request
+    /// ID is used. The ID is available as [`Request::request_id`], recorded
+    /// on the `tracing` span and in access and audit logs, and echoed in
+    /// the response's `header`.
+    pub fn request_id(&mut self, header: impl Into<String>) -> &mut Self {
+        self.request_id_header = Some(header.into());
+        self
+    }
+
+    /// The longest work deferred with [`Request::defer`] may run after the
+    /// response is produced (default 2 seconds). It is also capped by the
+    /// Lambda deadline, less a safety margin.
+    pub fn deferred_budget(&mut self, budget: Duration) -> &mut Self {
+        self.deferred_budget = budget;
+        self
+    }
+
+    /// On a cold start, print an EMF line with `ColdStart` and
+    /// `InitDuration` metrics under the CloudWatch `namespace`, dimensioned
+    /// by `FunctionName`.
+    ///
+    /// Handlers can check [`Request::is_cold_start`] regardless.
+    pub fn cold_start_metrics(&mut self, namespace: impl Into<String>) -> &mut Self {
+        self.cold_start_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Include error details in 500 responses and log full handler errors.
+    ///
+    /// With debug mode on, unhandled errors are answered with their message,
+    /// `source()` chain and `Debug` output (which includes a backtrace for
+    /// error types that capture one, e.g. `anyhow` with `RUST_BACKTRACE=1`).
+    /// Leave it off in production: clients then only see a generic message.
+    pub fn debug(&mut self, enabled: bool) -> &mut Self {
+        self.debug = enabled;
+        self
+    }
+
+    /// Restrict the media types accepted in request bodies for every route.
+    ///
+    /// Requests with a non-empty body whose `Content-Type` does not match are
+    /// rejected with 415. Routes can override this with [`Route::accepts`].
+    pub fn accept_content_types(&mut self, types: &[&str]) -> &mut Self {
+        self.content_types = Some(types.iter().map(|t| t.to_ascii_lowercase()).collect());
+        self
+    }
+
+    /// Share `value` with every handler through [`Request::state`], e.g. a
+    /// database pool or an SDK client built once at startup.
+    ///
+    /// Values are keyed by type, so registering a second value of the same
+    /// type replaces the first. Each request gets a clone; wrap large
+    /// values in an `Arc`.
+    pub fn state<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        self.state.insert(value);
+        self
+    }
+
+    /// Add middleware that runs for every matched route.
+    ///
+    /// App-wide middleware runs in registration order, before any route-level
+    /// middleware. See [`middleware::from_fn`] for building middleware from a
+    /// closure.
+    pub fn middleware(&mut self, middleware: impl Middleware) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Add a hook that can inspect and modify every outgoing response.
+    ///
+    /// Hooks run in registration order after the handler (including error
+    /// responses and 404/405) and before the response is converted for API
+    /// Gateway, so they see the uncompressed body. `req` is the request as it
+    /// arrived, before middleware ran.
+    ///
+    /// # Example
+    /// ```ignore
+    /// app.after_response(|_req, resp| {
+    ///     resp.headers
+    ///         .insert("Strict-Transport-Security".into(), "max-age=63072000".into());
+    /// });
+    /// ```
+    pub fn after_response<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Request, &mut Response) + Send + Sync + 'static,
+    {
+        self.after_response.push(Arc::new(hook));
+        self
     }
 
     /// Register a route with the given path pattern, HTTP methods, and handler.
     ///
+    /// Returns the registered [`Route`] so per-route options can be chained.
+    ///
     /// # Example
     /// ```ignore
     /// app.route("/users/{user_id}", &["GET"], |req| async move {
@@ -134,124 +1012,397 @@ impl Choko {
     ///     Ok(Response::json(serde_json::json!({"user_id": user_id})))
     /// });
     /// ```
-    pub fn route<F, Fut>(&mut self, path: &str, methods: &[&str], handler: F)
+    pub fn route<F, Fut>(&mut self, path: &str, methods: &[&str], handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
         let segments = compile_path(path);
         let methods = methods.iter().map(|m| m.to_uppercase()).collect();
-        self.routes.push(Route {
+        self.routes.push(Arc::new(Route {
+            pattern: path.to_string(),
             methods,
-            handler: Box::new(move |req| Box::pin(handler(req))),
+            handler: Arc::new(move |req| Box::pin(handler(req))),
             segments,
-        });
+            content_types: None,
+            middleware: Vec::new(),
+            #[cfg(feature = "json-schema")]
+            schema: None,
+        }));
+        let route = self.routes.last_mut().expect("route was just pushed");
+        Arc::get_mut(route).expect("routes are not shared during registration")
     }
 
     /// Register a GET route.
     ///
     /// Shortcut for `app.route(path, &["GET"], handler)`.
-    pub fn get<F, Fut>(&mut self, path: &str, handler: F)
+    pub fn get<F, Fut>(&mut self, path: &str, handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
-        self.route(path, &["GET"], handler);
+        self.route(path, &["GET"], handler)
     }
 
     /// Register a POST route.
     ///
     /// Shortcut for `app.route(path, &["POST"], handler)`.
-    pub fn post<F, Fut>(&mut self, path: &str, handler: F)
+    pub fn post<F, Fut>(&mut self, path: &str, handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
-        self.route(path, &["POST"], handler);
+        self.route(path, &["POST"], handler)
     }
 
     /// Register a PUT route.
     ///
     /// Shortcut for `app.route(path, &["PUT"], handler)`.
-    pub fn put<F, Fut>(&mut self, path: &str, handler: F)
+    pub fn put<F, Fut>(&mut self, path: &str, handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
-        self.route(path, &["PUT"], handler);
+        self.route(path, &["PUT"], handler)
     }
 
     /// Register a DELETE route.
     ///
     /// Shortcut for `app.route(path, &["DELETE"], handler)`.
-    pub fn delete<F, Fut>(&mut self, path: &str, handler: F)
+    pub fn delete<F, Fut>(&mut self, path: &str, handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
-        self.route(path, &["DELETE"], handler);
+        self.route(path, &["DELETE"], handler)
     }
 
     /// Register a PATCH route.
     ///
     /// Shortcut for `app.route(path, &["PATCH"], handler)`.
-    pub fn patch<F, Fut>(&mut self, path: &str, handler: F)
+    pub fn patch<F, Fut>(&mut self, path: &str, handler: F) -> &mut Route
     where
         F: Fn(Request) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<Response, Error>> + Send + 'static,
     {
-        self.route(path, &["PATCH"], handler);
+        self.route(path, &["PATCH"], handler)
+    }
+
+    /// Run `hook` when the execution environment shuts down (`shutdown`
+    /// feature), to flush buffered telemetry, metrics or batched writes.
+    ///
+    /// Lambda only signals shutdown to functions with an extension, so
+    /// [`run`](Self::run) registers an internal no-op extension when hooks
+    /// are present. Hooks run in registration order and, together, have
+    /// about 500ms before the environment is reaped.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let buffer = events.clone();
+    /// app.on_shutdown(move || async move {
+    ///     buffer.flush().await;
+    /// });
+    /// ```
+    #[cfg(feature = "shutdown")]
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnOnce() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(move || Box::pin(hook())));
+        self
     }
 
     /// Run the application as an AWS Lambda handler.
+    ///
+    /// Each invocation is dispatched by the shape of its event: API Gateway
+    /// requests go to the routes, WebSocket events to the WebSocket routes,
+    /// and ALB, Function URL, Lambda@Edge, SQS, SNS, DynamoDB Streams, Kinesis, Kafka,
+    /// S3, SES, scheduled and Cognito events to their handlers when the matching
+    /// feature is enabled. One function can so serve several triggers.
     pub async fn run(self) -> Result<(), Error> {
-        let app = std::sync::Arc::new(self);
-        let func = service_fn(move |event: LambdaEvent<ApiGatewayProxyRequest>| {
-            let app = app.clone();
-            async move { app.dispatch(event.payload).await }
+        self.serve(|app, event: Value, context| async move {
+            app.dispatch_event(event, Some(context)).await
+        })
+        .await
+    }
+
+    /// Run the Lambda runtime loop, passing each `E` event to `handle`.
+    ///
+    /// Takes care of what every event source shares: shutdown hooks, cold
+    /// start tracking and flushing telemetry after each invocation.
+    #[allow(unused_mut)]
+    pub(crate) async fn serve<E, R, B, F, Fut>(mut self, handle: F) -> Result<(), Error>
+    where
+        E: serde::de::DeserializeOwned + Send + 'static,
+        R: lambda_runtime::IntoFunctionResponse<B, lambda_runtime::streaming::Body>
+            + Send
+            + 'static,
+        B: serde::Serialize,
+        F: Fn(Arc<Choko>, E, LambdaContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, Error>> + Send + 'static,
+    {
+        #[cfg(feature = "shutdown")]
+        if !self.shutdown_hooks.is_empty() {
+            let hooks = std::mem::take(&mut self.shutdown_hooks);
+            lambda_runtime::spawn_graceful_shutdown_handler(move || async move {
+                for hook in hooks {
+                    hook().await;
+                }
+                #[cfg(feature = "otlp")]
+                telemetry::flush().await;
+            })
+            .await;
+        }
+        let app = Arc::new(self);
+        let handle = Arc::new(handle);
+        let func = service_fn(move |event: LambdaEvent<E>| {
+            let app = Arc::clone(&app);
+            let handle = Arc::clone(&handle);
+            async move {
+                let mut context = LambdaContext::from(&event.context);
+                context.init_duration = cold_start::take();
+                if let (Some(namespace), Some(init)) =
+                    (&app.cold_start_namespace, context.init_duration)
+                {
+                    cold_start::emit(namespace, init);
+                }
+                let result = handle(app, event.payload, context).await;
+                #[cfg(feature = "otlp")]
+                telemetry::flush().await;
+                result
+            }
         });
         lambda_runtime::run(func).await?;
         Ok(())
     }
 
+    #[cfg(test)]
     async fn dispatch(
         &self,
         event: ApiGatewayProxyRequest,
     ) -> Result<ApiGatewayProxyResponse, Error> {
-        let path = event.path.as_deref().unwrap_or("/");
+        self.dispatch_with_context(event, None).await
+    }
+
+    async fn dispatch_with_context(
+        &self,
+        event: ApiGatewayProxyRequest,
+        context: Option<LambdaContext>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let routed = self.route_event(event, context, true).await;
+        let response = self.build_apigw_response(routed.response);
+        routed
+            .deferred
+            .run(self.deferred_budget, routed.deadline)
+            .await;
+        Ok(response)
+    }
+
+    /// Route `event` through the middleware and handler, then the hooks.
+    ///
+    /// With `buffer`, a streamed body is collected; otherwise it is left
+    /// for the caller to send as it is produced.
+    pub(crate) async fn route_event(
+        &self,
+        event: ApiGatewayProxyRequest,
+        context: Option<LambdaContext>,
+        buffer: bool,
+    ) -> Routed {
+        let mut path = event.path.as_deref().unwrap_or("/");
+        if self.strip_stage {
+            path = strip_stage_prefix(path, event.request_context.stage.as_deref());
+        }
+        let path = path.to_string();
         let method = event.http_method.as_str().to_uppercase();
+        let correlation_id = self.request_id_header.as_deref().map(|name| {
+            request_id::resolve(
+                event.headers.get(name).and_then(|v| v.to_str().ok()),
+                event.request_context.request_id.as_deref(),
+                context.as_ref().map(LambdaContext::request_id),
+            )
+        });
 
         // Find matching route
         let mut path_matched = false;
+        let mut rejected = None;
         for route in &self.routes {
-            if let Some(path_params) = match_path(&route.segments, path) {
+            if let Some(path_params) = match_path(&route.segments, &path) {
                 path_matched = true;
                 if route.methods.contains(&method) {
-                    let request = self.build_request(&event, path_params);
-                    return match (route.handler)(request).await {
-                        Ok(response) => Ok(self.build_apigw_response(response)),
+                    let body = match self.decode_body(&event) {
+                        Ok(body) => body,
                         Err(e) => {
-                            eprintln!("Handler error: {e}");
-                            Ok(self.error_response(500, "Internal Server Error"))
+                            let (status, message) = e.status();
+                            let response = framework_error(status, message, self.problem_details);
+                            rejected = Some((response, path_params));
+                            break;
+                        }
+                    };
+                    let mut request = self.build_request(&event, path_params, body);
+                    let deadline = context.as_ref().map(LambdaContext::deadline);
+                    let deferred = defer::DeferredTasks::default();
+                    request.extensions.insert(deferred.clone());
+                    request.lambda_context = context;
+                    request.raw_event = Some(event);
+                    request.route = Some(route.pattern.clone());
+                    request.request_id = correlation_id.clone();
+                    #[cfg(feature = "compression")]
+                    let accept_encoding = request.header("accept-encoding").map(str::to_string);
+                    #[cfg(feature = "s3-offload")]
+                    let request_id = request.request_context.request_id.clone();
+                    let snapshot = (!self.after_response.is_empty()).then(|| request.clone());
+
+                    let chain: Arc<[Arc<dyn Middleware>]> = self
+                        .middleware
+                        .iter()
+                        .chain(route.middleware.iter())
+                        .cloned()
+                        .collect();
+                    let endpoint: HandlerFn = {
+                        let route = Arc::clone(route);
+                        let defaults = self.content_types.clone();
+                        let problem_details = self.problem_details;
+                        Arc::new(move |req| route.call(req, defaults.as_deref(), problem_details))
+                    };
+                    #[cfg(feature = "tracing")]
+                    let span = telemetry::request_span(
+                        &method,
+                        &route.pattern,
+                        request
+                            .lambda_context
+                            .as_ref()
+                            .map(LambdaContext::request_id),
+                        request.request_context.request_id.as_deref(),
+                        correlation_id.as_deref(),
+                    );
+                    #[cfg(feature = "otlp")]
+                    telemetry::set_parent(&span, &request.headers);
+                    #[cfg(feature = "tracing")]
+                    let started = std::time::Instant::now();
+                    let run = async move {
+                        match Next::new(chain, endpoint).run(request).await {
+                            Ok(response) if buffer => response.buffered().await,
+                            result => result,
                         }
                     };
+                    #[cfg(feature = "tracing")]
+                    let run = tracing::Instrument::instrument(run, span.clone());
+                    let result = run.await;
+                    let mut response = result.unwrap_or_else(|e| self.handler_error(e));
+                    #[cfg(feature = "tracing")]
+                    telemetry::finish(&span, response.status_code, started.elapsed());
+                    if let Some(req) = &snapshot {
+                        self.run_after_response(req, &mut response);
+                        if buffer {
+                            // A hook may have replaced the body with a stream
+                            response = self.buffer_response(response).await;
+                        }
+                    }
+                    #[cfg(feature = "compression")]
+                    if let Some(min_size) = self.compress_min_size {
+                        response = compress::compress_response(
+                            response,
+                            accept_encoding.as_deref(),
+                            min_size,
+                        );
+                    }
+                    #[cfg(feature = "s3-offload")]
+                    if let Some(offload) = &self.offload {
+                        // Boxed: the S3 SDK futures are deep enough to push
+                        // `run()` past the compiler's default recursion limit.
+                        response =
+                            Box::pin(offload.apply(response, request_id.as_deref().unwrap_or("")))
+                                .await
+                                .unwrap_or_else(|e| self.handler_error(e));
+                    }
+                    self.echo_request_id(&mut response, correlation_id);
+                    return Routed {
+                        response,
+                        deferred,
+                        deadline,
+                    };
                 }
             }
         }
 
-        if path_matched {
-            Ok(self.error_response(405, "Method Not Allowed"))
-        } else {
-            Ok(self.error_response(404, "Not Found"))
+        // Unroutable or undecodable requests still get the hooks and the
+        // request ID
+        let (mut response, path_params) = match rejected {
+            Some(rejected) => rejected,
+            None if path_matched => (
+                framework_error(405, "Method Not Allowed", self.problem_details),
+                HashMap::new(),
+            ),
+            None => (
+                framework_error(404, "Not Found", self.problem_details),
+                HashMap::new(),
+            ),
+        };
+        if !self.after_response.is_empty() {
+            let mut request = self.build_request(&event, path_params, None);
+            request.lambda_context = context;
+            request.raw_event = Some(event);
+            request.request_id = correlation_id.clone();
+            self.run_after_response(&request, &mut response);
+            if buffer {
+                response = self.buffer_response(response).await;
+            }
+        }
+        self.echo_request_id(&mut response, correlation_id);
+        Routed {
+            response,
+            deferred: defer::DeferredTasks::default(),
+            deadline: None,
         }
     }
 
-    fn build_request(
-        &self,
-        event: &ApiGatewayProxyRequest,
-        path_params: HashMap<String, String>,
-    ) -> Request {
-        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
+    /// Collect a streamed body, answering with an error response if its
+    /// producer fails.
+    async fn buffer_response(&self, response: Response) -> Response {
+        response
+            .buffered()
+            .await
+            .unwrap_or_else(|e| self.handler_error(e))
+    }
+
+    fn echo_request_id(&self, response: &mut Response, request_id: Option<String>) {
+        if let (Some(name), Some(id)) = (&self.request_id_header, request_id) {
+            response.headers.insert(name.clone(), id);
+        }
+    }
+    /// Decode the request body: base64 (for binary payloads) and then any
+    /// `Content-Encoding`.
+    fn decode_body(&self, event: &ApiGatewayProxyRequest) -> Result<Option<Vec<u8>>, BodyError> {
+        let Some(body) = event.body.as_deref() else {
+            return Ok(None);
+        };
+        let bytes = if event.is_base64_encoded {
+            base64::engine::general_purpose::STANDARD
+                .decode(body)
+                .map_err(|_| BodyError::BadBase64)?
+        } else {
+            body.as_bytes().to_vec()
+        };
+
+        #[cfg(feature = "compression")]
+        if let Some(encoding) = event.headers.get(http::header::CONTENT_ENCODING) {
+            let encoding = encoding.to_str().unwrap_or("");
+            return decompress::decode_body(encoding, bytes, self.max_decompressed_size)
+                .map(Some)
+                .map_err(BodyError::Decode);
+        }
+
+        Ok(Some(bytes))
+    }
+
+    fn build_request(
+        &self,
+        event: &ApiGatewayProxyRequest,
+        path_params: HashMap<String, String>,
+        body: Option<Vec<u8>>,
+    ) -> Request {
+        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
         for (k, v) in event.multi_value_query_string_parameters.iter() {
             query_params
                 .entry(k.to_string())
@@ -273,40 +1424,77 @@ impl Choko {
             .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
             .collect();
 
-        let body_str = event.body.clone();
+        // Text bodies live in `body`; only non-UTF-8 payloads are kept as bytes
+        let (body_str, binary_body) = match body.map(String::from_utf8) {
+            Some(Ok(text)) => (Some(text), None),
+            Some(Err(e)) => (None, Some(e.into_bytes())),
+            None => (None, None),
+        };
 
         let json_body = body_str
             .as_deref()
             .and_then(|s| serde_json::from_str(s).ok());
 
+        let mut extensions = self.state.clone();
+        extensions.insert(TraceContext::from_headers(|name| {
+            event.headers.get(name).and_then(|v| v.to_str().ok())
+        }));
+
         Request {
             path_params,
             query_params,
             headers,
             body: body_str,
+            binary_body,
             json_body,
+            request_context: RequestContext::from(&event.request_context),
+            lambda_context: None,
+            extensions,
+            raw_event: None,
+            route: None,
+            request_id: None,
         }
     }
 
     fn build_apigw_response(&self, resp: Response) -> ApiGatewayProxyResponse {
+        let mut r = self.response_head(&resp);
+        match resp.body {
+            ResponseBody::Json(v) => r.body = Some(Body::Text(v.to_string())),
+            ResponseBody::Text(t) => r.body = Some(Body::Text(t)),
+            ResponseBody::Binary(b) => {
+                r.body = Some(Body::Binary(b));
+                r.is_base64_encoded = true;
+            }
+            ResponseBody::Empty => {}
+            ResponseBody::Stream(_) => {
+                // Routed responses are buffered before they get here
+                eprintln!("Handler error: streamed body reached a buffered integration");
+                let resp = framework_error(500, "Internal Server Error", self.problem_details);
+                return self.build_apigw_response(resp);
+            }
+        }
+        r
+    }
+
+    /// The status and headers of `resp`, without its body.
+    pub(crate) fn response_head(&self, resp: &Response) -> ApiGatewayProxyResponse {
         let mut headers = http::HeaderMap::new();
-        headers.insert(
-            http::header::CONTENT_TYPE,
-            http::HeaderValue::from_static("application/json"),
-        );
+        if let Some(content_type) = resp.body.default_content_type() {
+            headers.insert(
+                http::header::CONTENT_TYPE,
+                http::HeaderValue::from_static(content_type),
+            );
+        }
         for (k, v) in &resp.headers {
-            match (
-                http::header::HeaderName::from_bytes(k.as_bytes()),
-                http::HeaderValue::from_str(v),
-            ) {
-                (Ok(name), Ok(val)) => {
-                    headers.insert(name, val);
-                }
-                (Err(e), _) => {
-                    eprintln!("Invalid header name {k:?}: {e}");
-                }
-                (_, Err(e)) => {
-                    eprintln!("Invalid header value for {k:?}: {e}");
+            if let Some((name, val)) = header_pair(k, v) {
+                headers.insert(name, val);
+            }
+        }
+        let mut multi_value_headers = http::HeaderMap::new();
+        for (k, values) in &resp.multi_value_headers {
+            for v in values {
+                if let Some((name, val)) = header_pair(k, v) {
+                    multi_value_headers.append(name, val);
                 }
             }
         }
@@ -314,22 +1502,72 @@ impl Choko {
         let mut r = ApiGatewayProxyResponse::default();
         r.status_code = resp.status_code;
         r.headers = headers;
-        r.body = Some(Body::Text(resp.body.to_string()));
+        r.multi_value_headers = multi_value_headers;
         r
     }
 
-    fn error_response(&self, status_code: i64, message: &str) -> ApiGatewayProxyResponse {
-        let body = serde_json::json!({ "error": message });
-        let mut headers = http::HeaderMap::new();
-        headers.insert(
-            http::header::CONTENT_TYPE,
-            http::HeaderValue::from_static("application/json"),
-        );
-        let mut r = ApiGatewayProxyResponse::default();
-        r.status_code = status_code;
-        r.headers = headers;
-        r.body = Some(Body::Text(body.to_string()));
-        r
+    /// Map an error returned by a handler (or middleware) to a response.
+    fn handler_error(&self, e: Error) -> Response {
+        if let Some(rejection) = e.downcast_ref::<QueryRejection>() {
+            return framework_error(400, &rejection.to_string(), self.problem_details);
+        }
+        if e.is::<PreconditionFailed>() {
+            return framework_error(412, "Precondition Failed", self.problem_details);
+        }
+        let e = match e.downcast::<Problem>() {
+            Ok(problem) => return Response::from(*problem),
+            Err(e) => e,
+        };
+        if let Some(err) = e.downcast_ref::<ChokoError>() {
+            if let ChokoError::Internal(source) = err {
+                return self.internal_error(source.as_ref());
+            }
+            return framework_error(
+                i64::from(err.status_code()),
+                &err.public_message(),
+                self.problem_details,
+            );
+        }
+        self.internal_error(e.as_ref())
+    }
+
+    /// Log an unhandled error and answer with 500. In debug mode the full
+    /// error chain is logged and included in the response body.
+    fn internal_error(&self, e: &(dyn std::error::Error + Send + Sync + 'static)) -> Response {
+        if !self.debug {
+            eprintln!("Handler error: {e}");
+            return framework_error(500, "Internal Server Error", self.problem_details);
+        }
+
+        eprintln!("Handler error: {e:?}");
+        let mut chain = Vec::new();
+        let mut source = e.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+        if self.problem_details {
+            Problem::new(500)
+                .with_detail(e.to_string())
+                .with_extension("chain", chain)
+                .with_extension("debug", format!("{e:?}"))
+                .into()
+        } else {
+            Response::json(serde_json::json!({
+                "error": "Internal Server Error",
+                "message": e.to_string(),
+                "chain": chain,
+                "debug": format!("{e:?}"),
+            }))
+            .with_status(500)
+        }
+    }
+
+    /// Run the after-response hooks against `resp`.
+    fn run_after_response(&self, req: &Request, resp: &mut Response) {
+        for hook in &self.after_response {
+            hook(req, resp);
+        }
     }
 }
 
@@ -410,6 +1648,78 @@ mod tests {
         assert!(match_path(&segments, "/api/posts").is_none());
     }
 
+    #[test]
+    fn strip_stage_prefix_only_strips_whole_segment() {
+        assert_eq!(
+            strip_stage_prefix("/prod/users/5", Some("prod")),
+            "/users/5"
+        );
+        assert_eq!(strip_stage_prefix("/prod", Some("prod")), "");
+        assert_eq!(
+            strip_stage_prefix("/production/x", Some("prod")),
+            "/production/x"
+        );
+        assert_eq!(strip_stage_prefix("/users/5", Some("prod")), "/users/5");
+        assert_eq!(strip_stage_prefix("/prod/users", None), "/prod/users");
+    }
+
+    // --- content type tests ---
+
+    #[test]
+    fn media_type_strips_parameters() {
+        assert_eq!(
+            media_type("Application/JSON; charset=utf-8"),
+            "application/json"
+        );
+        assert_eq!(media_type("text/plain"), "text/plain");
+    }
+
+    #[test]
+    fn with_attachment_sets_content_disposition() {
+        let resp = Response::text("a,b").with_attachment("report \"q1\".csv");
+        assert_eq!(
+            resp.header("content-disposition"),
+            Some("attachment; filename=\"report _q1_.csv\"")
+        );
+
+        let resp = Response::text("a,b").with_attachment("売上.csv");
+        assert_eq!(
+            resp.header("content-disposition"),
+            Some("attachment; filename=\"__.csv\"; filename*=UTF-8''%E5%A3%B2%E4%B8%8A.csv")
+        );
+    }
+
+    // --- Request extensions tests ---
+
+    #[test]
+    fn request_extensions_are_typed() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Tenant(&'static str);
+
+        let mut req = Request::default();
+        assert!(req.extensions().get::<Tenant>().is_none());
+        req.extensions_mut().insert(Tenant("acme"));
+        assert_eq!(req.extensions().get::<Tenant>(), Some(&Tenant("acme")));
+    }
+
+    #[tokio::test]
+    async fn app_state_reaches_handlers() {
+        #[derive(Clone)]
+        struct Greeting(&'static str);
+
+        let mut app = Choko::new("test");
+        app.state(Greeting("hello"));
+        app.get("/", |req| async move {
+            let greeting = req.state::<Greeting>().map_or("none", |g| g.0);
+            Ok(Response::text(greeting))
+        });
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.body, Some(Body::Text("hello".to_string())));
+    }
+
     // --- Response builder tests ---
 
     #[test]
@@ -435,6 +1745,71 @@ mod tests {
         assert_eq!(resp.headers.get("X-Other").unwrap(), "value2");
     }
 
+    #[test]
+    fn response_json_of_serializes_structs() {
+        #[derive(serde::Serialize)]
+        struct User {
+            id: u32,
+            name: &'static str,
+        }
+
+        let resp = Response::json_of(&User {
+            id: 1,
+            name: "alice",
+        })
+        .unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            "application/json"
+        );
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text(r#"{"id":1,"name":"alice"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn response_redirects() {
+        let resp = Response::redirect("/login");
+        assert_eq!(resp.status_code, 302);
+        assert_eq!(resp.headers.get("Location").unwrap(), "/login");
+        assert_eq!(resp.body, ResponseBody::Empty);
+        assert_eq!(Response::see_other("/a").status_code, 303);
+        assert_eq!(Response::temporary_redirect("/a").status_code, 307);
+        assert_eq!(Response::permanent_redirect("/a").status_code, 308);
+    }
+
+    #[test]
+    fn response_created_and_no_content() {
+        let resp = Response::created("/users/1", json!({"id": 1}));
+        assert_eq!(resp.status_code, 201);
+        assert_eq!(resp.headers.get("Location").unwrap(), "/users/1");
+        assert_eq!(resp.body, json!({"id": 1}));
+
+        let resp = Response::no_content();
+        assert_eq!(resp.status_code, 204);
+        assert_eq!(resp.body, ResponseBody::Empty);
+
+        let resp = Response::accepted(json!({"job": "j-1"}));
+        assert_eq!(resp.status_code, 202);
+        assert_eq!(resp.body, json!({"job": "j-1"}));
+    }
+
+    #[tokio::test]
+    async fn dispatch_empty_body_has_no_content_type() {
+        let mut app = Choko::new("test");
+        app.delete("/items/{id}", |_req| async { Ok(Response::no_content()) });
+
+        let resp = app
+            .dispatch(make_apigw_request("DELETE", "/items/1", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 204);
+        assert!(resp.body.is_none());
+        assert!(resp.headers.get(http::header::CONTENT_TYPE).is_none());
+    }
+
     // --- dispatch integration tests ---
 
     fn make_apigw_request(
@@ -488,6 +1863,54 @@ mod tests {
         assert_eq!(body["user_id"], "123");
     }
 
+    #[tokio::test]
+    async fn dispatch_records_matched_route_pattern() {
+        let mut app = Choko::new("test");
+        app.get("/users/{user_id}", |req| async move {
+            Ok(Response::text(req.route().unwrap_or_default().to_string()))
+        });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/users/7", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.body, Some(Body::Text("/users/{user_id}".to_string())));
+    }
+
+    #[tokio::test]
+    async fn dispatch_propagates_request_id() {
+        let mut app = Choko::new("test");
+        app.request_id(REQUEST_ID_HEADER);
+        app.get("/", |req| async move {
+            Ok(Response::text(
+                req.request_id().unwrap_or_default().to_string(),
+            ))
+        });
+
+        let mut event = make_apigw_request("GET", "/", None);
+        event
+            .headers
+            .insert("x-request-id", http::HeaderValue::from_static("client-42"));
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.body, Some(Body::Text("client-42".to_string())));
+        assert_eq!(resp.headers.get("x-request-id").unwrap(), "client-42");
+
+        let mut event = make_apigw_request("GET", "/missing", None);
+        event.request_context.request_id = Some("apigw-7".to_string());
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 404);
+        assert_eq!(resp.headers.get("x-request-id").unwrap(), "apigw-7");
+
+        let mut event = make_apigw_request("GET", "/", Some("not base64!".into()));
+        event.is_base64_encoded = true;
+        event
+            .headers
+            .insert("x-request-id", http::HeaderValue::from_static("client-43"));
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 400);
+        assert_eq!(resp.headers.get("x-request-id").unwrap(), "client-43");
+    }
+
     #[tokio::test]
     async fn dispatch_returns_404_for_unknown_path() {
         let mut app = Choko::new("test");
@@ -703,4 +2126,643 @@ mod tests {
             assert_eq!(body["method"], *method);
         }
     }
+
+    // --- JSON Schema validation tests ---
+
+    #[cfg(feature = "json-schema")]
+    fn schema_app() -> Choko {
+        let mut app = Choko::new("test");
+        app.post("/users", |_req| async {
+            Ok(Response::json(json!({"created": true})).with_status(201))
+        })
+        .json_schema(json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer", "minimum": 0}
+            }
+        }));
+        app
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[tokio::test]
+    async fn dispatch_accepts_body_matching_schema() {
+        let app = schema_app();
+        let body = json!({"name": "alice", "age": 30}).to_string();
+        let resp = app
+            .dispatch(make_apigw_request("POST", "/users", Some(body)))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 201);
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[tokio::test]
+    async fn dispatch_rejects_body_violating_schema() {
+        let app = schema_app();
+        let body = json!({"name": "alice", "age": -1}).to_string();
+        let resp = app
+            .dispatch(make_apigw_request("POST", "/users", Some(body)))
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status_code, 400);
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["details"][0]["path"], "/age");
+    }
+
+    #[cfg(feature = "json-schema")]
+    #[tokio::test]
+    async fn dispatch_rejects_missing_body_with_schema() {
+        let app = schema_app();
+        let resp = app
+            .dispatch(make_apigw_request("POST", "/users", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 400);
+    }
+
+    // --- Content-Type enforcement tests ---
+
+    fn make_apigw_request_with_content_type(
+        method: &str,
+        path: &str,
+        body: &str,
+        content_type: &str,
+    ) -> ApiGatewayProxyRequest {
+        let mut req = make_apigw_request(method, path, Some(body.to_string()));
+        req.headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_str(content_type).unwrap(),
+        );
+        req
+    }
+
+    #[tokio::test]
+    async fn dispatch_returns_415_for_unaccepted_content_type() {
+        let mut app = Choko::new("test");
+        app.post("/items", |_req| async { Ok(Response::json(json!({}))) })
+            .accepts(&["application/json"]);
+
+        let resp = app
+            .dispatch(make_apigw_request_with_content_type(
+                "POST",
+                "/items",
+                "<item/>",
+                "application/xml",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 415);
+
+        let resp = app
+            .dispatch(make_apigw_request_with_content_type(
+                "POST",
+                "/items",
+                "{}",
+                "application/json; charset=utf-8",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+
+        // A body without any Content-Type is rejected too
+        let resp = app
+            .dispatch(make_apigw_request("POST", "/items", Some("{}".into())))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 415);
+
+        // ...including a binary one
+        let mut binary = make_apigw_request("POST", "/items", Some("AJ+Slg==".into()));
+        binary.is_base64_encoded = true;
+        let resp = app.dispatch(binary).await.unwrap();
+        assert_eq!(resp.status_code, 415);
+    }
+
+    #[tokio::test]
+    async fn global_content_types_apply_unless_route_overrides() {
+        let mut app = Choko::new("test");
+        app.accept_content_types(&["application/json"]);
+        app.post("/json", |_req| async { Ok(Response::json(json!({}))) });
+        app.post("/text", |_req| async { Ok(Response::json(json!({}))) })
+            .accepts(&["text/*"]);
+        app.get("/json", |_req| async { Ok(Response::json(json!({}))) });
+
+        let resp = app
+            .dispatch(make_apigw_request_with_content_type(
+                "POST",
+                "/json",
+                "hi",
+                "text/plain",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 415);
+
+        let resp = app
+            .dispatch(make_apigw_request_with_content_type(
+                "POST",
+                "/text",
+                "hi",
+                "text/plain",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+
+        // Requests without a body are never rejected
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/json", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+    }
+
+    // --- non-JSON body tests ---
+
+    #[tokio::test]
+    async fn dispatch_text_and_binary_bodies() {
+        let mut app = Choko::new("test");
+        app.get("/text", |_req| async { Ok(Response::text("hello")) });
+        app.get("/bin", |_req| async {
+            Ok(Response::binary(vec![0u8, 1, 2], "image/png"))
+        });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/text", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers.get(http::header::CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert!(matches!(resp.body, Some(Body::Text(ref s)) if s == "hello"));
+        assert!(!resp.is_base64_encoded);
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/bin", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers.get(http::header::CONTENT_TYPE).unwrap(),
+            "image/png"
+        );
+        assert!(matches!(resp.body, Some(Body::Binary(ref b)) if b == &[0u8, 1, 2]));
+        assert!(resp.is_base64_encoded);
+    }
+
+    // --- Lambda context tests ---
+
+    #[tokio::test]
+    async fn dispatch_exposes_lambda_context() {
+        let mut app = Choko::new("test");
+        app.get("/", |req| async move {
+            let id = req.lambda_context().map(|c| c.request_id().to_string());
+            Ok(Response::json(json!({ "request_id": id })))
+        });
+
+        let mut ctx = lambda_runtime::Context::default();
+        ctx.request_id = "abc-123".to_string();
+        let resp = app
+            .dispatch_with_context(
+                make_apigw_request("GET", "/", None),
+                Some(LambdaContext::from(&ctx)),
+            )
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["request_id"], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn dispatch_strips_stage_prefix_when_enabled() {
+        let mut app = Choko::new("test");
+        app.get("/users/{id}", |req| async move {
+            Ok(Response::json(json!({ "id": req.path_params["id"] })))
+        });
+
+        let mut event = make_apigw_request("GET", "/prod/users/5", None);
+        event.request_context.stage = Some("prod".to_string());
+        let resp = app.dispatch(event.clone()).await.unwrap();
+        assert_eq!(resp.status_code, 404);
+
+        app.strip_stage_prefix(true);
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn dispatch_keeps_raw_event() {
+        let mut app = Choko::new("test");
+        app.get("/", |req| async move {
+            let resource = req.raw_event().and_then(|e| e.resource.clone());
+            Ok(Response::json(json!({ "resource": resource })))
+        });
+
+        let mut event = make_apigw_request("GET", "/", None);
+        event.resource = Some("/{proxy+}".to_string());
+        let resp = app.dispatch(event).await.unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["resource"], "/{proxy+}");
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn dispatch_decompresses_gzip_body() {
+        use base64::Engine;
+        use std::io::Write;
+
+        let mut app = Choko::new("test");
+        app.post("/items", |req| async move {
+            Ok(Response::json(json!({ "body": req.json_body })))
+        });
+
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(br#"{"name":"zipped"}"#).unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(enc.finish().unwrap());
+
+        let mut event = make_apigw_request("POST", "/items", Some(encoded));
+        event.is_base64_encoded = true;
+        event.headers.insert(
+            http::header::CONTENT_ENCODING,
+            http::HeaderValue::from_static("gzip"),
+        );
+        let resp = app.dispatch(event).await.unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["body"]["name"], "zipped");
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn dispatch_compresses_response_for_accept_encoding() {
+        let mut app = Choko::new("test");
+        app.compress_responses(16);
+        app.get("/items", |_req| async move {
+            Ok(Response::text("hello hello hello hello hello"))
+        });
+
+        let mut event = make_apigw_request("GET", "/items", None);
+        event.headers.insert(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("gzip"),
+        );
+        let resp = app.dispatch(event).await.unwrap();
+        assert!(resp.is_base64_encoded);
+        assert_eq!(resp.headers.get("content-encoding").unwrap(), "gzip");
+        assert_eq!(
+            resp.headers.get("content-type").unwrap(),
+            "text/plain; charset=utf-8"
+        );
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/items", None))
+            .await
+            .unwrap();
+        assert!(!resp.is_base64_encoded);
+        assert!(resp.headers.get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn dispatch_emits_problem_details_when_enabled() {
+        let mut app = Choko::new("test");
+        app.problem_details(true);
+        app.get("/items", |_req| async move { Ok(Response::text("ok")) });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/missing", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 404);
+        assert_eq!(
+            resp.headers.get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(
+            body,
+            json!({"type": "about:blank", "title": "Not Found", "status": 404})
+        );
+
+        let resp = app
+            .dispatch(make_apigw_request("POST", "/items", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 405);
+        assert_eq!(
+            resp.headers.get("content-type").unwrap(),
+            "application/problem+json"
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch_renders_problem_returned_by_handler() {
+        let mut app = Choko::new("test");
+        app.get("/items/{id}", |_req| async move {
+            Err(Problem::new(404).with_detail("no such item").into())
+        });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/items/7", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 404);
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["detail"], "no such item");
+    }
+
+    #[tokio::test]
+    async fn dispatch_maps_choko_error_status() {
+        let mut app = Choko::new("test");
+        app.get("/users/{id}", |req| async move {
+            match req.path_params["id"].as_str() {
+                "1" => Err(ChokoError::not_found("user").into()),
+                "2" => Err(ChokoError::unauthorized("login required").into()),
+                _ => Err(ChokoError::internal("connection refused").into()),
+            }
+        });
+
+        let cases = [
+            ("1", 404, "user not found"),
+            ("2", 401, "login required"),
+            ("3", 500, "Internal Server Error"),
+        ];
+        for (id, status, message) in cases {
+            let path = format!("/users/{id}");
+            let resp = app
+                .dispatch(make_apigw_request("GET", &path, None))
+                .await
+                .unwrap();
+            assert_eq!(resp.status_code, status);
+            let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+                Body::Text(s) => s,
+                _ => panic!("expected text body"),
+            })
+            .unwrap();
+            assert_eq!(body["error"], message);
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_includes_error_chain_in_debug_mode() {
+        #[derive(Debug)]
+        struct Outer(std::io::Error);
+        impl std::fmt::Display for Outer {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("loading config failed")
+            }
+        }
+        impl std::error::Error for Outer {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let mut app = Choko::new("test");
+        app.get("/fail", |_req| async move {
+            Err(Outer(std::io::Error::other("file missing")).into())
+        });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/fail", None))
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body, json!({"error": "Internal Server Error"}));
+
+        app.debug(true);
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/fail", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 500);
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["message"], "loading config failed");
+        assert_eq!(body["chain"], json!(["file missing"]));
+    }
+
+    #[tokio::test]
+    async fn after_response_hooks_modify_every_response() {
+        let mut app = Choko::new("test");
+        app.after_response(|_req, resp| {
+            resp.headers
+                .insert("X-Frame-Options".to_string(), "DENY".to_string());
+        });
+        app.after_response(|req, resp| {
+            let id = req.path_params.get("id").cloned().unwrap_or_default();
+            resp.headers.insert("X-Item".to_string(), id);
+        });
+        app.get(
+            "/items/{id}",
+            |_req| async move { Ok(Response::text("ok")) },
+        );
+        app.get("/fail", |_req| async move { Err("boom".into()) });
+
+        for (path, status, item) in [
+            ("/items/7", 200, "7"),
+            ("/fail", 500, ""),
+            ("/nope", 404, ""),
+        ] {
+            let resp = app
+                .dispatch(make_apigw_request("GET", path, None))
+                .await
+                .unwrap();
+            assert_eq!(resp.status_code, status);
+            assert_eq!(resp.headers.get("x-frame-options").unwrap(), "DENY");
+            assert_eq!(resp.headers.get("x-item").unwrap(), item);
+        }
+
+        // ...including bodies rejected before reaching the route
+        let mut event = make_apigw_request("GET", "/items/8", Some("not base64!".into()));
+        event.is_base64_encoded = true;
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 400);
+        assert_eq!(resp.headers.get("x-frame-options").unwrap(), "DENY");
+        assert_eq!(resp.headers.get("x-item").unwrap(), "8");
+    }
+
+    #[tokio::test]
+    async fn after_response_hooks_may_set_streamed_bodies() {
+        let mut app = Choko::new("test");
+        app.after_response(|_req, resp| {
+            let (tx, body) = BodyStream::channel(1);
+            tokio::spawn(async move {
+                let _ = tx.send("replaced").await;
+            });
+            resp.body = ResponseBody::Stream(body);
+        });
+        app.get("/items", |_req| async move { Ok(Response::text("ok")) });
+
+        for path in ["/items", "/nope"] {
+            let resp = app
+                .dispatch(make_apigw_request("GET", path, None))
+                .await
+                .unwrap();
+            assert_eq!(resp.body, Some(Body::Text("replaced".to_string())));
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_sends_cookies_as_multi_value_headers() {
+        let mut app = Choko::new("test");
+        app.get("/login", |_req| async move {
+            Ok(Response::no_content()
+                .with_cookie(Cookie::new("session", "s1").http_only(true))
+                .with_cookie(Cookie::new("csrf", "c1")))
+        });
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/login", None))
+            .await
+            .unwrap();
+        let cookies: Vec<_> = resp
+            .multi_value_headers
+            .get_all("set-cookie")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        assert_eq!(cookies, ["session=s1; HttpOnly", "csrf=c1"]);
+    }
+
+    #[tokio::test]
+    async fn dispatch_returns_400_for_query_rejection() {
+        #[derive(serde::Deserialize)]
+        struct Paging {
+            #[allow(dead_code)]
+            page: u32,
+        }
+
+        let mut app = Choko::new("test");
+        app.get("/items", |req| async move {
+            let _paging: Paging = req.query()?;
+            Ok::<_, Error>(Response::json(json!({})))
+        });
+
+        let mut event = make_apigw_request("GET", "/items", None);
+        event.query_string_parameters = [("page".to_string(), "x".to_string())]
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+            .into();
+        let resp = app.dispatch(event).await.unwrap();
+        assert_eq!(resp.status_code, 400);
+    }
+
+    // --- middleware tests ---
+
+    #[tokio::test]
+    async fn middleware_runs_app_then_route_level() {
+        let mut app = Choko::new("test");
+        app.middleware(middleware::from_fn(|mut req, next| async move {
+            req.headers.insert("x-trail".into(), "app".into());
+            let resp = next.run(req).await?;
+            Ok::<_, Error>(resp.with_header("x-app", "1"))
+        }));
+        app.get("/", |req| async move {
+            Ok(Response::json(json!({ "trail": req.headers["x-trail"] })))
+        })
+        .middleware(middleware::from_fn(|mut req, next| async move {
+            let trail = format!("{},route", req.headers["x-trail"]);
+            req.headers.insert("x-trail".into(), trail);
+            next.run(req).await
+        }));
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers.get("x-app").unwrap(), "1");
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body["trail"], "app,route");
+    }
+
+    #[tokio::test]
+    async fn middleware_can_short_circuit() {
+        let mut app = Choko::new("test");
+        app.get("/admin", |_req| async {
+            Ok(Response::json(json!({ "secret": true })))
+        })
+        .middleware(middleware::from_fn(|req, next| async move {
+            if req.header("authorization").is_none() {
+                return Ok(Response::json(json!({ "error": "Unauthorized" })).with_status(401));
+            }
+            next.run(req).await
+        }));
+
+        let resp = app
+            .dispatch(make_apigw_request("GET", "/admin", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 401);
+    }
+
+    #[tokio::test]
+    async fn dispatch_decodes_base64_bodies() {
+        let mut app = Choko::new("test");
+        app.post("/upload", |req| async move {
+            Ok(Response::json(json!({
+                "text": req.body,
+                "len": req.body_bytes().map(<[u8]>::len),
+            })))
+        });
+
+        // Valid UTF-8 is exposed as text
+        let mut event = make_apigw_request("POST", "/upload", Some("aGVsbG8=".into()));
+        event.is_base64_encoded = true;
+        let resp = app.dispatch(event).await.unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body, json!({ "text": "hello", "len": 5 }));
+
+        // Binary is only available as bytes
+        let mut event = make_apigw_request("POST", "/upload", Some("/wD+".into()));
+        event.is_base64_encoded = true;
+        let resp = app.dispatch(event).await.unwrap();
+        let body: Value = serde_json::from_str(match resp.body.as_ref().unwrap() {
+            Body::Text(s) => s,
+            _ => panic!("expected text body"),
+        })
+        .unwrap();
+        assert_eq!(body, json!({ "text": null, "len": 3 }));
+    }
 }
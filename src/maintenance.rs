@@ -0,0 +1,225 @@
+//! Maintenance mode kill switch.
+//!
+//! [`Maintenance`] answers requests with 503 and `Retry-After` while a flag
+//! is set, without a code deploy. The flag is read from an environment
+//! variable or, with the `ssm` feature, from an SSM parameter cached for a
+//! short time so it can be flipped on a running function.
+//!
+//! # Example
+//! ```ignore
+//! use choko::maintenance::Maintenance;
+//!
+//! app.middleware(Maintenance::env("MAINTENANCE_MODE").exempt("/health"));
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "ssm")]
+use std::{sync::Mutex, time::Instant};
+
+/// Whether a flag value means "on": `1`, `true`, `on` or `yes`, in any case.
+fn is_enabled(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "on" | "yes"
+    )
+}
+
+enum Source {
+    Env(String),
+    #[cfg(feature = "ssm")]
+    Ssm {
+        client: aws_sdk_ssm::Client,
+        name: String,
+        ttl: Duration,
+        cache: Mutex<Option<(Instant, bool)>>,
+    },
+}
+
+impl Source {
+    async fn enabled(&self) -> bool {
+        match self {
+            Source::Env(var) => std::env::var(var).is_ok_and(|v| is_enabled(&v)),
+            #[cfg(feature = "ssm")]
+            Source::Ssm {
+                client,
+                name,
+                ttl,
+                cache,
+            } => {
+                let cached = *cache.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some((fetched_at, enabled)) = cached {
+                    if fetched_at.elapsed() < *ttl {
+                        return enabled;
+                    }
+                }
+                let enabled = match client.get_parameter().name(name).send().await {
+                    Ok(output) => output
+                        .parameter()
+                        .and_then(|p| p.value())
+                        .is_some_and(is_enabled),
+                    Err(e)
+                        if e.as_service_error()
+                            .is_some_and(|e| e.is_parameter_not_found()) =>
+                    {
+                        false
+                    }
+                    Err(e) => {
+                        // Keep the last known state rather than flapping
+                        eprintln!("Failed to read maintenance flag {name}: {e}");
+                        cached.is_some_and(|(_, enabled)| enabled)
+                    }
+                };
+                *cache.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), enabled));
+                enabled
+            }
+        }
+    }
+}
+
+/// Middleware that short-circuits with 503 while maintenance mode is on.
+///
+/// Attach it app-wide to cover every route, or to selected routes only.
+/// Paths and route patterns passed to [`exempt`](Self::exempt) (health
+/// checks, status pages) are always served.
+pub struct Maintenance {
+    source: Arc<Source>,
+    retry_after: Duration,
+    message: String,
+    exempt: Vec<String>,
+}
+
+impl Maintenance {
+    /// Read the flag from the environment variable `var` on every request.
+    pub fn env(var: impl Into<String>) -> Self {
+        Self::from_source(Source::Env(var.into()))
+    }
+
+    /// Read the flag from the SSM parameter `name`, caching it for 30
+    /// seconds. A missing parameter means maintenance is off.
+    #[cfg(feature = "ssm")]
+    pub fn ssm(client: aws_sdk_ssm::Client, name: impl Into<String>) -> Self {
+        Self::from_source(Source::Ssm {
+            client,
+            name: name.into(),
+            ttl: Duration::from_secs(30),
+            cache: Mutex::new(None),
+        })
+    }
+
+    fn from_source(source: Source) -> Self {
+        Self {
+            source: Arc::new(source),
+            retry_after: Duration::from_secs(300),
+            message: "Service Unavailable".to_string(),
+            exempt: Vec::new(),
+        }
+    }
+
+    /// How long the SSM flag is cached. No effect for environment flags.
+    ///
+    /// # Panics
+    /// Panics if called after the middleware has been shared.
+    #[cfg(feature = "ssm")]
+    pub fn cache_ttl(mut self, new_ttl: Duration) -> Self {
+        if let Source::Ssm { ttl, .. } =
+            Arc::get_mut(&mut self.source).expect("Maintenance is configured before use")
+        {
+            *ttl = new_ttl;
+        }
+        self
+    }
+
+    /// The `Retry-After` value sent to clients. Defaults to 5 minutes.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The error message in the 503 body.
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Keep serving `path`, matched against the request path and the route
+    /// pattern.
+    pub fn exempt(mut self, path: impl Into<String>) -> Self {
+        self.exempt.push(path.into());
+        self
+    }
+
+    fn is_exempt(&self, req: &Request) -> bool {
+        self.exempt
+            .iter()
+            .any(|p| req.route() == Some(p.as_str()) || req.path() == p)
+    }
+}
+
+impl Middleware for Maintenance {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if self.is_exempt(&req) {
+            return next.run(req);
+        }
+        let source = Arc::clone(&self.source);
+        let resp = crate::error_json(503, &self.message)
+            .with_header("Retry-After", self.retry_after.as_secs().to_string());
+        Box::pin(async move {
+            if source.enabled().await {
+                Ok(resp)
+            } else {
+                next.run(req).await
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+
+    async fn call(mw: Maintenance, req: Request) -> Response {
+        run_middleware(mw, |_req| async { Ok(Response::no_content()) }, req)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn flag_values() {
+        for on in ["1", "true", "ON", " yes "] {
+            assert!(is_enabled(on), "{on}");
+        }
+        for off in ["", "0", "false", "off", "maybe"] {
+            assert!(!is_enabled(off), "{off}");
+        }
+    }
+
+    #[tokio::test]
+    async fn env_flag_short_circuits_with_retry_after() {
+        let var = "CHOKO_TEST_MAINTENANCE_ON";
+        std::env::set_var(var, "true");
+        let resp = call(
+            Maintenance::env(var).retry_after(Duration::from_secs(60)),
+            Request::default(),
+        )
+        .await;
+        assert_eq!(resp.status_code, 503);
+        assert_eq!(resp.headers["Retry-After"], "60");
+
+        let resp = call(Maintenance::env(var).exempt("/"), Request::default()).await;
+        assert_eq!(resp.status_code, 204);
+    }
+
+    #[tokio::test]
+    async fn unset_flag_passes_through() {
+        let resp = call(
+            Maintenance::env("CHOKO_TEST_MAINTENANCE_UNSET"),
+            Request::default(),
+        )
+        .await;
+        assert_eq!(resp.status_code, 204);
+    }
+}
@@ -0,0 +1,281 @@
+//! CloudWatch metrics in Embedded Metric Format.
+//!
+//! [`Metrics`] records request count, latency and errors per route and
+//! prints them as one [EMF] JSON line on stdout when the request finishes.
+//! CloudWatch Logs extracts the metrics from the log line, so no
+//! `PutMetricData` calls are made. Handlers can add their own values to the
+//! same line through [`Request::metrics`].
+//!
+//! With the `metrics-facade` feature, [`MetricsFacade`] records the same
+//! requests through the `metrics` crate instead, for any exporter.
+//!
+//! [EMF]: https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+//!
+//! # Example
+//! ```ignore
+//! use choko::metrics::{Metrics, Unit};
+//!
+//! app.middleware(Metrics::new("Orders"));
+//! app.post("/orders", |req| async move {
+//!     if let Some(metrics) = req.metrics() {
+//!         metrics.put("OrdersCreated", 1.0, Unit::Count);
+//!     }
+//!     // ...
+//! });
+//! ```
+
+#[cfg(feature = "metrics-facade")]
+mod facade;
+
+#[cfg(feature = "metrics-facade")]
+pub use facade::MetricsFacade;
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The route dimension of requests that matched no route.
+const UNMATCHED: &str = "unmatched";
+
+/// CloudWatch metric units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Count,
+    Milliseconds,
+    Seconds,
+    Bytes,
+    Percent,
+    None,
+}
+
+impl Unit {
+    fn as_str(self) -> &'static str {
+        match self {
+            Unit::Count => "Count",
+            Unit::Milliseconds => "Milliseconds",
+            Unit::Seconds => "Seconds",
+            Unit::Bytes => "Bytes",
+            Unit::Percent => "Percent",
+            Unit::None => "None",
+        }
+    }
+}
+
+/// Metric values buffered for the current request.
+///
+/// Cheap to clone; clones share the buffer.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsBuffer {
+    values: Arc<Mutex<Vec<(String, f64, Unit)>>>,
+}
+
+impl MetricsBuffer {
+    /// Record `value` for metric `name`. Repeated names become a list of
+    /// values, which CloudWatch aggregates.
+    pub fn put(&self, name: &str, value: f64, unit: Unit) {
+        self.values
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((name.to_string(), value, unit));
+    }
+
+    fn take(&self) -> Vec<(String, f64, Unit)> {
+        std::mem::take(&mut *self.values.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Request {
+    /// The metrics buffer of the [`Metrics`] middleware, if registered.
+    pub fn metrics(&self) -> Option<&MetricsBuffer> {
+        self.extensions().get::<MetricsBuffer>()
+    }
+}
+
+/// Build one EMF document holding `values` under the given dimensions.
+pub fn emf_document(
+    namespace: &str,
+    dimensions: &[(&str, &str)],
+    values: &[(String, f64, Unit)],
+    timestamp_ms: u64,
+) -> Value {
+    let mut definitions: Vec<Value> = Vec::new();
+    let mut root = Map::new();
+    for (name, value, unit) in values {
+        match root.get_mut(name) {
+            Some(Value::Array(list)) => list.push(json!(value)),
+            Some(existing) => *existing = json!([existing.clone(), value]),
+            None => {
+                definitions.push(json!({ "Name": name, "Unit": unit.as_str() }));
+                root.insert(name.clone(), json!(value));
+            }
+        }
+    }
+    for (key, value) in dimensions {
+        root.insert(key.to_string(), json!(value));
+    }
+    let dimension_keys: Vec<&str> = dimensions.iter().map(|(k, _)| *k).collect();
+    root.insert(
+        "_aws".to_string(),
+        json!({
+            "Timestamp": timestamp_ms,
+            "CloudWatchMetrics": [{
+                "Namespace": namespace,
+                "Dimensions": [dimension_keys],
+                "Metrics": definitions,
+            }],
+        }),
+    );
+    Value::Object(root)
+}
+
+type SinkFn = Arc<dyn Fn(&Value) + Send + Sync>;
+
+/// Middleware emitting per-route request metrics in EMF.
+///
+/// Each request produces `Requests` (1), `Latency` (ms), `Errors` (1 for a
+/// 5xx response or handler error, else 0) and `ClientErrors` (1 for 4xx),
+/// dimensioned by `Route` (the route pattern) and `Method`, plus whatever
+/// the handler put into [`Request::metrics`]. Register it first so the
+/// latency covers the rest of the chain.
+pub struct Metrics {
+    namespace: String,
+    dimensions: Vec<(String, String)>,
+    sink: SinkFn,
+}
+
+impl Metrics {
+    /// Publish metrics under the CloudWatch `namespace`.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            dimensions: Vec::new(),
+            sink: Arc::new(|doc| println!("{doc}")),
+        }
+    }
+
+    /// Add a fixed dimension (e.g. `Service` or `Stage`) to every metric.
+    pub fn dimension(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.dimensions.push((key.into(), value.into()));
+        self
+    }
+
+    /// Send documents to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&Value) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+}
+
+impl Middleware for Metrics {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let started = Instant::now();
+        let buffer = MetricsBuffer::default();
+        req.extensions_mut().insert(buffer.clone());
+        let route = req.route().unwrap_or(UNMATCHED).to_string();
+        let method = req.method().to_string();
+        let namespace = self.namespace.clone();
+        let fixed = self.dimensions.clone();
+        let sink = Arc::clone(&self.sink);
+        Box::pin(async move {
+            let result = next.run(req).await;
+            let status = match &result {
+                Ok(resp) => resp.status_code,
+                Err(_) => 500,
+            };
+            let mut values = vec![
+                ("Requests".to_string(), 1.0, Unit::Count),
+                (
+                    "Latency".to_string(),
+                    started.elapsed().as_secs_f64() * 1000.0,
+                    Unit::Milliseconds,
+                ),
+                (
+                    "Errors".to_string(),
+                    f64::from(u8::from(status >= 500)),
+                    Unit::Count,
+                ),
+                (
+                    "ClientErrors".to_string(),
+                    f64::from(u8::from((400..500).contains(&status))),
+                    Unit::Count,
+                ),
+            ];
+            values.extend(buffer.take());
+            let mut dimensions: Vec<(&str, &str)> = fixed
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            dimensions.push(("Route", &route));
+            dimensions.push(("Method", &method));
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            sink(&emf_document(
+                &namespace,
+                &dimensions,
+                &values,
+                timestamp_ms,
+            ));
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+
+    #[test]
+    fn emf_document_shape() {
+        let values = vec![
+            ("Hits".to_string(), 1.0, Unit::Count),
+            ("Hits".to_string(), 2.0, Unit::Count),
+            ("Size".to_string(), 10.0, Unit::Bytes),
+        ];
+        let doc = emf_document("App", &[("Route", "/a")], &values, 42);
+        assert_eq!(doc["_aws"]["Timestamp"], 42);
+        let directive = &doc["_aws"]["CloudWatchMetrics"][0];
+        assert_eq!(directive["Namespace"], "App");
+        assert_eq!(directive["Dimensions"], json!([["Route"]]));
+        assert_eq!(
+            directive["Metrics"],
+            json!([{"Name": "Hits", "Unit": "Count"}, {"Name": "Size", "Unit": "Bytes"}])
+        );
+        assert_eq!(doc["Hits"], json!([1.0, 2.0]));
+        assert_eq!(doc["Route"], "/a");
+    }
+
+    #[tokio::test]
+    async fn records_request_and_handler_metrics() {
+        let docs = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&docs);
+        let mw = Metrics::new("App")
+            .dimension("Service", "orders")
+            .sink(move |doc| captured.lock().unwrap().push(doc.clone()));
+        let handler = |req: Request| async move {
+            req.metrics().unwrap().put("Created", 3.0, Unit::Count);
+            Ok(Response::text("nope").with_status(503))
+        };
+        run_middleware(mw, handler, Request::default())
+            .await
+            .unwrap();
+
+        let doc = docs.lock().unwrap().pop().unwrap();
+        assert_eq!(doc["Requests"], 1.0);
+        assert_eq!(doc["Errors"], 1.0);
+        assert_eq!(doc["ClientErrors"], 0.0);
+        assert_eq!(doc["Created"], 3.0);
+        assert_eq!(doc["Route"], UNMATCHED);
+        assert_eq!(doc["Service"], "orders");
+        assert_eq!(
+            doc["_aws"]["CloudWatchMetrics"][0]["Dimensions"],
+            json!([["Service", "Route", "Method"]])
+        );
+    }
+}
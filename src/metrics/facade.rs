@@ -0,0 +1,124 @@
+//! Request metrics through the [`metrics`](::metrics) facade
+//! (`metrics-facade` feature).
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use std::time::{Duration, Instant};
+
+/// Middleware recording a request counter and a latency histogram through
+/// the `metrics` facade, for whichever exporter the application installs.
+///
+/// Both carry `route` (the route pattern, or `unmatched`), `method` and
+/// `status` labels:
+///
+/// - `http_server_requests_total` (counter)
+/// - `http_server_request_duration_seconds` (histogram)
+///
+/// Register it first so the latency covers the rest of the chain.
+///
+/// # Example
+/// ```ignore
+/// use choko::metrics::MetricsFacade;
+///
+/// metrics_exporter_prometheus::PrometheusBuilder::new().install()?;
+/// app.middleware(MetricsFacade::new().prefix("orders"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricsFacade {
+    requests: String,
+    duration: String,
+}
+
+impl Default for MetricsFacade {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsFacade {
+    pub fn new() -> Self {
+        Self {
+            requests: "http_server_requests_total".to_string(),
+            duration: "http_server_request_duration_seconds".to_string(),
+        }
+    }
+
+    /// Prefix both metric names with `<prefix>_`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.requests = format!("{prefix}_{}", self.requests);
+        self.duration = format!("{prefix}_{}", self.duration);
+        self
+    }
+
+    fn record(&self, route: String, method: String, status: i64, elapsed: Duration) {
+        let labels = [
+            ("route", route),
+            ("method", method),
+            ("status", status.to_string()),
+        ];
+        ::metrics::counter!(self.requests.clone(), &labels).increment(1);
+        ::metrics::histogram!(self.duration.clone(), &labels).record(elapsed.as_secs_f64());
+    }
+}
+
+impl Middleware for MetricsFacade {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let started = Instant::now();
+        let route = req.route().unwrap_or(super::UNMATCHED).to_string();
+        let method = req.method().to_string();
+        let this = self.clone();
+        Box::pin(async move {
+            let result = next.run(req).await;
+            let status = result.as_ref().map_or(500, |resp| resp.status_code);
+            this.record(route, method, status, started.elapsed());
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    #[test]
+    fn records_labelled_counter_and_histogram() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, || {
+            MetricsFacade::new().prefix("app").record(
+                "/users/{id}".to_string(),
+                "GET".to_string(),
+                200,
+                Duration::from_millis(250),
+            );
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        assert_eq!(snapshot.len(), 2);
+        for (key, _, _, value) in snapshot {
+            let key = key.key();
+            let labels: Vec<(&str, &str)> = key.labels().map(|l| (l.key(), l.value())).collect();
+            assert_eq!(
+                labels,
+                [
+                    ("route", "/users/{id}"),
+                    ("method", "GET"),
+                    ("status", "200")
+                ]
+            );
+            match value {
+                DebugValue::Counter(n) => {
+                    assert_eq!(key.name(), "app_http_server_requests_total");
+                    assert_eq!(n, 1);
+                }
+                DebugValue::Histogram(values) => {
+                    assert_eq!(key.name(), "app_http_server_request_duration_seconds");
+                    assert_eq!(values.len(), 1);
+                    assert!((values[0].into_inner() - 0.25).abs() < 1e-9);
+                }
+                DebugValue::Gauge(_) => panic!("unexpected gauge"),
+            }
+        }
+    }
+}
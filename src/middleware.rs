@@ -0,0 +1,109 @@
+//! Middleware that wraps route handlers.
+
+use crate::{BoxFuture, Error, HandlerFn, Request, Response};
+use std::future::Future;
+use std::sync::Arc;
+
+/// Code that runs around a route handler.
+///
+/// Middleware receives the request and a [`Next`] that invokes the rest of the
+/// chain. It can short-circuit by returning a response without calling
+/// `next`, attach data to the request's extensions, or inspect and modify the
+/// response on the way out.
+///
+/// App-wide middleware registered with [`Choko::middleware`](crate::Choko::middleware)
+/// runs first, in registration order, followed by the route's own middleware.
+/// Both only run for requests that matched a route.
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>>;
+}
+
+/// The remainder of a middleware chain, ending in the route handler.
+pub struct Next {
+    chain: Arc<[Arc<dyn Middleware>]>,
+    index: usize,
+    endpoint: HandlerFn,
+}
+
+impl Next {
+    pub(crate) fn new(chain: Arc<[Arc<dyn Middleware>]>, endpoint: HandlerFn) -> Self {
+        Self {
+            chain,
+            index: 0,
+            endpoint,
+        }
+    }
+
+    /// Run the rest of the chain with `req`.
+    pub fn run(mut self, req: Request) -> BoxFuture<Result<Response, Error>> {
+        match self.chain.get(self.index).cloned() {
+            Some(middleware) => {
+                self.index += 1;
+                middleware.handle(req, self)
+            }
+            None => (self.endpoint)(req),
+        }
+    }
+}
+
+/// Middleware built from an async closure. Created by [`from_fn`].
+pub struct FromFn<F> {
+    f: F,
+}
+
+impl<F, Fut> Middleware for FromFn<F>
+where
+    F: Fn(Request, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        Box::pin((self.f)(req, next))
+    }
+}
+
+/// Create middleware from an async closure.
+///
+/// # Example
+/// ```ignore
+/// app.middleware(middleware::from_fn(|req, next| async move {
+///     let resp = next.run(req).await?;
+///     Ok(resp.with_header("X-Frame-Options", "DENY"))
+/// }));
+/// ```
+pub fn from_fn<F, Fut>(f: F) -> FromFn<F>
+where
+    F: Fn(Request, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    FromFn { f }
+}
+
+/// Run `req` through `mw` and on to `handler`, for middleware tests.
+#[cfg(test)]
+pub(crate) async fn run_middleware<M, F, Fut>(
+    mw: M,
+    handler: F,
+    req: Request,
+) -> Result<Response, Error>
+where
+    M: Middleware,
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    run_chain(vec![Arc::new(mw)], handler, req).await
+}
+
+/// Run `req` through `chain` and on to `handler`, for middleware tests.
+#[cfg(test)]
+pub(crate) async fn run_chain<F, Fut>(
+    chain: Vec<Arc<dyn Middleware>>,
+    handler: F,
+    req: Request,
+) -> Result<Response, Error>
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+{
+    let endpoint: HandlerFn = Arc::new(move |req| Box::pin(handler(req)));
+    Next::new(Arc::from(chain), endpoint).run(req).await
+}
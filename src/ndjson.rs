@@ -0,0 +1,132 @@
+//! Newline-delimited JSON (`application/x-ndjson`) responses.
+
+use crate::stream::{BodySender, BodyStream};
+use crate::{Error, Response};
+use serde::Serialize;
+
+/// The media type for NDJSON bodies.
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Serialize one item as a JSON line.
+fn encode_line<T: Serialize + ?Sized>(item: &T) -> Result<Vec<u8>, serde_json::Error> {
+    let mut line = serde_json::to_vec(item)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+impl Response {
+    /// An `application/x-ndjson` response with one JSON document per item.
+    ///
+    /// Items are serialized one at a time, so no intermediate JSON array is
+    /// built, but the body is held in memory and must fit the Lambda
+    /// response payload limit (6 MB). For items produced asynchronously, or
+    /// to stream a large export, use [`NdjsonResponse`].
+    pub fn ndjson<I>(items: I) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let mut body = Vec::new();
+        for item in items {
+            body.extend(encode_line(&item)?);
+        }
+        Ok(Response::text(String::from_utf8(body)?)
+            .with_header("Content-Type", NDJSON_CONTENT_TYPE))
+    }
+}
+
+/// Produces the lines of an [`NdjsonResponse`].
+#[derive(Clone)]
+pub struct NdjsonSender {
+    inner: BodySender,
+}
+
+impl NdjsonSender {
+    /// Serialize `item` and send it as the next line.
+    pub async fn send<T: Serialize + ?Sized>(&self, item: &T) -> Result<(), Error> {
+        self.inner.send(encode_line(item)?).await?;
+        Ok(())
+    }
+
+    /// Abort the body with an error; the request then fails with a 500.
+    pub async fn abort(&self, error: impl Into<Error>) {
+        self.inner.abort(error).await;
+    }
+}
+
+/// An `application/x-ndjson` response fed by an [`NdjsonSender`].
+///
+/// The response completes when every sender has been dropped. Lines reach
+/// the client as they are sent with [`Choko::listen`](crate::Choko::listen)
+/// and [`Choko::run_function_url_streaming`](crate::Choko::run_function_url_streaming);
+/// other integrations collect the whole body in memory first, subject to
+/// the 6 MB Lambda response payload limit.
+///
+/// # Example
+/// ```ignore
+/// app.get("/export", |_req| async move {
+///     let (ndjson, tx) = NdjsonResponse::channel();
+///     tokio::spawn(async move {
+///         let mut rows = db::scan_orders();
+///         while let Some(row) = rows.next().await {
+///             if tx.send(&row).await.is_err() {
+///                 break;
+///             }
+///         }
+///     });
+///     Ok(ndjson.into())
+/// });
+/// ```
+#[derive(Debug)]
+pub struct NdjsonResponse {
+    stream: BodyStream,
+}
+
+impl NdjsonResponse {
+    /// Create an NDJSON response and the sender that produces its lines.
+    pub fn channel() -> (NdjsonResponse, NdjsonSender) {
+        let (inner, stream) = BodyStream::channel(64);
+        (NdjsonResponse { stream }, NdjsonSender { inner })
+    }
+}
+
+impl From<NdjsonResponse> for Response {
+    fn from(ndjson: NdjsonResponse) -> Self {
+        Response::stream(ndjson.stream).with_header("Content-Type", NDJSON_CONTENT_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+    use serde_json::json;
+
+    #[test]
+    fn ndjson_writes_one_line_per_item() {
+        let resp = Response::ndjson((1..=3).map(|id| json!({ "id": id }))).unwrap();
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            "application/x-ndjson"
+        );
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text("{\"id\":1}\n{\"id\":2}\n{\"id\":3}\n".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn ndjson_response_collects_sent_items() {
+        let (ndjson, tx) = NdjsonResponse::channel();
+        tokio::spawn(async move {
+            tx.send(&json!({"n": 1})).await.unwrap();
+            tx.send(&[1, 2]).await.unwrap();
+        });
+
+        let resp = Response::from(ndjson).buffered().await.unwrap();
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text("{\"n\":1}\n[1,2]\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! `Accept` header parsing and content negotiation.
+
+use crate::{Request, Response};
+
+/// A single media range from an `Accept` header, e.g. `text/*;q=0.8`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// The lowercased media range without parameters (`text/csv`, `text/*`, `*/*`).
+    pub media_type: String,
+    /// The quality weight in `0.0..=1.0` (defaults to `1.0`).
+    pub quality: f32,
+}
+
+impl MediaRange {
+    /// Whether this range matches the concrete media type `mime`.
+    pub fn matches(&self, mime: &str) -> bool {
+        let mime = mime.to_ascii_lowercase();
+        match self.media_type.as_str() {
+            "*/*" => true,
+            range => match range.strip_suffix("/*") {
+                Some(prefix) => mime.split('/').next() == Some(prefix),
+                None => range == mime,
+            },
+        }
+    }
+
+    /// Higher for more specific ranges: `type/subtype` > `type/*` > `*/*`.
+    fn specificity(&self) -> u8 {
+        if self.media_type == "*/*" {
+            0
+        } else if self.media_type.ends_with("/*") {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// Parse an `Accept` header into media ranges, sorted by descending quality
+/// and then by specificity. Malformed entries are skipped.
+fn parse_accept(header: &str) -> Vec<MediaRange> {
+    let mut ranges: Vec<MediaRange> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let media_type = parts.next()?.trim().to_ascii_lowercase();
+            if !media_type.contains('/') {
+                return None;
+            }
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some(MediaRange {
+                media_type,
+                quality,
+            })
+        })
+        .collect();
+    ranges.sort_by(|a, b| {
+        b.quality
+            .total_cmp(&a.quality)
+            .then(b.specificity().cmp(&a.specificity()))
+    });
+    ranges
+}
+
+impl Request {
+    /// The parsed `Accept` header, sorted by descending quality and specificity.
+    ///
+    /// A missing header is treated as `*/*`.
+    pub fn accept(&self) -> Vec<MediaRange> {
+        match self.headers.get("accept") {
+            Some(h) if !h.trim().is_empty() => parse_accept(h),
+            _ => vec![MediaRange {
+                media_type: "*/*".to_string(),
+                quality: 1.0,
+            }],
+        }
+    }
+
+    /// Pick the best of the `available` media types for this request.
+    ///
+    /// Each candidate is weighted by the most specific matching range in the
+    /// `Accept` header; ties are broken by the order of `available`. Returns
+    /// `None` if every candidate is unacceptable.
+    pub fn preferred_type<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let ranges = self.accept();
+        let mut best: Option<(&'a str, f32)> = None;
+        for &candidate in available {
+            let quality = ranges
+                .iter()
+                .filter(|r| r.matches(candidate))
+                .max_by_key(|r| r.specificity())
+                .map_or(0.0, |r| r.quality);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((candidate, quality));
+            }
+        }
+        best.map(|(mime, _)| mime)
+    }
+}
+
+type Render<'a> = Box<dyn FnOnce() -> Response + Send + 'a>;
+
+/// Choose between several representations of a resource based on the
+/// request's `Accept` header.
+///
+/// Only the selected representation is rendered. If none is acceptable the
+/// result is a 406 response.
+///
+/// # Example
+/// ```ignore
+/// Ok(Negotiate::new(&req)
+///     .json(|| Response::json(json!(rows)))
+///     .with("text/csv", || Response::text(to_csv(&rows)))
+///     .finish())
+/// ```
+pub struct Negotiate<'a> {
+    req: &'a Request,
+    variants: Vec<(&'a str, Render<'a>)>,
+}
+
+impl<'a> Negotiate<'a> {
+    /// Start negotiating a response for `req`.
+    pub fn new(req: &'a Request) -> Self {
+        Self {
+            req,
+            variants: Vec::new(),
+        }
+    }
+
+    /// Offer an `application/json` representation.
+    pub fn json(self, render: impl FnOnce() -> Response + Send + 'a) -> Self {
+        self.with("application/json", render)
+    }
+
+    /// Offer a representation of the given media type.
+    ///
+    /// The rendered response's `Content-Type` is set to `media_type`.
+    pub fn with(
+        mut self,
+        media_type: &'a str,
+        render: impl FnOnce() -> Response + Send + 'a,
+    ) -> Self {
+        self.variants.push((media_type, Box::new(render)));
+        self
+    }
+
+    /// Render the preferred representation, or a 406 if none is acceptable.
+    pub fn finish(self) -> Response {
+        let available: Vec<&str> = self.variants.iter().map(|(m, _)| *m).collect();
+        let Some(chosen) = self.req.preferred_type(&available) else {
+            return Response::not_acceptable(&available);
+        };
+        let (media_type, render) = self
+            .variants
+            .into_iter()
+            .find(|(m, _)| *m == chosen)
+            .expect("chosen media type is one of the variants");
+        render()
+            .with_header("Content-Type", media_type)
+            .with_header("Vary", "Accept")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn request_with_accept(accept: Option<&str>) -> Request {
+        let mut headers = HashMap::new();
+        if let Some(a) = accept {
+            headers.insert("accept".to_string(), a.to_string());
+        }
+        Request {
+            headers,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_accept_sorts_by_quality_then_specificity() {
+        let ranges = parse_accept("text/*;q=0.5, */*;q=0.1, text/csv, application/json;q=0.9");
+        let types: Vec<&str> = ranges.iter().map(|r| r.media_type.as_str()).collect();
+        assert_eq!(types, ["text/csv", "application/json", "text/*", "*/*"]);
+        assert_eq!(ranges[1].quality, 0.9);
+    }
+
+    #[test]
+    fn preferred_type_uses_most_specific_range() {
+        let req = request_with_accept(Some("text/*;q=0.9, text/csv;q=0, application/json;q=0.5"));
+        assert_eq!(
+            req.preferred_type(&["application/json", "text/csv", "text/plain"]),
+            Some("text/plain")
+        );
+        assert_eq!(req.preferred_type(&["text/csv"]), None);
+    }
+
+    #[test]
+    fn preferred_type_without_accept_picks_first() {
+        let req = request_with_accept(None);
+        assert_eq!(
+            req.preferred_type(&["application/json", "text/csv"]),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn negotiate_renders_selected_variant() {
+        let req = request_with_accept(Some("text/csv"));
+        let resp = Negotiate::new(&req)
+            .json(|| Response::json(json!({"a": 1})))
+            .with("text/csv", || Response::text("a\n1\n"))
+            .finish();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.headers.get("Content-Type").unwrap(), "text/csv");
+        assert_eq!(resp.body, crate::ResponseBody::Text("a\n1\n".into()));
+    }
+
+    #[test]
+    fn negotiate_returns_406_when_nothing_matches() {
+        let req = request_with_accept(Some("application/xml"));
+        let resp = Negotiate::new(&req)
+            .json(|| Response::json(json!({})))
+            .finish();
+        assert_eq!(resp.status_code, 406);
+    }
+}
@@ -0,0 +1,457 @@
+//! Sending email through SES and SMS and push notifications through SNS
+//! (`notifications` feature).
+//!
+//! [`Mailer`] sends SES templated emails; [`Notifier`] sends SMS messages
+//! and mobile push notifications. Both retry throttling, 5xx and network
+//! errors with exponential backoff, and log one JSON line per notification:
+//!
+//! ```text
+//! {"level":"INFO","message":"notification sent","channel":"email","template":"Welcome","recipients":1,"attempts":1,"message_id":"0100018f..."}
+//! ```
+//!
+//! Recipients are counted, never logged, to keep addresses and phone
+//! numbers out of the logs.
+//!
+//! # Example
+//! ```ignore
+//! use choko::notifications::{Mailer, Notifier};
+//!
+//! let config = aws_config::load_from_env().await;
+//! app.state(Mailer::new(aws_sdk_sesv2::Client::new(&config), "no-reply@example.com"));
+//! app.state(Notifier::new(aws_sdk_sns::Client::new(&config)));
+//!
+//! app.post("/signup", |req| async move {
+//!     let user = create_user(&req).await?;
+//!     let mailer = req.state::<Mailer>().ok_or("mailer not configured")?;
+//!     mailer
+//!         .send_template(&[&user.email], "Welcome", &json!({ "name": user.name }))
+//!         .await?;
+//!     Ok(Response::created(format!("/users/{}", user.id), json!(user)))
+//! });
+//! ```
+
+use crate::Error;
+use aws_sdk_sesv2::types::{Destination, EmailContent, Template};
+use aws_sdk_sns::config::http::HttpResponse;
+use aws_sdk_sns::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_sns::types::MessageAttributeValue;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The first retry's delay; each further retry doubles it.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// One notification log line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NotificationLog {
+    /// `INFO` when sent, `ERROR` when every attempt failed.
+    pub level: &'static str,
+    /// `notification sent` or `notification failed`.
+    pub message: &'static str,
+    /// `email`, `sms` or `push`.
+    pub channel: &'static str,
+    /// The SES template, for emails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    pub recipients: usize,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+type SinkFn = Arc<dyn Fn(&NotificationLog) + Send + Sync>;
+
+fn stdout_sink() -> SinkFn {
+    Arc::new(|entry| match serde_json::to_string(entry) {
+        Ok(line) => println!("{line}"),
+        Err(e) => eprintln!("Failed to serialize notification log: {e}"),
+    })
+}
+
+/// Sends SES templated emails from one address. Cheap to clone.
+#[derive(Clone)]
+pub struct Mailer {
+    client: aws_sdk_sesv2::Client,
+    from: String,
+    reply_to: Option<String>,
+    configuration_set: Option<String>,
+    retries: u32,
+    sink: SinkFn,
+}
+
+impl Mailer {
+    /// Send from `from`, a verified SES identity. Retries twice by default.
+    pub fn new(client: aws_sdk_sesv2::Client, from: impl Into<String>) -> Self {
+        Self {
+            client,
+            from: from.into(),
+            reply_to: None,
+            configuration_set: None,
+            retries: 2,
+            sink: stdout_sink(),
+        }
+    }
+
+    pub fn reply_to(mut self, address: impl Into<String>) -> Self {
+        self.reply_to = Some(address.into());
+        self
+    }
+
+    /// The SES configuration set, for delivery, bounce and open tracking.
+    pub fn configuration_set(mut self, name: impl Into<String>) -> Self {
+        self.configuration_set = Some(name.into());
+        self
+    }
+
+    /// How many times a failed send is retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Send log lines to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&NotificationLog) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    /// Send the SES template `template` to `to`, filled in with `data`.
+    /// Returns the SES message ID.
+    pub async fn send_template(
+        &self,
+        to: &[&str],
+        template: &str,
+        data: &impl Serialize,
+    ) -> Result<String, Error> {
+        if to.is_empty() {
+            return Err("emails need at least one recipient".into());
+        }
+        let data = serde_json::to_string(data)?;
+        let destination = Destination::builder()
+            .set_to_addresses(Some(to.iter().map(|a| a.to_string()).collect()))
+            .build();
+        let content = EmailContent::builder()
+            .template(
+                Template::builder()
+                    .template_name(template)
+                    .template_data(data)
+                    .build(),
+            )
+            .build();
+        let (result, attempts) = with_retries(self.retries, BASE_DELAY, || {
+            self.client
+                .send_email()
+                .from_email_address(&self.from)
+                .destination(destination.clone())
+                .content(content.clone())
+                .set_reply_to_addresses(self.reply_to.clone().map(|a| vec![a]))
+                .set_configuration_set_name(self.configuration_set.clone())
+                .send()
+        })
+        .await;
+        let result = result.map(|output| output.message_id().unwrap_or_default().to_string());
+        log(
+            &self.sink,
+            "email",
+            Some(template),
+            to.len(),
+            attempts,
+            &result,
+        );
+        result
+    }
+}
+
+/// Sends SMS messages and mobile push notifications through SNS. Cheap to
+/// clone.
+#[derive(Clone)]
+pub struct Notifier {
+    client: aws_sdk_sns::Client,
+    sender_id: Option<String>,
+    retries: u32,
+    sink: SinkFn,
+}
+
+impl Notifier {
+    /// Retries twice by default.
+    pub fn new(client: aws_sdk_sns::Client) -> Self {
+        Self {
+            client,
+            sender_id: None,
+            retries: 2,
+            sink: stdout_sink(),
+        }
+    }
+
+    /// The sender ID SMS messages show, in countries that support one.
+    pub fn sender_id(mut self, sender_id: impl Into<String>) -> Self {
+        self.sender_id = Some(sender_id.into());
+        self
+    }
+
+    /// How many times a failed send is retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Send log lines to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&NotificationLog) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    /// Send a transactional SMS to `phone_number` (E.164, e.g.
+    /// `+447700900123`). Returns the SNS message ID.
+    pub async fn sms(&self, phone_number: &str, message: &str) -> Result<String, Error> {
+        if !is_e164(phone_number) {
+            return Err(format!("{phone_number:?} is not an E.164 phone number").into());
+        }
+        let mut attributes = vec![("AWS.SNS.SMS.SMSType", "Transactional")];
+        if let Some(sender_id) = &self.sender_id {
+            attributes.push(("AWS.SNS.SMS.SenderID", sender_id.as_str()));
+        }
+        let attributes: HashMap<String, MessageAttributeValue> = attributes
+            .into_iter()
+            .map(|(name, value)| {
+                let value = MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(value)
+                    .build()?;
+                Ok((name.to_string(), value))
+            })
+            .collect::<Result<_, Error>>()?;
+        let (result, attempts) = with_retries(self.retries, BASE_DELAY, || {
+            self.client
+                .publish()
+                .phone_number(phone_number)
+                .message(message)
+                .set_message_attributes(Some(attributes.clone()))
+                .send()
+        })
+        .await;
+        let result = result.map(|output| output.message_id().unwrap_or_default().to_string());
+        log(&self.sink, "sms", None, 1, attempts, &result);
+        result
+    }
+
+    /// Send `message` to the platform endpoint `endpoint_arn` as is.
+    pub async fn push(&self, endpoint_arn: &str, message: &str) -> Result<String, Error> {
+        self.publish_push(endpoint_arn, message.to_string(), None)
+            .await
+    }
+
+    /// Send per-platform payloads to `endpoint_arn`, e.g.
+    /// `{"default": "New order", "GCM": {"notification": {...}}}`.
+    /// Payload objects are encoded as the nested JSON strings SNS expects.
+    pub async fn push_json(&self, endpoint_arn: &str, payloads: &Value) -> Result<String, Error> {
+        let message = platform_message(payloads)?;
+        self.publish_push(endpoint_arn, message, Some("json")).await
+    }
+
+    async fn publish_push(
+        &self,
+        endpoint_arn: &str,
+        message: String,
+        structure: Option<&str>,
+    ) -> Result<String, Error> {
+        let (result, attempts) = with_retries(self.retries, BASE_DELAY, || {
+            self.client
+                .publish()
+                .target_arn(endpoint_arn)
+                .message(&message)
+                .set_message_structure(structure.map(str::to_string))
+                .send()
+        })
+        .await;
+        let result = result.map(|output| output.message_id().unwrap_or_default().to_string());
+        log(&self.sink, "push", None, 1, attempts, &result);
+        result
+    }
+}
+
+/// Encode per-platform payloads for SNS's `json` message structure.
+fn platform_message(payloads: &Value) -> Result<String, Error> {
+    let Some(payloads) = payloads.as_object() else {
+        return Err("push payloads must be an object keyed by platform".into());
+    };
+    if !payloads.contains_key("default") {
+        return Err("push payloads need a \"default\" message".into());
+    }
+    let encoded: Map<String, Value> = payloads
+        .iter()
+        .map(|(platform, payload)| {
+            let payload = match payload {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (platform.clone(), Value::String(payload))
+        })
+        .collect();
+    Ok(Value::Object(encoded).to_string())
+}
+
+fn is_e164(phone_number: &str) -> bool {
+    phone_number.strip_prefix('+').is_some_and(|digits| {
+        (8..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit())
+    })
+}
+
+/// Whether `err` may succeed on a retry: throttling, server errors and
+/// failures to reach the service.
+fn retryable<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(service) => {
+            service.raw().status().as_u16() >= 500
+                || err
+                    .code()
+                    .is_some_and(|code| code.contains("Throttl") || code == "TooManyRequests")
+        }
+        _ => false,
+    }
+}
+
+/// Run `op` until it succeeds, fails permanently or has been retried
+/// `retries` times, backing off from `base_delay` with jitter. Returns the
+/// last result and the number of attempts.
+async fn with_retries<T, E, F, Fut>(
+    retries: u32,
+    base_delay: Duration,
+    mut op: F,
+) -> (Result<T, Error>, u32)
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, SdkError<E, HttpResponse>>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match op().await {
+            Ok(value) => return (Ok(value), attempts),
+            Err(e) if attempts <= retries && retryable(&e) => {
+                let backoff = base_delay * 2u32.saturating_pow(attempts - 1);
+                let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+                tokio::time::sleep(backoff.mul_f64(0.5 + jitter / 2.0)).await;
+            }
+            Err(e) => return (Err(e.into()), attempts),
+        }
+    }
+}
+
+fn log(
+    sink: &SinkFn,
+    channel: &'static str,
+    template: Option<&str>,
+    recipients: usize,
+    attempts: u32,
+    result: &Result<String, Error>,
+) {
+    let (level, message, message_id, error) = match result {
+        Ok(id) => ("INFO", "notification sent", Some(id.clone()), None),
+        Err(e) => ("ERROR", "notification failed", None, Some(e.to_string())),
+    };
+    sink(&NotificationLog {
+        level,
+        message,
+        channel,
+        template: template.map(str::to_string),
+        recipients,
+        attempts,
+        message_id,
+        error,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_sns::config::{BehaviorVersion, Credentials, Region};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    type TestError = SdkError<aws_sdk_sns::operation::publish::PublishError, HttpResponse>;
+
+    /// A client whose calls fail fast: nothing listens on the endpoint.
+    fn notifier(logs: Arc<Mutex<Vec<NotificationLog>>>) -> Notifier {
+        let config = aws_sdk_sns::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("AKID", "secret", None, None, "test"))
+            .endpoint_url("http://127.0.0.1:9")
+            .retry_config(aws_sdk_sns::config::retry::RetryConfig::disabled())
+            .build();
+        Notifier::new(aws_sdk_sns::Client::from_conf(config))
+            .retries(0)
+            .sink(move |entry| logs.lock().unwrap().push(entry.clone()))
+    }
+
+    #[tokio::test]
+    async fn retries_only_transient_errors() {
+        let mut calls = 0;
+        let (result, attempts) = with_retries(2, Duration::ZERO, || {
+            calls += 1;
+            async { Err::<(), TestError>(SdkError::timeout_error("timed out")) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!((attempts, calls), (3, 3));
+
+        let (result, attempts) = with_retries(2, Duration::ZERO, || async {
+            Err::<(), TestError>(SdkError::construction_failure("bad input"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let (result, attempts) =
+            with_retries(2, Duration::ZERO, || async { Ok::<_, TestError>(7) }).await;
+        assert_eq!((result.unwrap(), attempts), (7, 1));
+    }
+
+    #[test]
+    fn encodes_platform_payloads() {
+        let message = platform_message(&json!({
+            "default": "New order",
+            "GCM": { "notification": { "title": "New order" } }
+        }))
+        .unwrap();
+        let decoded: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(decoded["default"], "New order");
+        assert_eq!(decoded["GCM"], r#"{"notification":{"title":"New order"}}"#);
+        assert!(platform_message(&json!({ "GCM": {} })).is_err());
+    }
+
+    #[tokio::test]
+    async fn validates_and_logs_sms() {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let notifier = notifier(Arc::clone(&logs));
+        assert!(notifier.sms("07700900123", "hi").await.is_err());
+        assert!(logs.lock().unwrap().is_empty());
+
+        assert!(notifier.sms("+447700900123", "hi").await.is_err());
+        let logs = logs.lock().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].level, "ERROR");
+        assert_eq!((logs[0].channel, logs[0].attempts), ("sms", 1));
+        let line = serde_json::to_string(&logs[0]).unwrap();
+        assert!(!line.contains("7700900123"));
+    }
+}
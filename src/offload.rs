@@ -0,0 +1,182 @@
+//! Offloading oversized responses to S3 (`s3-offload` feature).
+//!
+//! Lambda rejects synchronous responses larger than 6 MB, which API Gateway
+//! reports as an opaque 502. When configured, responses that would exceed the
+//! limit are uploaded to S3 and replaced with a link to a presigned URL.
+
+use crate::{Error, Response, ResponseBody};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use std::time::Duration;
+
+/// Lambda's synchronous response payload limit.
+const LAMBDA_PAYLOAD_LIMIT: usize = 6 * 1024 * 1024;
+
+/// Allowance for the proxy response envelope (status, flags, JSON framing).
+const ENVELOPE_OVERHEAD: usize = 1024;
+
+/// How an offloaded response is presented to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffloadMode {
+    /// 303 See Other pointing at the presigned URL.
+    Redirect,
+    /// 200 with `{"url": ..., "expiresIn": seconds}`.
+    Envelope,
+}
+
+/// Settings for uploading oversized responses to S3.
+///
+/// # Example
+/// ```ignore
+/// let config = aws_config::load_from_env().await;
+/// app.offload_large_responses(
+///     S3Offload::new(aws_sdk_s3::Client::new(&config), "my-report-bucket")
+///         .prefix("responses/")
+///         .expires_in(Duration::from_secs(600)),
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct S3Offload {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+    expires_in: Duration,
+    mode: OffloadMode,
+    limit: usize,
+}
+
+impl S3Offload {
+    /// Offload to `bucket`, redirecting to URLs valid for 15 minutes.
+    pub fn new(client: aws_sdk_s3::Client, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: String::new(),
+            expires_in: Duration::from_secs(15 * 60),
+            mode: OffloadMode::Redirect,
+            limit: LAMBDA_PAYLOAD_LIMIT,
+        }
+    }
+
+    /// Prefix for object keys, e.g. `"responses/"`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// How long presigned URLs stay valid.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires_in = duration;
+        self
+    }
+
+    /// How offloaded responses are returned. Defaults to [`OffloadMode::Redirect`].
+    pub fn mode(mut self, mode: OffloadMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Override the payload size (in bytes) above which responses are
+    /// offloaded. Defaults to Lambda's 6 MB limit.
+    pub fn limit(mut self, bytes: usize) -> Self {
+        self.limit = bytes;
+        self
+    }
+
+    /// Upload `resp` if it would exceed the payload limit and return the
+    /// replacement response; smaller responses are returned unchanged.
+    pub(crate) async fn apply(&self, resp: Response, request_id: &str) -> Result<Response, Error> {
+        if payload_size(&resp) <= self.limit {
+            return Ok(resp);
+        }
+
+        let content_type = resp
+            .header("content-type")
+            .map(str::to_string)
+            .or_else(|| resp.body.default_content_type().map(str::to_string));
+        let content_encoding = resp.header("content-encoding").map(str::to_string);
+        let content_disposition = resp.header("content-disposition").map(str::to_string);
+        let body = match resp.body {
+            ResponseBody::Json(v) => v.to_string().into_bytes(),
+            ResponseBody::Text(t) => t.into_bytes(),
+            ResponseBody::Binary(b) => b,
+            ResponseBody::Empty | ResponseBody::Stream(_) => return Ok(resp),
+        };
+
+        let key = object_key(&self.prefix, request_id);
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .body(ByteStream::from(body))
+            .set_content_type(content_type)
+            .set_content_encoding(content_encoding)
+            .set_content_disposition(content_disposition)
+            .send()
+            .await?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .presigned(PresigningConfig::expires_in(self.expires_in)?)
+            .await?;
+        let url = presigned.uri().to_string();
+
+        Ok(match self.mode {
+            OffloadMode::Redirect => Response::see_other(url),
+            OffloadMode::Envelope => Response::json(serde_json::json!({
+                "url": url,
+                "expiresIn": self.expires_in.as_secs(),
+            })),
+        })
+    }
+}
+
+/// Estimate the size of the Lambda response payload for `resp`.
+fn payload_size(resp: &Response) -> usize {
+    let body = match &resp.body {
+        ResponseBody::Json(v) => v.to_string().len(),
+        ResponseBody::Text(t) => t.len(),
+        ResponseBody::Binary(b) => b.len().div_ceil(3) * 4,
+        ResponseBody::Empty | ResponseBody::Stream(_) => 0,
+    };
+    let headers: usize = resp
+        .headers
+        .iter()
+        .map(|(k, v)| k.len() + v.len() + 6)
+        .sum();
+    body + headers + ENVELOPE_OVERHEAD
+}
+
+/// Object key for an offloaded response.
+fn object_key(prefix: &str, request_id: &str) -> String {
+    let id = if request_id.is_empty() {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        format!("{nanos:x}")
+    } else {
+        request_id.to_string()
+    };
+    format!("{prefix}{id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_size_accounts_for_base64() {
+        let resp = Response::binary(vec![0u8; 3000], "application/pdf");
+        assert!(payload_size(&resp) >= 4000);
+        assert!(payload_size(&Response::text("x")) < LAMBDA_PAYLOAD_LIMIT);
+    }
+
+    #[test]
+    fn object_key_uses_request_id() {
+        assert_eq!(object_key("responses/", "abc-123"), "responses/abc-123");
+        assert!(object_key("r/", "").starts_with("r/"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Opaque pagination cursors and RFC 8288 `Link` headers.
+
+use crate::{ChokoError, Request, Response};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The query parameter read by [`Request::cursor`].
+pub const CURSOR_PARAM: &str = "cursor";
+
+/// Encode `position` as an opaque, URL-safe cursor string.
+///
+/// The value is serialized as JSON and base64url-encoded, so any serde type
+/// (a last-seen key, an offset, a DynamoDB `LastEvaluatedKey`) can be used.
+/// Cursors are not signed; don't put anything in them clients must not see
+/// or be able to forge.
+pub fn encode_cursor<T: Serialize + ?Sized>(position: &T) -> String {
+    let json = serde_json::to_vec(position).unwrap_or_default();
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+}
+
+/// Decode a cursor produced by [`encode_cursor`].
+///
+/// Malformed cursors yield a 400 [`ChokoError::BadRequest`].
+pub fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Result<T, ChokoError> {
+    let invalid = || ChokoError::bad_request("Invalid pagination cursor");
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| invalid())?;
+    serde_json::from_slice(&json).map_err(|_| invalid())
+}
+
+impl Request {
+    /// Decode the `cursor` query parameter, if present.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let after: Option<String> = req.cursor()?; // 400 on a malformed cursor
+    /// ```
+    pub fn cursor<T: DeserializeOwned>(&self) -> Result<Option<T>, ChokoError> {
+        self.query_params
+            .get(CURSOR_PARAM)
+            .and_then(|v| v.first())
+            .map(|c| decode_cursor(c))
+            .transpose()
+    }
+
+    /// The request path and query string with `param` set to `value`, for
+    /// building links to other pages of the same listing.
+    ///
+    /// Other query parameters are preserved (sorted by name).
+    pub fn url_with_param(&self, param: &str, value: &str) -> String {
+        let path = self
+            .raw_event
+            .as_ref()
+            .and_then(|e| e.path.as_deref())
+            .unwrap_or("/");
+        let mut pairs: Vec<(&str, &str)> = self
+            .query_params
+            .iter()
+            .filter(|(k, _)| k.as_str() != param)
+            .flat_map(|(k, vs)| vs.iter().map(move |v| (k.as_str(), v.as_str())))
+            .collect();
+        pairs.sort();
+        pairs.push((param, value));
+        let query = serde_urlencoded::to_string(&pairs).unwrap_or_default();
+        format!("{path}?{query}")
+    }
+
+    /// The URL of the page starting at `position`: the current URL with its
+    /// `cursor` parameter replaced.
+    pub fn page_url<T: Serialize + ?Sized>(&self, position: &T) -> String {
+        self.url_with_param(CURSOR_PARAM, &encode_cursor(position))
+    }
+}
+
+impl Response {
+    /// Append a `Link: <url>; rel="rel"` entry, e.g. `rel` = `"next"`.
+    ///
+    /// Multiple links are combined into one comma-separated header.
+    pub fn with_link(mut self, url: &str, rel: &str) -> Self {
+        let link = format!("<{url}>; rel=\"{rel}\"");
+        let existing = self
+            .headers
+            .keys()
+            .find(|k| k.eq_ignore_ascii_case("link"))
+            .cloned();
+        match existing.and_then(|k| self.headers.get_mut(&k)) {
+            Some(value) => {
+                value.push_str(", ");
+                value.push_str(&link);
+                self
+            }
+            None => self.with_header("Link", link),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+    use std::collections::HashMap;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = encode_cursor(&("2024-01-01", 42));
+        assert!(!cursor.contains(['+', '/', '=']));
+        let decoded: (String, u32) = decode_cursor(&cursor).unwrap();
+        assert_eq!(decoded, ("2024-01-01".to_string(), 42));
+    }
+
+    #[test]
+    fn malformed_cursor_is_bad_request() {
+        let err = decode_cursor::<u32>("not base64!").unwrap_err();
+        assert_eq!(err.status_code(), 400);
+    }
+
+    #[test]
+    fn page_url_replaces_cursor_and_keeps_other_params() {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.path = Some("/items".to_string());
+        let req = Request {
+            query_params: HashMap::from([
+                ("limit".to_string(), vec!["20".to_string()]),
+                ("cursor".to_string(), vec!["old".to_string()]),
+            ]),
+            raw_event: Some(event),
+            ..Default::default()
+        };
+
+        assert!(req.cursor::<u32>().is_err());
+        assert_eq!(
+            req.page_url(&7),
+            format!("/items?limit=20&cursor={}", encode_cursor(&7))
+        );
+    }
+
+    #[test]
+    fn with_link_combines_entries() {
+        let resp = Response::text("")
+            .with_link("/items?cursor=b", "next")
+            .with_link("/items?cursor=a", "prev");
+        assert_eq!(
+            resp.header("link"),
+            Some("</items?cursor=b>; rel=\"next\", </items?cursor=a>; rel=\"prev\"")
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Typed configuration from SSM Parameter Store (`ssm` feature).
+//!
+//! [`Parameters`] loads every parameter under a path such as
+//! `/myapp/prod/` into a typed config struct. The path below the prefix
+//! gives the structure: `/myapp/prod/db/host` becomes `db.host`.
+//! `StringList` parameters become arrays, and values that read as numbers
+//! or booleans become those, so fields can be typed accordingly.
+//!
+//! With [`Parameters::refresh_every`] the config is reloaded in the
+//! background; handlers see the new values on their next call to
+//! [`Parameters::current`]. A failed reload keeps the previous config. In
+//! Lambda, background work only runs while the function is handling an
+//! invocation, so refreshes happen at the first opportunity after each
+//! interval.
+//!
+//! # Example
+//! ```ignore
+//! use choko::parameters::Parameters;
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     db: DbConfig,          // /myapp/prod/db/host, /myapp/prod/db/port
+//!     allowed_origins: Vec<String>, // a StringList
+//! }
+//!
+//! let client = aws_sdk_ssm::Client::new(&aws_config::load_from_env().await);
+//! let config = Parameters::<Config>::load(client, "/myapp/prod/").await?
+//!     .refresh_every(Duration::from_secs(300));
+//! app.state(config);
+//!
+//! // In a handler
+//! let config = req.state::<Parameters<Config>>().ok_or("config missing")?.current();
+//! ```
+
+use crate::Error;
+use aws_sdk_ssm::types::ParameterType;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Configuration of type `T` loaded from a Parameter Store path. Cheap to
+/// clone; clones share the current value.
+pub struct Parameters<T> {
+    client: aws_sdk_ssm::Client,
+    path: String,
+    current: Arc<RwLock<Arc<T>>>,
+}
+
+impl<T> Clone for Parameters<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            path: self.path.clone(),
+            current: Arc::clone(&self.current),
+        }
+    }
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> Parameters<T> {
+    /// Load the parameters under `path`, decrypting `SecureString`s.
+    pub async fn load(client: aws_sdk_ssm::Client, path: impl Into<String>) -> Result<Self, Error> {
+        let mut path = path.into();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        let config = fetch(&client, &path).await?;
+        Ok(Self {
+            client,
+            path,
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// The current configuration.
+    pub fn current(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Reload the parameters now.
+    pub async fn refresh(&self) -> Result<(), Error> {
+        let config = fetch(&self.client, &self.path).await?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        Ok(())
+    }
+
+    /// Reload the parameters every `interval` in a background task, for as
+    /// long as the runtime is alive.
+    pub fn refresh_every(self, interval: Duration) -> Self {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(e) = this.refresh().await {
+                    eprintln!("Failed to refresh parameters under {}: {e}", this.path);
+                }
+            }
+        });
+        self
+    }
+}
+
+async fn fetch<T: DeserializeOwned>(client: &aws_sdk_ssm::Client, path: &str) -> Result<T, Error> {
+    Ok(serde_json::from_value(fetch_tree(client, path).await?)?)
+}
+
+/// The parameters under `path` (ending in `/`) as a nested object.
+pub(crate) async fn fetch_tree(client: &aws_sdk_ssm::Client, path: &str) -> Result<Value, Error> {
+    let mut params = Vec::new();
+    let mut pages = client
+        .get_parameters_by_path()
+        .path(path)
+        .recursive(true)
+        .with_decryption(true)
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        for param in page?.parameters() {
+            let (Some(name), Some(value)) = (param.name(), param.value()) else {
+                continue;
+            };
+            let list = param.r#type() == Some(&ParameterType::StringList);
+            params.push((name.to_string(), value.to_string(), list));
+        }
+    }
+    Ok(nest(path, params))
+}
+
+/// Build a nested object from `(name, value, is_string_list)` parameters
+/// under `path`.
+fn nest(path: &str, params: Vec<(String, String, bool)>) -> Value {
+    let mut root = Map::new();
+    for (name, value, list) in params {
+        let Some(relative) = name.strip_prefix(path) else {
+            continue;
+        };
+        let mut segments: Vec<&str> = relative.split('/').filter(|s| !s.is_empty()).collect();
+        let Some(leaf) = segments.pop() else {
+            continue;
+        };
+        let mut map = &mut root;
+        for segment in segments {
+            let entry = map
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !entry.is_object() {
+                *entry = Value::Object(Map::new());
+            }
+            map = entry.as_object_mut().expect("just made an object");
+        }
+        let value = if list {
+            value.split(',').map(scalar).collect()
+        } else {
+            scalar(&value)
+        };
+        map.insert(leaf.to_string(), value);
+    }
+    Value::Object(root)
+}
+
+/// A number or boolean if `value` reads as one, the string otherwise.
+fn scalar(value: &str) -> Value {
+    match serde_json::from_str::<Value>(value) {
+        Ok(v @ (Value::Number(_) | Value::Bool(_))) => v,
+        _ => Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn nests_parameters_by_path() {
+        let params = vec![
+            (
+                "/app/prod/db/host".to_string(),
+                "db.internal".to_string(),
+                false,
+            ),
+            ("/app/prod/db/port".to_string(), "5432".to_string(), false),
+            ("/app/prod/debug".to_string(), "false".to_string(), false),
+            (
+                "/app/prod/origins".to_string(),
+                "a.com,b.com".to_string(),
+                true,
+            ),
+            ("/other/key".to_string(), "x".to_string(), false),
+        ];
+        let value = nest("/app/prod/", params);
+        assert_eq!(
+            value,
+            json!({
+                "db": { "host": "db.internal", "port": 5432 },
+                "debug": false,
+                "origins": ["a.com", "b.com"]
+            })
+        );
+
+        #[derive(Deserialize)]
+        struct Db {
+            host: String,
+            port: u16,
+        }
+        #[derive(Deserialize)]
+        struct Config {
+            db: Db,
+            origins: Vec<String>,
+        }
+        let Config { db, origins } = serde_json::from_value(value).unwrap();
+        assert_eq!((db.host.as_str(), db.port), ("db.internal", 5432));
+        assert_eq!(origins, ["a.com", "b.com"]);
+    }
+}
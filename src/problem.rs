@@ -0,0 +1,157 @@
+//! RFC 7807 Problem Details responses.
+
+use crate::Response;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The media type for Problem Details bodies.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// An RFC 7807 Problem Details object.
+///
+/// Convert it into a [`Response`] with `Response::from(problem)`, or return it
+/// from a handler as an error (`Err(problem.into())`) to have the framework
+/// render it with the matching status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    /// A URI identifying the problem type. Defaults to `about:blank`.
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    /// A short, human-readable summary of the problem type.
+    pub title: String,
+    /// The HTTP status code.
+    pub status: u16,
+    /// A human-readable explanation specific to this occurrence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// A URI identifying this specific occurrence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Additional members, serialized alongside the standard ones.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// A problem with the given status, `about:blank` type and the status's
+    /// standard reason phrase as its title.
+    pub fn new(status: u16) -> Self {
+        let title = http::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("Unknown Error");
+        Self {
+            type_uri: "about:blank".to_string(),
+            title: title.to_string(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Set the problem type URI.
+    pub fn with_type(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = type_uri.into();
+        self
+    }
+
+    /// Set the title.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Set the occurrence-specific detail.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the occurrence URI.
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Add an extension member. Names that clash with the standard members
+    /// are ignored when serializing.
+    pub fn with_extension(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {detail}", self.title),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
+impl From<Problem> for Response {
+    fn from(problem: Problem) -> Self {
+        let mut body = serde_json::to_value(&problem).unwrap_or_default();
+        // Standard members win over same-named extensions
+        if let Value::Object(map) = &mut body {
+            map.insert("type".to_string(), Value::String(problem.type_uri));
+            map.insert("title".to_string(), Value::String(problem.title));
+            map.insert("status".to_string(), Value::from(problem.status));
+        }
+        Response::json(body)
+            .with_status(i64::from(problem.status))
+            .with_header("Content-Type", PROBLEM_CONTENT_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+    use serde_json::json;
+
+    #[test]
+    fn new_uses_reason_phrase_as_title() {
+        let problem = Problem::new(404);
+        assert_eq!(problem.title, "Not Found");
+        assert_eq!(problem.type_uri, "about:blank");
+    }
+
+    #[test]
+    fn into_response_serializes_members_and_extensions() {
+        let resp: Response = Problem::new(403)
+            .with_type("https://example.com/probs/out-of-credit")
+            .with_detail("Your current balance is 30, but that costs 50.")
+            .with_instance("/account/12345/msgs/abc")
+            .with_extension("balance", 30)
+            .into();
+
+        assert_eq!(resp.status_code, 403);
+        assert_eq!(resp.header("content-type"), Some(PROBLEM_CONTENT_TYPE));
+        assert_eq!(
+            resp.body,
+            ResponseBody::Json(json!({
+                "type": "https://example.com/probs/out-of-credit",
+                "title": "Forbidden",
+                "status": 403,
+                "detail": "Your current balance is 30, but that costs 50.",
+                "instance": "/account/12345/msgs/abc",
+                "balance": 30,
+            }))
+        );
+    }
+
+    #[test]
+    fn extensions_cannot_override_standard_members() {
+        let resp: Response = Problem::new(400).with_extension("status", 200).into();
+        assert_eq!(
+            resp.body,
+            json!({"type": "about:blank", "title": "Bad Request", "status": 400})
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Protocol Buffers request and response bodies (`protobuf` feature).
+
+use crate::{Error, Request, Response};
+use prost::Message;
+
+/// The media type used for protobuf bodies.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+impl Request {
+    /// Decode the (binary) request body as a protobuf message.
+    ///
+    /// API Gateway must be configured to treat `application/x-protobuf` as a
+    /// binary media type so the body arrives base64-encoded.
+    pub fn protobuf<M: Message + Default>(&self) -> Result<M, Error> {
+        let bytes = self.body_bytes().ok_or("request body is empty")?;
+        Ok(M::decode(bytes)?)
+    }
+}
+
+impl Response {
+    /// Encode `message` as an `application/x-protobuf` response with status 200.
+    pub fn protobuf<M: Message>(message: &M) -> Self {
+        Response::binary(message.encode_to_vec(), PROTOBUF_CONTENT_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Ping {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(uint32, tag = "2")]
+        count: u32,
+    }
+
+    #[test]
+    fn protobuf_round_trip() {
+        let ping = Ping {
+            name: "choko".to_string(),
+            count: 3,
+        };
+        let resp = Response::protobuf(&ping);
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            PROTOBUF_CONTENT_TYPE
+        );
+        let ResponseBody::Binary(bytes) = resp.body else {
+            panic!("expected binary body");
+        };
+
+        let req = Request {
+            binary_body: Some(bytes),
+            ..Default::default()
+        };
+        assert_eq!(req.protobuf::<Ping>().unwrap(), ping);
+    }
+
+    #[test]
+    fn protobuf_rejects_garbage() {
+        let req = Request {
+            binary_body: Some(vec![0xff, 0xff, 0xff]),
+            ..Default::default()
+        };
+        assert!(req.protobuf::<Ping>().is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Typed query string deserialization.
+
+use crate::Request;
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// The query string could not be deserialized into the requested type.
+///
+/// Returning this error from a handler (e.g. via `?`) produces a 400 response.
+#[derive(Debug)]
+pub struct QueryRejection {
+    message: String,
+}
+
+impl fmt::Display for QueryRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid query string: {}", self.message)
+    }
+}
+
+impl std::error::Error for QueryRejection {}
+
+impl Request {
+    /// Deserialize the query string parameters into `T`.
+    ///
+    /// Values are parsed like a URL-encoded form, so numeric and boolean
+    /// fields are converted from their string form and `Option` fields may be
+    /// omitted. Only the first value of a repeated parameter is used; read
+    /// [`query_params`](Request::query_params) directly for multi-value access.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(serde::Deserialize)]
+    /// struct Search { q: String, page: Option<u32> }
+    ///
+    /// let search: Search = req.query()?; // 400 on failure
+    /// ```
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, QueryRejection> {
+        let pairs: Vec<(&str, &str)> = self
+            .query_params
+            .iter()
+            .filter_map(|(k, v)| v.first().map(|v| (k.as_str(), v.as_str())))
+            .collect();
+        let encoded = serde_urlencoded::to_string(&pairs).map_err(|e| QueryRejection {
+            message: e.to_string(),
+        })?;
+        serde_urlencoded::from_str(&encoded).map_err(|e| QueryRejection {
+            message: e.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+        exact: Option<bool>,
+    }
+
+    fn request_with_query(pairs: &[(&str, &str)]) -> Request {
+        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
+        for (k, v) in pairs {
+            query_params
+                .entry(k.to_string())
+                .or_default()
+                .push(v.to_string());
+        }
+        Request {
+            query_params,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn query_parses_numbers_and_optionals() {
+        let req = request_with_query(&[("q", "rust & lambda"), ("page", "3")]);
+        let search: Search = req.query().unwrap();
+        assert_eq!(
+            search,
+            Search {
+                q: "rust & lambda".to_string(),
+                page: Some(3),
+                exact: None,
+            }
+        );
+    }
+
+    #[test]
+    fn query_rejects_invalid_values() {
+        let req = request_with_query(&[("q", "x"), ("page", "three")]);
+        assert!(req.query::<Search>().is_err());
+
+        let req = request_with_query(&[("page", "1")]);
+        let err = req.query::<Search>().unwrap_err();
+        assert!(err.to_string().contains("q"));
+    }
+}
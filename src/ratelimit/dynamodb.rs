@@ -0,0 +1,164 @@
+//! Rate limit counters shared through DynamoDB (`dynamodb` feature).
+
+use super::{Decision, Policy, QuotaStore, QuotaUsage, QuotaWindow, RateLimitStore};
+use crate::{BoxFuture, Error};
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Counters kept in a DynamoDB table, shared by every Lambda instance.
+///
+/// Requests are counted in fixed windows with one atomic `UpdateItem` per
+/// request. Token bucket policies are approximated as a window of
+/// `capacity / refill_per_sec` seconds holding `capacity` requests.
+///
+/// The table needs a string partition key (default `pk`); enable TTL on the
+/// `expires_at` attribute so old windows are cleaned up.
+///
+/// As a [`QuotaStore`], usage items (`<key>#<period>`) are kept for
+/// [`QUOTA_RETENTION`] after their period ends so they can be exported for
+/// billing.
+#[derive(Debug, Clone)]
+pub struct DynamoDbStore {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    key_attribute: String,
+}
+
+impl DynamoDbStore {
+    /// Store counters in `table`.
+    pub fn new(client: aws_sdk_dynamodb::Client, table: impl Into<String>) -> Self {
+        Self {
+            client,
+            table: table.into(),
+            key_attribute: "pk".to_string(),
+        }
+    }
+
+    /// The partition key attribute name.
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        self.key_attribute = name.into();
+        self
+    }
+}
+
+/// How long quota usage items outlive their period.
+pub const QUOTA_RETENTION: Duration = Duration::from_secs(90 * 86_400);
+
+/// The fixed window equivalent of `policy`: `(limit, window)`.
+fn as_window(policy: &Policy) -> (u64, Duration) {
+    match *policy {
+        Policy::FixedWindow { limit, window } => (limit, window),
+        Policy::TokenBucket {
+            capacity,
+            refill_per_sec,
+        } => {
+            let secs = if refill_per_sec > 0.0 {
+                (capacity as f64 / refill_per_sec).max(1.0)
+            } else {
+                86_400.0
+            };
+            (capacity, Duration::from_secs_f64(secs))
+        }
+    }
+}
+
+impl RateLimitStore for DynamoDbStore {
+    fn hit(&self, key: &str, policy: &Policy) -> BoxFuture<Result<Decision, Error>> {
+        let this = self.clone();
+        let (limit, window) = as_window(policy);
+        let key = key.to_string();
+        Box::pin(async move {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            let window_secs = window.as_secs().max(1);
+            let window_start = now.as_secs() / window_secs * window_secs;
+            let window_end = window_start + window_secs;
+
+            let output = this
+                .client
+                .update_item()
+                .table_name(&this.table)
+                .key(
+                    &this.key_attribute,
+                    AttributeValue::S(format!("{key}#{window_start}")),
+                )
+                .update_expression("ADD hits :one SET expires_at = :ttl")
+                .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+                .expression_attribute_values(":ttl", AttributeValue::N(window_end.to_string()))
+                .return_values(ReturnValue::UpdatedNew)
+                .send()
+                .await?;
+            let hits: u64 = match output.attributes().and_then(|a| a.get("hits")) {
+                Some(AttributeValue::N(n)) => n.parse()?,
+                _ => return Err("rate limit counter missing from UpdateItem output".into()),
+            };
+
+            Ok(Decision {
+                allowed: hits <= limit,
+                remaining: limit.saturating_sub(hits),
+                retry_after: Duration::from_secs(window_end).saturating_sub(now),
+            })
+        })
+    }
+}
+
+impl QuotaStore for DynamoDbStore {
+    fn consume(
+        &self,
+        key: &str,
+        window: &QuotaWindow,
+        limit: u64,
+    ) -> BoxFuture<Result<QuotaUsage, Error>> {
+        let this = self.clone();
+        let pk = format!("{key}#{}", window.id);
+        let ttl = window.resets_at + QUOTA_RETENTION.as_secs();
+        Box::pin(async move {
+            let result = this
+                .client
+                .update_item()
+                .table_name(&this.table)
+                .key(&this.key_attribute, AttributeValue::S(pk))
+                .update_expression("ADD hits :one SET expires_at = :ttl")
+                .condition_expression("attribute_not_exists(hits) OR hits < :limit")
+                .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+                .expression_attribute_values(":ttl", AttributeValue::N(ttl.to_string()))
+                .expression_attribute_values(":limit", AttributeValue::N(limit.to_string()))
+                .return_values(ReturnValue::UpdatedNew)
+                .send()
+                .await;
+            let output = match result {
+                Ok(output) => output,
+                Err(e)
+                    if e.as_service_error()
+                        .is_some_and(|e| e.is_conditional_check_failed_exception()) =>
+                {
+                    return Ok(QuotaUsage {
+                        allowed: false,
+                        used: limit,
+                    });
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let used: u64 = match output.attributes().and_then(|a| a.get("hits")) {
+                Some(AttributeValue::N(n)) => n.parse()?,
+                _ => return Err("quota counter missing from UpdateItem output".into()),
+            };
+            Ok(QuotaUsage {
+                allowed: true,
+                used,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_maps_to_window() {
+        assert_eq!(
+            as_window(&Policy::token_bucket(10, 2.0)),
+            (10, Duration::from_secs(5))
+        );
+    }
+}
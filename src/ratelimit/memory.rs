@@ -0,0 +1,262 @@
+//! Per-container in-memory rate limit store.
+
+use super::{Decision, Policy, QuotaStore, QuotaUsage, QuotaWindow, RateLimitStore};
+use crate::{BoxFuture, Error};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Drop idle entries once the map grows past this many keys.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+enum State {
+    Bucket { tokens: f64, updated: Instant },
+    Window { start: Instant, count: u64 },
+}
+
+/// Quota counters, keyed by quota key and period.
+struct Quotas {
+    counters: HashMap<String, QuotaCounter>,
+    /// The earliest `resets_at` among the counters, before which pruning
+    /// can't free anything.
+    next_expiry: u64,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Self {
+            counters: HashMap::new(),
+            next_expiry: u64::MAX,
+        }
+    }
+}
+
+struct QuotaCounter {
+    used: u64,
+    resets_at: u64,
+}
+
+/// Counters held in the Lambda container's memory.
+///
+/// Each warm container enforces limits independently, so the effective
+/// limit scales with concurrency. Use it to blunt bursts cheaply; use a
+/// shared store for hard limits.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<HashMap<String, State>>,
+    quotas: Mutex<Quotas>,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn decide(state: &mut State, policy: &Policy, now: Instant) -> Decision {
+    match (state, policy) {
+        (
+            State::Bucket { tokens, updated },
+            Policy::TokenBucket {
+                capacity,
+                refill_per_sec,
+            },
+        ) => {
+            let elapsed = now.duration_since(*updated).as_secs_f64();
+            *tokens = (*tokens + elapsed * refill_per_sec).min(*capacity as f64);
+            *updated = now;
+            if *tokens >= 1.0 {
+                *tokens -= 1.0;
+                Decision {
+                    allowed: true,
+                    remaining: *tokens as u64,
+                    retry_after: Duration::ZERO,
+                }
+            } else {
+                let wait = if *refill_per_sec > 0.0 {
+                    (1.0 - *tokens) / refill_per_sec
+                } else {
+                    f64::from(u32::MAX)
+                };
+                Decision {
+                    allowed: false,
+                    remaining: 0,
+                    retry_after: Duration::from_secs_f64(wait),
+                }
+            }
+        }
+        (State::Window { start, count }, Policy::FixedWindow { limit, window }) => {
+            if now.duration_since(*start) >= *window {
+                *start = now;
+                *count = 0;
+            }
+            let retry_after = window.saturating_sub(now.duration_since(*start));
+            if *count < *limit {
+                *count += 1;
+                Decision {
+                    allowed: true,
+                    remaining: limit - *count,
+                    retry_after: Duration::ZERO,
+                }
+            } else {
+                Decision {
+                    allowed: false,
+                    remaining: 0,
+                    retry_after,
+                }
+            }
+        }
+        // The policy changed shape for this key; start over
+        (state, policy) => {
+            *state = initial(policy, now);
+            decide(state, policy, now)
+        }
+    }
+}
+
+fn initial(policy: &Policy, now: Instant) -> State {
+    match policy {
+        Policy::TokenBucket { capacity, .. } => State::Bucket {
+            tokens: *capacity as f64,
+            updated: now,
+        },
+        Policy::FixedWindow { .. } => State::Window {
+            start: now,
+            count: 0,
+        },
+    }
+}
+
+fn is_idle(state: &State, policy: &Policy, now: Instant) -> bool {
+    match (state, policy) {
+        (
+            State::Bucket { tokens, updated },
+            Policy::TokenBucket {
+                capacity,
+                refill_per_sec,
+            },
+        ) => {
+            tokens + now.duration_since(*updated).as_secs_f64() * refill_per_sec >= *capacity as f64
+        }
+        (State::Window { start, .. }, Policy::FixedWindow { window, .. }) => {
+            now.duration_since(*start) >= *window
+        }
+        _ => true,
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn hit(&self, key: &str, policy: &Policy) -> BoxFuture<Result<Decision, Error>> {
+        let now = Instant::now();
+        let mut map = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if map.len() > PRUNE_THRESHOLD {
+            map.retain(|_, state| !is_idle(state, policy, now));
+        }
+        let state = map
+            .entry(key.to_string())
+            .or_insert_with(|| initial(policy, now));
+        let decision = decide(state, policy, now);
+        Box::pin(async move { Ok(decision) })
+    }
+}
+
+impl QuotaStore for MemoryStore {
+    fn consume(
+        &self,
+        key: &str,
+        window: &QuotaWindow,
+        limit: u64,
+    ) -> BoxFuture<Result<QuotaUsage, Error>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut quotas = self.quotas.lock().unwrap_or_else(|e| e.into_inner());
+        if quotas.counters.len() > PRUNE_THRESHOLD && now >= quotas.next_expiry {
+            quotas.counters.retain(|_, c| c.resets_at > now);
+            quotas.next_expiry = quotas
+                .counters
+                .values()
+                .map(|c| c.resets_at)
+                .min()
+                .unwrap_or(u64::MAX);
+        }
+        quotas.next_expiry = quotas.next_expiry.min(window.resets_at);
+        let counter = quotas
+            .counters
+            .entry(format!("{key}#{}", window.id))
+            .or_insert(QuotaCounter {
+                used: 0,
+                resets_at: window.resets_at,
+            });
+        let allowed = counter.used < limit;
+        if allowed {
+            counter.used += 1;
+        }
+        let usage = QuotaUsage {
+            allowed,
+            used: counter.used,
+        };
+        Box::pin(async move { Ok(usage) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let policy = Policy::token_bucket(2, 1.0);
+        let start = Instant::now();
+        let mut state = initial(&policy, start);
+        assert!(decide(&mut state, &policy, start).allowed);
+        assert!(decide(&mut state, &policy, start).allowed);
+        let denied = decide(&mut state, &policy, start);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, Duration::from_secs(1));
+
+        let later = start + Duration::from_millis(1500);
+        assert!(decide(&mut state, &policy, later).allowed);
+    }
+
+    #[test]
+    fn fixed_window_resets() {
+        let policy = Policy::fixed_window(1, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = initial(&policy, start);
+        assert!(decide(&mut state, &policy, start).allowed);
+        assert!(!decide(&mut state, &policy, start + Duration::from_secs(9)).allowed);
+        assert!(decide(&mut state, &policy, start + Duration::from_secs(10)).allowed);
+    }
+
+    #[tokio::test]
+    async fn pruning_keeps_counters_of_longer_periods() {
+        let store = MemoryStore::new();
+        let month = QuotaWindow {
+            id: "2026-10".to_string(),
+            resets_at: u64::MAX,
+        };
+        assert_eq!(store.consume("k", &month, 10).await.unwrap().used, 1);
+
+        let yesterday = QuotaWindow {
+            id: "2026-10-15".to_string(),
+            resets_at: 1,
+        };
+        for i in 0..PRUNE_THRESHOLD {
+            store
+                .consume(&format!("d{i}"), &yesterday, 10)
+                .await
+                .unwrap();
+        }
+        let today = QuotaWindow {
+            id: "2026-10-16".to_string(),
+            resets_at: u64::MAX,
+        };
+        store.consume("k", &today, 10).await.unwrap();
+
+        // Expired daily counters are gone, the monthly one survived
+        assert_eq!(store.quotas.lock().unwrap().counters.len(), 2);
+        assert_eq!(store.consume("k", &month, 10).await.unwrap().used, 2);
+    }
+}
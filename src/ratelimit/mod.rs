@@ -0,0 +1,215 @@
+//! Rate limiting middleware.
+//!
+//! [`RateLimit`] counts requests per key (client IP, API key, or anything a
+//! closure extracts) in a [`RateLimitStore`] and answers over-limit requests
+//! with 429 and `Retry-After`. [`MemoryStore`] keeps counters inside the
+//! Lambda container, so limits apply per instance; use the DynamoDB store
+//! (`dynamodb` feature) to share them across instances. [`Quota`] enforces
+//! daily or monthly allowances for billing-grade usage tracking.
+//!
+//! # Example
+//! ```ignore
+//! use choko::ratelimit::{MemoryStore, Policy, RateLimit};
+//!
+//! app.middleware(RateLimit::per_ip(
+//!     Policy::token_bucket(20, 5.0), // bursts of 20, 5 requests/s sustained
+//!     MemoryStore::new(),
+//! ));
+//! ```
+
+#[cfg(feature = "dynamodb")]
+mod dynamodb;
+mod memory;
+mod quota;
+
+#[cfg(feature = "dynamodb")]
+pub use dynamodb::{DynamoDbStore, QUOTA_RETENTION};
+pub use memory::MemoryStore;
+pub use quota::{Period, Quota, QuotaStore, QuotaUsage, QuotaWindow};
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many requests a key may make.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Buckets hold up to `capacity` tokens and refill at `refill_per_sec`;
+    /// each request takes one token.
+    TokenBucket { capacity: u64, refill_per_sec: f64 },
+    /// At most `limit` requests per aligned `window`.
+    FixedWindow { limit: u64, window: Duration },
+}
+
+impl Policy {
+    /// A token bucket policy.
+    pub fn token_bucket(capacity: u64, refill_per_sec: f64) -> Self {
+        Policy::TokenBucket {
+            capacity,
+            refill_per_sec,
+        }
+    }
+
+    /// A fixed window policy.
+    pub fn fixed_window(limit: u64, window: Duration) -> Self {
+        Policy::FixedWindow { limit, window }
+    }
+
+    /// The maximum number of requests allowed in a burst.
+    pub fn limit(&self) -> u64 {
+        match self {
+            Policy::TokenBucket { capacity, .. } => *capacity,
+            Policy::FixedWindow { limit, .. } => *limit,
+        }
+    }
+}
+
+/// The outcome of counting one request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// Requests left before the limit is hit.
+    pub remaining: u64,
+    /// When a rejected client may retry.
+    pub retry_after: Duration,
+}
+
+/// Where rate limit counters live.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Count one request for `key` under `policy`.
+    fn hit(&self, key: &str, policy: &Policy) -> BoxFuture<Result<Decision, Error>>;
+}
+
+type KeyFn = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+/// Middleware enforcing a [`Policy`] per request key.
+///
+/// Requests for which no key can be extracted are not limited. Limited
+/// responses carry `Retry-After`; all responses for keyed requests carry
+/// `X-RateLimit-Limit` and `X-RateLimit-Remaining`.
+pub struct RateLimit {
+    policy: Policy,
+    store: Arc<dyn RateLimitStore>,
+    key: Arc<KeyFn>,
+    prefix: String,
+}
+
+impl RateLimit {
+    /// Limit by a key extracted from the request.
+    pub fn per_key<F>(policy: Policy, store: impl RateLimitStore, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            policy,
+            store: Arc::new(store),
+            key: Arc::new(key),
+            prefix: String::new(),
+        }
+    }
+
+    /// Limit by client IP (see [`Request::client_ip`]).
+    pub fn per_ip(policy: Policy, store: impl RateLimitStore) -> Self {
+        Self::per_key(policy, store, |req| {
+            req.client_ip().map(|ip| format!("ip:{ip}"))
+        })
+    }
+
+    /// Limit by the identity [`ApiKeyAuth`](crate::auth::ApiKeyAuth)
+    /// resolved, so it must run after the auth middleware. Requests without
+    /// an authenticated key are not limited: counting the raw `x-api-key`
+    /// header would let clients dodge the limit with made-up keys and store
+    /// real keys as counter names.
+    pub fn per_api_key(policy: Policy, store: impl RateLimitStore) -> Self {
+        Self::per_key(policy, store, |req| {
+            req.api_key_identity()
+                .map(|identity| format!("api-key:{}", identity.id))
+        })
+    }
+
+    /// Namespace this limiter's keys, so several limiters can share a store.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let Some(key) = (self.key)(&req) else {
+            return next.run(req);
+        };
+        let key = format!("{}{key}", self.prefix);
+        let store = Arc::clone(&self.store);
+        let policy = self.policy;
+        Box::pin(async move {
+            let decision = store.hit(&key, &policy).await?;
+            let limit = policy.limit().to_string();
+            let remaining = decision.remaining.to_string();
+            if !decision.allowed {
+                let retry_after = decision.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                return Ok(crate::error_json(429, "Too Many Requests")
+                    .with_header("Retry-After", retry_after.to_string())
+                    .with_header("X-RateLimit-Limit", limit)
+                    .with_header("X-RateLimit-Remaining", remaining));
+            }
+            let resp = next.run(req).await?;
+            Ok(resp
+                .with_header("X-RateLimit-Limit", limit)
+                .with_header("X-RateLimit-Remaining", remaining))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ApiKeyIdentity;
+    use crate::middleware::run_chain;
+    use std::collections::HashMap;
+
+    async fn call(limiter: &Arc<dyn Middleware>, identity: Option<&str>) -> Response {
+        let mut req = Request {
+            headers: HashMap::from([("x-api-key".to_string(), "secret".to_string())]),
+            ..Default::default()
+        };
+        if let Some(id) = identity {
+            req.extensions_mut().insert(ApiKeyIdentity::new(id));
+        }
+        let ok = |_req| async { Ok(Response::text("ok")) };
+        run_chain(vec![Arc::clone(limiter)], ok, req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_with_429_and_retry_after_once_exhausted() {
+        let limiter: Arc<dyn Middleware> = Arc::new(RateLimit::per_api_key(
+            Policy::fixed_window(2, Duration::from_secs(60)),
+            MemoryStore::new(),
+        ));
+        assert_eq!(call(&limiter, Some("a")).await.status_code, 200);
+        let resp = call(&limiter, Some("a")).await;
+        assert_eq!(resp.header("x-ratelimit-remaining"), Some("0"));
+
+        let resp = call(&limiter, Some("a")).await;
+        assert_eq!(resp.status_code, 429);
+        let retry_after: u64 = resp.header("retry-after").unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // Other keys have their own budget
+        assert_eq!(call(&limiter, Some("b")).await.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_api_keys_are_not_counted() {
+        let limiter: Arc<dyn Middleware> = Arc::new(RateLimit::per_api_key(
+            Policy::fixed_window(1, Duration::from_secs(60)),
+            MemoryStore::new(),
+        ));
+        for _ in 0..2 {
+            let resp = call(&limiter, None).await;
+            assert_eq!(resp.status_code, 200);
+            assert_eq!(resp.header("x-ratelimit-limit"), None);
+        }
+    }
+}
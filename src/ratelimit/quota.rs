@@ -0,0 +1,287 @@
+//! Calendar quotas: daily or monthly request allowances per key.
+
+use super::KeyFn;
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAY: u64 = 86_400;
+
+/// The calendar period a quota covers, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Daily,
+    Monthly,
+}
+
+/// One concrete quota period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaWindow {
+    /// A stable name for the period, e.g. `2026-10-15` or `2026-10`.
+    pub id: String,
+    /// When the next period starts, as Unix seconds.
+    pub resets_at: u64,
+}
+
+impl Period {
+    /// The period containing `now` (Unix seconds).
+    pub fn window(&self, now: u64) -> QuotaWindow {
+        let day = (now / DAY) as i64;
+        let (year, month, date) = civil_from_days(day);
+        match self {
+            Period::Daily => QuotaWindow {
+                id: format!("{year:04}-{month:02}-{date:02}"),
+                resets_at: (day as u64 + 1) * DAY,
+            },
+            Period::Monthly => {
+                let (next_year, next_month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                QuotaWindow {
+                    id: format!("{year:04}-{month:02}"),
+                    resets_at: days_from_civil(next_year, next_month, 1) as u64 * DAY,
+                }
+            }
+        }
+    }
+}
+
+/// Days since the Unix epoch to a proleptic Gregorian `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * if month > 2 { month - 3 } else { month + 9 } + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The outcome of counting one request against a quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Whether the request was within quota (and was counted).
+    pub allowed: bool,
+    /// Requests counted in the period so far.
+    pub used: u64,
+}
+
+/// Where quota usage is recorded.
+pub trait QuotaStore: Send + Sync + 'static {
+    /// Count one request for `key` in `window` unless `limit` has been
+    /// reached. Rejected requests are not counted.
+    fn consume(
+        &self,
+        key: &str,
+        window: &QuotaWindow,
+        limit: u64,
+    ) -> BoxFuture<Result<QuotaUsage, Error>>;
+}
+
+type LimitFn = dyn Fn(&Request) -> Option<u64> + Send + Sync;
+
+/// Middleware enforcing a daily or monthly request quota per key.
+///
+/// Unlike [`RateLimit`](super::RateLimit), quotas follow calendar periods
+/// (UTC) and only count accepted requests, so the stored usage can be used
+/// for billing. Over-quota requests get 429 with `Retry-After` until the
+/// period resets; keyed responses carry `X-RateLimit-Limit`,
+/// `X-RateLimit-Remaining` and `X-RateLimit-Reset` (Unix seconds).
+///
+/// # Example
+/// ```ignore
+/// use choko::ratelimit::{DynamoDbStore, Period, Quota};
+///
+/// app.middleware(
+///     Quota::per_api_key(Period::Monthly, 10_000, DynamoDbStore::new(client, "usage"))
+///         // Plans can raise the default allowance
+///         .limit_for(|req| {
+///             req.api_key_identity()?.attributes.get("monthly_quota")?.as_u64()
+///         }),
+/// );
+/// ```
+pub struct Quota {
+    period: Period,
+    limit: u64,
+    limit_for: Option<Arc<LimitFn>>,
+    store: Arc<dyn QuotaStore>,
+    key: Arc<KeyFn>,
+    prefix: String,
+}
+
+impl Quota {
+    /// A quota of `limit` requests per `period` for a key extracted from
+    /// the request. Requests without a key are not counted.
+    pub fn per_key<F>(period: Period, limit: u64, store: impl QuotaStore, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            period,
+            limit,
+            limit_for: None,
+            store: Arc::new(store),
+            key: Arc::new(key),
+            prefix: "quota:".to_string(),
+        }
+    }
+
+    /// A quota per API key, identified like
+    /// [`RateLimit::per_api_key`](super::RateLimit::per_api_key): it must
+    /// run after [`ApiKeyAuth`](crate::auth::ApiKeyAuth), and requests
+    /// without an authenticated key are not counted.
+    pub fn per_api_key(period: Period, limit: u64, store: impl QuotaStore) -> Self {
+        Self::per_key(period, limit, store, |req| {
+            req.api_key_identity()
+                .map(|identity| format!("api-key:{}", identity.id))
+        })
+    }
+
+    /// Override the limit per request (e.g. from the caller's plan); `None`
+    /// falls back to the default.
+    pub fn limit_for<F>(mut self, limit: F) -> Self
+    where
+        F: Fn(&Request) -> Option<u64> + Send + Sync + 'static,
+    {
+        self.limit_for = Some(Arc::new(limit));
+        self
+    }
+
+    /// Namespace this quota's keys. Defaults to `quota:`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+impl Middleware for Quota {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let Some(key) = (self.key)(&req) else {
+            return next.run(req);
+        };
+        let key = format!("{}{key}", self.prefix);
+        let limit = self
+            .limit_for
+            .as_ref()
+            .and_then(|f| f(&req))
+            .unwrap_or(self.limit);
+        let store = Arc::clone(&self.store);
+        let period = self.period;
+        Box::pin(async move {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let window = period.window(now);
+            let usage = store.consume(&key, &window, limit).await?;
+            let headers = [
+                ("X-RateLimit-Limit", limit.to_string()),
+                (
+                    "X-RateLimit-Remaining",
+                    limit.saturating_sub(usage.used).to_string(),
+                ),
+                ("X-RateLimit-Reset", window.resets_at.to_string()),
+            ];
+            let resp = if usage.allowed {
+                next.run(req).await?
+            } else {
+                crate::error_json(429, "Quota Exceeded").with_header(
+                    "Retry-After",
+                    window.resets_at.saturating_sub(now).max(1).to_string(),
+                )
+            };
+            Ok(headers
+                .into_iter()
+                .fold(resp, |resp, (name, value)| resp.with_header(name, value)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::ApiKeyIdentity;
+    use crate::middleware::run_chain;
+    use crate::ratelimit::MemoryStore;
+    use std::collections::HashMap;
+
+    #[test]
+    fn calendar_windows() {
+        // 2023-11-14T22:13:20Z
+        assert_eq!(
+            Period::Daily.window(1_700_000_000),
+            QuotaWindow {
+                id: "2023-11-14".to_string(),
+                resets_at: 1_700_006_400
+            }
+        );
+        assert_eq!(
+            Period::Monthly.window(1_700_000_000),
+            QuotaWindow {
+                id: "2023-11".to_string(),
+                resets_at: 1_701_388_800
+            }
+        );
+        // Year rollover: 2024-12-31T12:00:00Z
+        assert_eq!(
+            Period::Monthly.window(1_735_646_400).resets_at,
+            1_735_689_600
+        );
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        for days in [-1, 0, 59, 11_016, 19_675, 20_000] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_over_quota_without_counting() {
+        let quota: Arc<dyn Middleware> = Arc::new(
+            Quota::per_api_key(Period::Daily, 1, MemoryStore::new())
+                .limit_for(|req| req.header("x-plan").map(|_| 2)),
+        );
+        let call = |plan: bool| {
+            let mut headers = HashMap::new();
+            if plan {
+                headers.insert("x-plan".to_string(), "pro".to_string());
+            }
+            let mut req = Request {
+                headers,
+                ..Default::default()
+            };
+            req.extensions_mut().insert(ApiKeyIdentity::new("k"));
+            let ok = |_req| async { Ok(Response::text("ok")) };
+            run_chain(vec![Arc::clone(&quota)], ok, req)
+        };
+
+        let resp = call(false).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.headers["X-RateLimit-Remaining"], "0");
+
+        let resp = call(false).await.unwrap();
+        assert_eq!(resp.status_code, 429);
+        assert!(resp.headers.contains_key("Retry-After"));
+
+        // A higher per-request limit sees the same usage
+        let resp = call(true).await.unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.headers["X-RateLimit-Limit"], "2");
+    }
+}
@@ -0,0 +1,76 @@
+//! Correlation IDs for tracing a request across client, API Gateway and
+//! Lambda logs.
+
+use crate::Request;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The header [`Choko::request_id`](crate::Choko::request_id) uses by
+/// default.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest incoming ID that is reused as-is.
+const MAX_LEN: usize = 128;
+
+impl Request {
+    /// The correlation ID of this request, when
+    /// [`Choko::request_id`](crate::Choko::request_id) is enabled: the
+    /// caller's `X-Request-Id` if it sent a usable one, otherwise a
+    /// generated ID.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+}
+
+/// Whether a caller-supplied ID is safe to log and echo back: non-empty,
+/// bounded and free of whitespace and control characters.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// The correlation ID for a request: the incoming one if valid, else the
+/// API Gateway request ID (so the ID also finds the API Gateway logs), else
+/// the Lambda request ID, else a locally generated one.
+pub(crate) fn resolve(
+    incoming: Option<&str>,
+    apigw_request_id: Option<&str>,
+    lambda_request_id: Option<&str>,
+) -> String {
+    incoming
+        .map(str::trim)
+        .filter(|id| is_valid(id))
+        .or(apigw_request_id.filter(|id| !id.is_empty()))
+        .or(lambda_request_id.filter(|id| !id.is_empty()))
+        .map_or_else(generate, str::to_string)
+}
+
+fn generate() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{nanos:016x}-{:08x}-{count:08x}", std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_valid_incoming_id() {
+        assert_eq!(resolve(Some(" abc-123 "), Some("gw"), None), "abc-123");
+        assert_eq!(resolve(Some("has space"), Some("gw"), None), "gw");
+        assert_eq!(
+            resolve(Some(&"x".repeat(200)), None, Some("lambda")),
+            "lambda"
+        );
+    }
+
+    #[test]
+    fn generated_ids_are_unique() {
+        let (a, b) = (resolve(None, None, None), resolve(None, None, None));
+        assert_ne!(a, b);
+        assert!(is_valid(&a));
+    }
+}
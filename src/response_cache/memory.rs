@@ -0,0 +1,131 @@
+//! Per-container in-memory response cache.
+
+use super::ResponseCacheStore;
+use crate::{BoxFuture, Error};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    value: Vec<u8>,
+    expires: Instant,
+    tags: Vec<String>,
+}
+
+/// Responses held in the Lambda container's memory.
+///
+/// Each warm container has its own cache, so invalidation only reaches the
+/// container that handled the write; keep TTLs short, or use a shared store
+/// when stale reads matter.
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    max_entries: usize,
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryCache {
+    /// An empty cache holding up to 1,000 responses.
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            max_entries: 1_000,
+        }
+    }
+
+    /// The most responses kept. When full, expired entries are dropped
+    /// first, then the ones closest to expiring.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max.max(1);
+        self
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ResponseCacheStore for MemoryCache {
+    fn get(&self, key: &str) -> BoxFuture<Result<Option<Vec<u8>>, Error>> {
+        let mut entries = self.lock();
+        let value = match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn put(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Duration,
+        tags: Vec<String>,
+    ) -> BoxFuture<Result<(), Error>> {
+        let now = Instant::now();
+        let mut entries = self.lock();
+        if entries.len() >= self.max_entries && !entries.contains_key(key) {
+            entries.retain(|_, entry| entry.expires > now);
+            while entries.len() >= self.max_entries {
+                let soonest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone());
+                match soonest {
+                    Some(soonest) => entries.remove(&soonest),
+                    None => break,
+                };
+            }
+        }
+        entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires: now + ttl,
+                tags,
+            },
+        );
+        Box::pin(async { Ok(()) })
+    }
+
+    fn invalidate_tag(&self, tag: &str) -> BoxFuture<Result<(), Error>> {
+        self.lock()
+            .retain(|_, entry| !entry.tags.iter().any(|t| t == tag));
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expires_and_evicts_entries() {
+        let cache = MemoryCache::new().max_entries(2);
+        let ttl = Duration::from_secs(60);
+        cache.put("a", b"1".to_vec(), ttl, vec![]).await.unwrap();
+        cache
+            .put("gone", b"x".to_vec(), Duration::ZERO, vec![])
+            .await
+            .unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), Some(b"1".to_vec()));
+        assert_eq!(cache.get("gone").await.unwrap(), None);
+
+        cache
+            .put("b", b"2".to_vec(), Duration::from_secs(30), vec![])
+            .await
+            .unwrap();
+        cache.put("c", b"3".to_vec(), ttl, vec![]).await.unwrap();
+        // "b" expired soonest, so it made room for "c"
+        assert_eq!(cache.get("b").await.unwrap(), None);
+        assert_eq!(cache.get("c").await.unwrap(), Some(b"3".to_vec()));
+    }
+}
@@ -0,0 +1,497 @@
+//! Response caching middleware.
+//!
+//! [`ResponseCache`] stores successful `GET` responses in a
+//! [`ResponseCacheStore`] and serves repeats from there until their TTL
+//! runs out. Entries are keyed by route, path parameters, query string and
+//! the values of any [`vary`](ResponseCache::vary) headers. [`MemoryCache`]
+//! keeps entries inside the Lambda container; [`RedisCache`] (`redis`
+//! feature) shares them across instances through Redis or ElastiCache.
+//!
+//! Only 200 responses are cached, and never ones that set cookies, are
+//! streamed, say `Cache-Control: no-store` or `private`, or have a `Vary`
+//! header naming a request header the cache doesn't vary on. Requests
+//! carrying credentials (`Authorization`, `Cookie`, `X-Api-Key` or
+//! `X-Signature` by default, see
+//! [`credential_header`](ResponseCache::credential_header)) bypass the cache
+//! unless that header is one of the vary headers, since the response may
+//! belong to that caller. Responses carry `X-Cache: HIT` or
+//! `X-Cache: MISS`.
+//!
+//! After a write, drop the stale entries with
+//! [`ResponseCache::invalidate_path`] (every variant of one path) or
+//! [`ResponseCache::invalidate_route`] (every path of a route pattern).
+//!
+//! # Example
+//! ```ignore
+//! use choko::response_cache::{MemoryCache, ResponseCache};
+//!
+//! let cache = ResponseCache::new(MemoryCache::new(), Duration::from_secs(60))
+//!     .vary("accept-language");
+//! app.get("/products/{id}", get_product).middleware(cache.clone());
+//! app.state(cache);
+//!
+//! app.put("/products/{id}", |req| async move {
+//!     let product = update_product(&req).await?;
+//!     let cache = req.state::<ResponseCache>().ok_or("cache not configured")?;
+//!     cache.invalidate_path(req.path()).await?;
+//!     cache.invalidate_route("/products").await?;
+//!     Ok(Response::json(json!(product)))
+//! });
+//! ```
+
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisCache;
+pub use memory::MemoryCache;
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, ResponseBody};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where cached responses live.
+pub trait ResponseCacheStore: Send + Sync + 'static {
+    /// The entry stored under `key`, unless missing or expired.
+    fn get(&self, key: &str) -> BoxFuture<Result<Option<Vec<u8>>, Error>>;
+
+    /// Store `value` under `key` for `ttl`, labelled with `tags`.
+    fn put(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Duration,
+        tags: Vec<String>,
+    ) -> BoxFuture<Result<(), Error>>;
+
+    /// Remove every entry labelled with `tag`.
+    fn invalidate_tag(&self, tag: &str) -> BoxFuture<Result<(), Error>>;
+}
+
+/// A response in serializable form, as stored in the cache. Also used to
+/// record responses for idempotent replays.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    status: i64,
+    headers: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    multi_value_headers: HashMap<String, Vec<String>>,
+    body: CachedBody,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+enum CachedBody {
+    Json(Value),
+    Text(String),
+    /// Base64-encoded.
+    Binary(String),
+    Empty,
+}
+
+impl CachedResponse {
+    /// The cacheable form of `resp`, or `None` if it must not be cached.
+    fn from_response(resp: &Response) -> Option<Self> {
+        if resp.status_code != 200
+            || resp.header("set-cookie").is_some()
+            || !resp.multi_value_headers.is_empty()
+        {
+            return None;
+        }
+        if let Some(cache_control) = resp.header("cache-control") {
+            let cache_control = cache_control.to_ascii_lowercase();
+            if cache_control.contains("no-store") || cache_control.contains("private") {
+                return None;
+            }
+        }
+        Self::capture(resp)
+    }
+
+    /// A copy of `resp`, or `None` for streamed responses.
+    pub(crate) fn capture(resp: &Response) -> Option<Self> {
+        let body = match &resp.body {
+            ResponseBody::Json(value) => CachedBody::Json(value.clone()),
+            ResponseBody::Text(text) => CachedBody::Text(text.clone()),
+            ResponseBody::Binary(bytes) => {
+                CachedBody::Binary(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+            ResponseBody::Empty => CachedBody::Empty,
+            ResponseBody::Stream(_) => return None,
+        };
+        Some(Self {
+            status: resp.status_code,
+            headers: resp.headers.clone(),
+            multi_value_headers: resp.multi_value_headers.clone(),
+            body,
+        })
+    }
+
+    pub(crate) fn into_response(self) -> Result<Response, Error> {
+        let body = match self.body {
+            CachedBody::Json(value) => ResponseBody::Json(value),
+            CachedBody::Text(text) => ResponseBody::Text(text),
+            CachedBody::Binary(encoded) => {
+                ResponseBody::Binary(base64::engine::general_purpose::STANDARD.decode(encoded)?)
+            }
+            CachedBody::Empty => ResponseBody::Empty,
+        };
+        Ok(Response {
+            status_code: self.status,
+            body,
+            headers: self.headers,
+            multi_value_headers: self.multi_value_headers,
+        })
+    }
+}
+
+/// Middleware caching `GET` responses. Cheap to clone; clones share the
+/// store, so the same cache can be registered as middleware and in app
+/// state for invalidation.
+#[derive(Clone)]
+pub struct ResponseCache {
+    store: Arc<dyn ResponseCacheStore>,
+    ttl: Duration,
+    vary: Vec<String>,
+    credential_headers: Vec<String>,
+    prefix: String,
+}
+
+/// Request headers that may identify the caller: those read by the
+/// browser, [`ApiKeyAuth`](crate::auth::ApiKeyAuth) and
+/// [`HmacAuth`](crate::auth::HmacAuth) by default.
+const CREDENTIAL_HEADERS: [&str; 4] = ["authorization", "cookie", "x-api-key", "x-signature"];
+
+impl ResponseCache {
+    /// Cache responses in `store` for `ttl`.
+    pub fn new(store: impl ResponseCacheStore, ttl: Duration) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl,
+            vary: Vec::new(),
+            credential_headers: CREDENTIAL_HEADERS.map(String::from).to_vec(),
+            prefix: String::new(),
+        }
+    }
+
+    /// A copy caching for `ttl` instead, sharing the store; for routes
+    /// that need a different TTL.
+    pub fn with_ttl(&self, ttl: Duration) -> Self {
+        Self {
+            ttl,
+            ..self.clone()
+        }
+    }
+
+    /// Cache a separate variant per value of the request header `name`.
+    pub fn vary(mut self, name: &str) -> Self {
+        self.vary.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Bypass the cache for requests carrying the header `name`, e.g. an
+    /// API key read from a custom header, unless the cache varies on it.
+    pub fn credential_header(mut self, name: &str) -> Self {
+        self.credential_headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Namespace keys and tags, so several apps can share a store.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Drop every cached variant of `path`, e.g. `/products/7`.
+    pub async fn invalidate_path(&self, path: &str) -> Result<(), Error> {
+        self.store
+            .invalidate_tag(&format!("{}path:{path}", self.prefix))
+            .await
+    }
+
+    /// Drop every cached response of the route pattern `route`, e.g.
+    /// `/products/{id}`.
+    pub async fn invalidate_route(&self, route: &str) -> Result<(), Error> {
+        self.store
+            .invalidate_tag(&format!("{}route:{route}", self.prefix))
+            .await
+    }
+
+    fn cacheable(&self, req: &Request) -> bool {
+        req.method() == "GET"
+            && self
+                .credential_headers
+                .iter()
+                .all(|name| req.header(name).is_none() || self.vary.contains(name))
+    }
+
+    fn key(&self, req: &Request) -> String {
+        let route = req.route().unwrap_or_else(|| req.path());
+        let params: BTreeMap<_, _> = req.path_params.iter().collect();
+        let query: BTreeMap<_, _> = req.query_params.iter().collect();
+        let vary: Vec<&str> = self
+            .vary
+            .iter()
+            .map(|name| req.header(name).unwrap_or(""))
+            .collect();
+        let parts = serde_json::json!([params, query, vary]);
+        format!("{}response:{route}:{parts}", self.prefix)
+    }
+
+    fn tags(&self, req: &Request) -> Vec<String> {
+        let mut tags = vec![format!("{}path:{}", self.prefix, req.path())];
+        if let Some(route) = req.route() {
+            tags.push(format!("{}route:{route}", self.prefix));
+        }
+        tags
+    }
+}
+
+impl Middleware for ResponseCache {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if !self.cacheable(&req) {
+            return next.run(req);
+        }
+        let key = self.key(&req);
+        let tags = self.tags(&req);
+        let store = Arc::clone(&self.store);
+        let ttl = self.ttl;
+        let vary = self.vary.clone();
+        Box::pin(async move {
+            match store.get(&key).await {
+                Ok(Some(bytes)) => {
+                    let cached = serde_json::from_slice::<CachedResponse>(&bytes)
+                        .map_err(Error::from)
+                        .and_then(CachedResponse::into_response);
+                    match cached {
+                        Ok(resp) => return Ok(resp.with_header("X-Cache", "HIT")),
+                        Err(e) => eprintln!("Ignoring unreadable cached response {key}: {e}"),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to read cached response {key}: {e}"),
+            }
+            let resp = next.run(req).await?;
+            let cached =
+                CachedResponse::from_response(&resp).filter(|_| keyed_on_vary(&resp, &vary));
+            if let Some(cached) = cached {
+                match serde_json::to_vec(&cached) {
+                    Ok(bytes) => {
+                        if let Err(e) = store.put(&key, bytes, ttl, tags).await {
+                            eprintln!("Failed to cache response {key}: {e}");
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to serialize response {key}: {e}"),
+                }
+            }
+            Ok(resp.with_header("X-Cache", "MISS"))
+        })
+    }
+}
+
+/// Whether the cache key covers every request header `resp` varies on.
+fn keyed_on_vary(resp: &Response, vary: &[String]) -> bool {
+    resp.header("vary").is_none_or(|fields| {
+        fields.split(',').map(str::trim).all(|field| {
+            field.is_empty() || (field != "*" && vary.iter().any(|v| v.eq_ignore_ascii_case(field)))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Choko;
+    use aws_lambda_events::event::apigw::ApiGatewayProxyRequest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn get(path: &str, query: &str) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some(path.to_string());
+        if !query.is_empty() {
+            event.query_string_parameters =
+                serde_urlencoded::from_str::<HashMap<String, String>>(query)
+                    .unwrap()
+                    .into();
+        }
+        event
+    }
+
+    #[tokio::test]
+    async fn serves_hits_until_invalidated() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cache = ResponseCache::new(MemoryCache::new(), Duration::from_secs(60));
+        let mut app = Choko::new("test");
+        let counter = Arc::clone(&calls);
+        app.get("/products/{id}", move |req| {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                Ok(Response::json(
+                    serde_json::json!({ "id": req.path_params["id"], "n": n }),
+                ))
+            }
+        })
+        .middleware(cache.clone());
+
+        let first = app.dispatch(get("/products/7", "")).await.unwrap();
+        assert_eq!(first.headers.get("x-cache").unwrap(), "MISS");
+        let second = app.dispatch(get("/products/7", "")).await.unwrap();
+        assert_eq!(second.headers.get("x-cache").unwrap(), "HIT");
+        assert_eq!(first.body, second.body);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Different query strings are different entries
+        app.dispatch(get("/products/7", "fields=name"))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        cache.invalidate_path("/products/7").await.unwrap();
+        let resp = app.dispatch(get("/products/7", "")).await.unwrap();
+        assert_eq!(resp.headers.get("x-cache").unwrap(), "MISS");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        cache.invalidate_route("/products/{id}").await.unwrap();
+        app.dispatch(get("/products/7", "fields=name"))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn skips_uncacheable_responses() {
+        assert!(CachedResponse::from_response(&Response::text("ok")).is_some());
+        assert!(CachedResponse::from_response(&Response::text("ok").with_status(404)).is_none());
+        let private = Response::text("ok").with_header("Cache-Control", "private, max-age=60");
+        assert!(CachedResponse::from_response(&private).is_none());
+        let cookie = Response::text("ok").with_header("Set-Cookie", "a=b");
+        assert!(CachedResponse::from_response(&cookie).is_none());
+
+        let binary = Response::binary(vec![0, 159, 146, 150], "application/octet-stream");
+        let cached = CachedResponse::from_response(&binary).unwrap();
+        let bytes = serde_json::to_vec(&cached).unwrap();
+        let restored = serde_json::from_slice::<CachedResponse>(&bytes)
+            .unwrap()
+            .into_response()
+            .unwrap();
+        assert!(matches!(&restored.body, ResponseBody::Binary(b) if *b == [0, 159, 146, 150]));
+        assert_eq!(
+            restored.header("content-type"),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[tokio::test]
+    async fn bypasses_cookies_and_unkeyed_vary() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = |cache: ResponseCache| {
+            let mut app = Choko::new("test");
+            let counter = Arc::clone(&calls);
+            app.get("/me", move |_req| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Ok(Response::text("page")) }
+            })
+            .middleware(cache.clone());
+            let counter = Arc::clone(&calls);
+            app.get("/negotiated", move |_req| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Ok(Response::text("page").with_header("Vary", "Accept")) }
+            })
+            .middleware(cache);
+            app
+        };
+        let with_cookie = || {
+            let mut event = get("/me", "");
+            event
+                .headers
+                .insert("cookie", "session=alice".parse().unwrap());
+            event
+        };
+
+        let plain = app(ResponseCache::new(
+            MemoryCache::new(),
+            Duration::from_secs(60),
+        ));
+        for _ in 0..2 {
+            let resp = plain.dispatch(with_cookie()).await.unwrap();
+            assert!(resp.headers.get("x-cache").is_none());
+            plain.dispatch(get("/negotiated", "")).await.unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        let keyed = app(
+            ResponseCache::new(MemoryCache::new(), Duration::from_secs(60))
+                .vary("cookie")
+                .vary("accept"),
+        );
+        for _ in 0..2 {
+            keyed.dispatch(with_cookie()).await.unwrap();
+            keyed.dispatch(get("/negotiated", "")).await.unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn keys_by_vary_headers_and_skips_authorized_requests() {
+        let cache =
+            ResponseCache::new(MemoryCache::new(), Duration::from_secs(60)).vary("Accept-Language");
+        let mut en = Request::default();
+        en.headers
+            .insert("accept-language".to_string(), "en".to_string());
+        let mut ja = Request::default();
+        ja.headers
+            .insert("accept-language".to_string(), "ja".to_string());
+        assert_ne!(cache.key(&en), cache.key(&ja));
+
+        let mut authorized = Request::default();
+        authorized
+            .headers
+            .insert("authorization".to_string(), "Bearer x".to_string());
+        assert!(!cache.cacheable(&authorized));
+    }
+
+    #[tokio::test]
+    async fn bypasses_api_key_requests() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut app = Choko::new("test");
+        let counter = Arc::clone(&calls);
+        app.get("/usage", move |req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let key = req.header("x-api-key").unwrap_or("anonymous").to_string();
+            async move { Ok(Response::text(key)) }
+        })
+        .middleware(
+            ResponseCache::new(MemoryCache::new(), Duration::from_secs(60))
+                .credential_header("X-Tenant-Token"),
+        );
+        let with_header = |name: &'static str, value: &str| {
+            let mut event = get("/usage", "");
+            event.headers.insert(name, value.parse().unwrap());
+            event
+        };
+
+        for key in ["key-a", "key-b"] {
+            let resp = app.dispatch(with_header("x-api-key", key)).await.unwrap();
+            assert!(resp.headers.get("x-cache").is_none());
+            assert_eq!(resp.body, Some(crate::Body::Text(key.to_string())));
+        }
+        app.dispatch(with_header("x-tenant-token", "t1"))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Unauthenticated callers never see a keyed response
+        let anonymous = app.dispatch(get("/usage", "")).await.unwrap();
+        assert_eq!(anonymous.headers.get("x-cache").unwrap(), "MISS");
+        assert_eq!(
+            anonymous.body,
+            Some(crate::Body::Text("anonymous".to_string()))
+        );
+    }
+}
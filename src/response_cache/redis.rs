@@ -0,0 +1,78 @@
+//! Response cache shared through Redis or ElastiCache (`redis` feature).
+
+use super::ResponseCacheStore;
+use crate::{BoxFuture, Error};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::time::Duration;
+
+/// Responses kept in Redis, shared by every Lambda instance.
+///
+/// Entries are stored with `SET ... EX`; each tag is a set of the keys
+/// labelled with it, expiring along with them. Connect to ElastiCache with
+/// in-transit encryption through a `rediss://` URL; the function needs to
+/// run in the cluster's VPC.
+#[derive(Clone)]
+pub struct RedisCache {
+    connection: ConnectionManager,
+}
+
+impl RedisCache {
+    /// Use an existing connection.
+    pub fn new(connection: ConnectionManager) -> Self {
+        Self { connection }
+    }
+
+    /// Connect to `url`, e.g. `redis://cache.abc.euw1.cache.amazonaws.com:6379`.
+    /// The connection is reestablished automatically if it drops.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let client = redis::Client::open(url)?;
+        Ok(Self::new(ConnectionManager::new(client).await?))
+    }
+}
+
+impl ResponseCacheStore for RedisCache {
+    fn get(&self, key: &str) -> BoxFuture<Result<Option<Vec<u8>>, Error>> {
+        let mut connection = self.connection.clone();
+        let key = key.to_string();
+        Box::pin(async move {
+            let value: Option<Vec<u8>> = connection.get(&key).await?;
+            Ok(value)
+        })
+    }
+
+    fn put(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Duration,
+        tags: Vec<String>,
+    ) -> BoxFuture<Result<(), Error>> {
+        let mut connection = self.connection.clone();
+        let key = key.to_string();
+        let seconds = ttl.as_secs().max(1);
+        Box::pin(async move {
+            let mut pipe = redis::pipe();
+            pipe.atomic().set_ex(&key, value, seconds).ignore();
+            for tag in &tags {
+                pipe.sadd(tag, &key)
+                    .ignore()
+                    .expire(tag, seconds as i64)
+                    .ignore();
+            }
+            let () = pipe.query_async(&mut connection).await?;
+            Ok(())
+        })
+    }
+
+    fn invalidate_tag(&self, tag: &str) -> BoxFuture<Result<(), Error>> {
+        let mut connection = self.connection.clone();
+        let tag = tag.to_string();
+        Box::pin(async move {
+            let mut keys: Vec<String> = connection.smembers(&tag).await?;
+            keys.push(tag);
+            let () = connection.del(keys).await?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,268 @@
+//! S3 helpers (`s3` feature).
+//!
+//! [`Bucket`] wraps an `aws_sdk_s3::Client` for the things handlers
+//! usually need: presigned GET and PUT URLs for direct client transfers,
+//! objects served as (streamed) responses, and request bodies uploaded as
+//! objects. Create the bucket once when the function starts and clone it
+//! into handlers, so the client is reused across invocations.
+//!
+//! # Example
+//! ```ignore
+//! use choko::s3::Bucket;
+//!
+//! let client = aws_sdk_s3::Client::new(&aws_config::load_from_env().await);
+//! let uploads = Bucket::new(client, "uploads");
+//!
+//! let bucket = uploads.clone();
+//! app.get("/files/{name}", move |req| {
+//!     let bucket = bucket.clone();
+//!     async move { bucket.object_response(&req.path_params["name"]).await }
+//! });
+//! let bucket = uploads.clone();
+//! app.post("/files/{name}/upload-url", move |req| {
+//!     let bucket = bucket.clone();
+//!     async move {
+//!         let url = bucket
+//!             .presign_put(&req.path_params["name"], Some("image/png"), Duration::from_secs(300))
+//!             .await?;
+//!         Ok(Response::json(json!({ "url": url })))
+//!     }
+//! });
+//! ```
+
+use crate::{BodyStream, ChokoError, Error, Request, Response};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use std::time::Duration;
+
+/// Chunks buffered between S3 and a streamed response.
+const STREAM_BUFFER: usize = 8;
+
+/// An S3 bucket.
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    client: aws_sdk_s3::Client,
+    name: String,
+    #[cfg(feature = "s3-uploads")]
+    pub(crate) credentials: Option<aws_credential_types::provider::SharedCredentialsProvider>,
+}
+
+impl Bucket {
+    pub fn new(client: aws_sdk_s3::Client, name: impl Into<String>) -> Self {
+        Self {
+            client,
+            name: name.into(),
+            #[cfg(feature = "s3-uploads")]
+            credentials: None,
+        }
+    }
+
+    /// The credentials presigned POST policies are signed with, usually
+    /// the ones the client was built from
+    /// (`sdk_config.credentials_provider()`).
+    #[cfg(feature = "s3-uploads")]
+    pub fn credentials(
+        mut self,
+        provider: impl aws_credential_types::provider::ProvideCredentials + 'static,
+    ) -> Self {
+        self.credentials =
+            Some(aws_credential_types::provider::SharedCredentialsProvider::new(provider));
+        self
+    }
+
+    /// The underlying client, for operations not covered here.
+    pub fn client(&self) -> &aws_sdk_s3::Client {
+        &self.client
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A URL that downloads `key` without credentials until `expires_in`
+    /// has passed.
+    pub async fn presign_get(&self, key: &str, expires_in: Duration) -> Result<String, Error> {
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.name)
+            .key(key)
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+        Ok(presigned.uri().to_string())
+    }
+
+    /// A URL that uploads `key` with a `PUT` without credentials until
+    /// `expires_in` has passed. With a `content_type`, the upload must send
+    /// that `Content-Type`.
+    pub async fn presign_put(
+        &self,
+        key: &str,
+        content_type: Option<&str>,
+        expires_in: Duration,
+    ) -> Result<String, Error> {
+        let presigned = self
+            .client
+            .put_object()
+            .bucket(&self.name)
+            .key(key)
+            .set_content_type(content_type.map(str::to_string))
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+        Ok(presigned.uri().to_string())
+    }
+
+    /// The contents of `key`, or `None` if it doesn't exist.
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self
+            .client
+            .get_object()
+            .bucket(&self.name)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(object) => Ok(Some(object.body.collect().await?.into_bytes().to_vec())),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Serve `key` as a response, streaming the body from S3 with its
+    /// content type, `ETag` and `Last-Modified`. A missing object
+    /// is a 404.
+    pub async fn object_response(&self, key: &str) -> Result<Response, Error> {
+        let object = match self
+            .client
+            .get_object()
+            .bucket(&self.name)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => {
+                return Err(ChokoError::not_found(key).into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let (tx, body) = BodyStream::channel(STREAM_BUFFER);
+        let mut stream = object.body;
+        tokio::spawn(async move {
+            loop {
+                match stream.try_next().await {
+                    Ok(Some(chunk)) => {
+                        if tx.send(chunk.to_vec()).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => return,
+                    Err(e) => return tx.abort(e).await,
+                }
+            }
+        });
+        let mut resp = Response::stream(body).with_header(
+            "content-type",
+            object
+                .content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+        );
+        if let Some(etag) = object.e_tag {
+            resp = resp.with_header("etag", etag);
+        }
+        if let Some(modified) = object.last_modified {
+            let modified = std::time::SystemTime::try_from(modified)?;
+            resp = resp.with_header("last-modified", httpdate::fmt_http_date(modified));
+        }
+        Ok(resp)
+    }
+
+    /// Upload `body` to `key`.
+    pub async fn put(
+        &self,
+        key: &str,
+        body: impl Into<Vec<u8>>,
+        content_type: Option<&str>,
+    ) -> Result<(), Error> {
+        self.client
+            .put_object()
+            .bucket(&self.name)
+            .key(key)
+            .body(ByteStream::from(body.into()))
+            .set_content_type(content_type.map(str::to_string))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Upload the body of `req` to `key` with the request's
+    /// `Content-Type`. An empty body is a 400.
+    pub async fn put_request_body(&self, key: &str, req: &Request) -> Result<(), Error> {
+        let body = req
+            .body_bytes()
+            .filter(|body| !body.is_empty())
+            .ok_or_else(|| ChokoError::bad_request("Request body is empty"))?;
+        self.put(key, body, req.header("content-type")).await
+    }
+
+    /// Delete `key`. Deleting a missing object is not an error.
+    pub async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.name)
+            .key(key)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+
+    fn bucket() -> Bucket {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new(
+                "AKIDEXAMPLE",
+                "secret",
+                None,
+                None,
+                "test",
+            ))
+            .build();
+        Bucket::new(aws_sdk_s3::Client::from_conf(config), "uploads")
+    }
+
+    #[tokio::test]
+    async fn presigns_get_and_put_urls() {
+        let bucket = bucket();
+        let url = bucket
+            .presign_get("reports/may.pdf", Duration::from_secs(300))
+            .await
+            .unwrap();
+        assert!(url.starts_with("https://uploads.s3.eu-west-1.amazonaws.com/reports/may.pdf?"));
+        assert!(url.contains("X-Amz-Expires=300"));
+        assert!(url.contains("X-Amz-Signature="));
+
+        let url = bucket
+            .presign_put("avatars/1.png", Some("image/png"), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(url.contains("/avatars/1.png?"));
+        assert!(url.contains("X-Amz-SignedHeaders=content-type%3Bhost"));
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_uploads() {
+        let req = Request::default();
+        let err = bucket().put_request_body("x", &req).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ChokoError>(),
+            Some(ChokoError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+//! S3 event notification handlers (`s3-events` feature).
+//!
+//! [`Choko::s3_bucket`] registers a handler for the object notifications
+//! of one bucket, and [`Choko::run_s3_events`] starts the app as the
+//! bucket's notification target.
+//!
+//! # Example
+//! ```ignore
+//! use choko::s3_events::ObjectEvent;
+//!
+//! app.s3_bucket("uploads", |event: ObjectEvent| async move {
+//!     if event.is_created() {
+//!         thumbnail(&event.bucket, &event.key).await?;
+//!     }
+//!     Ok(())
+//! });
+//! app.run_s3_events().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type BucketHandlerFn =
+    Arc<dyn Fn(ObjectEvent) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// An S3 notification invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct S3Event {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<S3EventRecord>,
+}
+
+/// One notification record as delivered by S3.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3EventRecord {
+    /// e.g. `ObjectCreated:Put` or `ObjectRemoved:Delete`.
+    pub event_name: String,
+    /// RFC 3339.
+    #[serde(default)]
+    pub event_time: String,
+    pub s3: S3Entity,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct S3Entity {
+    pub bucket: S3Bucket,
+    pub object: S3Object,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct S3Bucket {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Object {
+    /// URL-encoded, as S3 delivers it.
+    pub key: String,
+    pub size: Option<u64>,
+    pub e_tag: Option<String>,
+    pub version_id: Option<String>,
+    pub sequencer: Option<String>,
+}
+
+/// A change to an object.
+#[derive(Debug, Clone)]
+pub struct ObjectEvent {
+    /// e.g. `ObjectCreated:Put` or `ObjectRemoved:Delete`.
+    pub event_name: String,
+    /// RFC 3339.
+    pub event_time: String,
+    pub bucket: String,
+    /// The object key, URL-decoded.
+    pub key: String,
+    pub size: Option<u64>,
+    pub etag: Option<String>,
+    pub version_id: Option<String>,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl ObjectEvent {
+    /// Whether the object was created or overwritten.
+    pub fn is_created(&self) -> bool {
+        self.event_name.starts_with("ObjectCreated:")
+    }
+
+    /// Whether the object was deleted.
+    pub fn is_removed(&self) -> bool {
+        self.event_name.starts_with("ObjectRemoved:")
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    fn from_record(record: S3EventRecord, lambda_context: Option<LambdaContext>) -> Self {
+        let object = record.s3.object;
+        Self {
+            event_name: record.event_name,
+            event_time: record.event_time,
+            bucket: record.s3.bucket.name,
+            key: crate::cookie::decode_value(&object.key.replace('+', " ")),
+            size: object.size,
+            etag: object.e_tag,
+            version_id: object.version_id,
+            lambda_context,
+        }
+    }
+}
+
+impl Choko {
+    /// Handle the object notifications of the bucket `bucket`.
+    pub fn s3_bucket<F, Fut>(&mut self, bucket: &str, handler: F) -> &mut Self
+    where
+        F: Fn(ObjectEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.s3_buckets.insert(
+            bucket.to_string(),
+            Arc::new(move |event| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(event).await })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the notification target of its buckets.
+    ///
+    /// A failed record fails the invocation, so Lambda's asynchronous
+    /// retries (and on-failure destination) apply.
+    pub async fn run_s3_events(self) -> Result<(), Error> {
+        self.serve(|app, event: S3Event, context| async move {
+            app.dispatch_s3(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_s3(
+        &self,
+        event: S3Event,
+        context: Option<LambdaContext>,
+    ) -> Result<(), Error> {
+        for record in event.records {
+            let event = ObjectEvent::from_record(record, context.clone());
+            let handler = self
+                .s3_buckets
+                .get(&event.bucket)
+                .cloned()
+                .ok_or_else(|| format!("no handler for bucket {:?}", event.bucket))?;
+            handler(event).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn decodes_keys_and_routes_by_bucket() {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&keys);
+        let mut app = Choko::new("test");
+        app.s3_bucket("uploads", move |event: ObjectEvent| {
+            let log = Arc::clone(&log);
+            async move {
+                assert!(event.is_created());
+                log.lock().unwrap().push(event.key);
+                Ok(())
+            }
+        });
+
+        let event: S3Event = serde_json::from_value(serde_json::json!({
+            "Records": [{
+                "eventVersion": "2.1",
+                "eventSource": "aws:s3",
+                "eventTime": "2024-05-01T12:00:00.000Z",
+                "eventName": "ObjectCreated:Put",
+                "s3": {
+                    "bucket": { "name": "uploads", "arn": "arn:aws:s3:::uploads" },
+                    "object": { "key": "photos/my+cat%281%29.jpg", "size": 1024 }
+                }
+            }]
+        }))
+        .unwrap();
+        app.dispatch_s3(event.clone(), None).await.unwrap();
+        assert_eq!(*keys.lock().unwrap(), ["photos/my cat(1).jpg"]);
+
+        let mut other = event;
+        other.records[0].s3.bucket.name = "other".to_string();
+        assert!(app.dispatch_s3(other, None).await.is_err());
+    }
+}
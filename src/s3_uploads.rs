@@ -0,0 +1,617 @@
+//! Direct-to-S3 uploads with presigned POST policies (`s3-uploads`
+//! feature).
+//!
+//! Large files shouldn't pass through Lambda: API Gateway caps payloads at
+//! 10 MB and the function pays for every second of the transfer. Instead
+//! the client asks for a presigned POST, uploads the file straight to S3
+//! with an HTML form-style POST, then tells the API the upload is done.
+//!
+//! [`Bucket::presign_post`] signs a POST policy by hand, since the SDK
+//! doesn't: S3 itself enforces the key, content type and size limits in
+//! the policy. [`Uploads`] builds the usual flow on top of it:
+//!
+//! 1. [`Uploads::presign_handler`] gives each caller a policy for a fresh
+//!    key under their own prefix, `{prefix}{owner}/{id}`;
+//! 2. the client POSTs the file to `url` with `fields`;
+//! 3. [`Uploads::complete_handler`] checks the key belongs to the caller,
+//!    that the object exists and is within the limits, and passes it to
+//!    your handler to record.
+//!
+//! # Example
+//! ```ignore
+//! use choko::s3::Bucket;
+//! use choko::s3_uploads::Uploads;
+//!
+//! let aws = aws_config::load_from_env().await;
+//! let bucket = Bucket::new(aws_sdk_s3::Client::new(&aws), "user-uploads")
+//!     .credentials(aws.credentials_provider().ok_or("no AWS credentials")?);
+//! let uploads = Uploads::new(bucket)
+//!     .content_types(&["image/png", "image/jpeg"])
+//!     .max_size(20 * 1024 * 1024);
+//!
+//! // {"content_type": "image/png"} -> {"key", "url", "fields", "expires_at"}
+//! app.post("/uploads", uploads.presign_handler());
+//! // {"key": "..."} -> your handler, with the validated object
+//! app.post(
+//!     "/uploads/complete",
+//!     uploads.complete_handler(|req, object| async move {
+//!         photos::insert(&req, &object.key, object.size).await?;
+//!         Ok(Response::created(format!("/photos/{}", object.key), json!(object)))
+//!     }),
+//! );
+//! ```
+
+use crate::s3::Bucket;
+use crate::{BoxFuture, ChokoError, Error, Request, Response};
+use aws_credential_types::provider::ProvideCredentials;
+use aws_credential_types::Credentials;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// A POST policy being built by [`Bucket::presign_post`].
+pub struct PostPolicy<'a> {
+    bucket: &'a Bucket,
+    key: String,
+    key_is_prefix: bool,
+    content_type: Option<(String, bool)>,
+    size: Option<(u64, u64)>,
+    expires_in: Duration,
+}
+
+/// A signed POST policy: the client sends a `multipart/form-data` POST to
+/// `url` with `fields`, then the file as the last field, named `file`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PresignedPost {
+    pub url: String,
+    pub fields: BTreeMap<String, String>,
+    /// When the policy expires, in RFC 3339.
+    pub expires_at: String,
+}
+
+impl Bucket {
+    /// Start a presigned POST policy allowing an upload to `key`, valid for
+    /// five minutes.
+    pub fn presign_post(&self, key: impl Into<String>) -> PostPolicy<'_> {
+        PostPolicy {
+            bucket: self,
+            key: key.into(),
+            key_is_prefix: false,
+            content_type: None,
+            size: None,
+            expires_in: Duration::from_secs(300),
+        }
+    }
+}
+
+impl PostPolicy<'_> {
+    /// Allow any key starting with the policy's key, chosen by the client
+    /// in the `key` field.
+    pub fn key_starts_with(mut self) -> Self {
+        self.key_is_prefix = true;
+        self
+    }
+
+    /// Require this `Content-Type`.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some((content_type.into(), false));
+        self
+    }
+
+    /// Require a `Content-Type` starting with `prefix`, e.g. `image/`.
+    pub fn content_type_starts_with(mut self, prefix: impl Into<String>) -> Self {
+        self.content_type = Some((prefix.into(), true));
+        self
+    }
+
+    /// Require the file to be `min` to `max` bytes.
+    pub fn size(mut self, min: u64, max: u64) -> Self {
+        self.size = Some((min, max));
+        self
+    }
+
+    /// How long the policy can be used (at most 7 days).
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires_in = duration;
+        self
+    }
+
+    /// Sign the policy with the bucket's credentials (see
+    /// [`Bucket::credentials`]) for the client's region.
+    pub async fn sign(self) -> Result<PresignedPost, Error> {
+        let region = self
+            .bucket
+            .client()
+            .config()
+            .region()
+            .ok_or("the S3 client has no region")?
+            .to_string();
+        let provider = self.bucket.credentials.as_ref().ok_or(
+            "the bucket has no credentials to sign with; set them with Bucket::credentials",
+        )?;
+        let credentials = provider.provide_credentials().await?;
+        self.sign_with(&region, &credentials, SystemTime::now())
+    }
+
+    fn sign_with(
+        self,
+        region: &str,
+        credentials: &Credentials,
+        now: SystemTime,
+    ) -> Result<PresignedPost, Error> {
+        if self.expires_in > Duration::from_secs(7 * 24 * 60 * 60) {
+            return Err("presigned POST policies expire within 7 days".into());
+        }
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let timestamp = DateTime::from_secs(now_secs as i64).fmt(DateTimeFormat::DateTime)?;
+        let expires_at = DateTime::from_secs((now_secs + self.expires_in.as_secs()) as i64)
+            .fmt(DateTimeFormat::DateTime)?;
+        // 2024-05-01T12:00:00Z -> 20240501T120000Z
+        let amz_date: String = timestamp
+            .chars()
+            .filter(|c| *c != '-' && *c != ':')
+            .collect();
+        let date = &amz_date[..8];
+        let credential = format!(
+            "{}/{date}/{region}/s3/aws4_request",
+            credentials.access_key_id()
+        );
+
+        let mut fields = BTreeMap::new();
+        let mut conditions = vec![json!({ "bucket": self.bucket.name() })];
+        if self.key_is_prefix {
+            conditions.push(json!(["starts-with", "$key", self.key]));
+        } else {
+            conditions.push(json!({ "key": self.key }));
+            fields.insert("key".to_string(), self.key);
+        }
+        match self.content_type {
+            Some((prefix, true)) => {
+                conditions.push(json!(["starts-with", "$Content-Type", prefix]));
+            }
+            Some((content_type, false)) => {
+                conditions.push(json!({ "Content-Type": content_type }));
+                fields.insert("Content-Type".to_string(), content_type);
+            }
+            None => {}
+        }
+        if let Some((min, max)) = self.size {
+            conditions.push(json!(["content-length-range", min, max]));
+        }
+        fields.insert("x-amz-algorithm".to_string(), ALGORITHM.to_string());
+        fields.insert("x-amz-credential".to_string(), credential);
+        fields.insert("x-amz-date".to_string(), amz_date.clone());
+        if let Some(token) = credentials.session_token() {
+            fields.insert("x-amz-security-token".to_string(), token.to_string());
+        }
+        for name in [
+            "x-amz-algorithm",
+            "x-amz-credential",
+            "x-amz-date",
+            "x-amz-security-token",
+        ] {
+            if let Some(value) = fields.get(name) {
+                let condition = [(name.to_string(), Value::String(value.clone()))];
+                conditions.push(Value::Object(condition.into_iter().collect()));
+            }
+        }
+
+        let policy = json!({ "expiration": expires_at, "conditions": conditions });
+        let policy = base64::engine::general_purpose::STANDARD.encode(policy.to_string());
+        let key = signing_key(credentials.secret_access_key(), date, region, "s3");
+        let signature = hex::encode(hmac(&key, &policy));
+        fields.insert("policy".to_string(), policy);
+        fields.insert("x-amz-signature".to_string(), signature);
+
+        let name = self.bucket.name();
+        // Dotted bucket names don't match S3's wildcard certificate
+        let url = if name.contains('.') {
+            format!("https://s3.{region}.amazonaws.com/{name}")
+        } else {
+            format!("https://{name}.s3.{region}.amazonaws.com/")
+        };
+        Ok(PresignedPost {
+            url,
+            fields,
+            expires_at,
+        })
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The SigV4 signing key for `date` (YYYYMMDD), `region` and `service`.
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{secret}").as_bytes(), date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    hmac(&key, "aws4_request")
+}
+
+type OwnerFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// An upload checked by [`Uploads::complete`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UploadedObject {
+    pub key: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// Per-user direct uploads into a bucket. Cheap to clone.
+///
+/// Callers are identified by the `sub` claim of the API Gateway authorizer
+/// unless [`owner`](Self::owner) says otherwise; requests without an owner
+/// get 401.
+#[derive(Clone)]
+pub struct Uploads {
+    bucket: Bucket,
+    prefix: String,
+    content_types: Vec<String>,
+    max_size: u64,
+    expires_in: Duration,
+    owner: OwnerFn,
+}
+
+impl Uploads {
+    /// Uploads into `bucket` under `uploads/`, of any content type up to
+    /// 10 MiB, with policies valid for five minutes.
+    pub fn new(bucket: Bucket) -> Self {
+        Self {
+            bucket,
+            prefix: "uploads/".to_string(),
+            content_types: Vec::new(),
+            max_size: 10 * 1024 * 1024,
+            expires_in: Duration::from_secs(300),
+            owner: Arc::new(|req| {
+                req.request_context
+                    .claims()
+                    .and_then(|claims| claims.get("sub"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            }),
+        }
+    }
+
+    /// The key prefix, before the owner's directory.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The accepted content types; a type ending in `/*` (e.g. `image/*`)
+    /// accepts its whole family. Empty accepts any.
+    pub fn content_types(mut self, types: &[&str]) -> Self {
+        self.content_types = types.iter().map(|t| t.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// The largest accepted file, in bytes.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// How long a presigned POST can be used.
+    pub fn expires_in(mut self, duration: Duration) -> Self {
+        self.expires_in = duration;
+        self
+    }
+
+    /// Identify the caller with `owner` instead of the `sub` claim.
+    pub fn owner<F>(mut self, owner: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.owner = Arc::new(owner);
+        self
+    }
+
+    /// The caller's key prefix, `{prefix}{owner}/`. Owners that aren't
+    /// safe in a key are hashed.
+    fn owner_prefix(&self, req: &Request) -> Result<String, ChokoError> {
+        let owner = (self.owner)(req)
+            .filter(|owner| !owner.is_empty())
+            .ok_or_else(|| ChokoError::unauthorized("Unauthorized"))?;
+        let safe = owner.len() <= 128
+            && owner
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.@".contains(&b))
+            && owner != "."
+            && owner != "..";
+        let owner = if safe {
+            owner
+        } else {
+            hex::encode(Sha256::digest(owner.as_bytes()))
+        };
+        Ok(format!("{}{owner}/", self.prefix))
+    }
+
+    fn accepts(&self, content_type: &str) -> bool {
+        let content_type = content_type.to_ascii_lowercase();
+        self.content_types.is_empty()
+            || self
+                .content_types
+                .iter()
+                .any(|t| match t.strip_suffix("/*") {
+                    Some(family) => content_type.split('/').next() == Some(family),
+                    None => *t == content_type,
+                })
+    }
+
+    /// Presign an upload of a `content_type` file to a new key under the
+    /// caller's prefix. Returns the key and the policy.
+    pub async fn presign(
+        &self,
+        req: &Request,
+        content_type: &str,
+    ) -> Result<(String, PresignedPost), Error> {
+        let prefix = self.owner_prefix(req)?;
+        if !self.accepts(content_type) {
+            return Err(ChokoError::status(
+                415,
+                format!("{content_type} uploads are not accepted"),
+            )
+            .into());
+        }
+        let key = format!("{prefix}{}", upload_id(req));
+        let post = self
+            .bucket
+            .presign_post(key.as_str())
+            .content_type(content_type)
+            .size(1, self.max_size)
+            .expires_in(self.expires_in)
+            .sign()
+            .await?;
+        Ok((key, post))
+    }
+
+    /// Check a finished upload: `key` must be under the caller's prefix,
+    /// and the object must exist and meet the limits. Objects over the
+    /// limits are deleted.
+    pub async fn complete(&self, req: &Request, key: &str) -> Result<UploadedObject, Error> {
+        let prefix = self.owner_prefix(req)?;
+        if !key.starts_with(&prefix) || key.contains("..") {
+            return Err(ChokoError::status(403, "Forbidden").into());
+        }
+        let head = match self
+            .bucket
+            .client()
+            .head_object()
+            .bucket(self.bucket.name())
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(head) => head,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                return Err(ChokoError::not_found("upload").into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let object = UploadedObject {
+            key: key.to_string(),
+            size: head.content_length().unwrap_or(0).max(0) as u64,
+            content_type: head.content_type().map(str::to_string),
+            etag: head.e_tag().map(str::to_string),
+        };
+        let valid = object.size > 0
+            && object.size <= self.max_size
+            && object
+                .content_type
+                .as_deref()
+                .is_some_and(|t| self.accepts(t));
+        if !valid {
+            self.bucket.delete(key).await?;
+            return Err(ChokoError::status(422, "upload does not meet the limits").into());
+        }
+        Ok(object)
+    }
+
+    /// A handler answering `{"content_type": "..."}` with the key and
+    /// presigned POST for a new upload.
+    pub fn presign_handler(
+        &self,
+    ) -> impl Fn(Request) -> BoxFuture<Result<Response, Error>> + Send + Sync + 'static {
+        let uploads = self.clone();
+        move |req| {
+            let uploads = uploads.clone();
+            Box::pin(async move {
+                let content_type = req
+                    .json_body
+                    .as_ref()
+                    .and_then(|body| body.get("content_type"))
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| ChokoError::bad_request("content_type is required"))?;
+                let (key, post) = uploads.presign(&req, content_type).await?;
+                Ok(Response::json(json!({
+                    "key": key,
+                    "url": post.url,
+                    "fields": post.fields,
+                    "expires_at": post.expires_at,
+                })))
+            })
+        }
+    }
+
+    /// A handler checking the upload in `{"key": "..."}` with
+    /// [`complete`](Self::complete), then passing it to `on_complete`.
+    pub fn complete_handler<F, Fut>(
+        &self,
+        on_complete: F,
+    ) -> impl Fn(Request) -> BoxFuture<Result<Response, Error>> + Send + Sync + 'static
+    where
+        F: Fn(Request, UploadedObject) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+    {
+        let uploads = self.clone();
+        let on_complete = Arc::new(on_complete);
+        move |req| {
+            let uploads = uploads.clone();
+            let on_complete = Arc::clone(&on_complete);
+            Box::pin(async move {
+                let key = req
+                    .json_body
+                    .as_ref()
+                    .and_then(|body| body.get("key"))
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| ChokoError::bad_request("key is required"))?
+                    .to_string();
+                let object = uploads.complete(&req, &key).await?;
+                on_complete(req, object).await
+            })
+        }
+    }
+}
+
+/// A new object name: the time, then random bits.
+fn upload_id(req: &Request) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write(req.request_id().unwrap_or_default().as_bytes());
+    hasher.write_u128(millis);
+    format!("{millis:x}-{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Region};
+
+    fn bucket(name: &str) -> Bucket {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .build();
+        Bucket::new(aws_sdk_s3::Client::from_conf(config), name).credentials(Credentials::new(
+            "AKIDEXAMPLE",
+            "secret",
+            Some("token".to_string()),
+            None,
+            "test",
+        ))
+    }
+
+    fn request(sub: Option<&str>) -> Request {
+        let mut req = Request::default();
+        if let Some(sub) = sub {
+            req.request_context
+                .authorizer
+                .insert("claims".to_string(), json!({ "sub": sub }));
+        }
+        req
+    }
+
+    #[test]
+    fn derives_the_signing_key() {
+        // From the AWS Signature Version 4 documentation
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_post_policies() {
+        let photos = bucket("uploads");
+        let post = photos
+            .presign_post("uploads/u1/")
+            .key_starts_with()
+            .content_type_starts_with("image/")
+            .size(1, 1024)
+            .sign()
+            .await
+            .unwrap();
+        assert_eq!(post.url, "https://uploads.s3.eu-west-1.amazonaws.com/");
+        assert!(!post.fields.contains_key("key"));
+        assert_eq!(post.fields["x-amz-security-token"], "token");
+        assert!(post.fields["x-amz-credential"].starts_with("AKIDEXAMPLE/"));
+        assert!(post.fields["x-amz-credential"].ends_with("/eu-west-1/s3/aws4_request"));
+
+        let policy = base64::engine::general_purpose::STANDARD
+            .decode(&post.fields["policy"])
+            .unwrap();
+        let policy: Value = serde_json::from_slice(&policy).unwrap();
+        assert_eq!(policy["expiration"], post.expires_at);
+        let conditions = policy["conditions"].as_array().unwrap();
+        assert!(conditions.contains(&json!(["starts-with", "$key", "uploads/u1/"])));
+        assert!(conditions.contains(&json!(["starts-with", "$Content-Type", "image/"])));
+        assert!(conditions.contains(&json!(["content-length-range", 1, 1024])));
+        assert!(conditions.contains(&json!({ "x-amz-security-token": "token" })));
+
+        let post = photos
+            .presign_post("a")
+            .expires_in(Duration::from_secs(8 * 24 * 60 * 60))
+            .sign()
+            .await;
+        assert!(post.is_err());
+        let post = bucket("my.uploads").presign_post("a").sign().await.unwrap();
+        assert_eq!(post.url, "https://s3.eu-west-1.amazonaws.com/my.uploads");
+        assert_eq!(post.fields["key"], "a");
+    }
+
+    #[tokio::test]
+    async fn presigns_per_user_keys() {
+        let uploads = Uploads::new(bucket("uploads")).content_types(&["image/*"]);
+        let (key, post) = uploads
+            .presign(&request(Some("user-1")), "image/png")
+            .await
+            .unwrap();
+        assert!(key.starts_with("uploads/user-1/"));
+        assert_eq!(post.fields["key"], key);
+        assert_eq!(post.fields["Content-Type"], "image/png");
+
+        let err = uploads
+            .presign(&request(Some("user-1")), "text/html")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ChokoError>().unwrap().status_code(), 415);
+        let err = uploads
+            .presign(&request(None), "image/png")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ChokoError>().unwrap().status_code(), 401);
+
+        let (key, _) = uploads
+            .presign(&request(Some("../admin")), "image/png")
+            .await
+            .unwrap();
+        assert!(!key.contains(".."));
+    }
+
+    #[tokio::test]
+    async fn completion_requires_the_owners_prefix() {
+        let uploads = Uploads::new(bucket("uploads"));
+        let err = uploads
+            .complete(&request(Some("user-1")), "uploads/user-2/x")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ChokoError>().unwrap().status_code(), 403);
+    }
+}
@@ -0,0 +1,270 @@
+//! Scheduled tasks (`schedule` feature).
+//!
+//! [`Choko::schedule`] registers a task handler under a `rate(...)` or
+//! `cron(...)` expression, and [`Choko::run_schedule`] starts the app as
+//! the target of the matching EventBridge rules. Invocations are routed by
+//! rule name: a rule named after the task (or ending in `-<task>`, for
+//! prefixed names) runs that task, so one binary can serve several
+//! housekeeping jobs.
+//!
+//! # Example
+//! ```ignore
+//! app.schedule("rate(5 minutes)", |_event| async move {
+//!     purge_expired_sessions().await
+//! })
+//! .name("purge-sessions");
+//! app.schedule("cron(0 3 * * ? *)", |_event| async move { rotate_keys().await })
+//!     .name("rotate-keys");
+//! app.run_schedule().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+
+type TaskFn = Arc<dyn Fn(ScheduledEvent) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// An EventBridge scheduled event.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScheduledEvent {
+    pub id: String,
+    #[serde(rename = "detail-type")]
+    pub detail_type: String,
+    pub account: String,
+    pub region: String,
+    /// When the rule fired, RFC 3339.
+    pub time: String,
+    /// The ARN of the rule that fired.
+    #[serde(default)]
+    pub resources: Vec<String>,
+    #[serde(skip)]
+    lambda_context: Option<LambdaContext>,
+}
+
+impl ScheduledEvent {
+    /// The name of the rule that fired.
+    pub fn rule_name(&self) -> Option<&str> {
+        self.resources
+            .iter()
+            .find_map(|arn| arn.split_once(":rule/"))
+            .map(|(_, name)| name.rsplit('/').next().unwrap_or(name))
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+}
+
+/// A task registered with [`Choko::schedule`].
+pub struct ScheduledTask {
+    name: String,
+    expression: String,
+    handler: TaskFn,
+}
+
+impl ScheduledTask {
+    /// Name the task, i.e. the rule name it is invoked by. Defaults to the
+    /// expression with its punctuation replaced by `-`, e.g.
+    /// `rate-5-minutes`.
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// The task name.
+    pub fn task_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The schedule expression, for creating the rule.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn handles(&self, rule: &str) -> bool {
+        rule == self.name
+            || rule
+                .strip_suffix(self.name.as_str())
+                .is_some_and(|prefix| prefix.ends_with('-'))
+    }
+}
+
+/// Check `expression` against EventBridge's `rate(value unit)` and
+/// six-field `cron(...)` syntax.
+fn validate(expression: &str) -> Result<(), String> {
+    if let Some(rate) = expression
+        .strip_prefix("rate(")
+        .and_then(|e| e.strip_suffix(')'))
+    {
+        let (value, unit) = rate
+            .split_once(' ')
+            .ok_or("rate needs a value and a unit")?;
+        let value: u32 = value
+            .parse()
+            .map_err(|_| format!("invalid rate value {value:?}"))?;
+        let singular = match unit {
+            "minute" | "hour" | "day" => true,
+            "minutes" | "hours" | "days" => false,
+            _ => return Err(format!("invalid rate unit {unit:?}")),
+        };
+        return match value {
+            0 => Err("rate value must be positive".to_string()),
+            1 if !singular => Err(format!("use rate(1 {})", unit.trim_end_matches('s'))),
+            2.. if singular => Err(format!("use rate({value} {unit}s)")),
+            _ => Ok(()),
+        };
+    }
+    if let Some(cron) = expression
+        .strip_prefix("cron(")
+        .and_then(|e| e.strip_suffix(')'))
+    {
+        let fields: Vec<&str> = cron.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(format!("cron needs 6 fields, got {}", fields.len()));
+        }
+        if (fields[2] == "?") == (fields[4] == "?") {
+            return Err("exactly one of day-of-month and day-of-week must be ?".to_string());
+        }
+        return Ok(());
+    }
+    Err("expected rate(...) or cron(...)".to_string())
+}
+
+fn default_name(expression: &str) -> String {
+    expression
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl Choko {
+    /// Run `handler` on the schedule `expression`, e.g. `rate(5 minutes)`
+    /// or `cron(0 3 * * ? *)`.
+    ///
+    /// The expression is validated here (panicking if it's malformed) and
+    /// exposed via [`scheduled_tasks`](Self::scheduled_tasks) for
+    /// deployment; EventBridge does the scheduling.
+    pub fn schedule<F, Fut>(&mut self, expression: &str, handler: F) -> &mut ScheduledTask
+    where
+        F: Fn(ScheduledEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        if let Err(e) = validate(expression) {
+            panic!("invalid schedule expression {expression:?}: {e}");
+        }
+        let handler = Arc::new(handler);
+        self.scheduled_tasks.push(ScheduledTask {
+            name: default_name(expression),
+            expression: expression.to_string(),
+            handler: Arc::new(move |event| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(event).await })
+            }),
+        });
+        self.scheduled_tasks.last_mut().unwrap()
+    }
+
+    /// The registered scheduled tasks.
+    pub fn scheduled_tasks(&self) -> &[ScheduledTask] {
+        &self.scheduled_tasks
+    }
+
+    /// Run the application as the target of its EventBridge schedule rules.
+    ///
+    /// A failed task fails the invocation, so EventBridge's retry policy
+    /// applies.
+    pub async fn run_schedule(self) -> Result<(), Error> {
+        self.serve(|app, event: ScheduledEvent, context| async move {
+            app.dispatch_schedule(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_schedule(
+        &self,
+        mut event: ScheduledEvent,
+        context: Option<LambdaContext>,
+    ) -> Result<(), Error> {
+        let rule = event.rule_name().unwrap_or_default().to_string();
+        let handler = self
+            .scheduled_tasks
+            .iter()
+            .find(|task| task.handles(&rule))
+            .map(|task| Arc::clone(&task.handler))
+            .ok_or_else(|| format!("no scheduled task for rule {rule:?}"))?;
+        event.lambda_context = context;
+        handler(event).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn event(rule: &str) -> ScheduledEvent {
+        serde_json::from_value(serde_json::json!({
+            "version": "0",
+            "id": "53dc4d37-cffa-4f76-80c9-8b7d4a4d2eaa",
+            "detail-type": "Scheduled Event",
+            "source": "aws.events",
+            "account": "123456789012",
+            "time": "2024-05-01T03:00:00Z",
+            "region": "eu-west-1",
+            "resources": [format!("arn:aws:events:eu-west-1:123456789012:rule/{rule}")],
+            "detail": {}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validates_expressions() {
+        assert!(validate("rate(5 minutes)").is_ok());
+        assert!(validate("rate(1 day)").is_ok());
+        assert!(validate("cron(0 3 * * ? *)").is_ok());
+        assert!(validate("rate(1 days)").is_err());
+        assert!(validate("rate(0 minutes)").is_err());
+        assert!(validate("cron(0 3 * * *)").is_err());
+        assert!(validate("cron(0 3 * * MON *)").is_err());
+        assert!(validate("every 5 minutes").is_err());
+    }
+
+    #[tokio::test]
+    async fn routes_by_rule_name() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Choko::new("test");
+        let log = Arc::clone(&ran);
+        app.schedule("rate(5 minutes)", move |e: ScheduledEvent| {
+            let log = Arc::clone(&log);
+            async move {
+                log.lock().unwrap().push(format!("purge {}", e.time));
+                Ok(())
+            }
+        })
+        .name("purge-sessions");
+        let log = Arc::clone(&ran);
+        app.schedule("cron(0 3 * * ? *)", move |_| {
+            let log = Arc::clone(&log);
+            async move {
+                log.lock().unwrap().push("nightly".to_string());
+                Ok(())
+            }
+        });
+        assert_eq!(app.scheduled_tasks()[1].task_name(), "cron-0-3");
+
+        app.dispatch_schedule(event("purge-sessions"), None)
+            .await
+            .unwrap();
+        app.dispatch_schedule(event("prod-cron-0-3"), None)
+            .await
+            .unwrap();
+        assert!(app.dispatch_schedule(event("other"), None).await.is_err());
+        assert_eq!(
+            *ran.lock().unwrap(),
+            ["purge 2024-05-01T03:00:00Z", "nightly"]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Secrets Manager values with caching (`secrets-manager` feature).
+//!
+//! [`Secrets`] fetches secrets once and keeps them for a TTL, so handlers
+//! don't pay a Secrets Manager call on every request. [`Secrets::preload`]
+//! fetches the secrets a function needs during the cold start; after the
+//! TTL a secret is refreshed on its next use. If a refresh fails, the
+//! previous value keeps being served (and the error logged), so a Secrets
+//! Manager outage doesn't take the function down with it.
+//!
+//! # Example
+//! ```ignore
+//! use choko::secrets::Secrets;
+//!
+//! let client = aws_sdk_secretsmanager::Client::new(&aws_config::load_from_env().await);
+//! let secrets = Secrets::new(client).ttl(Duration::from_secs(600));
+//! secrets.preload(&["prod/db"]).await?;
+//! app.state(secrets);
+//!
+//! app.get("/report", |req| async move {
+//!     let secrets = req.state::<Secrets>().ok_or("secrets not configured")?;
+//!     let db: DbCredentials = secrets.get_json("prod/db").await?;
+//!     ...
+//! });
+//! ```
+
+use crate::Error;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Inner {
+    client: aws_sdk_secretsmanager::Client,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (Instant, Arc<str>)>>,
+}
+
+/// A cache of Secrets Manager secrets. Cheap to clone; clones share the
+/// cache.
+#[derive(Clone)]
+pub struct Secrets {
+    inner: Arc<Inner>,
+}
+
+impl Secrets {
+    /// Secrets read with `client`, cached for 5 minutes.
+    pub fn new(client: aws_sdk_secretsmanager::Client) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client,
+                ttl: Duration::from_secs(5 * 60),
+                cache: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// How long a secret is served before it is fetched again.
+    ///
+    /// # Panics
+    /// Panics if called after the cache has been cloned.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("Secrets is configured before use")
+            .ttl = ttl;
+        self
+    }
+
+    /// Fetch `names` now, typically during the cold start. Fails if any of
+    /// them can't be read.
+    pub async fn preload(&self, names: &[&str]) -> Result<(), Error> {
+        for name in names {
+            self.refresh(name).await?;
+        }
+        Ok(())
+    }
+
+    /// The string value of the secret `name` (a name or ARN).
+    pub async fn get(&self, name: &str) -> Result<Arc<str>, Error> {
+        let cached = self.cached(name);
+        match cached {
+            Some((fetched_at, value)) if fetched_at.elapsed() < self.inner.ttl => Ok(value),
+            Some((_, stale)) => match self.refresh(name).await {
+                Ok(value) => Ok(value),
+                Err(e) => {
+                    eprintln!("Failed to refresh secret {name}: {e}");
+                    Ok(stale)
+                }
+            },
+            None => self.refresh(name).await,
+        }
+    }
+
+    /// The secret `name`, deserialized from JSON (the format of secrets
+    /// with key/value pairs).
+    pub async fn get_json<T: DeserializeOwned>(&self, name: &str) -> Result<T, Error> {
+        Ok(serde_json::from_str(&self.get(name).await?)?)
+    }
+
+    /// Drop every cached value, so the next use of each secret fetches it.
+    pub fn invalidate(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Instant, Arc<str>)>> {
+        self.inner.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn cached(&self, name: &str) -> Option<(Instant, Arc<str>)> {
+        self.lock().get(name).cloned()
+    }
+
+    async fn refresh(&self, name: &str) -> Result<Arc<str>, Error> {
+        let output = self
+            .inner
+            .client
+            .get_secret_value()
+            .secret_id(name)
+            .send()
+            .await?;
+        let value: Arc<str> = match (output.secret_string(), output.secret_binary()) {
+            (Some(text), _) => text.into(),
+            (None, Some(binary)) => String::from_utf8(binary.as_ref().to_vec())?.into(),
+            (None, None) => return Err(format!("secret {name} has no value").into()),
+        };
+        self.lock()
+            .insert(name.to_string(), (Instant::now(), Arc::clone(&value)));
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_secretsmanager::config::{BehaviorVersion, Credentials, Region};
+
+    /// A client whose calls fail fast: nothing listens on the endpoint.
+    fn secrets() -> Secrets {
+        let config = aws_sdk_secretsmanager::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-west-1"))
+            .credentials_provider(Credentials::new("AKID", "secret", None, None, "test"))
+            .endpoint_url("http://127.0.0.1:9")
+            .retry_config(aws_sdk_secretsmanager::config::retry::RetryConfig::disabled())
+            .build();
+        Secrets::new(aws_sdk_secretsmanager::Client::from_conf(config)).ttl(Duration::from_secs(60))
+    }
+
+    fn seed(secrets: &Secrets, name: &str, value: &str, age: Duration) {
+        let fetched_at = Instant::now() - age;
+        secrets
+            .lock()
+            .insert(name.to_string(), (fetched_at, value.into()));
+    }
+
+    #[tokio::test]
+    async fn serves_cached_and_stale_values() {
+        let secrets = secrets();
+        seed(&secrets, "fresh", r#"{"user":"app"}"#, Duration::ZERO);
+        seed(&secrets, "stale", "old", Duration::from_secs(120));
+
+        #[derive(serde::Deserialize)]
+        struct Creds {
+            user: String,
+        }
+        let creds: Creds = secrets.get_json("fresh").await.unwrap();
+        assert_eq!(creds.user, "app");
+        // The refresh fails, so the previous value is kept
+        assert_eq!(&*secrets.get("stale").await.unwrap(), "old");
+        assert!(secrets.get("missing").await.is_err());
+
+        secrets.invalidate();
+        assert!(secrets.get("fresh").await.is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Sentry error reporting (`sentry` feature).
+//!
+//! [`SentryReporter`] reports handler errors and panics to Sentry with the
+//! request's route, method, request ID and (redacted) parameters attached,
+//! then lets the dispatcher answer 500 as usual. Initialize the client
+//! yourself with `sentry::init` and keep its guard alive in `main`.
+//!
+//! # Example
+//! ```ignore
+//! use choko::sentry::SentryReporter;
+//!
+//! let _guard = sentry::init(std::env::var("SENTRY_DSN")?);
+//! app.middleware(SentryReporter::new().redact("session_id"));
+//! ```
+
+use crate::audit::{DEFAULT_FIELDS, REDACTED};
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, ChokoError, Error, Request, Response};
+use ::sentry::protocol::{Context, Value};
+use ::sentry::{Hub, SentryFutureExt};
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+/// How long to wait for queued events to be sent after a failure.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Middleware reporting errors and panics from the rest of the chain.
+///
+/// Each request runs on its own Sentry hub whose scope carries the route
+/// (as the transaction), method and request ID tags, and a `request`
+/// context with path and query parameters. Parameters whose names are
+/// sensitive (`password`, `token`, ... or added with
+/// [`redact`](Self::redact)) are replaced with [`REDACTED`].
+///
+/// Errors mapping to 4xx (such as [`ChokoError::NotFound`]) are not
+/// reported. Panics are reported by Sentry's panic integration (on by
+/// default) with the same scope, and turned into an error here so the
+/// caller gets a 500 instead of the invocation failing. Events are flushed before
+/// the response is returned, since the environment may be frozen right
+/// after.
+pub struct SentryReporter {
+    redact: Arc<HashSet<String>>,
+}
+
+impl Default for SentryReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SentryReporter {
+    pub fn new() -> Self {
+        Self {
+            redact: Arc::new(DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect()),
+        }
+    }
+
+    /// Also redact parameters named `name` (case-insensitive).
+    pub fn redact(mut self, name: &str) -> Self {
+        Arc::make_mut(&mut self.redact).insert(name.to_ascii_lowercase());
+        self
+    }
+}
+
+fn redacted<'a, I>(pairs: I, redact: &HashSet<String>) -> Value
+where
+    I: IntoIterator<Item = (&'a String, String)>,
+{
+    pairs
+        .into_iter()
+        .map(|(name, value)| {
+            let value = if redact.contains(&name.to_ascii_lowercase()) {
+                REDACTED.to_string()
+            } else {
+                value
+            };
+            (name.clone(), Value::from(value))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn request_context(req: &Request, redact: &HashSet<String>) -> Context {
+    let mut context = BTreeMap::new();
+    context.insert("path".to_string(), Value::from(req.path()));
+    context.insert(
+        "path_params".to_string(),
+        redacted(req.path_params.iter().map(|(k, v)| (k, v.clone())), redact),
+    );
+    context.insert(
+        "query_params".to_string(),
+        redacted(
+            req.query_params.iter().map(|(k, v)| (k, v.join(","))),
+            redact,
+        ),
+    );
+    Context::Other(context)
+}
+
+/// Whether `e` is a server-side failure worth reporting.
+fn is_reportable(e: &Error) -> bool {
+    match e.downcast_ref::<ChokoError>() {
+        Some(err) => err.status_code() >= 500,
+        None => !e.is::<crate::Problem>() && !e.is::<crate::QueryRejection>(),
+    }
+}
+
+/// Resolves to `Err` with the payload if polling the inner future panics.
+struct CatchUnwind(BoxFuture<Result<Response, Error>>);
+
+impl Future for CatchUnwind {
+    type Output = std::thread::Result<Result<Response, Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let inner = &mut self.0;
+        match std::panic::catch_unwind(AssertUnwindSafe(|| inner.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(result)) => Poll::Ready(Ok(result)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("handler panicked")
+}
+
+impl Middleware for SentryReporter {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        hub.configure_scope(|scope| {
+            scope.set_transaction(Some(req.route().unwrap_or(req.path())));
+            scope.set_tag("http.method", req.method());
+            if let Some(id) = req
+                .request_id()
+                .or(req.request_context().request_id.as_deref())
+            {
+                scope.set_tag("request_id", id);
+            }
+            scope.set_context("request", request_context(&req, &self.redact));
+        });
+        let run = CatchUnwind(next.run(req)).bind_hub(Arc::clone(&hub));
+        Box::pin(async move {
+            let (result, reported) = match run.await {
+                Ok(Ok(resp)) => (Ok(resp), false),
+                Ok(Err(e)) if is_reportable(&e) => {
+                    hub.capture_error(e.as_ref());
+                    (Err(e), true)
+                }
+                Ok(Err(e)) => (Err(e), false),
+                // The panic integration has already reported it from the
+                // panic hook, which ran on this request's hub
+                Err(panic) => {
+                    let message = format!("Handler panicked: {}", panic_message(&*panic));
+                    (Err(message.into()), true)
+                }
+            };
+            if reported {
+                if let Some(client) = hub.client() {
+                    let _ = tokio::task::spawn_blocking(move || client.flush(Some(FLUSH_TIMEOUT)))
+                        .await;
+                }
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use std::collections::HashMap;
+
+    #[test]
+    fn sensitive_params_are_redacted() {
+        let req = Request {
+            path_params: HashMap::from([("id".to_string(), "7".to_string())]),
+            query_params: HashMap::from([("Token".to_string(), vec!["abc".to_string()])]),
+            ..Default::default()
+        };
+        let Context::Other(context) = request_context(&req, &SentryReporter::new().redact) else {
+            panic!("expected other context");
+        };
+        assert_eq!(context["path_params"]["id"], "7");
+        assert_eq!(context["query_params"]["Token"], REDACTED);
+    }
+
+    #[test]
+    fn client_errors_are_not_reported() {
+        assert!(!is_reportable(&ChokoError::NotFound("user".into()).into()));
+        assert!(is_reportable(&"boom".into()));
+    }
+
+    #[tokio::test]
+    async fn panics_become_errors() {
+        let panicking = |_req| async { panic!("index out of bounds") };
+        let err = run_middleware(SentryReporter::new(), panicking, Request::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Handler panicked: index out of bounds");
+    }
+}
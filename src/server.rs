@@ -0,0 +1,347 @@
+//! Standalone HTTP server (`server` feature).
+//!
+//! [`Choko::listen`] serves the same app over HTTP/1.1 with hyper, for
+//! containers (ECS, Fargate, Kubernetes) or local development. Requests go
+//! through the routes, middleware and hooks as they would from API
+//! Gateway; Lambda-only data (request context identity beyond the client
+//! address, authorizer output, [`LambdaContext`](crate::LambdaContext)) is
+//! absent.
+//!
+//! Streamed bodies ([`SseResponse`](crate::SseResponse),
+//! [`NdjsonResponse`](crate::NdjsonResponse), [`Response::stream`](crate::Response::stream))
+//! are sent chunk by chunk as they are produced; their deferred work
+//! starts once the body ends.
+//!
+//! # Example
+//! ```ignore
+//! if std::env::var("AWS_LAMBDA_RUNTIME_API").is_ok() {
+//!     app.run().await
+//! } else {
+//!     app.listen("0.0.0.0:8080").await
+//! }
+//! ```
+
+use crate::http_compat::{to_apigw_request, to_http_response};
+use crate::{BodyStream, BoxFuture, Choko, Error, Response, ResponseBody};
+use http_body_util::{BodyExt, Either, Full, LengthLimitError, Limited};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use hyper_util::server::graceful::GracefulShutdown;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+type ServerBody = Either<Full<Bytes>, StreamedBody>;
+
+/// A streamed response body, sent to the client chunk by chunk.
+struct StreamedBody {
+    stream: BodyStream,
+    /// The handler's deferred work, started when the body is dropped.
+    deferred: Option<BoxFuture<()>>,
+}
+
+impl hyper::body::Body for StreamedBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+        self.get_mut()
+            .stream
+            .poll_chunk(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(|bytes| Frame::data(Bytes::from(bytes)))))
+    }
+}
+
+impl Drop for StreamedBody {
+    fn drop(&mut self) {
+        // Also when the client disconnects or the producer fails midway
+        if let (Some(deferred), Ok(runtime)) =
+            (self.deferred.take(), tokio::runtime::Handle::try_current())
+        {
+            runtime.spawn(deferred);
+        }
+    }
+}
+
+/// Resolves on Ctrl-C (`SIGINT`) or `SIGTERM`, which ECS, Fargate and
+/// Kubernetes send to stop a container.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+}
+
+impl Choko {
+    /// Serve the application over HTTP on `addr` until Ctrl-C or `SIGTERM`,
+    /// then finish in-flight requests and run the shutdown hooks.
+    pub async fn listen(self, addr: impl ToSocketAddrs) -> Result<(), Error> {
+        self.listen_on(TcpListener::bind(addr).await?).await
+    }
+
+    /// Serve the application over HTTP on an already bound `listener`.
+    pub async fn listen_on(self, listener: TcpListener) -> Result<(), Error> {
+        self.listen_until(listener, shutdown_signal()).await
+    }
+
+    /// Limit the size of request bodies read by the HTTP server.
+    ///
+    /// Larger bodies are rejected with 413. Defaults to 10 MiB, API
+    /// Gateway's own limit.
+    pub fn max_request_body_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_request_body_size = bytes;
+        self
+    }
+
+    #[allow(unused_mut)]
+    async fn listen_until(
+        mut self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "shutdown")]
+        let hooks = std::mem::take(&mut self.shutdown_hooks);
+        eprintln!("Listening on http://{}", listener.local_addr()?);
+        let app = Arc::new(self);
+        let graceful = GracefulShutdown::new();
+        tokio::pin!(shutdown);
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = &mut shutdown => break,
+            };
+            let app = Arc::clone(&app);
+            let service = service_fn(move |req| {
+                let app = Arc::clone(&app);
+                async move { Ok::<_, Infallible>(app.handle_hyper(req, peer).await) }
+            });
+            let connection = graceful
+                .watch(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    eprintln!("HTTP connection from {peer} failed: {e}");
+                }
+            });
+        }
+        // Stop accepting, then let in-flight requests finish
+        drop(listener);
+        graceful.shutdown().await;
+        #[cfg(feature = "shutdown")]
+        for hook in hooks {
+            hook().await;
+        }
+        #[cfg(feature = "otlp")]
+        crate::telemetry::flush().await;
+        Ok(())
+    }
+
+    async fn handle_hyper(
+        &self,
+        req: hyper::Request<Incoming>,
+        peer: SocketAddr,
+    ) -> hyper::Response<ServerBody> {
+        let (parts, body) = req.into_parts();
+        let body = match Limited::new(body, self.max_request_body_size)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes().to_vec(),
+            Err(e) => {
+                let resp = if e.is::<LengthLimitError>() {
+                    crate::framework_error(413, "Payload Too Large", self.problem_details)
+                } else {
+                    self.handler_error(e)
+                };
+                return self.buffered_response(resp);
+            }
+        };
+        let req = http::Request::from_parts(parts, body);
+        let routed = self
+            .route_event(to_apigw_request(req, Some(peer)), None, false)
+            .await;
+        let mut resp = routed.response;
+        let head = to_http_response(self.response_head(&resp));
+        match resp.body {
+            ResponseBody::Stream(stream) => {
+                let budget = self.deferred_budget;
+                let deferred = routed.deferred;
+                head.map(|_| {
+                    Either::Right(StreamedBody {
+                        stream,
+                        deferred: Some(Box::pin(async move { deferred.run(budget, None).await })),
+                    })
+                })
+            }
+            body => {
+                resp.body = body;
+                let resp = self.buffered_response(resp);
+                routed.deferred.run(self.deferred_budget, None).await;
+                resp
+            }
+        }
+    }
+
+    fn buffered_response(&self, resp: Response) -> hyper::Response<ServerBody> {
+        to_http_response(self.build_apigw_response(resp))
+            .map(|body| Either::Left(Full::new(Bytes::from(body))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn serves_requests_over_tcp() {
+        let mut app = Choko::new("test");
+        app.get("/hello/{name}", |req| async move {
+            Ok(Response::text(format!("hi {}", req.path_params["name"])))
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(app.listen_on(listener));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /hello/ada HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"), "{raw}");
+        assert!(raw.ends_with("hi ada"), "{raw}");
+    }
+
+    async fn send(addr: SocketAddr, request: &[u8]) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        raw
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies_with_413() {
+        let mut app = Choko::new("test");
+        app.post("/echo", |req| async move {
+            Ok(Response::text(req.body.unwrap_or_default()))
+        });
+        app.max_request_body_size(4);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(app.listen_on(listener));
+
+        let raw = send(
+            addr,
+            b"POST /echo HTTP/1.1\r\nhost: x\r\ncontent-length: 4\r\nconnection: close\r\n\r\nabcd",
+        )
+        .await;
+        assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"), "{raw}");
+        let raw = send(
+            addr,
+            b"POST /echo HTTP/1.1\r\nhost: x\r\ncontent-length: 5\r\nconnection: close\r\n\r\nabcde",
+        )
+        .await;
+        assert!(raw.starts_with("HTTP/1.1 413 "), "{raw}");
+    }
+
+    #[tokio::test]
+    async fn streams_bodies_as_they_are_produced() {
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let released = std::sync::Mutex::new(Some(released));
+        let mut app = Choko::new("test");
+        app.get("/progress", move |_req| {
+            let released = released.lock().unwrap().take();
+            async move {
+                let (sse, tx) = crate::SseResponse::channel();
+                tokio::spawn(async move {
+                    tx.send(crate::SseEvent::data("25")).await.unwrap();
+                    if let Some(released) = released {
+                        released.await.ok();
+                    }
+                    tx.send(crate::SseEvent::data("100")).await.unwrap();
+                });
+                Ok(sse.into())
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(app.listen_on(listener));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /progress HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        // The first event arrives while the producer is still waiting
+        let mut raw = Vec::new();
+        while !String::from_utf8_lossy(&raw).contains("data: 25\n\n") {
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "{}", String::from_utf8_lossy(&raw));
+            raw.extend_from_slice(&buf[..n]);
+        }
+        let head = String::from_utf8_lossy(&raw).to_string();
+        assert!(
+            head.contains("content-type: text/event-stream\r\n"),
+            "{head}"
+        );
+        assert!(!head.contains("data: 100"), "{head}");
+
+        release.send(()).unwrap();
+        stream.read_to_end(&mut raw).await.unwrap();
+        assert!(String::from_utf8_lossy(&raw).contains("data: 100\n\n"));
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_in_flight_requests() {
+        let mut app = Choko::new("test");
+        app.get("/slow", |_req| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            Ok(Response::text("done"))
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(app.listen_until(listener, async {
+            stopped.await.ok();
+        }));
+
+        let request = tokio::spawn(send(
+            addr,
+            b"GET /slow HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n",
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let stopped_at = std::time::Instant::now();
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        // The server waited for the handler to finish
+        assert!(stopped_at.elapsed() >= std::time::Duration::from_millis(50));
+
+        let raw = request.await.unwrap();
+        assert!(raw.ends_with("done"), "{raw}");
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! `tower` integration (`tower` feature).
+//!
+//! [`Choko::into_service`] turns the app into a
+//! [`tower::Service`] over `http::Request<Vec<u8>>`, so any
+//! [`tower::Layer`] from the ecosystem (timeouts, concurrency limits,
+//! `tower-http` tracing, ...) can wrap the dispatch pipeline.
+//! [`Choko::run_layered`] runs the wrapped service as the Lambda handler
+//! for API Gateway requests.
+//!
+//! Requests reaching the service from Lambda carry the original
+//! `ApiGatewayProxyRequest` and the [`LambdaContext`] in their extensions,
+//! so authorizer output and request context survive the trip through the
+//! layers.
+//!
+//! # Example
+//! ```ignore
+//! use std::time::Duration;
+//! use tower::ServiceBuilder;
+//!
+//! let layers = ServiceBuilder::new()
+//!     .layer(tower_http::trace::TraceLayer::new_for_http())
+//!     .timeout(Duration::from_secs(5));
+//! app.run_layered(layers).await
+//! ```
+
+use crate::http_compat::{from_apigw_request, to_apigw_response};
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use aws_lambda_events::event::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyResponse};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tower::{Layer, Service, ServiceExt};
+
+/// The application as a [`tower::Service`].
+///
+/// The client address is read from a `SocketAddr` request extension, if
+/// any. Cheap to clone.
+#[derive(Clone)]
+pub struct ChokoService {
+    app: Arc<Choko>,
+}
+
+impl Service<http::Request<Vec<u8>>> for ChokoService {
+    type Response = http::Response<Vec<u8>>;
+    type Error = Error;
+    type Future = BoxFuture<Result<Self::Response, Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<Vec<u8>>) -> Self::Future {
+        let app = Arc::clone(&self.app);
+        Box::pin(async move {
+            let peer = req.extensions().get::<SocketAddr>().copied();
+            app.call_http(req, peer).await
+        })
+    }
+}
+
+impl Choko {
+    /// The application as a [`tower::Service`], for wrapping in layers or
+    /// serving with any tower-compatible server.
+    pub fn into_service(self) -> ChokoService {
+        ChokoService {
+            app: Arc::new(self),
+        }
+    }
+
+    /// Run the application as an AWS Lambda handler for API Gateway
+    /// requests, with every invocation going through `layer`.
+    ///
+    /// Errors from the layers (a timeout, say) fail the invocation.
+    pub async fn run_layered<L>(self, layer: L) -> Result<(), Error>
+    where
+        L: Layer<ChokoService> + Send + Sync + 'static,
+        L::Service: Service<http::Request<Vec<u8>>, Response = http::Response<Vec<u8>>>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        <L::Service as Service<http::Request<Vec<u8>>>>::Error: Into<Error>,
+        <L::Service as Service<http::Request<Vec<u8>>>>::Future: Send,
+    {
+        // The service wraps the same `Arc<Choko>` the runtime loop holds, so
+        // it is built on the first invocation and reused afterwards.
+        let service = Arc::new(OnceLock::new());
+        let layer = Arc::new(layer);
+        self.serve(move |app, event: ApiGatewayProxyRequest, context| {
+            let service = service
+                .get_or_init(|| layer.layer(ChokoService { app }))
+                .clone();
+            call_layered(service, event, Some(context))
+        })
+        .await
+    }
+}
+
+async fn call_layered<S>(
+    service: S,
+    event: ApiGatewayProxyRequest,
+    context: Option<LambdaContext>,
+) -> Result<ApiGatewayProxyResponse, Error>
+where
+    S: Service<http::Request<Vec<u8>>, Response = http::Response<Vec<u8>>>,
+    S::Error: Into<Error>,
+{
+    let req = from_apigw_request(event, context)?;
+    let resp = service.oneshot(req).await.map_err(Into::into)?;
+    Ok(to_apigw_response(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Response;
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn app() -> Choko {
+        let mut app = Choko::new("test");
+        app.get("/users/{id}", |req| async move {
+            Ok(Response::json(json!({
+                "id": req.path_params["id"],
+                "stage": req.request_context.stage,
+                "page": req.query_params.get("page"),
+            }))
+            .with_header("x-user", "7"))
+        });
+        app.get("/slow", |_req| async move {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(Response::no_content())
+        });
+        app
+    }
+
+    fn event(path: &str) -> ApiGatewayProxyRequest {
+        let mut event = ApiGatewayProxyRequest::default();
+        event.http_method = http::Method::GET;
+        event.path = Some(path.to_string());
+        event.query_string_parameters = [("page".to_string(), "2".to_string())]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>()
+            .into();
+        event.request_context.stage = Some("prod".to_string());
+        event
+    }
+
+    #[tokio::test]
+    async fn dispatches_through_layers_with_the_original_event() {
+        let service =
+            tower::timeout::TimeoutLayer::new(Duration::from_secs(1)).layer(app().into_service());
+        let resp = call_layered(service, event("/users/7"), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 200);
+        assert_eq!(resp.headers["x-user"], "7");
+        let body = match resp.body {
+            Some(aws_lambda_events::encodings::Body::Text(text)) => text,
+            other => panic!("unexpected body {other:?}"),
+        };
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+            json!({ "id": "7", "stage": "prod", "page": ["2"] })
+        );
+    }
+
+    #[tokio::test]
+    async fn layer_errors_fail_the_invocation() {
+        let service = tower::timeout::TimeoutLayer::new(Duration::from_millis(10))
+            .layer(app().into_service());
+        let err = call_layered(service, event("/slow"), None)
+            .await
+            .unwrap_err();
+        assert!(err.is::<tower::timeout::error::Elapsed>());
+    }
+}
@@ -0,0 +1,374 @@
+//! SES inbound email handlers (`ses` feature).
+//!
+//! [`Choko::ses_recipient`] registers a handler for mail sent to addresses
+//! matching a pattern, and [`Choko::run_ses`] starts the app as the Lambda
+//! action of an SES receipt rule. Handlers get the parsed headers and the
+//! spam, virus and authentication verdicts. SES doesn't pass the message
+//! body to Lambda; add an S3 action before the Lambda action and fetch the
+//! MIME source with [`Email::fetch_raw`].
+//!
+//! # Example
+//! ```ignore
+//! use choko::ses::Email;
+//!
+//! app.ses_recipient("support@example.com", |email: Email| async move {
+//!     if email.is_spam() || email.has_virus() {
+//!         return Ok(());
+//!     }
+//!     let raw = email.fetch_raw(&s3, "inbound-mail", "support/").await?;
+//!     tickets.open(email.subject(), raw).await
+//! });
+//! app.ses_recipient("*@reply.example.com", handle_reply);
+//! app.run_ses().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type EmailHandlerFn = Arc<dyn Fn(Email) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// An SES receipt invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SesEvent {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<SesEventRecord>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SesEventRecord {
+    pub ses: SesMessage,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SesMessage {
+    pub mail: Mail,
+    pub receipt: Receipt,
+}
+
+/// The `mail` section: envelope and headers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mail {
+    /// RFC 3339.
+    pub timestamp: String,
+    /// The envelope sender (`MAIL FROM`).
+    pub source: String,
+    pub message_id: String,
+    #[serde(default)]
+    pub destination: Vec<String>,
+    #[serde(default)]
+    pub headers_truncated: bool,
+    #[serde(default)]
+    pub headers: Vec<MailHeader>,
+    #[serde(default)]
+    pub common_headers: CommonHeaders,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommonHeaders {
+    #[serde(default)]
+    pub from: Vec<String>,
+    #[serde(default)]
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    pub subject: Option<String>,
+    pub date: Option<String>,
+    pub message_id: Option<String>,
+}
+
+/// The `receipt` section: recipients and verdicts.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    /// The recipients of this receipt rule, a subset of the destination.
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub spam_verdict: Verdict,
+    #[serde(default)]
+    pub virus_verdict: Verdict,
+    #[serde(default)]
+    pub spf_verdict: Verdict,
+    #[serde(default)]
+    pub dkim_verdict: Verdict,
+    #[serde(default)]
+    pub dmarc_verdict: Verdict,
+}
+
+/// The outcome of one of SES's checks.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Verdict {
+    /// `PASS`, `FAIL`, `GRAY`, `PROCESSING_FAILED` or `DISABLED`.
+    pub status: String,
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        self.status == "PASS"
+    }
+
+    pub fn failed(&self) -> bool {
+        self.status == "FAIL"
+    }
+}
+
+/// An email received by SES.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub mail: Mail,
+    pub receipt: Receipt,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl Email {
+    /// The SES message ID, also the S3 object name of a stored message.
+    pub fn message_id(&self) -> &str {
+        &self.mail.message_id
+    }
+
+    /// The `Subject` header.
+    pub fn subject(&self) -> &str {
+        self.mail.common_headers.subject.as_deref().unwrap_or("")
+    }
+
+    /// The addresses of the `From` header.
+    pub fn from(&self) -> &[String] {
+        &self.mail.common_headers.from
+    }
+
+    /// The recipients this email was delivered to.
+    pub fn recipients(&self) -> &[String] {
+        &self.receipt.recipients
+    }
+
+    /// The first header named `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.mail
+            .headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+    }
+
+    /// Whether SES flagged the email as spam.
+    pub fn is_spam(&self) -> bool {
+        self.receipt.spam_verdict.failed()
+    }
+
+    /// Whether SES found a virus in the email.
+    pub fn has_virus(&self) -> bool {
+        self.receipt.virus_verdict.failed()
+    }
+
+    /// Whether SPF, DKIM and DMARC all passed.
+    pub fn is_authenticated(&self) -> bool {
+        self.receipt.spf_verdict.passed()
+            && self.receipt.dkim_verdict.passed()
+            && self.receipt.dmarc_verdict.passed()
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    /// Download the MIME source stored by the rule's S3 action into
+    /// `bucket` under `prefix`.
+    pub async fn fetch_raw(
+        &self,
+        client: &aws_sdk_s3::Client,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let object = client
+            .get_object()
+            .bucket(bucket)
+            .key(format!("{prefix}{}", self.mail.message_id))
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+}
+
+/// Whether `address` matches `pattern`, where `*` matches any run of
+/// characters. Case-insensitive.
+fn address_matches(pattern: &str, address: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let address = address.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = address.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl Choko {
+    /// Handle email to recipients matching `pattern`, e.g.
+    /// `support@example.com` or `*@reply.example.com`.
+    ///
+    /// An email is passed to every handler one of its recipients matches,
+    /// once each.
+    pub fn ses_recipient<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Email) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.ses_handlers.push((
+            pattern.to_string(),
+            Arc::new(move |email| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move { handler(email).await })
+            }),
+        ));
+        self
+    }
+
+    /// Run the application as the Lambda action of SES receipt rules.
+    pub async fn run_ses(self) -> Result<(), Error> {
+        self.serve(|app, event: SesEvent, context| async move {
+            app.dispatch_ses(event, Some(context)).await
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_ses(
+        &self,
+        event: SesEvent,
+        context: Option<LambdaContext>,
+    ) -> Result<(), Error> {
+        for record in event.records {
+            let email = Email {
+                mail: record.ses.mail,
+                receipt: record.ses.receipt,
+                lambda_context: context.clone(),
+            };
+            let handlers: Vec<_> = self
+                .ses_handlers
+                .iter()
+                .filter(|(pattern, _)| {
+                    email
+                        .recipients()
+                        .iter()
+                        .any(|r| address_matches(pattern, r))
+                })
+                .map(|(_, handler)| Arc::clone(handler))
+                .collect();
+            if handlers.is_empty() {
+                return Err(format!("no handler for recipients {:?}", email.recipients()).into());
+            }
+            for handler in handlers {
+                handler(email.clone()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[test]
+    fn matches_address_patterns() {
+        assert!(address_matches(
+            "support@example.com",
+            "Support@Example.com"
+        ));
+        assert!(address_matches(
+            "*@reply.example.com",
+            "t-42@reply.example.com"
+        ));
+        assert!(address_matches(
+            "orders+*@example.com",
+            "orders+7@example.com"
+        ));
+        assert!(!address_matches("*@reply.example.com", "a@example.com"));
+        assert!(!address_matches(
+            "support@example.com",
+            "support@example.com.evil"
+        ));
+    }
+
+    #[tokio::test]
+    async fn routes_by_recipient_with_verdicts() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Choko::new("test");
+        for pattern in ["support@example.com", "*@reply.example.com"] {
+            let log = Arc::clone(&seen);
+            app.ses_recipient(pattern, move |email: Email| {
+                let log = Arc::clone(&log);
+                async move {
+                    assert!(email.is_spam() && !email.has_virus());
+                    assert_eq!(email.header("x-mailer"), Some("mutt"));
+                    log.lock()
+                        .unwrap()
+                        .push(format!("{pattern}: {}", email.subject()));
+                    Ok(())
+                }
+            });
+        }
+
+        let event: SesEvent = serde_json::from_value(json!({
+            "Records": [{
+                "eventSource": "aws:ses",
+                "eventVersion": "1.0",
+                "ses": {
+                    "mail": {
+                        "timestamp": "2024-05-01T12:00:00.000Z",
+                        "source": "alice@example.org",
+                        "messageId": "o3vrnil0e2ic",
+                        "destination": ["support@example.com", "t-1@reply.example.com"],
+                        "headers": [{ "name": "X-Mailer", "value": "mutt" }],
+                        "commonHeaders": {
+                            "from": ["Alice <alice@example.org>"],
+                            "to": ["support@example.com"],
+                            "subject": "Help"
+                        }
+                    },
+                    "receipt": {
+                        "recipients": ["support@example.com", "t-1@reply.example.com"],
+                        "spamVerdict": { "status": "FAIL" },
+                        "virusVerdict": { "status": "PASS" },
+                        "spfVerdict": { "status": "PASS" },
+                        "dkimVerdict": { "status": "PASS" },
+                        "dmarcVerdict": { "status": "PASS" }
+                    }
+                }
+            }]
+        }))
+        .unwrap();
+        app.dispatch_ses(event.clone(), None).await.unwrap();
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["support@example.com: Help", "*@reply.example.com: Help"]
+        );
+
+        let mut other = event;
+        other.records[0].ses.receipt.recipients = vec!["sales@example.com".to_string()];
+        assert!(app.dispatch_ses(other, None).await.is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Sessions stored in the cookie itself.
+
+use super::{SessionData, SessionStore};
+use crate::{BoxFuture, Error};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// AES-GCM nonce length in bytes.
+const NONCE_LEN: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    /// Expiry as Unix seconds.
+    exp: u64,
+    data: SessionData,
+}
+
+enum Protection {
+    Signed(Vec<u8>),
+    Encrypted(Box<Aes256Gcm>),
+}
+
+/// Keeps the whole session in the cookie, so no storage is needed.
+///
+/// Cookies are limited to about 4 KB, so keep sessions small. A signed
+/// cookie can be read (but not altered) by the client; use
+/// [`CookieStore::encrypted`] if the contents are private. Clearing a cookie
+/// session can't revoke copies the client kept, so expiry is embedded in the
+/// cookie and checked on load.
+pub struct CookieStore {
+    protection: Protection,
+}
+
+impl CookieStore {
+    /// Sign sessions with HMAC-SHA256 under `secret`.
+    pub fn signed(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            protection: Protection::Signed(secret.as_ref().to_vec()),
+        }
+    }
+
+    /// Encrypt sessions with AES-256-GCM under a key derived from `secret`
+    /// by SHA-256. Use at least 32 random bytes.
+    pub fn encrypted(secret: impl AsRef<[u8]>) -> Self {
+        let key = Sha256::digest(secret.as_ref());
+        Self {
+            protection: Protection::Encrypted(Box::new(Aes256Gcm::new(
+                Key::<Aes256Gcm>::from_slice(&key),
+            ))),
+        }
+    }
+
+    fn seal(&self, payload: &[u8]) -> Result<String, Error> {
+        match &self.protection {
+            Protection::Signed(secret) => {
+                let mut mac = <HmacSha256 as Mac>::new_from_slice(secret)
+                    .expect("HMAC accepts keys of any size");
+                mac.update(payload);
+                let tag = mac.finalize().into_bytes();
+                Ok(format!(
+                    "{}.{}",
+                    URL_SAFE_NO_PAD.encode(payload),
+                    URL_SAFE_NO_PAD.encode(tag)
+                ))
+            }
+            Protection::Encrypted(cipher) => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let mut sealed = nonce.to_vec();
+                sealed.extend(
+                    cipher
+                        .encrypt(&nonce, payload)
+                        .map_err(|_| "session encryption failed")?,
+                );
+                Ok(URL_SAFE_NO_PAD.encode(sealed))
+            }
+        }
+    }
+
+    /// The payload of a cookie value, or `None` if it was tampered with.
+    fn open(&self, value: &str) -> Option<Vec<u8>> {
+        match &self.protection {
+            Protection::Signed(secret) => {
+                let (payload, tag) = value.split_once('.')?;
+                let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+                let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+                let mut mac = <HmacSha256 as Mac>::new_from_slice(secret)
+                    .expect("HMAC accepts keys of any size");
+                mac.update(&payload);
+                mac.verify_slice(&tag).ok()?;
+                Some(payload)
+            }
+            Protection::Encrypted(cipher) => {
+                let sealed = URL_SAFE_NO_PAD.decode(value).ok()?;
+                if sealed.len() < NONCE_LEN {
+                    return None;
+                }
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl SessionStore for CookieStore {
+    fn load(&self, cookie: &str) -> BoxFuture<Result<Option<SessionData>, Error>> {
+        let envelope = self
+            .open(cookie)
+            .and_then(|payload| serde_json::from_slice::<Envelope>(&payload).ok())
+            .filter(|envelope| envelope.exp > now());
+        Box::pin(async move { Ok(envelope.map(|e| e.data)) })
+    }
+
+    fn save(
+        &self,
+        _cookie: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> BoxFuture<Result<String, Error>> {
+        let envelope = Envelope {
+            exp: now() + ttl.as_secs(),
+            data: data.clone(),
+        };
+        let sealed = serde_json::to_vec(&envelope)
+            .map_err(Error::from)
+            .and_then(|payload| self.seal(&payload));
+        Box::pin(async move { sealed })
+    }
+
+    fn destroy(&self, _cookie: &str) -> BoxFuture<Result<(), Error>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn data() -> SessionData {
+        let mut data = SessionData::new();
+        data.insert("user".to_string(), Value::from("alice"));
+        data
+    }
+
+    async fn round_trip(store: &CookieStore, ttl: Duration) -> Option<SessionData> {
+        let value = store.save(None, &data(), ttl).await.unwrap();
+        store.load(&value).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_round_trip_and_tamper() {
+        let store = CookieStore::signed("secret");
+        assert_eq!(
+            round_trip(&store, Duration::from_secs(60)).await,
+            Some(data())
+        );
+
+        let value = store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        let (_, tag) = value.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{tag}",
+            URL_SAFE_NO_PAD.encode(br#"{"exp":9999999999,"data":{"user":"root"}}"#)
+        );
+        assert_eq!(store.load(&forged).await.unwrap(), None);
+        assert_eq!(
+            CookieStore::signed("other").load(&value).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn encrypted_round_trip_hides_contents() {
+        let store = CookieStore::encrypted("0123456789abcdef0123456789abcdef");
+        assert_eq!(
+            round_trip(&store, Duration::from_secs(60)).await,
+            Some(data())
+        );
+
+        let value = store
+            .save(None, &data(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        let raw = URL_SAFE_NO_PAD.decode(&value).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("alice"));
+        assert_eq!(
+            CookieStore::encrypted("another key")
+                .load(&value)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_ignored() {
+        let store = CookieStore::signed("secret");
+        assert_eq!(round_trip(&store, Duration::ZERO).await, None);
+    }
+}
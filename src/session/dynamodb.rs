@@ -0,0 +1,136 @@
+//! Server-side sessions in DynamoDB (`dynamodb` feature).
+
+use super::{SessionData, SessionStore};
+use crate::{BoxFuture, Error};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Sessions kept in a DynamoDB table; the cookie holds only a random
+/// session ID.
+///
+/// The table needs a string partition key (default `id`). Data is stored as
+/// a JSON string in `data`; enable TTL on the `expires_at` attribute so
+/// expired sessions are deleted. Because TTL deletion is lazy, expiry is
+/// also checked on load.
+#[derive(Debug, Clone)]
+pub struct DynamoDbSessionStore {
+    client: aws_sdk_dynamodb::Client,
+    table: String,
+    key_attribute: String,
+}
+
+impl DynamoDbSessionStore {
+    /// Store sessions in `table`.
+    pub fn new(client: aws_sdk_dynamodb::Client, table: impl Into<String>) -> Self {
+        Self {
+            client,
+            table: table.into(),
+            key_attribute: "id".to_string(),
+        }
+    }
+
+    /// The partition key attribute name.
+    pub fn key_attribute(mut self, name: impl Into<String>) -> Self {
+        self.key_attribute = name.into();
+        self
+    }
+}
+
+/// A new unguessable session ID.
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl SessionStore for DynamoDbSessionStore {
+    fn load(&self, cookie: &str) -> BoxFuture<Result<Option<SessionData>, Error>> {
+        let this = self.clone();
+        let id = cookie.to_string();
+        Box::pin(async move {
+            let output = this
+                .client
+                .get_item()
+                .table_name(&this.table)
+                .key(&this.key_attribute, AttributeValue::S(id))
+                .consistent_read(true)
+                .send()
+                .await?;
+            let Some(item) = output.item() else {
+                return Ok(None);
+            };
+            let expires_at = match item.get("expires_at") {
+                Some(AttributeValue::N(n)) => n.parse::<u64>()?,
+                _ => 0,
+            };
+            if expires_at <= now() {
+                return Ok(None);
+            }
+            match item.get("data") {
+                Some(AttributeValue::S(json)) => Ok(Some(serde_json::from_str(json)?)),
+                _ => Ok(None),
+            }
+        })
+    }
+
+    fn save(
+        &self,
+        cookie: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> BoxFuture<Result<String, Error>> {
+        let this = self.clone();
+        let id = cookie.map_or_else(new_session_id, str::to_string);
+        let json = serde_json::to_string(data);
+        Box::pin(async move {
+            this.client
+                .put_item()
+                .table_name(&this.table)
+                .item(&this.key_attribute, AttributeValue::S(id.clone()))
+                .item("data", AttributeValue::S(json?))
+                .item(
+                    "expires_at",
+                    AttributeValue::N((now() + ttl.as_secs()).to_string()),
+                )
+                .send()
+                .await?;
+            Ok(id)
+        })
+    }
+
+    fn destroy(&self, cookie: &str) -> BoxFuture<Result<(), Error>> {
+        let this = self.clone();
+        let id = cookie.to_string();
+        Box::pin(async move {
+            this.client
+                .delete_item()
+                .table_name(&this.table)
+                .key(&this.key_attribute, AttributeValue::S(id))
+                .send()
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_ids_are_random() {
+        let id = new_session_id();
+        assert_eq!(id.len(), 43);
+        assert_ne!(id, new_session_id());
+    }
+}
@@ -0,0 +1,359 @@
+//! Cookie-backed sessions (`sessions` feature).
+//!
+//! [`Sessions`] middleware loads the session named by the request's session
+//! cookie, exposes it to handlers through [`Request::session`], and writes it
+//! back after the handler returns if it was modified. Where the data lives is
+//! up to the [`SessionStore`]: [`CookieStore`] keeps small sessions in the
+//! cookie itself (signed or encrypted), while the DynamoDB store
+//! (`dynamodb` feature) keeps them server-side and puts only an ID in the
+//! cookie.
+//!
+//! # Example
+//! ```ignore
+//! use choko::session::{CookieStore, Sessions};
+//!
+//! app.middleware(Sessions::new(CookieStore::encrypted(secret)));
+//!
+//! app.post("/login", |req| async move {
+//!     let session = req.session().unwrap();
+//!     session.clear(); // start a fresh session on privilege change
+//!     session.set("user_id", &42)?;
+//!     Ok(Response::no_content())
+//! });
+//! ```
+
+mod cookie_store;
+#[cfg(feature = "dynamodb")]
+mod dynamodb;
+
+pub use cookie_store::CookieStore;
+#[cfg(feature = "dynamodb")]
+pub use dynamodb::DynamoDbSessionStore;
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Cookie, Error, Request, Response, SameSite};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The key/value contents of a session.
+pub type SessionData = Map<String, Value>;
+
+/// Where session data is kept between requests.
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load the session referenced by a session cookie value.
+    ///
+    /// Returns `Ok(None)` for unknown, expired or tampered sessions, which
+    /// are then treated as empty.
+    fn load(&self, cookie: &str) -> BoxFuture<Result<Option<SessionData>, Error>>;
+
+    /// Persist `data` for `ttl` and return the cookie value referencing it.
+    /// `cookie` is the current cookie value, or `None` to start a new session.
+    fn save(
+        &self,
+        cookie: Option<&str>,
+        data: &SessionData,
+        ttl: Duration,
+    ) -> BoxFuture<Result<String, Error>>;
+
+    /// Delete the session referenced by `cookie`.
+    fn destroy(&self, cookie: &str) -> BoxFuture<Result<(), Error>>;
+}
+
+#[derive(Debug, Default)]
+struct State {
+    data: SessionData,
+    changed: bool,
+    cleared: bool,
+}
+
+/// The current request's session, shared between handler and middleware.
+///
+/// Obtained from [`Request::session`]; clones refer to the same session.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    fn new(data: SessionData) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                data,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The value stored under `key`, if present and of type `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.lock().data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Store `value` under `key`.
+    pub fn set<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), Error> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.lock();
+        state.data.insert(key.to_string(), value);
+        state.changed = true;
+        Ok(())
+    }
+
+    /// Remove `key`, returning whether it was present.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut state = self.lock();
+        let removed = state.data.remove(key).is_some();
+        state.changed |= removed;
+        removed
+    }
+
+    /// Discard the session. Its stored copy is destroyed and, if values are
+    /// set afterwards, they are saved under a new session.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.data.clear();
+        state.changed = true;
+        state.cleared = true;
+    }
+
+    /// Whether the session holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.lock().data.is_empty()
+    }
+}
+
+impl Request {
+    /// The session loaded by the [`Sessions`] middleware, or `None` if the
+    /// route doesn't use it.
+    pub fn session(&self) -> Option<Session> {
+        self.extensions().get::<Session>().cloned()
+    }
+}
+
+/// Middleware that loads and saves sessions through a [`SessionStore`].
+///
+/// The cookie is `HttpOnly`, `Secure`, `SameSite=Lax` and scoped to `/` by
+/// default. Sessions expire `ttl` (default 24 hours) after they were last
+/// modified.
+pub struct Sessions<S> {
+    store: Arc<S>,
+    ttl: Duration,
+    cookie: CookieSettings,
+}
+
+#[derive(Debug, Clone)]
+struct CookieSettings {
+    name: String,
+    secure: bool,
+    same_site: SameSite,
+    path: String,
+    domain: Option<String>,
+}
+
+impl CookieSettings {
+    fn scoped(&self, cookie: Cookie) -> Cookie {
+        let cookie = cookie.path(&self.path);
+        match &self.domain {
+            Some(domain) => cookie.domain(domain),
+            None => cookie,
+        }
+    }
+
+    fn session(&self, value: String, ttl: Duration) -> Cookie {
+        self.scoped(
+            Cookie::new(&self.name, value)
+                .http_only(true)
+                .secure(self.secure)
+                .same_site(self.same_site)
+                .max_age(ttl),
+        )
+    }
+
+    fn removal(&self) -> Cookie {
+        self.scoped(Cookie::removal(&self.name))
+    }
+}
+
+impl<S: SessionStore> Sessions<S> {
+    /// Keep sessions in `store`.
+    pub fn new(store: S) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            cookie: CookieSettings {
+                name: "session".to_string(),
+                secure: true,
+                same_site: SameSite::Lax,
+                path: "/".to_string(),
+                domain: None,
+            },
+        }
+    }
+
+    /// The session cookie's name.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie.name = name.into();
+        self
+    }
+
+    /// How long a session lives after its last modification.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Whether the cookie carries `Secure`. Disable only for local HTTP
+    /// testing.
+    pub fn secure(mut self, enabled: bool) -> Self {
+        self.cookie.secure = enabled;
+        self
+    }
+
+    /// The cookie's `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.cookie.same_site = same_site;
+        self
+    }
+
+    /// The cookie's `Path`.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.cookie.path = path.into();
+        self
+    }
+
+    /// The cookie's `Domain`.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.cookie.domain = Some(domain.into());
+        self
+    }
+}
+
+impl<S: SessionStore> Middleware for Sessions<S> {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let current = req.cookie(&self.cookie.name).filter(|c| !c.is_empty());
+        let store = Arc::clone(&self.store);
+        let ttl = self.ttl;
+        let settings = self.cookie.clone();
+        Box::pin(async move {
+            let data = match &current {
+                Some(cookie) => store.load(cookie).await?,
+                None => None,
+            };
+            let loaded = data.is_some();
+            let session = Session::new(data.unwrap_or_default());
+            req.extensions_mut().insert(session.clone());
+
+            let resp = next.run(req).await?;
+
+            let (data, changed, cleared) = {
+                let state = session.lock();
+                (state.data.clone(), state.changed, state.cleared)
+            };
+            if !changed {
+                return Ok(resp);
+            }
+            let mut current = current.filter(|_| loaded);
+            if cleared {
+                if let Some(cookie) = current.take() {
+                    store.destroy(&cookie).await?;
+                }
+            }
+            if data.is_empty() {
+                return Ok(if loaded {
+                    resp.with_cookie(settings.removal())
+                } else {
+                    resp
+                });
+            }
+            let value = store.save(current.as_deref(), &data, ttl).await?;
+            Ok(resp.with_cookie(settings.session(value, ttl)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use std::collections::HashMap;
+
+    async fn run(
+        mw: Sessions<CookieStore>,
+        cookie: Option<&str>,
+        handler: fn(Session),
+    ) -> Response {
+        let endpoint = move |req: Request| async move {
+            handler(req.session().unwrap());
+            Ok(Response::no_content())
+        };
+        let mut req = Request::default();
+        if let Some(cookie) = cookie {
+            req.headers = HashMap::from([("cookie".to_string(), format!("session={cookie}"))]);
+        }
+        run_middleware(mw, endpoint, req).await.unwrap()
+    }
+
+    fn set_cookie_value(resp: &Response) -> Option<String> {
+        let header = resp.multi_value_headers.get("Set-Cookie")?.first()?;
+        let (pair, _) = header.split_once(';').unwrap_or((header, ""));
+        Some(pair.split_once('=')?.1.to_string())
+    }
+
+    #[test]
+    fn session_get_set_remove() {
+        let session = Session::default();
+        session.set("user", "alice").unwrap();
+        assert_eq!(session.get::<String>("user").as_deref(), Some("alice"));
+        assert_eq!(session.get::<u32>("user"), None);
+        assert!(session.remove("user"));
+        assert!(session.is_empty());
+    }
+
+    #[tokio::test]
+    async fn untouched_session_sets_no_cookie() {
+        let resp = run(Sessions::new(CookieStore::signed("k")), None, |_| {}).await;
+        assert!(!resp.multi_value_headers.contains_key("Set-Cookie"));
+    }
+
+    #[tokio::test]
+    async fn modified_session_round_trips_through_cookie() {
+        let resp = run(Sessions::new(CookieStore::signed("k")), None, |s| {
+            s.set("n", &1).unwrap();
+        })
+        .await;
+        let header = &resp.multi_value_headers["Set-Cookie"][0];
+        assert!(header.contains("HttpOnly") && header.contains("Max-Age=86400"));
+        let value = set_cookie_value(&resp).unwrap();
+
+        let resp = run(Sessions::new(CookieStore::signed("k")), Some(&value), |s| {
+            assert_eq!(s.get::<u32>("n"), Some(1));
+            s.set("n", &2).unwrap();
+        })
+        .await;
+        assert!(set_cookie_value(&resp).is_some());
+    }
+
+    #[tokio::test]
+    async fn cleared_session_removes_cookie() {
+        let store = CookieStore::signed("k");
+        let mut data = SessionData::new();
+        data.insert("n".to_string(), Value::from(1));
+        let value = store
+            .save(None, &data, Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        let resp = run(Sessions::new(CookieStore::signed("k")), Some(&value), |s| {
+            s.clear()
+        })
+        .await;
+        assert_eq!(set_cookie_value(&resp).as_deref(), Some(""));
+    }
+}
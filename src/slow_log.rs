@@ -0,0 +1,256 @@
+//! Slow request logging.
+//!
+//! [`SlowLog`] prints a warning for every request slower than a threshold,
+//! flagging p99 outliers without exporting full traces. A sample of the
+//! warnings also carries the request's parameters and (redacted) headers:
+//!
+//! ```text
+//! {"level":"WARN","message":"slow request","method":"GET","route":"/reports/{id}","path":"/reports/7","status":200,"duration_ms":3120,"threshold_ms":1000,"request_id":"c6af9ac6-..."}
+//! ```
+//!
+//! # Example
+//! ```ignore
+//! use choko::slow_log::SlowLog;
+//! use std::time::Duration;
+//!
+//! app.middleware(SlowLog::new(Duration::from_secs(1)).sample_rate(0.1));
+//! // Reports are expected to take a while
+//! app.get("/reports/{id}", report).slow_threshold(Duration::from_secs(5));
+//! ```
+
+use crate::audit::{DEFAULT_HEADERS, REDACTED};
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, Route};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One slow request warning.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SlowRequest {
+    /// Always `WARN`.
+    pub level: &'static str,
+    /// Always `slow request`.
+    pub message: &'static str,
+    pub method: String,
+    /// The matched route pattern, e.g. `/users/{user_id}`.
+    pub route: Option<String>,
+    pub path: String,
+    /// The response status; 500 if the handler returned an error.
+    pub status: u16,
+    pub duration_ms: u64,
+    pub threshold_ms: u64,
+    /// The correlation ID, or else the API Gateway request ID.
+    pub request_id: Option<String>,
+    /// Request details, for sampled warnings only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<RequestDetails>,
+}
+
+/// What a sampled warning records about the request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RequestDetails {
+    pub path_params: BTreeMap<String, String>,
+    pub query_params: BTreeMap<String, Vec<String>>,
+    /// Headers, with credentials replaced by `[REDACTED]`.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl RequestDetails {
+    fn from_request(req: &Request) -> Self {
+        Self {
+            path_params: req.path_params.clone().into_iter().collect(),
+            query_params: req.query_params.clone().into_iter().collect(),
+            headers: req
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let value = if DEFAULT_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                        REDACTED.to_string()
+                    } else {
+                        value.clone()
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        }
+    }
+}
+
+type SinkFn = Arc<dyn Fn(&SlowRequest) + Send + Sync>;
+
+/// The threshold a route-level [`SlowLog`] sets for the app-wide one.
+#[derive(Clone)]
+struct ThresholdOverride(Arc<Mutex<Option<Duration>>>);
+
+/// Middleware warning about requests slower than a threshold.
+///
+/// Register it first so the duration covers the rest of the chain. When a
+/// route has its own `SlowLog` (see [`Route::slow_threshold`]), the route's
+/// threshold replaces the app-wide one instead of logging twice.
+pub struct SlowLog {
+    threshold: Duration,
+    sample_rate: f64,
+    sink: SinkFn,
+}
+
+impl SlowLog {
+    /// Warn about requests taking longer than `threshold`, on stdout.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            sample_rate: 0.0,
+            sink: Arc::new(|entry| match serde_json::to_string(entry) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("Failed to serialize slow request warning: {e}"),
+            }),
+        }
+    }
+
+    /// Fraction of warnings (0.0–1.0) that include [`RequestDetails`].
+    /// Default 0.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Send warnings to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&SlowRequest) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+
+    fn sampled(&self) -> bool {
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+        let roll = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        roll < self.sample_rate
+    }
+}
+
+impl Middleware for SlowLog {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        if let Some(cell) = req.extensions().get::<ThresholdOverride>() {
+            *cell.0.lock().unwrap_or_else(|e| e.into_inner()) = Some(self.threshold);
+            return next.run(req);
+        }
+        let started = Instant::now();
+        let cell = ThresholdOverride(Arc::new(Mutex::new(None)));
+        req.extensions_mut().insert(cell.clone());
+        let mut entry = SlowRequest {
+            level: "WARN",
+            message: "slow request",
+            method: req.method().to_string(),
+            route: req.route().map(str::to_string),
+            path: req.path().to_string(),
+            status: 0,
+            duration_ms: 0,
+            threshold_ms: 0,
+            request_id: req
+                .request_id()
+                .map(str::to_string)
+                .or_else(|| req.request_context().request_id.clone()),
+            request: None,
+        };
+        let details = self.sampled().then(|| RequestDetails::from_request(&req));
+        let default_threshold = self.threshold;
+        let sink = Arc::clone(&self.sink);
+        Box::pin(async move {
+            let result = next.run(req).await;
+            let elapsed = started.elapsed();
+            let threshold = cell
+                .0
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .unwrap_or(default_threshold);
+            if elapsed > threshold {
+                entry.status = match &result {
+                    Ok(resp) => u16::try_from(resp.status_code).unwrap_or(500),
+                    Err(_) => 500,
+                };
+                entry.duration_ms = elapsed.as_millis() as u64;
+                entry.threshold_ms = threshold.as_millis() as u64;
+                entry.request = details;
+                sink(&entry);
+            }
+            result
+        })
+    }
+}
+
+impl Route {
+    /// Warn when this route takes longer than `threshold`, overriding an
+    /// app-wide [`SlowLog`]'s threshold.
+    pub fn slow_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.middleware(SlowLog::new(threshold))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_chain;
+    use std::collections::HashMap;
+
+    /// Run `chain` around a handler that sleeps for `delay`, collecting
+    /// what it logs.
+    async fn run(chain: Vec<SlowLog>, delay: Duration) -> Vec<SlowRequest> {
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        let chain: Vec<Arc<dyn Middleware>> = chain
+            .into_iter()
+            .map(|mw| {
+                let captured = Arc::clone(&entries);
+                Arc::new(mw.sink(move |e| captured.lock().unwrap().push(e.clone())))
+                    as Arc<dyn Middleware>
+            })
+            .collect();
+        let req = Request {
+            headers: HashMap::from([("Authorization".to_string(), "Bearer x".to_string())]),
+            ..Default::default()
+        };
+        let sleeper = move |_req| async move {
+            tokio::time::sleep(delay).await;
+            Ok(Response::no_content())
+        };
+        run_chain(chain, sleeper, req).await.unwrap();
+        let mut entries = entries.lock().unwrap();
+        std::mem::take(&mut *entries)
+    }
+
+    #[tokio::test]
+    async fn logs_only_slow_requests() {
+        let slow = run(
+            vec![SlowLog::new(Duration::from_millis(5)).sample_rate(1.0)],
+            Duration::from_millis(20),
+        )
+        .await;
+        assert_eq!(slow.len(), 1);
+        assert_eq!(slow[0].status, 204);
+        assert_eq!(slow[0].threshold_ms, 5);
+        let details = slow[0].request.as_ref().unwrap();
+        assert_eq!(details.headers["Authorization"], REDACTED);
+
+        let fast = run(vec![SlowLog::new(Duration::from_secs(5))], Duration::ZERO).await;
+        assert!(fast.is_empty());
+    }
+
+    #[tokio::test]
+    async fn route_threshold_overrides_global() {
+        let entries = run(
+            vec![
+                SlowLog::new(Duration::from_millis(5)),
+                SlowLog::new(Duration::from_secs(5)),
+            ],
+            Duration::from_millis(20),
+        )
+        .await;
+        assert!(entries.is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+//! SQS queue consumers (`sqs` feature).
+//!
+//! [`Choko::sqs_queue`] registers a handler for the messages of one queue;
+//! [`Choko::run_sqs`] starts the app as the queue's Lambda consumer. Each
+//! message is deserialized and handled on its own, and the ones that fail
+//! are reported back as `batchItemFailures`, so only they are retried.
+//! Enable *ReportBatchItemFailures* on the event source mapping.
+//!
+//! # Example
+//! ```ignore
+//! #[derive(serde::Deserialize)]
+//! struct OrderPlaced { order_id: String }
+//!
+//! app.sqs_queue("orders", |msg: Message<OrderPlaced>| async move {
+//!     fulfil(&msg.body.order_id).await?;
+//!     Ok(())
+//! });
+//! app.run_sqs().await
+//! ```
+
+use crate::{BoxFuture, Choko, Error, LambdaContext};
+use aws_lambda_events::event::sqs::{
+    BatchItemFailure, SqsBatchResponse, SqsEvent, SqsMessage, SqsMessageAttribute,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type SqsHandlerFn =
+    Arc<dyn Fn(SqsMessage, Option<LambdaContext>) -> BoxFuture<Result<(), Error>> + Send + Sync>;
+
+/// A queue message with its body deserialized as `T`.
+///
+/// JSON bodies are deserialized as-is; a body that isn't JSON is treated
+/// as a string, so `Message<String>` accepts any text.
+#[derive(Debug, Clone)]
+pub struct Message<T> {
+    pub message_id: String,
+    pub body: T,
+    /// System attributes such as `ApproximateReceiveCount` and
+    /// `MessageGroupId`.
+    pub attributes: HashMap<String, String>,
+    /// Message attributes set by the sender.
+    pub message_attributes: HashMap<String, SqsMessageAttribute>,
+    queue_name: String,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl<T> Message<T> {
+    /// The name of the queue the message came from.
+    pub fn queue_name(&self) -> &str {
+        &self.queue_name
+    }
+
+    /// How many times the message has been received, including this time.
+    pub fn receive_count(&self) -> u32 {
+        self.attributes
+            .get("ApproximateReceiveCount")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(1)
+    }
+
+    /// A string message attribute by name.
+    pub fn message_attribute(&self, name: &str) -> Option<&str> {
+        self.message_attributes
+            .get(name)
+            .and_then(|a| a.string_value.as_deref())
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+}
+
+/// The queue name from an SQS ARN (`arn:aws:sqs:region:account:name`).
+fn queue_name(arn: Option<&str>) -> &str {
+    arn.and_then(|arn| arn.rsplit(':').next()).unwrap_or("")
+}
+
+impl Choko {
+    /// Handle the messages of the SQS queue named `queue` (the last part of
+    /// its ARN).
+    ///
+    /// A message fails if its body doesn't deserialize as `T` or the
+    /// handler returns an error. On FIFO queues (`.fifo`) the first failure
+    /// also fails every later message of the batch, so ordering holds on
+    /// retry.
+    pub fn sqs_queue<T, F, Fut>(&mut self, queue: &str, handler: F) -> &mut Self
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: Fn(Message<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.sqs_queues.insert(
+            queue.to_string(),
+            Arc::new(move |msg: SqsMessage, lambda_context| {
+                let handler = Arc::clone(&handler);
+                Box::pin(async move {
+                    let body: T = crate::codec::parse_message(msg.body.as_deref().unwrap_or(""))?;
+                    handler(Message {
+                        message_id: msg.message_id.unwrap_or_default(),
+                        body,
+                        attributes: msg.attributes,
+                        message_attributes: msg.message_attributes,
+                        queue_name: queue_name(msg.event_source_arn.as_deref()).to_string(),
+                        lambda_context,
+                    })
+                    .await
+                })
+            }),
+        );
+        self
+    }
+
+    /// Run the application as the Lambda consumer of its SQS queues.
+    pub async fn run_sqs(self) -> Result<(), Error> {
+        self.serve(|app, event: SqsEvent, context| async move {
+            Ok(app.dispatch_sqs(event, Some(context)).await)
+        })
+        .await
+    }
+
+    pub(crate) async fn dispatch_sqs(
+        &self,
+        event: SqsEvent,
+        context: Option<LambdaContext>,
+    ) -> SqsBatchResponse {
+        let mut failures = Vec::new();
+        let mut fifo_failed = false;
+        for msg in event.records {
+            let id = msg.message_id.clone().unwrap_or_default();
+            let queue = queue_name(msg.event_source_arn.as_deref()).to_string();
+            if fifo_failed {
+                failures.push(id);
+                continue;
+            }
+            let outcome = match self.sqs_queues.get(&queue) {
+                Some(handler) => handler(msg, context.clone()).await,
+                None => Err(format!("no handler for queue {queue:?}").into()),
+            };
+            if let Err(e) = outcome {
+                eprintln!("SQS message {id} from {queue} failed: {e}");
+                failures.push(id);
+                fifo_failed = queue.ends_with(".fifo");
+            }
+        }
+        let mut response = SqsBatchResponse::default();
+        response.batch_item_failures = failures
+            .into_iter()
+            .map(|id| {
+                let mut failure = BatchItemFailure::default();
+                failure.item_identifier = id;
+                failure
+            })
+            .collect();
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, queue: &str, body: &str) -> SqsMessage {
+        let mut msg = SqsMessage::default();
+        msg.message_id = Some(id.to_string());
+        msg.event_source_arn = Some(format!("arn:aws:sqs:eu-west-1:123456789012:{queue}"));
+        msg.body = Some(body.to_string());
+        msg
+    }
+
+    fn failed_ids(resp: &SqsBatchResponse) -> Vec<&str> {
+        resp.batch_item_failures
+            .iter()
+            .map(|f| f.item_identifier.as_str())
+            .collect()
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Job {
+        n: u32,
+    }
+
+    #[tokio::test]
+    async fn reports_only_failed_messages() {
+        let mut app = Choko::new("test");
+        app.sqs_queue("jobs", |msg: Message<Job>| async move {
+            if msg.body.n == 2 {
+                return Err("boom".into());
+            }
+            Ok(())
+        });
+        app.sqs_queue("notes", |msg: Message<String>| async move {
+            assert_eq!(msg.body, "plain text");
+            Ok(())
+        });
+
+        let mut event = SqsEvent::default();
+        event.records = vec![
+            message("1", "jobs", r#"{"n":1}"#),
+            message("2", "jobs", r#"{"n":2}"#),
+            message("3", "jobs", "not json"),
+            message("4", "notes", "plain text"),
+            message("5", "unknown", "{}"),
+        ];
+        let resp = app.dispatch_sqs(event, None).await;
+        assert_eq!(failed_ids(&resp), ["2", "3", "5"]);
+    }
+
+    #[tokio::test]
+    async fn fifo_failure_fails_the_rest_of_the_batch() {
+        let mut app = Choko::new("test");
+        app.sqs_queue("jobs.fifo", |msg: Message<Job>| async move {
+            if msg.body.n == 1 {
+                return Err("boom".into());
+            }
+            Ok(())
+        });
+
+        let mut event = SqsEvent::default();
+        event.records = vec![
+            message("a", "jobs.fifo", r#"{"n":0}"#),
+            message("b", "jobs.fifo", r#"{"n":1}"#),
+            message("c", "jobs.fifo", r#"{"n":2}"#),
+        ];
+        let resp = app.dispatch_sqs(event, None).await;
+        assert_eq!(failed_ids(&resp), ["b", "c"]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Enqueueing SQS messages from handlers (`sqs-sender` feature).
+//!
+//! [`SqsSender`] sends JSON messages to one queue. Messages can carry
+//! attributes and, with [`OutgoingMessage::propagate`], the request's W3C
+//! trace context and request ID, so consumers can continue the trace.
+//! FIFO queues (URLs ending in `.fifo`) require a message group.
+//!
+//! # Example
+//! ```ignore
+//! use choko::sqs_sender::SqsSender;
+//!
+//! let client = aws_sdk_sqs::Client::new(&aws_config::load_from_env().await);
+//! app.state(SqsSender::new(client, std::env::var("JOBS_QUEUE_URL")?));
+//!
+//! app.post("/reports", |req| async move {
+//!     let jobs = req.state::<SqsSender>().ok_or("queue not configured")?;
+//!     let id = jobs
+//!         .message(&req.json_body)?
+//!         .attribute("kind", "report")
+//!         .propagate(&req)
+//!         .send()
+//!         .await?;
+//!     Ok(Response::accepted(json!({ "job": id })))
+//! });
+//! ```
+
+use crate::{Error, Request};
+use aws_sdk_sqs::types::{MessageAttributeValue, SendMessageBatchRequestEntry};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// SQS's limit on messages per batch request.
+const BATCH_SIZE: usize = 10;
+
+/// Sends messages to one SQS queue. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct SqsSender {
+    client: aws_sdk_sqs::Client,
+    queue_url: String,
+}
+
+impl SqsSender {
+    pub fn new(client: aws_sdk_sqs::Client, queue_url: impl Into<String>) -> Self {
+        Self {
+            client,
+            queue_url: queue_url.into(),
+        }
+    }
+
+    pub fn queue_url(&self) -> &str {
+        &self.queue_url
+    }
+
+    /// Whether the queue is a FIFO queue.
+    pub fn is_fifo(&self) -> bool {
+        self.queue_url.ends_with(".fifo")
+    }
+
+    /// Send `body` as JSON and return the message ID.
+    pub async fn send<T: Serialize + ?Sized>(&self, body: &T) -> Result<String, Error> {
+        self.message(body)?.send().await
+    }
+
+    /// Start a message with `body` serialized as JSON, to add attributes or
+    /// FIFO settings before sending it.
+    pub fn message<T: Serialize + ?Sized>(&self, body: &T) -> Result<OutgoingMessage<'_>, Error> {
+        Ok(OutgoingMessage {
+            sender: self,
+            body: serde_json::to_string(body)?,
+            attributes: HashMap::new(),
+            group_id: None,
+            deduplication_id: None,
+            delay: None,
+        })
+    }
+
+    /// Send each of `bodies` as JSON, ten per request. Returns the indices
+    /// of the messages SQS rejected, so they can be retried.
+    pub async fn send_batch<T: Serialize>(&self, bodies: &[T]) -> Result<Vec<usize>, Error> {
+        let mut failed = Vec::new();
+        for (chunk_index, chunk) in bodies.chunks(BATCH_SIZE).enumerate() {
+            let entries = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, body)| {
+                    Ok(SendMessageBatchRequestEntry::builder()
+                        .id(i.to_string())
+                        .message_body(serde_json::to_string(body)?)
+                        .build()?)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let output = self
+                .client
+                .send_message_batch()
+                .queue_url(&self.queue_url)
+                .set_entries(Some(entries))
+                .send()
+                .await?;
+            for entry in output.failed() {
+                if let Ok(i) = entry.id().parse::<usize>() {
+                    failed.push(chunk_index * BATCH_SIZE + i);
+                }
+            }
+        }
+        failed.sort_unstable();
+        Ok(failed)
+    }
+}
+
+/// A message being built by [`SqsSender::message`].
+pub struct OutgoingMessage<'a> {
+    sender: &'a SqsSender,
+    body: String,
+    attributes: HashMap<String, MessageAttributeValue>,
+    group_id: Option<String>,
+    deduplication_id: Option<String>,
+    delay: Option<Duration>,
+}
+
+impl OutgoingMessage<'_> {
+    /// Add a string message attribute.
+    pub fn attribute(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.insert(name, "String", value.into());
+        self
+    }
+
+    /// Add a number message attribute.
+    pub fn number_attribute(mut self, name: &str, value: impl std::fmt::Display) -> Self {
+        self.insert(name, "Number", value.to_string());
+        self
+    }
+
+    /// Copy the request's `traceparent`, `tracestate` and request ID into
+    /// message attributes of the same names.
+    pub fn propagate(mut self, req: &Request) -> Self {
+        if let Some(trace) = req.trace_context() {
+            for (name, value) in trace.headers() {
+                self.insert(name, "String", value);
+            }
+        }
+        if let Some(id) = req.request_id() {
+            self.insert("request-id", "String", id.to_string());
+        }
+        self
+    }
+
+    /// The FIFO message group; messages in a group are delivered in order.
+    pub fn group(mut self, group_id: impl Into<String>) -> Self {
+        self.group_id = Some(group_id.into());
+        self
+    }
+
+    /// The FIFO deduplication ID; messages with the same ID sent within five
+    /// minutes are delivered once. Not needed when the queue has
+    /// content-based deduplication.
+    pub fn deduplication_id(mut self, id: impl Into<String>) -> Self {
+        self.deduplication_id = Some(id.into());
+        self
+    }
+
+    /// Deliver the message after `delay` (up to 15 minutes). Standard
+    /// queues only.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    fn insert(&mut self, name: &str, data_type: &str, value: String) {
+        let attribute = MessageAttributeValue::builder()
+            .data_type(data_type)
+            .string_value(value)
+            .build()
+            .expect("data type is set");
+        self.attributes.insert(name.to_string(), attribute);
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.sender.is_fifo() && self.group_id.is_none() {
+            return Err("messages to a FIFO queue need a message group".into());
+        }
+        if self.sender.is_fifo() && self.delay.is_some() {
+            return Err("FIFO queues don't support per-message delays".into());
+        }
+        Ok(())
+    }
+
+    /// Send the message and return its ID.
+    pub async fn send(self) -> Result<String, Error> {
+        self.validate()?;
+        let delay = self
+            .delay
+            .map(|d| i32::try_from(d.as_secs()).unwrap_or(i32::MAX));
+        let output = self
+            .sender
+            .client
+            .send_message()
+            .queue_url(&self.sender.queue_url)
+            .message_body(self.body)
+            .set_message_attributes((!self.attributes.is_empty()).then_some(self.attributes))
+            .set_message_group_id(self.group_id)
+            .set_message_deduplication_id(self.deduplication_id)
+            .set_delay_seconds(delay)
+            .send()
+            .await?;
+        Ok(output.message_id().unwrap_or_default().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sender(url: &str) -> SqsSender {
+        let config = aws_sdk_sqs::Config::builder()
+            .behavior_version(aws_sdk_sqs::config::BehaviorVersion::latest())
+            .build();
+        SqsSender::new(aws_sdk_sqs::Client::from_conf(config), url)
+    }
+
+    #[test]
+    fn builds_messages_with_attributes() {
+        let sender = sender("https://sqs.eu-west-1.amazonaws.com/123456789012/jobs");
+        assert!(!sender.is_fifo());
+        let message = sender
+            .message(&json!({ "report": 7 }))
+            .unwrap()
+            .attribute("kind", "report")
+            .number_attribute("priority", 2);
+        assert_eq!(message.body, r#"{"report":7}"#);
+        assert_eq!(message.attributes["kind"].string_value(), Some("report"));
+        assert_eq!(message.attributes["priority"].data_type(), "Number");
+        assert!(message.validate().is_ok());
+    }
+
+    #[test]
+    fn fifo_messages_need_a_group() {
+        let sender = sender("https://sqs.eu-west-1.amazonaws.com/123456789012/jobs.fifo");
+        assert!(sender.is_fifo());
+        let message = sender.message("x").unwrap();
+        assert!(message.validate().is_err());
+        let message = sender
+            .message("x")
+            .unwrap()
+            .group("user-42")
+            .deduplication_id("order-7");
+        assert!(message.validate().is_ok());
+        let message = sender
+            .message("x")
+            .unwrap()
+            .group("g")
+            .delay(Duration::from_secs(5));
+        assert!(message.validate().is_err());
+    }
+
+    #[test]
+    fn propagates_trace_context() {
+        let mut req = Request::default();
+        req.extensions_mut()
+            .insert(crate::trace_context::TraceContext::from_headers(|name| {
+                (name == "traceparent")
+                    .then_some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+            }));
+        let sender = sender("https://sqs.eu-west-1.amazonaws.com/123456789012/jobs");
+        let message = sender.message("x").unwrap().propagate(&req);
+        let traceparent = message.attributes["traceparent"].string_value().unwrap();
+        assert!(traceparent.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Server-Sent Events responses.
+
+use crate::stream::{BodySender, BodyStream, StreamClosed};
+use crate::Response;
+use std::time::Duration;
+
+/// A single Server-Sent Events frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseEvent {
+    /// An event carrying `data`. Multi-line data is split across `data:` lines.
+    pub fn data(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// An event whose data is `value` serialized as JSON.
+    pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::data(serde_json::to_string(value)?))
+    }
+
+    /// Set the event name (`event:`).
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(name.into());
+        self
+    }
+
+    /// Set the event ID (`id:`), echoed back by browsers as `Last-Event-ID`.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the client reconnection delay (`retry:`).
+    pub fn retry(mut self, delay: Duration) -> Self {
+        self.retry = Some(delay);
+        self
+    }
+
+    /// Encode the event as a wire frame, terminated by a blank line.
+    pub fn encode(&self) -> String {
+        let mut frame = String::new();
+        if let Some(event) = &self.event {
+            frame.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            frame.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(retry) = self.retry {
+            frame.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in self.data.split('\n') {
+            frame.push_str("data: ");
+            frame.push_str(line.strip_suffix('\r').unwrap_or(line));
+            frame.push('\n');
+        }
+        frame.push('\n');
+        frame
+    }
+}
+
+/// Newlines would end the field early, so they are dropped from single-line fields.
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], "")
+}
+
+/// Sends events to an [`SseResponse`].
+#[derive(Clone)]
+pub struct SseSender {
+    inner: BodySender,
+}
+
+impl SseSender {
+    /// Send an event to the client.
+    pub async fn send(&self, event: SseEvent) -> Result<(), StreamClosed> {
+        self.inner.send(event.encode()).await
+    }
+
+    /// Send a comment line, e.g. as a keep-alive.
+    pub async fn comment(&self, text: &str) -> Result<(), StreamClosed> {
+        self.inner
+            .send(format!(": {}\n\n", single_line(text)))
+            .await
+    }
+}
+
+/// A `text/event-stream` response fed by an [`SseSender`].
+///
+/// The response completes when every sender has been dropped. Events reach
+/// the client as they are sent with [`Choko::listen`](crate::Choko::listen)
+/// (`server` feature) and
+/// [`Choko::run_function_url_streaming`](crate::Choko::run_function_url_streaming)
+/// (`function-url` feature). API Gateway, ALB and buffered Function URL
+/// integrations return the whole response at once, so there the client
+/// receives every event together when the response completes.
+///
+/// # Example
+/// ```ignore
+/// app.get("/jobs/{id}/progress", |req| async move {
+///     let (sse, tx) = SseResponse::channel();
+///     tokio::spawn(async move {
+///         for pct in [25, 50, 75, 100] {
+///             let _ = tx.send(SseEvent::data(pct.to_string()).event("progress")).await;
+///         }
+///     });
+///     Ok(sse.into())
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SseResponse {
+    stream: BodyStream,
+}
+
+impl SseResponse {
+    /// Create an SSE response and the sender that produces its events.
+    pub fn channel() -> (SseResponse, SseSender) {
+        let (inner, stream) = BodyStream::channel(16);
+        (SseResponse { stream }, SseSender { inner })
+    }
+}
+
+impl From<SseResponse> for Response {
+    fn from(sse: SseResponse) -> Self {
+        Response::stream(sse.stream)
+            .with_header("Content-Type", "text/event-stream")
+            .with_header("Cache-Control", "no-cache")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+
+    #[test]
+    fn encode_formats_fields_and_multiline_data() {
+        let frame = SseEvent::data("line1\nline2")
+            .event("progress")
+            .id("7")
+            .retry(Duration::from_secs(3))
+            .encode();
+        assert_eq!(
+            frame,
+            "event: progress\nid: 7\nretry: 3000\ndata: line1\ndata: line2\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn sse_response_collects_events() {
+        let (sse, tx) = SseResponse::channel();
+        tokio::spawn(async move {
+            tx.send(SseEvent::data("a")).await.unwrap();
+            tx.comment("ping").await.unwrap();
+            tx.send(SseEvent::json(&serde_json::json!({"pct": 50})).unwrap())
+                .await
+                .unwrap();
+        });
+
+        let resp = Response::from(sse).buffered().await.unwrap();
+        assert_eq!(
+            resp.headers.get("Content-Type").unwrap(),
+            "text/event-stream"
+        );
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text("data: a\n\n: ping\n\ndata: {\"pct\":50}\n\n".to_string())
+        );
+    }
+}
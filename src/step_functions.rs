@@ -0,0 +1,327 @@
+//! Starting Step Functions executions from handlers (`step-functions`
+//! feature).
+//!
+//! [`StateMachine`] starts executions of one state machine with JSON input.
+//! Standard workflows are started asynchronously; express workflows can
+//! also run synchronously with [`ExecutionStart::run`], which waits for
+//! their output.
+//!
+//! Executions can be named, or named after a deduplication key with
+//! [`ExecutionStart::dedupe`], so that a retried request doesn't start the
+//! workflow twice. Step Functions only enforces unique names for standard
+//! workflows, for 90 days.
+//!
+//! # Example
+//! ```ignore
+//! use choko::step_functions::StateMachine;
+//!
+//! let client = aws_sdk_sfn::Client::new(&aws_config::load_from_env().await);
+//! app.state(StateMachine::new(client, std::env::var("FULFILMENT_ARN")?));
+//!
+//! app.post("/orders/{id}/fulfil", |req| async move {
+//!     let workflow = req.state::<StateMachine>().ok_or("workflow not configured")?;
+//!     let id = &req.path_params["id"];
+//!     let execution = workflow
+//!         .execution(&json!({ "order": id }))?
+//!         .dedupe(format!("fulfil-{id}"))
+//!         .propagate(&req)
+//!         .start()
+//!         .await?;
+//!     Ok(Response::accepted(json!({ "execution": execution.arn })))
+//! });
+//! ```
+
+use crate::{Error, Request};
+use aws_sdk_sfn::operation::start_execution::StartExecutionError;
+use aws_sdk_sfn::types::SyncExecutionStatus;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Step Functions' limit on execution input, in bytes.
+const MAX_INPUT: usize = 256 * 1024;
+
+/// Step Functions' limit on execution names.
+const MAX_NAME: usize = 80;
+
+/// Starts executions of one state machine. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct StateMachine {
+    client: aws_sdk_sfn::Client,
+    arn: String,
+}
+
+impl StateMachine {
+    pub fn new(client: aws_sdk_sfn::Client, arn: impl Into<String>) -> Self {
+        Self {
+            client,
+            arn: arn.into(),
+        }
+    }
+
+    pub fn arn(&self) -> &str {
+        &self.arn
+    }
+
+    /// Start an execution with `input` serialized as JSON.
+    pub async fn start<T: Serialize + ?Sized>(&self, input: &T) -> Result<Execution, Error> {
+        self.execution(input)?.start().await
+    }
+
+    /// Prepare an execution with `input` serialized as JSON, to name it or
+    /// propagate the trace before starting it.
+    pub fn execution<T: Serialize + ?Sized>(&self, input: &T) -> Result<ExecutionStart<'_>, Error> {
+        let input = serde_json::to_string(input)?;
+        if input.len() > MAX_INPUT {
+            return Err(format!(
+                "execution input is {} bytes, over the {MAX_INPUT} byte limit",
+                input.len()
+            )
+            .into());
+        }
+        Ok(ExecutionStart {
+            machine: self,
+            input,
+            name: None,
+            trace_header: None,
+        })
+    }
+
+    /// The ARN of the execution of this state machine named `name`.
+    fn execution_arn(&self, name: &str) -> Option<String> {
+        let (prefix, machine) = self.arn.split_once(":stateMachine:")?;
+        Some(format!("{prefix}:execution:{machine}:{name}"))
+    }
+}
+
+/// A started execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub arn: String,
+    /// `false` when an execution with the same name had already been
+    /// started, so this call started nothing.
+    pub started: bool,
+}
+
+/// The result of a synchronous express execution.
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    pub arn: String,
+    pub status: SyncExecutionStatus,
+    /// The execution's JSON output, when it succeeded.
+    pub output: Option<String>,
+    /// The error name and cause, when it failed or timed out.
+    pub error: Option<String>,
+    pub cause: Option<String>,
+}
+
+impl ExecutionResult {
+    pub fn succeeded(&self) -> bool {
+        self.status == SyncExecutionStatus::Succeeded
+    }
+
+    /// Deserialize the output of a successful execution; a failed or timed
+    /// out execution is an error carrying its error name and cause.
+    pub fn output<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        if !self.succeeded() {
+            return Err(format!(
+                "execution {} {}: {} ({})",
+                self.arn,
+                self.status.as_str().to_ascii_lowercase(),
+                self.error.as_deref().unwrap_or("unknown error"),
+                self.cause.as_deref().unwrap_or("no cause given")
+            )
+            .into());
+        }
+        Ok(serde_json::from_str(
+            self.output.as_deref().unwrap_or("null"),
+        )?)
+    }
+}
+
+/// An execution being prepared by [`StateMachine::execution`].
+pub struct ExecutionStart<'a> {
+    machine: &'a StateMachine,
+    input: String,
+    name: Option<String>,
+    trace_header: Option<String>,
+}
+
+impl ExecutionStart<'_> {
+    /// Name the execution: 1-80 letters, digits, `-` or `_`. Without a
+    /// name Step Functions generates a UUID.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Name the execution after a hash of `key` (e.g. an order ID or an
+    /// `Idempotency-Key`), so starting it again with the same key returns
+    /// the existing execution of a standard workflow instead of a second
+    /// one.
+    pub fn dedupe(self, key: impl AsRef<[u8]>) -> Self {
+        let name = hex::encode(Sha256::digest(key.as_ref()));
+        self.name(name)
+    }
+
+    /// Continue the invocation's X-Ray trace in the execution.
+    pub fn propagate(mut self, req: &Request) -> Self {
+        self.trace_header = req
+            .lambda_context()
+            .and_then(|ctx| ctx.xray_trace_id())
+            .map(str::to_string);
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let Some(name) = &self.name else {
+            return Ok(());
+        };
+        let valid = (1..=MAX_NAME).contains(&name.len())
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(format!(
+                "invalid execution name {name:?}: use 1-{MAX_NAME} letters, digits, '-' or '_'"
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Start the execution without waiting for it.
+    ///
+    /// If a standard workflow already has an execution with this name, that
+    /// execution is returned with `started: false`.
+    pub async fn start(self) -> Result<Execution, Error> {
+        self.validate()?;
+        let result = self
+            .machine
+            .client
+            .start_execution()
+            .state_machine_arn(&self.machine.arn)
+            .input(self.input)
+            .set_name(self.name.clone())
+            .set_trace_header(self.trace_header)
+            .send()
+            .await;
+        let e = match result {
+            Ok(output) => {
+                return Ok(Execution {
+                    arn: output.execution_arn().to_string(),
+                    started: true,
+                })
+            }
+            Err(e) => e,
+        };
+        let existing = match (e.as_service_error(), &self.name) {
+            (Some(StartExecutionError::ExecutionAlreadyExists(_)), Some(name)) => {
+                self.machine.execution_arn(name)
+            }
+            _ => None,
+        };
+        match existing {
+            Some(arn) => Ok(Execution {
+                arn,
+                started: false,
+            }),
+            None => Err(e.into()),
+        }
+    }
+
+    /// Run an express workflow synchronously and wait for its result.
+    ///
+    /// Express executions run for up to five minutes; leave enough of the
+    /// Lambda timeout for them.
+    pub async fn run(self) -> Result<ExecutionResult, Error> {
+        self.validate()?;
+        let output = self
+            .machine
+            .client
+            .start_sync_execution()
+            .state_machine_arn(&self.machine.arn)
+            .input(self.input)
+            .set_name(self.name)
+            .set_trace_header(self.trace_header)
+            .send()
+            .await?;
+        Ok(ExecutionResult {
+            arn: output.execution_arn().to_string(),
+            status: output.status().clone(),
+            output: output.output().map(str::to_string),
+            error: output.error().map(str::to_string),
+            cause: output.cause().map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ARN: &str = "arn:aws:states:eu-west-1:123456789012:stateMachine:fulfilment";
+
+    fn machine() -> StateMachine {
+        let config = aws_sdk_sfn::Config::builder()
+            .behavior_version(aws_sdk_sfn::config::BehaviorVersion::latest())
+            .build();
+        StateMachine::new(aws_sdk_sfn::Client::from_conf(config), ARN)
+    }
+
+    #[test]
+    fn names_executions() {
+        let machine = machine();
+        let start = machine.execution(&json!({ "order": 7 })).unwrap();
+        assert_eq!(start.input, r#"{"order":7}"#);
+        assert!(start.validate().is_ok());
+
+        let a = machine.execution("x").unwrap().dedupe("order-7");
+        let b = machine.execution("y").unwrap().dedupe("order-7");
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.name.as_ref().unwrap().len(), 64);
+        assert!(a.validate().is_ok());
+
+        for name in ["", "has space", &"x".repeat(81)] {
+            assert!(machine
+                .execution("x")
+                .unwrap()
+                .name(name)
+                .validate()
+                .is_err());
+        }
+        assert_eq!(
+            machine.execution_arn("order-7").unwrap(),
+            "arn:aws:states:eu-west-1:123456789012:execution:fulfilment:order-7"
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_input() {
+        let input = "x".repeat(MAX_INPUT);
+        assert!(machine().execution(&input).is_err());
+    }
+
+    #[test]
+    fn reads_sync_results() {
+        let result = ExecutionResult {
+            arn: "arn".to_string(),
+            status: SyncExecutionStatus::Succeeded,
+            output: Some(r#"{"total":12}"#.to_string()),
+            error: None,
+            cause: None,
+        };
+        assert_eq!(result.output::<serde_json::Value>().unwrap()["total"], 12);
+
+        let failed = ExecutionResult {
+            status: SyncExecutionStatus::Failed,
+            output: None,
+            error: Some("PaymentDeclined".to_string()),
+            cause: Some("card expired".to_string()),
+            ..result
+        };
+        let e = failed.output::<serde_json::Value>().unwrap_err();
+        assert!(e.to_string().contains("PaymentDeclined"));
+    }
+}
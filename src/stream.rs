@@ -0,0 +1,122 @@
+//! Response bodies produced incrementally through a channel.
+//!
+//! The container server ([`Choko::listen`](crate::Choko::listen), `server`
+//! feature) and Function URLs in response streaming mode
+//! ([`Choko::run_function_url_streaming`](crate::Choko::run_function_url_streaming),
+//! `function-url` feature) send each chunk to the client as it is produced.
+//!
+//! API Gateway, ALB and buffered Function URL integrations return the whole
+//! response at once, so there a streamed body is collected in memory
+//! before it is sent and is subject to the Lambda response payload limit
+//! (6 MB). Producers still avoid materialising an intermediate
+//! representation (such as one large JSON array) and can run concurrently
+//! with other work in the handler.
+
+use crate::Error;
+use std::fmt;
+#[cfg(feature = "server")]
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// The receiving half of a streamed response body.
+pub struct BodyStream {
+    rx: mpsc::Receiver<Result<Vec<u8>, Error>>,
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyStream").finish_non_exhaustive()
+    }
+}
+
+/// The producing half of a streamed response body.
+///
+/// The body ends when every sender has been dropped.
+#[derive(Clone)]
+pub struct BodySender {
+    tx: mpsc::Sender<Result<Vec<u8>, Error>>,
+}
+
+/// The response has been dropped and no longer accepts chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamClosed;
+
+impl fmt::Display for StreamClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("response stream closed")
+    }
+}
+
+impl std::error::Error for StreamClosed {}
+
+impl BodyStream {
+    /// Create a body channel holding up to `buffer` pending chunks.
+    pub fn channel(buffer: usize) -> (BodySender, BodyStream) {
+        let (tx, rx) = mpsc::channel(buffer.max(1));
+        (BodySender { tx }, BodyStream { rx })
+    }
+
+    /// Wait for the next chunk; `None` once every sender has been dropped.
+    pub(crate) async fn next_chunk(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        self.rx.recv().await
+    }
+
+    /// Poll for the next chunk; `None` once every sender has been dropped.
+    #[cfg(feature = "server")]
+    pub(crate) fn poll_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<u8>, Error>>> {
+        self.rx.poll_recv(cx)
+    }
+
+    /// Wait for the producer to finish and concatenate every chunk.
+    pub(crate) async fn collect(mut self) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        while let Some(chunk) = self.next_chunk().await {
+            out.extend_from_slice(&chunk?);
+        }
+        Ok(out)
+    }
+}
+
+impl BodySender {
+    /// Append a chunk to the body.
+    pub async fn send(&self, chunk: impl Into<Vec<u8>>) -> Result<(), StreamClosed> {
+        self.tx
+            .send(Ok(chunk.into()))
+            .await
+            .map_err(|_| StreamClosed)
+    }
+
+    /// Abort the body with an error; the request then fails with a 500.
+    pub async fn abort(&self, error: impl Into<Error>) {
+        let _ = self.tx.send(Err(error.into())).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn collect_concatenates_chunks_until_senders_drop() {
+        let (tx, stream) = BodyStream::channel(2);
+        tokio::spawn(async move {
+            for i in 0..5 {
+                tx.send(format!("{i},")).await.unwrap();
+            }
+        });
+        assert_eq!(stream.collect().await.unwrap(), b"0,1,2,3,4,");
+    }
+
+    #[tokio::test]
+    async fn abort_fails_the_body() {
+        let (tx, stream) = BodyStream::channel(2);
+        tokio::spawn(async move {
+            tx.send("partial").await.unwrap();
+            tx.abort("database went away").await;
+        });
+        assert!(stream.collect().await.is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! Partial batch responses shared by the stream consumers.
+
+use serde::Serialize;
+
+/// The `batchItemFailures` response Lambda checkpoints stream batches with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamBatchResponse {
+    pub batch_item_failures: Vec<BatchItemFailure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItemFailure {
+    /// The sequence number of the failed record.
+    pub item_identifier: String,
+}
+
+impl StreamBatchResponse {
+    /// Report the batch as processed up to, but not including, the record
+    /// with `sequence_number`; `None` when the whole batch succeeded.
+    pub(crate) fn failed_at(sequence_number: Option<String>) -> Self {
+        Self {
+            batch_item_failures: sequence_number
+                .map(|item_identifier| BatchItemFailure { item_identifier })
+                .into_iter()
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! `tracing` instrumentation (`tracing` feature).
+//!
+//! Every matched request runs inside a `request` span carrying the method,
+//! route pattern, request IDs and correlation ID; when it completes, the status and latency
+//! are recorded on the span and a `request completed` event is emitted.
+//! Handlers' own `tracing` events inherit the span's fields.
+//!
+//! With `tracing-json`, [`init_json`] installs a subscriber writing one JSON
+//! object per event to stdout, the format CloudWatch Logs Insights parses
+//! out of the box. With `otlp`, [`init_otlp`] additionally exports spans
+//! to an OpenTelemetry collector.
+
+#[cfg(feature = "otlp")]
+mod otlp;
+
+#[cfg(feature = "otlp")]
+pub(crate) use otlp::set_parent;
+#[cfg(feature = "otlp")]
+pub use otlp::{flush, init_otlp};
+
+use std::time::Duration;
+use tracing::field::Empty;
+use tracing::Span;
+
+/// The span a request is dispatched in.
+pub(crate) fn request_span(
+    method: &str,
+    route: &str,
+    lambda_request_id: Option<&str>,
+    apigw_request_id: Option<&str>,
+    request_id: Option<&str>,
+) -> Span {
+    tracing::info_span!(
+        "request",
+        http.method = method,
+        http.route = route,
+        lambda.request_id = lambda_request_id.unwrap_or_default(),
+        apigw.request_id = apigw_request_id.unwrap_or_default(),
+        request_id = request_id.unwrap_or_default(),
+        http.status_code = Empty,
+        latency_ms = Empty,
+    )
+}
+
+/// Record the outcome on `span` and log completion.
+pub(crate) fn finish(span: &Span, status: i64, latency: Duration) {
+    let latency_ms = latency.as_millis() as u64;
+    span.record("http.status_code", status);
+    span.record("latency_ms", latency_ms);
+    let _entered = span.enter();
+    if status >= 500 {
+        tracing::error!(status, latency_ms, "request completed");
+    } else {
+        tracing::info!(status, latency_ms, "request completed");
+    }
+}
+
+/// Install a global JSON subscriber on stdout, filtered by `RUST_LOG`
+/// (default `info`). Events include the fields of the current request span.
+///
+/// Fails if a global subscriber is already installed.
+///
+/// # Example
+/// ```ignore
+/// #[tokio::main]
+/// async fn main() -> Result<(), choko::Error> {
+///     choko::telemetry::init_json()?;
+///     let mut app = Choko::new("orders");
+///     // ...
+///     app.run().await
+/// }
+/// ```
+#[cfg(feature = "tracing-json")]
+pub fn init_json() -> Result<(), crate::Error> {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_target(false)
+        .try_init()
+}
@@ -0,0 +1,127 @@
+//! OpenTelemetry export over OTLP (`otlp` feature).
+
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/// Install a global subscriber exporting spans over OTLP/gRPC, alongside
+/// the JSON log output of [`init_json`](super::init_json).
+///
+/// The exporter honours the standard `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// (default `http://localhost:4317`, where the ADOT Lambda layer's
+/// collector listens). Request spans continue the W3C trace context of
+/// incoming `traceparent`/`tracestate` headers, and spans are flushed at
+/// the end of every invocation, before the environment is frozen.
+///
+/// Fails if a global subscriber is already installed.
+///
+/// # Example
+/// ```ignore
+/// #[tokio::main]
+/// async fn main() -> Result<(), choko::Error> {
+///     choko::telemetry::init_otlp("orders")?;
+///     let mut app = Choko::new("orders");
+///     // ...
+///     app.run().await
+/// }
+/// ```
+pub fn init_otlp(service_name: &str) -> Result<(), crate::Error> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build();
+    let tracer = provider.tracer("choko");
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    PROVIDER
+        .set(provider)
+        .map_err(|_| "OTLP export is already initialized")?;
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_target(false),
+        )
+        .try_init()?;
+    Ok(())
+}
+
+/// Export every finished span now. Called by the dispatcher after each
+/// invocation; a no-op unless [`init_otlp`] ran.
+pub async fn flush() {
+    let Some(provider) = PROVIDER.get() else {
+        return;
+    };
+    // Flushing blocks until the batch processor's task has exported
+    let flushed = tokio::task::spawn_blocking(move || provider.force_flush()).await;
+    for result in flushed.into_iter().flatten() {
+        if let Err(e) = result {
+            eprintln!("Failed to flush OTLP spans: {e}");
+        }
+    }
+}
+
+/// Request headers as an OpenTelemetry propagation carrier, matching
+/// names case-insensitively.
+struct HeaderExtractor<'a>(&'a HashMap<String, String>);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
+
+/// Make `span` a child of the trace context carried by `headers`, if any.
+pub(crate) fn set_parent(span: &Span, headers: &HashMap<String, String>) {
+    let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    span.set_parent(context);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extractor_matches_any_case() {
+        let headers = HashMap::from([(
+            "TraceParent".to_string(),
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string(),
+        )]);
+        let extractor = HeaderExtractor(&headers);
+        assert!(extractor.get("traceparent").unwrap().starts_with("00-0af7"));
+        assert_eq!(extractor.get("tracestate"), None);
+    }
+}
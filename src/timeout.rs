@@ -0,0 +1,142 @@
+//! Handler timeouts.
+//!
+//! When a function runs past its configured timeout the runtime kills it
+//! and API Gateway answers 502 with nothing in the logs to say which
+//! request hung. [`Timeout`] instead races the rest of the chain against a
+//! deadline that ends a safety margin before the Lambda deadline, returning
+//! 504 and logging a warning so the invocation finishes cleanly.
+//!
+//! # Example
+//! ```ignore
+//! use choko::timeout::Timeout;
+//! use std::time::Duration;
+//!
+//! // Stop every request shortly before the Lambda deadline...
+//! app.middleware(Timeout::lambda_deadline());
+//! // ...and give this route at most 3 seconds
+//! app.get("/reports/{id}", report).timeout(Duration::from_secs(3));
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response, Route};
+use std::time::{Duration, Instant};
+
+/// Time kept in reserve before the Lambda deadline by default.
+pub const DEFAULT_MARGIN: Duration = Duration::from_millis(500);
+
+/// Middleware answering 504 when the rest of the chain runs too long.
+///
+/// The time allowed is the configured limit or the time left before the
+/// Lambda deadline minus the margin, whichever is shorter. The handler
+/// future is dropped when the time runs out.
+#[derive(Debug, Clone)]
+pub struct Timeout {
+    limit: Option<Duration>,
+    margin: Duration,
+}
+
+impl Timeout {
+    /// Allow at most `limit`, and never past the Lambda deadline.
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            limit: Some(limit),
+            margin: DEFAULT_MARGIN,
+        }
+    }
+
+    /// Allow as long as the Lambda deadline permits.
+    pub fn lambda_deadline() -> Self {
+        Self {
+            limit: None,
+            margin: DEFAULT_MARGIN,
+        }
+    }
+
+    /// Time to keep in reserve before the Lambda deadline (default 500ms),
+    /// for the response to be sent and logs flushed.
+    pub fn margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// The time `req` may take, or `None` for no limit (no configured
+    /// limit and no Lambda context).
+    fn budget(&self, req: &Request) -> Option<Duration> {
+        let remaining = req
+            .lambda_context()
+            .map(|ctx| ctx.remaining_time().saturating_sub(self.margin));
+        match (self.limit, remaining) {
+            (Some(limit), Some(remaining)) => Some(limit.min(remaining)),
+            (limit, remaining) => limit.or(remaining),
+        }
+    }
+}
+
+impl Middleware for Timeout {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let Some(budget) = self.budget(&req) else {
+            return next.run(req);
+        };
+        let method = req.method().to_string();
+        let route = req.route().unwrap_or(req.path()).to_string();
+        Box::pin(async move {
+            let started = Instant::now();
+            match tokio::time::timeout(budget, next.run(req)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    eprintln!(
+                        "Handler for {method} {route} timed out after {}ms",
+                        started.elapsed().as_millis()
+                    );
+                    Ok(crate::error_json(504, "Gateway Timeout"))
+                }
+            }
+        })
+    }
+}
+
+impl Route {
+    /// Answer 504 if this route's handler takes longer than `limit` (or
+    /// runs into the Lambda deadline).
+    pub fn timeout(&mut self, limit: Duration) -> &mut Self {
+        self.middleware(Timeout::new(limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+
+    /// Run `mw` around a handler that sleeps for `delay`.
+    async fn run(mw: Timeout, delay: Duration) -> Response {
+        let sleeper = move |_req| async move {
+            tokio::time::sleep(delay).await;
+            Ok(Response::no_content())
+        };
+        run_middleware(mw, sleeper, Request::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn slow_handler_gets_504() {
+        let resp = run(
+            Timeout::new(Duration::from_millis(10)),
+            Duration::from_secs(5),
+        )
+        .await;
+        assert_eq!(resp.status_code, 504);
+    }
+
+    #[tokio::test]
+    async fn fast_handler_passes() {
+        let resp = run(Timeout::new(Duration::from_secs(5)), Duration::ZERO).await;
+        assert_eq!(resp.status_code, 204);
+    }
+
+    #[test]
+    fn no_limit_without_lambda_context() {
+        assert_eq!(Timeout::lambda_deadline().budget(&Request::default()), None);
+    }
+}
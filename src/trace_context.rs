@@ -0,0 +1,213 @@
+//! W3C Trace Context (`traceparent` / `tracestate`) propagation.
+
+use crate::Request;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The trace a request belongs to, continued from the caller's
+/// `traceparent` header or started fresh.
+///
+/// The request gets its own span ID, so outbound calls carrying
+/// [`traceparent`](Self::traceparent) show up as children of this hop.
+///
+/// # Example
+/// ```ignore
+/// let mut call = http.get("https://inventory.internal/items");
+/// if let Some(trace) = req.trace_context() {
+///     for (name, value) in trace.headers() {
+///         call = call.header(name, value);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: String,
+    parent_id: Option<String>,
+    span_id: String,
+    flags: u8,
+    tracestate: Option<String>,
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// A random, non-zero ID of `bytes` bytes in lowercase hex.
+fn random_id(bytes: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
+    let mut id = String::with_capacity(bytes * 2);
+    while id.len() < bytes * 2 {
+        // RandomState is seeded from the OS once per process
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        id.push_str(&format!("{:016x}", hasher.finish() | 1));
+    }
+    id.truncate(bytes * 2);
+    id
+}
+
+impl TraceContext {
+    /// Start a new, sampled trace.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_id(16),
+            parent_id: None,
+            span_id: random_id(8),
+            flags: 0x01,
+            tracestate: None,
+        }
+    }
+
+    /// Continue the trace in `traceparent` (with the caller's `tracestate`),
+    /// or `None` if the header is invalid.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let [version, trace_id, parent_id, flags, rest @ ..] = parts.as_slice() else {
+            return None;
+        };
+        // Version 00 has exactly four fields; later versions may append more
+        let valid = is_hex(version, 2)
+            && *version != "ff"
+            && (*version != "00" || rest.is_empty())
+            && is_hex(trace_id, 32)
+            && trace_id.bytes().any(|b| b != b'0')
+            && is_hex(parent_id, 16)
+            && parent_id.bytes().any(|b| b != b'0')
+            && is_hex(flags, 2);
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: Some(parent_id.to_string()),
+            span_id: random_id(8),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate: tracestate
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// The context for a request with these headers: continued when a valid
+    /// `traceparent` is present, otherwise a new root.
+    pub(crate) fn from_headers<'a>(mut header: impl FnMut(&str) -> Option<&'a str>) -> Self {
+        header("traceparent")
+            .and_then(|tp| Self::parse(tp, header("tracestate")))
+            .unwrap_or_else(Self::new_root)
+    }
+
+    /// The 32-hex-digit trace ID.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// The caller's span ID, if the trace was continued.
+    pub fn parent_id(&self) -> Option<&str> {
+        self.parent_id.as_deref()
+    }
+
+    /// This hop's span ID.
+    pub fn span_id(&self) -> &str {
+        &self.span_id
+    }
+
+    /// Whether the caller sampled this trace.
+    pub fn sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    /// The caller's vendor-specific `tracestate`, passed on unchanged.
+    pub fn tracestate(&self) -> Option<&str> {
+        self.tracestate.as_deref()
+    }
+
+    /// The `traceparent` to send on outbound calls.
+    pub fn traceparent(&self) -> String {
+        self.to_string()
+    }
+
+    /// The headers to send on outbound calls: `traceparent`, plus
+    /// `tracestate` when the caller sent one.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("traceparent", self.traceparent())];
+        if let Some(state) = &self.tracestate {
+            headers.push(("tracestate", state.clone()));
+        }
+        headers
+    }
+
+    /// Add [`headers`](Self::headers) to an outbound `http` request's
+    /// headers.
+    pub fn inject(&self, map: &mut http::HeaderMap) {
+        for (name, value) in self.headers() {
+            if let Ok(value) = http::HeaderValue::from_str(&value) {
+                map.insert(name, value);
+            }
+        }
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{}-{}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+impl Request {
+    /// The W3C trace this request belongs to. Always present for requests
+    /// dispatched by [`Choko`](crate::Choko).
+    pub fn trace_context(&self) -> Option<&TraceContext> {
+        self.extensions().get::<TraceContext>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn continues_incoming_trace_with_new_span() {
+        let ctx = TraceContext::parse(PARENT, Some("congo=t61rcWkgMzE")).unwrap();
+        assert_eq!(ctx.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(ctx.parent_id(), Some("b7ad6b7169203331"));
+        assert_ne!(ctx.span_id(), "b7ad6b7169203331");
+        assert!(ctx.sampled());
+
+        let mut map = http::HeaderMap::new();
+        ctx.inject(&mut map);
+        let outbound = map["traceparent"].to_str().unwrap();
+        assert!(outbound.starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+        assert!(outbound.ends_with(&format!("{}-01", ctx.span_id())));
+        assert_eq!(map["tracestate"], "congo=t61rcWkgMzE");
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for bad in [
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+        ] {
+            assert!(TraceContext::parse(bad, None).is_none(), "{bad}");
+        }
+        assert!(TraceContext::parse(&format!("01{}-extra", &PARENT[2..]), None).is_some());
+    }
+
+    #[test]
+    fn new_root_is_well_formed() {
+        let root = TraceContext::from_headers(|_| None);
+        assert!(TraceContext::parse(&root.traceparent(), None).is_some());
+        assert_eq!(root.parent_id(), None);
+    }
+}
@@ -0,0 +1,357 @@
+//! Signature verification for incoming webhooks (`webhooks` feature).
+//!
+//! Each verifier can be called directly from a handler, or wrapped in
+//! [`VerifyWebhook`] and attached to a route as middleware, in which case
+//! requests with a missing or invalid signature are rejected with 401.
+//!
+//! # Example
+//! ```ignore
+//! use choko::webhooks::{GitHub, VerifyWebhook};
+//!
+//! app.post("/hooks/github", handle_push)
+//!     .middleware(VerifyWebhook::new(GitHub::new(secret)));
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Default tolerance between a signed timestamp and the current time.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(300);
+
+/// Why a webhook signature was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    /// A required signature or timestamp header is missing or malformed.
+    MissingSignature,
+    /// The signature does not match the payload.
+    InvalidSignature,
+    /// The signed timestamp is outside the allowed tolerance.
+    TimestampOutOfRange,
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::MissingSignature => f.write_str("missing webhook signature"),
+            WebhookError::InvalidSignature => f.write_str("invalid webhook signature"),
+            WebhookError::TimestampOutOfRange => f.write_str("webhook timestamp outside tolerance"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// A webhook signature scheme.
+pub trait WebhookVerifier: Send + Sync + 'static {
+    /// Check the request's signature against its raw body.
+    fn verify(&self, req: &Request) -> Result<(), WebhookError>;
+}
+
+/// Constant-time check of a hex-encoded HMAC-SHA256 over `parts`.
+fn verify_hex(secret: &[u8], parts: &[&[u8]], signature_hex: &str) -> Result<(), WebhookError> {
+    let expected = hex::decode(signature_hex.trim()).map_err(|_| WebhookError::InvalidSignature)?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.verify_slice(&expected)
+        .map_err(|_| WebhookError::InvalidSignature)
+}
+
+fn check_timestamp(timestamp: &str, tolerance: Duration) -> Result<(), WebhookError> {
+    let ts: u64 = timestamp
+        .trim()
+        .parse()
+        .map_err(|_| WebhookError::MissingSignature)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    if now.abs_diff(ts) > tolerance.as_secs() {
+        return Err(WebhookError::TimestampOutOfRange);
+    }
+    Ok(())
+}
+
+/// GitHub's `X-Hub-Signature-256: sha256=<hex>` scheme.
+pub struct GitHub {
+    secret: Vec<u8>,
+}
+
+impl GitHub {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+}
+
+impl WebhookVerifier for GitHub {
+    fn verify(&self, req: &Request) -> Result<(), WebhookError> {
+        let signature = req
+            .header("x-hub-signature-256")
+            .and_then(|s| s.trim().strip_prefix("sha256="))
+            .ok_or(WebhookError::MissingSignature)?;
+        verify_hex(
+            &self.secret,
+            &[req.body_bytes().unwrap_or_default()],
+            signature,
+        )
+    }
+}
+
+/// Stripe's `Stripe-Signature: t=<ts>,v1=<hex>` scheme.
+///
+/// Any of several `v1` signatures may match (Stripe sends more than one while
+/// a secret is being rolled).
+pub struct Stripe {
+    secret: Vec<u8>,
+    tolerance: Duration,
+}
+
+impl Stripe {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Set the allowed clock skew for the signed timestamp.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl WebhookVerifier for Stripe {
+    fn verify(&self, req: &Request) -> Result<(), WebhookError> {
+        let header = req
+            .header("stripe-signature")
+            .ok_or(WebhookError::MissingSignature)?;
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for pair in header.split(',') {
+            match pair.trim().split_once('=') {
+                Some(("t", v)) => timestamp = Some(v),
+                Some(("v1", v)) => signatures.push(v),
+                _ => {}
+            }
+        }
+        let timestamp = timestamp.ok_or(WebhookError::MissingSignature)?;
+        if signatures.is_empty() {
+            return Err(WebhookError::MissingSignature);
+        }
+        let signed: [&[u8]; 3] = [
+            timestamp.as_bytes(),
+            b".",
+            req.body_bytes().unwrap_or_default(),
+        ];
+        if !signatures
+            .iter()
+            .any(|sig| verify_hex(&self.secret, &signed, sig).is_ok())
+        {
+            return Err(WebhookError::InvalidSignature);
+        }
+        check_timestamp(timestamp, self.tolerance)
+    }
+}
+
+/// Slack's `X-Slack-Signature: v0=<hex>` scheme with
+/// `X-Slack-Request-Timestamp`.
+pub struct Slack {
+    signing_secret: Vec<u8>,
+    tolerance: Duration,
+}
+
+impl Slack {
+    pub fn new(signing_secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            signing_secret: signing_secret.into(),
+            tolerance: DEFAULT_TOLERANCE,
+        }
+    }
+
+    /// Set the allowed clock skew for the signed timestamp.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+}
+
+impl WebhookVerifier for Slack {
+    fn verify(&self, req: &Request) -> Result<(), WebhookError> {
+        let timestamp = req
+            .header("x-slack-request-timestamp")
+            .ok_or(WebhookError::MissingSignature)?;
+        let signature = req
+            .header("x-slack-signature")
+            .and_then(|s| s.trim().strip_prefix("v0="))
+            .ok_or(WebhookError::MissingSignature)?;
+        let signed: [&[u8]; 4] = [
+            b"v0:",
+            timestamp.trim().as_bytes(),
+            b":",
+            req.body_bytes().unwrap_or_default(),
+        ];
+        verify_hex(&self.signing_secret, &signed, signature)?;
+        check_timestamp(timestamp, self.tolerance)
+    }
+}
+
+/// Middleware that rejects requests failing a [`WebhookVerifier`] with 401.
+pub struct VerifyWebhook<V> {
+    verifier: V,
+}
+
+impl<V: WebhookVerifier> VerifyWebhook<V> {
+    pub fn new(verifier: V) -> Self {
+        Self { verifier }
+    }
+}
+
+impl<V: WebhookVerifier> Middleware for VerifyWebhook<V> {
+    fn handle(&self, req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        match self.verifier.verify(&req) {
+            Ok(()) => next.run(req),
+            Err(e) => {
+                let resp = crate::error_json(401, &e.to_string());
+                Box::pin(async move { Ok(resp) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+
+    fn sign(secret: &[u8], parts: &[&[u8]]) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        for part in parts {
+            mac.update(part);
+        }
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn request(body: &str, headers: &[(&str, String)]) -> Request {
+        Request {
+            body: Some(body.to_string()),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn now() -> String {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string()
+    }
+
+    #[test]
+    fn github_signature() {
+        let sig = sign(b"s3cret", &[b"{\"zen\":1}"]);
+        let req = request(
+            "{\"zen\":1}",
+            &[("x-hub-signature-256", format!("sha256={sig}"))],
+        );
+        assert_eq!(GitHub::new("s3cret").verify(&req), Ok(()));
+        assert_eq!(
+            GitHub::new("other").verify(&req),
+            Err(WebhookError::InvalidSignature)
+        );
+        assert_eq!(
+            GitHub::new("s3cret").verify(&request("{}", &[])),
+            Err(WebhookError::MissingSignature)
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware_rejects_unsigned_requests() {
+        let sig = sign(b"s3cret", &[b"{}"]);
+        let ok = |_req| async { Ok(Response::no_content()) };
+        let signed = request("{}", &[("x-hub-signature-256", format!("sha256={sig}"))]);
+        let resp = run_middleware(VerifyWebhook::new(GitHub::new("s3cret")), ok, signed)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 204);
+
+        let resp = run_middleware(
+            VerifyWebhook::new(GitHub::new("s3cret")),
+            ok,
+            request("{}", &[]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status_code, 401);
+    }
+
+    #[test]
+    fn signs_binary_bodies() {
+        let payload = [0, 159, 146, 150];
+        let sig = sign(b"s3cret", &[&payload]);
+        let mut req = request("", &[("x-hub-signature-256", format!("sha256={sig}"))]);
+        req.body = None;
+        req.binary_body = Some(payload.to_vec());
+        assert_eq!(GitHub::new("s3cret").verify(&req), Ok(()));
+    }
+
+    #[test]
+    fn stripe_signature_with_tolerance() {
+        let ts = now();
+        let sig = sign(b"whsec", &[ts.as_bytes(), b".", b"payload"]);
+        let req = request(
+            "payload",
+            &[("stripe-signature", format!("t={ts},v1=deadbeef,v1={sig}"))],
+        );
+        assert_eq!(Stripe::new("whsec").verify(&req), Ok(()));
+
+        let old = "1000";
+        let sig = sign(b"whsec", &[old.as_bytes(), b".", b"payload"]);
+        let req = request(
+            "payload",
+            &[("stripe-signature", format!("t={old},v1={sig}"))],
+        );
+        assert_eq!(
+            Stripe::new("whsec").verify(&req),
+            Err(WebhookError::TimestampOutOfRange)
+        );
+    }
+
+    #[test]
+    fn slack_signature() {
+        let ts = now();
+        let sig = sign(b"slack", &[b"v0:", ts.as_bytes(), b":", b"a=b"]);
+        let req = request(
+            "a=b",
+            &[
+                ("x-slack-request-timestamp", ts.clone()),
+                ("x-slack-signature", format!("v0={sig}")),
+            ],
+        );
+        assert_eq!(Slack::new("slack").verify(&req), Ok(()));
+
+        let tampered = request(
+            "a=c",
+            &[
+                ("x-slack-request-timestamp", ts),
+                ("x-slack-signature", format!("v0={sig}")),
+            ],
+        );
+        assert_eq!(
+            Slack::new("slack").verify(&tampered),
+            Err(WebhookError::InvalidSignature)
+        );
+    }
+}
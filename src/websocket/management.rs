@@ -0,0 +1,165 @@
+//! Pushing messages to WebSocket clients (`websocket-management` feature).
+
+use super::WsRequest;
+use crate::{ChokoError, Error};
+use aws_sdk_apigatewaymanagement::primitives::Blob;
+use aws_sdk_apigatewaymanagement::Client;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+impl WsRequest {
+    /// The API Gateway Management API endpoint for this API and stage,
+    /// `https://{domain}/{stage}`.
+    ///
+    /// With a custom domain name, build the endpoint from the
+    /// `execute-api` domain instead (or include the base path mapping).
+    pub fn management_endpoint(&self) -> Option<String> {
+        Some(format!("https://{}/{}", self.domain_name()?, self.stage()?))
+    }
+}
+
+/// What API Gateway knows about a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub connected_at: Option<SystemTime>,
+    pub last_active_at: Option<SystemTime>,
+    pub source_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+fn system_time(dt: &aws_sdk_apigatewaymanagement::primitives::DateTime) -> Option<SystemTime> {
+    let secs = u64::try_from(dt.secs()).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_nanos(dt.subsec_nanos().into()))
+}
+
+/// A client for the API Gateway Management API of one WebSocket API stage.
+///
+/// # Example
+/// ```ignore
+/// use choko::websocket::Connections;
+///
+/// let sdk = aws_sdk_apigatewaymanagement::Client::new(&aws_config::load_from_env().await);
+/// app.ws_route("broadcast", move |req| {
+///     let sdk = sdk.clone();
+///     async move {
+///         let connections = Connections::from_request(&sdk, &req)?;
+///         for id in members.list().await? {
+///             if !connections.post(&id, req.body.clone().unwrap_or_default()).await? {
+///                 members.remove(&id).await?; // client went away
+///             }
+///         }
+///         Ok(Response::no_content())
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Connections {
+    client: Client,
+}
+
+impl Connections {
+    /// Use `client`'s configuration (credentials, region) against the
+    /// management `endpoint`, e.g.
+    /// `https://abc123.execute-api.eu-west-1.amazonaws.com/prod`.
+    pub fn new(client: &Client, endpoint: &str) -> Self {
+        let config = client.config().to_builder().endpoint_url(endpoint).build();
+        Self {
+            client: Client::from_conf(config),
+        }
+    }
+
+    /// Target the API and stage `req` arrived through.
+    pub fn from_request(client: &Client, req: &WsRequest) -> Result<Self, ChokoError> {
+        let endpoint = req
+            .management_endpoint()
+            .ok_or_else(|| ChokoError::internal("WebSocket event has no domain name or stage"))?;
+        Ok(Self::new(client, &endpoint))
+    }
+
+    /// Send `data` to a connection. Returns `Ok(false)` if the connection
+    /// is gone, so callers can forget it.
+    pub async fn post(&self, connection_id: &str, data: impl Into<Vec<u8>>) -> Result<bool, Error> {
+        let result = self
+            .client
+            .post_to_connection()
+            .connection_id(connection_id)
+            .data(Blob::new(data))
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_gone_exception()) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Send `value` serialized as JSON. See [`post`](Self::post).
+    pub async fn post_json<T: Serialize + ?Sized>(
+        &self,
+        connection_id: &str,
+        value: &T,
+    ) -> Result<bool, Error> {
+        self.post(connection_id, serde_json::to_vec(value)?).await
+    }
+
+    /// Close a connection. Closing one that is already gone succeeds.
+    pub async fn delete(&self, connection_id: &str) -> Result<(), Error> {
+        let result = self
+            .client
+            .delete_connection()
+            .connection_id(connection_id)
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_gone_exception()) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Look up a connection; `None` if it is gone.
+    pub async fn get(&self, connection_id: &str) -> Result<Option<ConnectionInfo>, Error> {
+        let result = self
+            .client
+            .get_connection()
+            .connection_id(connection_id)
+            .send()
+            .await;
+        let output = match result {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_gone_exception()) => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(ConnectionInfo {
+            connected_at: output.connected_at().and_then(system_time),
+            last_active_at: output.last_active_at().and_then(system_time),
+            source_ip: output
+                .identity()
+                .and_then(|i| i.source_ip())
+                .map(str::to_string),
+            user_agent: output
+                .identity()
+                .and_then(|i| i.user_agent())
+                .map(str::to_string),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_from_domain_and_stage() {
+        let mut req = WsRequest::default();
+        assert_eq!(req.management_endpoint(), None);
+        req.domain_name = Some("abc123.execute-api.eu-west-1.amazonaws.com".to_string());
+        req.stage = Some("prod".to_string());
+        assert_eq!(
+            req.management_endpoint().as_deref(),
+            Some("https://abc123.execute-api.eu-west-1.amazonaws.com/prod")
+        );
+    }
+}
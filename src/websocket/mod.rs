@@ -0,0 +1,309 @@
+//! API Gateway WebSocket APIs.
+//!
+//! Register handlers by route key with [`Choko::ws_route`] — `$connect`,
+//! `$disconnect`, `$default` or your own keys selected by the API's route
+//! selection expression — and start the app with
+//! [`Choko::run_websocket`]. Handlers receive a [`WsRequest`] and return a
+//! [`Response`]; for `$connect` a non-2xx status rejects the connection,
+//! and for routes with a route response the body is sent back to the
+//! client. With the `websocket-management` feature, [`Connections`] pushes
+//! messages back to connected clients.
+//!
+//! # Example
+//! ```ignore
+//! app.ws_route("$connect", |req| async move {
+//!     connections.add(req.connection_id()).await?;
+//!     Ok(Response::no_content())
+//! });
+//! app.ws_route("sendMessage", |req| async move {
+//!     let msg: Chat = req.json()?;
+//!     // ...
+//!     Ok(Response::no_content())
+//! });
+//! ```
+
+#[cfg(feature = "websocket-management")]
+mod management;
+
+#[cfg(feature = "websocket-management")]
+pub use management::{ConnectionInfo, Connections};
+
+use crate::{BoxFuture, Choko, ChokoError, Error, LambdaContext, RequestContext, Response};
+use aws_lambda_events::event::apigw::{ApiGatewayProxyResponse, ApiGatewayWebsocketProxyRequest};
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+pub(crate) type WsHandlerFn =
+    Arc<dyn Fn(WsRequest) -> BoxFuture<Result<Response, Error>> + Send + Sync>;
+
+/// A WebSocket event passed to route handlers.
+#[derive(Debug, Clone, Default)]
+pub struct WsRequest {
+    /// Headers of the connection request (only present on `$connect`).
+    pub headers: HashMap<String, String>,
+    /// Query string parameters of the connection URL (only on `$connect`).
+    pub query_params: HashMap<String, Vec<String>>,
+    /// The message body (base64 already decoded for binary frames).
+    pub body: Option<Vec<u8>>,
+    connection_id: String,
+    route_key: String,
+    event_type: Option<String>,
+    domain_name: Option<String>,
+    stage: Option<String>,
+    request_context: RequestContext,
+    lambda_context: Option<LambdaContext>,
+}
+
+impl WsRequest {
+    /// The ID of the client connection, for replying through the
+    /// management API.
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    /// The route key that selected this handler, e.g. `$connect` or
+    /// `sendMessage`.
+    pub fn route_key(&self) -> &str {
+        &self.route_key
+    }
+
+    /// `CONNECT`, `MESSAGE` or `DISCONNECT`.
+    pub fn event_type(&self) -> Option<&str> {
+        self.event_type.as_deref()
+    }
+
+    /// The API's domain name, e.g. `abc123.execute-api.eu-west-1.amazonaws.com`.
+    pub fn domain_name(&self) -> Option<&str> {
+        self.domain_name.as_deref()
+    }
+
+    /// The API stage name.
+    pub fn stage(&self) -> Option<&str> {
+        self.stage.as_deref()
+    }
+
+    /// A header value by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The message body as text, if it is valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        self.body
+            .as_deref()
+            .and_then(|b| std::str::from_utf8(b).ok())
+    }
+
+    /// Deserialize the message body as JSON. Fails with 400.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ChokoError> {
+        let body = self
+            .body
+            .as_deref()
+            .ok_or_else(|| ChokoError::bad_request("Missing message body"))?;
+        serde_json::from_slice(body).map_err(|e| ChokoError::bad_request(e.to_string()))
+    }
+
+    /// Client identity, request ID and authorizer output supplied by API
+    /// Gateway.
+    pub fn request_context(&self) -> &RequestContext {
+        &self.request_context
+    }
+
+    /// Metadata about the Lambda invocation, when run through the runtime.
+    pub fn lambda_context(&self) -> Option<&LambdaContext> {
+        self.lambda_context.as_ref()
+    }
+
+    fn from_event(event: ApiGatewayWebsocketProxyRequest) -> Self {
+        let ctx = event.request_context;
+        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
+        for (k, v) in event.multi_value_query_string_parameters.iter() {
+            query_params
+                .entry(k.to_string())
+                .or_default()
+                .push(v.to_string());
+        }
+        if query_params.is_empty() {
+            for (k, v) in event.query_string_parameters.iter() {
+                query_params
+                    .entry(k.to_string())
+                    .or_default()
+                    .push(v.to_string());
+            }
+        }
+        let body = event.body.map(|body| {
+            if event.is_base64_encoded {
+                base64::engine::general_purpose::STANDARD
+                    .decode(&body)
+                    .unwrap_or_else(|_| body.into_bytes())
+            } else {
+                body.into_bytes()
+            }
+        });
+        Self {
+            headers: event
+                .headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                .collect(),
+            query_params,
+            body,
+            connection_id: ctx.connection_id.clone().unwrap_or_default(),
+            route_key: ctx.route_key.clone().unwrap_or_default(),
+            event_type: ctx.event_type.clone(),
+            domain_name: ctx.domain_name.clone(),
+            stage: ctx.stage.clone(),
+            request_context: RequestContext {
+                source_ip: ctx.identity.source_ip.clone(),
+                user_agent: ctx.identity.user_agent.clone(),
+                stage: ctx.stage.clone(),
+                request_id: ctx.request_id.clone(),
+                authorizer: ctx.authorizer.fields.clone(),
+                ..Default::default()
+            },
+            lambda_context: None,
+        }
+    }
+}
+
+impl Choko {
+    /// Register a handler for WebSocket `route_key`.
+    ///
+    /// Messages whose route key has no handler go to `$default`; without
+    /// one they are answered with 404. `$connect` and `$disconnect` are
+    /// accepted when no handler is registered for them.
+    pub fn ws_route<F, Fut>(&mut self, route_key: &str, handler: F) -> &mut Self
+    where
+        F: Fn(WsRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response, Error>> + Send + 'static,
+    {
+        self.ws_routes.insert(
+            route_key.to_string(),
+            Arc::new(move |req| Box::pin(handler(req))),
+        );
+        self
+    }
+
+    /// Run the application as the integration of an API Gateway WebSocket
+    /// API.
+    pub async fn run_websocket(self) -> Result<(), Error> {
+        self.serve(
+            |app, event: ApiGatewayWebsocketProxyRequest, context| async move {
+                app.dispatch_ws(event, Some(context)).await
+            },
+        )
+        .await
+    }
+
+    pub(crate) async fn dispatch_ws(
+        &self,
+        event: ApiGatewayWebsocketProxyRequest,
+        context: Option<LambdaContext>,
+    ) -> Result<ApiGatewayProxyResponse, Error> {
+        let mut request = WsRequest::from_event(event);
+        request.lambda_context = context;
+        let handler = self
+            .ws_routes
+            .get(request.route_key())
+            .or_else(|| match request.route_key() {
+                "$connect" | "$disconnect" => None,
+                _ => self.ws_routes.get("$default"),
+            })
+            .cloned();
+        let response = match handler {
+            Some(handler) => handler(request)
+                .await
+                .unwrap_or_else(|e| self.handler_error(e)),
+            None if matches!(request.route_key(), "$connect" | "$disconnect") => {
+                Response::no_content()
+            }
+            None => crate::error_json(404, "Not Found"),
+        };
+        Ok(self.build_apigw_response(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lambda_events::encodings::Body;
+
+    fn ws_event(route_key: &str, body: Option<&str>) -> ApiGatewayWebsocketProxyRequest {
+        let mut event = ApiGatewayWebsocketProxyRequest::default();
+        event.request_context.route_key = Some(route_key.to_string());
+        event.request_context.connection_id = Some("conn-1".to_string());
+        event.body = body.map(str::to_string);
+        event
+    }
+
+    #[tokio::test]
+    async fn routes_by_key_with_default_fallback() {
+        let mut app = Choko::new("test");
+        app.ws_route("echo", |req| async move {
+            Ok(Response::text(format!(
+                "{}:{}",
+                req.connection_id(),
+                req.text().unwrap_or_default()
+            )))
+        });
+        app.ws_route("$default", |req| async move {
+            Ok(Response::text(format!("default:{}", req.route_key())))
+        });
+
+        let resp = app
+            .dispatch_ws(ws_event("echo", Some("hi")), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.body, Some(Body::Text("conn-1:hi".to_string())));
+        let resp = app
+            .dispatch_ws(ws_event("other", None), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.body, Some(Body::Text("default:other".to_string())));
+        // $connect is accepted without falling through to $default
+        let resp = app
+            .dispatch_ws(ws_event("$connect", None), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 204);
+    }
+
+    #[tokio::test]
+    async fn rejects_connection_and_maps_errors() {
+        let mut app = Choko::new("test");
+        app.ws_route("$connect", |_req| async {
+            Ok(crate::error_json(403, "Forbidden"))
+        });
+        app.ws_route("join", |req| async move {
+            #[derive(serde::Deserialize)]
+            struct Join {
+                #[allow(dead_code)]
+                room: String,
+            }
+            let _: Join = req.json()?;
+            Ok(Response::no_content())
+        });
+
+        let resp = app
+            .dispatch_ws(ws_event("$connect", None), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 403);
+        let resp = app
+            .dispatch_ws(ws_event("join", Some("{}")), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 400);
+        let resp = app
+            .dispatch_ws(ws_event("missing", None), None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status_code, 404);
+    }
+}
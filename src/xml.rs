@@ -0,0 +1,79 @@
+//! XML request and response bodies (`xml` feature).
+
+use crate::{Error, Request, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+impl Request {
+    /// Deserialize the request body as XML.
+    ///
+    /// # Example
+    /// ```ignore
+    /// #[derive(serde::Deserialize)]
+    /// struct Order { id: u32 }
+    ///
+    /// let order: Order = req.xml()?;
+    /// ```
+    pub fn xml<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let body = self.body.as_deref().ok_or("request body is empty")?;
+        Ok(quick_xml::de::from_str(body)?)
+    }
+}
+
+impl Response {
+    /// Serialize `value` as an `application/xml` response with status 200.
+    ///
+    /// The root element is named after the serialized type.
+    pub fn xml<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let body = quick_xml::se::to_string(value)?;
+        Ok(Response::text(body).with_header("Content-Type", "application/xml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseBody;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Order {
+        id: u32,
+        item: String,
+    }
+
+    #[test]
+    fn request_xml_deserializes_body() {
+        let req = Request {
+            body: Some("<Order><id>7</id><item>tea</item></Order>".to_string()),
+            ..Default::default()
+        };
+        let order: Order = req.xml().unwrap();
+        assert_eq!(
+            order,
+            Order {
+                id: 7,
+                item: "tea".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn request_xml_errors_on_missing_body() {
+        assert!(Request::default().xml::<Order>().is_err());
+    }
+
+    #[test]
+    fn response_xml_sets_content_type() {
+        let resp = Response::xml(&Order {
+            id: 1,
+            item: "cup".to_string(),
+        })
+        .unwrap();
+        assert_eq!(resp.headers.get("Content-Type").unwrap(), "application/xml");
+        assert_eq!(
+            resp.body,
+            ResponseBody::Text("<Order><id>1</id><item>cup</item></Order>".to_string())
+        );
+    }
+}
@@ -0,0 +1,464 @@
+//! AWS X-Ray subsegments (`xray` feature).
+//!
+//! Lambda records a segment for each invocation; [`XRay`] middleware adds a
+//! subsegment per handler, annotated with the method, route and status, so
+//! requests show up by route in traces and the service map. Downstream
+//! calls are recorded as child subsegments with [`instrument_aws`] and
+//! [`instrument_http`]. Subsegments are sent to the X-Ray daemon over UDP
+//! (`AWS_XRAY_DAEMON_ADDRESS`), and only for sampled traces.
+//!
+//! # Example
+//! ```ignore
+//! use choko::xray::{self, XRay};
+//!
+//! app.middleware(XRay::new());
+//! app.get("/orders/{id}", |req| async move {
+//!     let item = xray::instrument_aws(req.xray(), "DynamoDB", "GetItem", async {
+//!         dynamodb.get_item().table_name("orders").key("id", id).send().await
+//!     })
+//!     .await?;
+//!     // ...
+//! });
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{BoxFuture, Error, Request, Response};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::future::Future;
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Header carrying the trace context on HTTP requests.
+pub const TRACE_HEADER: &str = "X-Amzn-Trace-Id";
+
+/// A parsed `Root=...;Parent=...;Sampled=...` trace header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceHeader {
+    /// The trace ID, e.g. `1-5759e988-bd862e3fe1be46a994272793`.
+    pub root: String,
+    /// The ID of the parent segment or subsegment.
+    pub parent: Option<String>,
+    pub sampled: bool,
+}
+
+impl FromStr for TraceHeader {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut root = None;
+        let mut parent = None;
+        let mut sampled = false;
+        for part in s.split(';') {
+            match part.trim().split_once('=') {
+                Some(("Root", v)) => root = Some(v.to_string()),
+                Some(("Parent", v)) => parent = Some(v.to_string()),
+                Some(("Sampled", v)) => sampled = v == "1",
+                _ => {}
+            }
+        }
+        Ok(Self {
+            root: root.ok_or("trace header has no Root")?,
+            parent,
+            sampled,
+        })
+    }
+}
+
+impl fmt::Display for TraceHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Root={}", self.root)?;
+        if let Some(parent) = &self.parent {
+            write!(f, ";Parent={parent}")?;
+        }
+        write!(f, ";Sampled={}", u8::from(self.sampled))
+    }
+}
+
+impl Request {
+    /// The X-Ray trace context of this invocation: from the Lambda context,
+    /// else the `X-Amzn-Trace-Id` header, else `_X_AMZN_TRACE_ID`.
+    pub fn trace_header(&self) -> Option<TraceHeader> {
+        self.lambda_context()
+            .and_then(|ctx| ctx.xray_trace_id().map(str::to_string))
+            .or_else(|| self.header(TRACE_HEADER).map(str::to_string))
+            .or_else(|| std::env::var("_X_AMZN_TRACE_ID").ok())
+            .and_then(|h| h.parse().ok())
+    }
+
+    /// The handler subsegment opened by [`XRay`], for instrumenting
+    /// downstream calls.
+    pub fn xray(&self) -> Option<&XRayContext> {
+        self.extensions().get::<XRayContext>()
+    }
+}
+
+/// The trace position downstream subsegments attach to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XRayContext {
+    trace_id: String,
+    parent_id: String,
+    sampled: bool,
+}
+
+impl XRayContext {
+    /// A new subsegment under this position.
+    pub fn subsegment(&self, name: &str) -> Subsegment {
+        Subsegment::new(name, self.clone())
+    }
+
+    /// The header to send on outgoing HTTP requests so the callee joins the
+    /// trace.
+    pub fn trace_header(&self) -> TraceHeader {
+        TraceHeader {
+            root: self.trace_id.clone(),
+            parent: Some(self.parent_id.clone()),
+            sampled: self.sampled,
+        }
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// A random 64-bit segment ID in hex.
+fn new_id() -> String {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes).expect("OS random source is available");
+    format!("{:016x}", u64::from_be_bytes(bytes))
+}
+
+/// `name` restricted to the characters X-Ray accepts in segment names.
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .filter_map(|c| match c {
+            '{' => Some(':'),
+            c if c.is_alphanumeric() || c.is_whitespace() || "_.:/%&#=+\\-@".contains(c) => Some(c),
+            _ => None,
+        })
+        .take(200)
+        .collect()
+}
+
+/// An open subsegment. Call [`end`](Self::end) to record it.
+#[derive(Debug)]
+pub struct Subsegment {
+    name: String,
+    id: String,
+    parent: XRayContext,
+    start_time: f64,
+    namespace: Option<&'static str>,
+    annotations: Map<String, Value>,
+    metadata: Map<String, Value>,
+    http: Option<Value>,
+    aws: Option<Value>,
+    error: bool,
+    fault: bool,
+}
+
+impl Subsegment {
+    fn new(name: &str, parent: XRayContext) -> Self {
+        Self {
+            name: sanitize_name(name),
+            id: new_id(),
+            parent,
+            start_time: now(),
+            namespace: None,
+            annotations: Map::new(),
+            metadata: Map::new(),
+            http: None,
+            aws: None,
+            error: false,
+            fault: false,
+        }
+    }
+
+    /// The context for subsegments nested inside this one.
+    pub fn context(&self) -> XRayContext {
+        XRayContext {
+            trace_id: self.parent.trace_id.clone(),
+            parent_id: self.id.clone(),
+            sampled: self.parent.sampled,
+        }
+    }
+
+    /// Add an indexed annotation (searchable in trace filters).
+    pub fn annotate(&mut self, key: &str, value: impl Into<Value>) -> &mut Self {
+        self.annotations.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Add non-indexed metadata.
+    pub fn metadata(&mut self, key: &str, value: impl Into<Value>) -> &mut Self {
+        self.metadata.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Mark this subsegment as a call to an AWS service operation.
+    pub fn aws_operation(&mut self, operation: &str) -> &mut Self {
+        self.namespace = Some("aws");
+        self.aws = Some(json!({ "operation": operation }));
+        self
+    }
+
+    /// Mark this subsegment as an HTTP call to `url`.
+    pub fn http_request(&mut self, method: &str, url: &str) -> &mut Self {
+        self.namespace = Some("remote");
+        self.http = Some(json!({ "request": { "method": method, "url": url } }));
+        self
+    }
+
+    /// Record the response status, flagging 4xx as error and 5xx as fault.
+    pub fn http_status(&mut self, status: u16) -> &mut Self {
+        let http = self.http.get_or_insert_with(|| json!({}));
+        http["response"] = json!({ "status": status });
+        self.error = (400..500).contains(&status);
+        self.fault = status >= 500;
+        self
+    }
+
+    /// Flag the subsegment as failed.
+    pub fn fault(&mut self) -> &mut Self {
+        self.fault = true;
+        self
+    }
+
+    fn document(&self, end_time: f64) -> Value {
+        let mut doc = json!({
+            "name": self.name,
+            "id": self.id,
+            "trace_id": self.parent.trace_id,
+            "parent_id": self.parent.parent_id,
+            "type": "subsegment",
+            "start_time": self.start_time,
+            "end_time": end_time,
+        });
+        if let Some(namespace) = self.namespace {
+            doc["namespace"] = json!(namespace);
+        }
+        if !self.annotations.is_empty() {
+            doc["annotations"] = Value::Object(self.annotations.clone());
+        }
+        if !self.metadata.is_empty() {
+            doc["metadata"] = json!({ "default": self.metadata });
+        }
+        if let Some(http) = &self.http {
+            doc["http"] = http.clone();
+        }
+        if let Some(aws) = &self.aws {
+            doc["aws"] = aws.clone();
+        }
+        if self.error {
+            doc["error"] = json!(true);
+        }
+        if self.fault {
+            doc["fault"] = json!(true);
+        }
+        doc
+    }
+
+    /// Close the subsegment and send it to the daemon if the trace is
+    /// sampled.
+    pub fn end(self) {
+        if self.parent.sampled {
+            send(&self.document(now()));
+        }
+    }
+}
+
+/// The daemon's UDP address from `AWS_XRAY_DAEMON_ADDRESS`, which is either
+/// `host:port` or `tcp:host:port udp:host:port`.
+fn daemon_address() -> String {
+    let configured = std::env::var("AWS_XRAY_DAEMON_ADDRESS").unwrap_or_default();
+    configured
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix("udp:"))
+        .or_else(|| configured.split_whitespace().find(|p| !p.contains("tcp:")))
+        .unwrap_or("127.0.0.1:2000")
+        .to_string()
+}
+
+fn send(document: &Value) {
+    static SOCKET: OnceLock<Option<UdpSocket>> = OnceLock::new();
+    let socket = SOCKET.get_or_init(|| {
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect(daemon_address()).ok()?;
+        Some(socket)
+    });
+    let Some(socket) = socket else {
+        return;
+    };
+    let packet = format!("{{\"format\":\"json\",\"version\":1}}\n{document}");
+    if let Err(e) = socket.send(packet.as_bytes()) {
+        eprintln!("Failed to send X-Ray subsegment: {e}");
+    }
+}
+
+async fn instrument<F, T, E>(mut subsegment: Option<Subsegment>, fut: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let result = fut.await;
+    if let Some(mut subsegment) = subsegment.take() {
+        if result.is_err() {
+            subsegment.fault();
+        }
+        subsegment.end();
+    }
+    result
+}
+
+/// Record `fut` as a call to AWS `service`'s `operation`. Without a
+/// context (no [`XRay`] middleware) `fut` simply runs.
+pub async fn instrument_aws<F, T, E>(
+    ctx: Option<&XRayContext>,
+    service: &str,
+    operation: &str,
+    fut: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let subsegment = ctx.map(|ctx| {
+        let mut subsegment = ctx.subsegment(service);
+        subsegment.aws_operation(operation);
+        subsegment
+    });
+    instrument(subsegment, fut).await
+}
+
+/// Record `fut` as an HTTP `method` call to `url` named after its host.
+/// Pass the response status to the returned subsegment yourself if needed;
+/// errors are recorded as faults.
+pub async fn instrument_http<F, T, E>(
+    ctx: Option<&XRayContext>,
+    method: &str,
+    url: &str,
+    fut: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let subsegment = ctx.map(|ctx| {
+        let host = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', '?'])
+            .next()
+            .unwrap_or(url);
+        let mut subsegment = ctx.subsegment(host);
+        subsegment.http_request(method, url);
+        subsegment
+    });
+    instrument(subsegment, fut).await
+}
+
+/// Middleware opening a subsegment around each request's handler.
+///
+/// The subsegment is named `<METHOD> <route>` and annotated with `method`,
+/// `route` and `status`; handlers reach it through [`Request::xray`].
+/// Requests without trace context pass through untouched.
+#[derive(Debug, Default)]
+pub struct XRay;
+
+impl XRay {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Middleware for XRay {
+    fn handle(&self, mut req: Request, next: Next) -> BoxFuture<Result<Response, Error>> {
+        let Some(header) = req.trace_header() else {
+            return next.run(req);
+        };
+        let parent = XRayContext {
+            trace_id: header.root,
+            parent_id: header.parent.unwrap_or_default(),
+            sampled: header.sampled,
+        };
+        let route = req.route().unwrap_or(req.path()).to_string();
+        let method = req.method().to_string();
+        let mut subsegment = parent.subsegment(&format!("{method} {route}"));
+        subsegment.annotate("method", method.as_str());
+        subsegment.annotate("route", route.as_str());
+        req.extensions_mut().insert(subsegment.context());
+        Box::pin(async move {
+            let result = next.run(req).await;
+            match &result {
+                Ok(resp) => {
+                    let status = u16::try_from(resp.status_code).unwrap_or(500);
+                    subsegment.annotate("status", status);
+                    subsegment.http_status(status);
+                }
+                Err(_) => {
+                    subsegment.fault();
+                }
+            }
+            subsegment.end();
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::run_middleware;
+    use std::collections::HashMap;
+
+    const HEADER: &str =
+        "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1";
+
+    #[test]
+    fn trace_header_round_trip() {
+        let header: TraceHeader = HEADER.parse().unwrap();
+        assert_eq!(header.root, "1-5759e988-bd862e3fe1be46a994272793");
+        assert_eq!(header.parent.as_deref(), Some("53995c3f42cd8ad8"));
+        assert!(header.sampled);
+        assert_eq!(header.to_string(), HEADER);
+        assert!("Parent=1".parse::<TraceHeader>().is_err());
+    }
+
+    #[test]
+    fn subsegment_document() {
+        let ctx = XRayContext {
+            trace_id: "1-abc".to_string(),
+            parent_id: "0123456789abcdef".to_string(),
+            sampled: true,
+        };
+        let mut sub = ctx.subsegment("GET /users/{id}");
+        sub.annotate("route", "/users/{id}").http_status(503);
+        let doc = sub.document(1.0);
+        assert_eq!(doc["name"], "GET /users/:id");
+        assert_eq!(doc["parent_id"], "0123456789abcdef");
+        assert_eq!(doc["annotations"]["route"], "/users/{id}");
+        assert_eq!(doc["http"]["response"]["status"], 503);
+        assert_eq!(doc["fault"], true);
+        assert_eq!(sub.id.len(), 16);
+    }
+
+    #[tokio::test]
+    async fn middleware_exposes_context_under_new_subsegment() {
+        let handler = |req: Request| async move {
+            let ctx = req.xray().unwrap().trace_header();
+            assert_eq!(ctx.root, "1-5759e988-bd862e3fe1be46a994272793");
+            assert_ne!(ctx.parent.as_deref(), Some("53995c3f42cd8ad8"));
+            Ok(Response::no_content())
+        };
+        let req = Request {
+            headers: HashMap::from([(
+                TRACE_HEADER.to_string(),
+                HEADER.replace("Sampled=1", "Sampled=0"),
+            )]),
+            ..Default::default()
+        };
+        let resp = run_middleware(XRay::new(), handler, req).await.unwrap();
+        assert_eq!(resp.status_code, 204);
+    }
+}